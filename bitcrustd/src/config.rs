@@ -46,6 +46,13 @@ mod tests {
     }
 }
 
+fn store_config_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("store-config")
+        .long("store-config")
+        .help("Location of the TOML config of the store, default: a store in the data directory")
+        .takes_value(true)
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ConfigFile {
     key: Vec<u8>,
@@ -129,6 +136,20 @@ impl<'a, 'b> Config {
                     .help("Address to get balance for")
                     .takes_value(true)
                     .required(true))
+                .arg(Arg::with_name("network")
+                    .short("n")
+                    .long("network")
+                    .help("Network of the address, default: main")
+                    .takes_value(true)
+                    .possible_values(&["main", "test", "signet", "regtest"]))
+            )
+            .subcommand(SubCommand::with_name("verify-store")
+                .about("Checks the block store for inconsistencies")
+                .arg(store_config_arg())
+            )
+            .subcommand(SubCommand::with_name("reindex-store")
+                .about("Rebuilds the indexes of the block store; no node may use the store meanwhile")
+                .arg(store_config_arg())
             )
             .subcommand(SubCommand::with_name("db")
                 .about("Query the database")
@@ -136,6 +157,12 @@ impl<'a, 'b> Config {
                     .about("Finds a transaction")
                     .arg(Arg::with_name("tx-hash")
                         .required(true))
+                    .arg(Arg::with_name("network")
+                        .short("n")
+                        .long("network")
+                        .help("Network used for the addresses of the outputs, default: main")
+                        .takes_value(true)
+                        .possible_values(&["main", "test", "signet", "regtest"]))
                 )
                 .subcommand(SubCommand::with_name("get-block")
                     .about("Finds a block")
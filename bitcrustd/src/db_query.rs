@@ -3,8 +3,11 @@ use clap::ArgMatches;
 use config::Config;
 use util::*;
 use store;
+use network;
 
+use bitcrust_lib::script::{address, asm, standard};
 use serde_json;
+use serde_json::Value;
 
 pub fn db_query(matches: &ArgMatches, config: &Config) {
 
@@ -13,6 +16,7 @@ pub fn db_query(matches: &ArgMatches, config: &Config) {
 
     match matches.subcommand() {
         ("get-transaction", Some(txhash)) => {
+            let network = network(txhash);
             let txhash = txhash.value_of("tx-hash").unwrap();
             let tx_db = store::transaction_get(db, &hash_from_hex(txhash))
                 .unwrap()
@@ -20,7 +24,17 @@ pub fn db_query(matches: &ArgMatches, config: &Config) {
 
             let tx = tx_db.as_tx().unwrap();
 
-            println!("{}", serde_json::to_string_pretty(&tx).unwrap());
+            // show the outputs with their script template and address instead of raw bytes
+            let mut json = serde_json::to_value(&tx).unwrap();
+            json["txs_out"] = Value::Array(tx.txs_out.iter().map(|output| json!({
+                "value":     output.value,
+                "pk_script": to_hex(output.pk_script),
+                "asm":       asm::to_asm(output.pk_script),
+                "type":      standard::classify(output.pk_script).name(),
+                "address":   address::to_address(output.pk_script, network)
+            })).collect());
+
+            println!("{}", serde_json::to_string_pretty(&json).unwrap());
 
 
         },
@@ -12,13 +12,17 @@ extern crate serde;
 extern crate toml;
 
 extern crate store;
-extern crate serde_json;
+extern crate bitcrust_lib;
+#[macro_use] extern crate serde_json;
 
+use std::process;
 use std::thread;
 use std::time::Duration;
 
 use clap::ArgMatches;
 
+use bitcrust_lib::script::{address, asm, standard};
+use bitcrust_lib::script::address::Network;
 use bitcrust_net::{BitcoinNetworkConnection, BitcoinNetworkError, Message, AuthenticatedBitcrustMessage};
 
 mod client_message;
@@ -50,6 +54,12 @@ fn main() {
         ("stats", Some(stats_matches)) => {
             stats(stats_matches, &config);
         }
+        ("verify-store", Some(verify_matches)) => {
+            verify_store(verify_matches, &config);
+        }
+        ("reindex-store", Some(reindex_matches)) => {
+            reindex_store(reindex_matches, &config);
+        }
         ("", None) => println!("No subcommand was used"), // If no subcommand was usd it'll match the tuple ("", None)
         _ => unreachable!(), // If all subcommands are defined above, anything else is unreachabe!()
     }
@@ -61,9 +71,76 @@ fn node(_matches: &ArgMatches, config: &Config) {
 }
 
 fn balance(matches: &ArgMatches, _config: &Config) {
-    // This unwrap is safe because we require it above
+    // This unwrap is safe because we require the address above
     let address = matches.value_of("address").unwrap();
-    println!("I'd love to get your balance on '{}' but it's not yet implemented!", address);
+    let network = network(matches);
+
+    let script = match address::from_address(address, network) {
+        Ok(script) => script,
+        Err(e) => {
+            println!("Invalid address '{}': {}", address, e);
+            return
+        }
+    };
+    println!("Address '{}' pays to {} script {}", address, standard::classify(&script).name(), asm::to_asm(&script));
+    println!("I'd love to get your balance but there is no address index yet!");
+}
+
+/// Returns the network given with --network, or main; exits if the name is not a network
+fn network(matches: &ArgMatches) -> Network {
+    let name = matches.value_of("network").unwrap_or("main");
+    match Network::from_name(name) {
+        Some(network) => network,
+        None => {
+            println!("Invalid network '{}': expected main, test, signet or regtest", name);
+            process::exit(2)
+        }
+    }
+}
+
+fn store_config(matches: &ArgMatches, config: &Config) -> bitcrust_lib::Config {
+    match matches.value_of("store-config") {
+        Some(path) => match bitcrust_lib::Config::from_file(path) {
+            Ok(store_config) => store_config,
+            Err(e) => {
+                println!("Invalid store config '{}': {}", path, e);
+                process::exit(2)
+            }
+        },
+        None => bitcrust_lib::Config::new(&config.data_dir.to_string_lossy())
+    }
+}
+
+fn verify_store(matches: &ArgMatches, config: &Config) {
+    let store_config = store_config(matches, config);
+
+    // opening the store first recovers it, if it was not closed properly
+    let mut store = bitcrust_lib::Store::new(&store_config);
+    let inconsistencies = store.verify_integrity();
+
+    for inconsistency in inconsistencies.iter() {
+        println!("{}", inconsistency);
+    }
+    if inconsistencies.is_empty() {
+        println!("No inconsistencies found in {}", store_config.root.display());
+    } else {
+        println!("{} inconsistencies found in {}", inconsistencies.len(), store_config.root.display());
+        process::exit(1);
+    }
+}
+
+fn reindex_store(matches: &ArgMatches, config: &Config) {
+    let store_config = store_config(matches, config);
+
+    let mut store = bitcrust_lib::Store::new(&store_config);
+    match store.reindex() {
+        Ok(stats) => println!("Reindexed {}: {} transactions, {} blocks, {} orphans, {} rejected blocks",
+            store_config.root.display(), stats.transactions, stats.blocks, stats.orphans, stats.rejected),
+        Err(bitcrust_lib::ReindexError::StoreInUse) => {
+            println!("The store {} is in use", store_config.root.display());
+            process::exit(1);
+        }
+    }
 }
 
 fn stats(matches: &ArgMatches, config: &Config) {
@@ -8,8 +8,15 @@
 
 use config;
 use store;
-use store::Store;
+use store::{Store, RecordPtr, HashIndexGuard};
 use block_add;
+use tx_add;
+use block::BlockError;
+use transaction::{TransactionOk, TransactionError};
+use hash::Hash32;
+use buffer::write_compact_size;
+
+pub use block_add::BlockAddOk;
 
 
 
@@ -36,18 +43,104 @@ pub fn init_prs() -> Store {
 }
 
 // This is a preliminary interface.
-pub fn add_block(store: &mut store::Store, buffer: &[u8]) {
+pub fn add_block(store: &mut store::Store, buffer: &[u8]) -> Result<BlockAddOk, BlockError> {
     block_add::add_block(store, buffer)
 }
 
-pub fn add_transaction(_: &[u8]) {
+/// Verifies and stores a transaction that is not part of a block, against the best tip
+///
+/// Missing parents, outputs that are already spent and failing scripts are reported with the
+/// corresponding TransactionError
+pub fn add_transaction(store: &mut store::Store, buffer: &[u8]) -> Result<TransactionOk, TransactionError> {
+    tx_add::add_transaction(store, buffer)
+}
+
+
+
+/// The transactions of a stored block, read one at a time
+///
+/// This is obtained with `get_block_transactions` and yields the raw transactions in block order
+pub struct BlockTransactions<'a> {
+    store:     &'a mut Store,
+
+    /// The raw block header
+    pub header:   Vec<u8>,
 
+    /// The number of transactions in the block
+    pub tx_count: usize,
+
+    // the index of the next spend-tree record to look at, and of the end-of-block record
+    next:      u64,
+    end:       u64,
+    remaining: usize
 }
 
+impl<'a> Iterator for BlockTransactions<'a> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+
+        // skip the output records of the previous transaction
+        while self.next < self.end {
+            let record = self.store.spend_tree.get_record(RecordPtr::new(self.next));
+            self.next += 1;
 
+            if record.is_transaction() {
+                self.remaining -= 1;
+                return Some(self.store.transactions.read(record.get_transaction_ptr()));
+            }
+        }
+        None
+    }
 
-pub fn get_block(_: [u8; 32]) {
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
 
+/// Looks up a block by its hash, to read its transactions one at a time
+///
+/// The hash is in internal byte order. Returns None if the block is not stored
+pub fn get_block_transactions<'a>(store: &'a mut Store, hash: &[u8; 32]) -> Option<BlockTransactions<'a>> {
+
+    let block = match store.block_index.get(Hash32(hash)).into_iter().find(|ptr| !ptr.is_guard()) {
+        Some(block) => block,
+        None        => return None
+    };
+
+    let header   = store.get_block_header(block).to_vec();
+    let tx_count = store.spend_tree.get_block_records(block).iter()
+        .filter(|rec| rec.is_transaction())
+        .count();
+
+    Some(BlockTransactions {
+        store:     store,
+        header:    header,
+        tx_count:  tx_count,
+        next:      block.start.to_index() + 1,
+        end:       block.end().to_index(),
+        remaining: tx_count
+    })
+}
+
+/// Returns the network-serialized block with the given hash
+///
+/// The hash is in internal byte order. Returns None if the block is not stored
+pub fn get_block(store: &mut Store, hash: &[u8; 32]) -> Option<Vec<u8>> {
+
+    let transactions = match get_block_transactions(store, hash) {
+        Some(transactions) => transactions,
+        None               => return None
+    };
+
+    let mut block = Vec::new();
+    block.extend_from_slice(&transactions.header);
+    write_compact_size(&mut block, transactions.tx_count);
+
+    for tx in transactions {
+        block.extend_from_slice(&tx);
+    }
+    Some(block)
 }
 
 
@@ -57,6 +150,7 @@ mod tests {
 
     use util::*;
     use super::*;
+    use hash::Hash32Buf;
 
     #[test]
     pub fn test_add_block() {
@@ -74,7 +168,50 @@ mod tests {
         let slice = &from_hex(hex);
         let mut store = init();
 
-        add_block(&mut store, slice);
+        assert_eq!(add_block(&mut store, slice), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, slice), Ok(BlockAddOk::AlreadyExists));
 
     }
+
+    #[test]
+    pub fn test_get_block() {
+
+        let mut store = init();
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => a;11 ),
+            tx!(bld; a => b,c )
+        );
+        let block2 = blk!(prev = block1;
+            tx!(bld; coinbase => d;13 ),
+            tx!(bld; b => e ),
+            tx!(bld; c,e => f )
+        );
+
+        for block in [&block0, &block1, &block2].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+
+        for block in [&block0, &block1, &block2].iter() {
+            let hash = Hash32Buf::double_sha256(&block[0..80]);
+            assert_eq!(get_block(&mut store, hash.as_ref().0).as_ref(), Some(*block));
+        }
+
+        let hash = Hash32Buf::double_sha256(&block2[0..80]);
+        {
+            let txs = get_block_transactions(&mut store, hash.as_ref().0).unwrap();
+            assert_eq!(txs.header, &block2[0..80]);
+            assert_eq!(txs.tx_count, 3);
+            assert_eq!(txs.size_hint(), (3, Some(3)));
+
+            let raw: Vec<u8> = txs.flat_map(|tx| tx.into_iter()).collect();
+            assert_eq!(raw, &block2[81..]);
+        }
+
+        assert_eq!(get_block(&mut store, &[0; 32]), None);
+    }
 }
@@ -12,14 +12,33 @@ use buffer::*;
 use hash::*;
 
 use store::SpendingError;
+use pow;
+use merkle_tree;
 
 
-use transaction::{Transaction, TransactionError};
+use transaction::{Transaction, TransactionError, COIN, WITNESS_SCALE_FACTOR};
 
+/// Maximum size of a block without witness data
 const MAX_BLOCK_SIZE: usize =  1_000_000;
 
+/// Maximum weight of a block (BIP141)
+pub const MAX_BLOCK_WEIGHT: usize = 4_000_000;
 
-#[derive(Debug)]
+/// Maximum sigop cost of a block (BIP141)
+pub const MAX_BLOCK_SIGOPS_COST: usize = 80_000;
+
+/// Maximum number of signature operations in a block, counted by legacy rules
+pub const MAX_BLOCK_SIGOPS: usize = MAX_BLOCK_SIGOPS_COST / WITNESS_SCALE_FACTOR;
+
+/// Maximum number of seconds a block time may be ahead of the current time
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+/// The start of the script of the coinbase output that holds the witness commitment (BIP141):
+/// OP_RETURN, a push of 36 bytes and a 4-byte tag
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
+
+#[derive(Debug, PartialEq)]
 pub enum BlockError {
     NoTransanctions,
     FirstNotCoinbase,
@@ -27,10 +46,53 @@ pub enum BlockError {
 
     BlockTooLarge,
 
+    /// The weight of the block is above MAX_BLOCK_WEIGHT
+    BlockWeightTooHigh,
+
+    /// The block has more than MAX_BLOCK_SIGOPS signature operations, or its sigop cost is
+    /// above MAX_BLOCK_SIGOPS_COST
+    TooManySigops,
+
     IncorrectMerkleRoot,
 
     UnexpectedEndOfBuffer,
 
+    /// The bits field is not a valid target or above the proof-of-work limit
+    InvalidDifficultyTarget,
+
+    /// The header hash does not meet the target
+    HashAboveTarget,
+
+    /// The bits field does not match the expected difficulty
+    IncorrectDifficulty { expected: u32, found: u32 },
+
+    /// The time is not after the median time of the previous 11 blocks
+    TimeTooOld,
+
+    /// The time is too far in the future
+    TimeTooNew,
+
+    /// The coinbase claims more than the subsidy plus the fees of the block
+    CoinbaseAmountTooHigh { allowed: i64, found: i64 },
+
+    /// The coinbase script doesn't start with the height of the block (BIP34)
+    IncorrectCoinbaseHeight,
+
+    /// The coinbase has the txid of an earlier transaction in the chain that has unspent
+    /// outputs (BIP30)
+    DuplicateTransaction,
+
+    /// The block has a witness commitment, but the witness of the coinbase is not a single
+    /// 32-byte nonce (BIP141)
+    InvalidWitnessNonce,
+
+    /// The witness commitment doesn't match the witnesses of the block (BIP141)
+    IncorrectWitnessCommitment,
+
+    /// A transaction has witness data, but the block has no witness commitment or segwit is
+    /// not active
+    UnexpectedWitness,
+
 
     SpendingError(SpendingError),
     TransactionError(TransactionError)
@@ -65,6 +127,92 @@ impl convert::From<SpendingError> for BlockError {
 type BlockResult<T> = Result<T, BlockError>;
 
 
+/// Returns the amount of new coins a coinbase at the given height may create, when the subsidy
+/// is halved every `halving_interval` blocks
+pub fn get_block_subsidy(height: u64, halving_interval: u64) -> i64 {
+
+    let halvings = height / halving_interval;
+    if halvings >= 64 {
+        return 0;
+    }
+
+    (50 * COIN) >> halvings
+}
+
+/// Returns the script that pushes the given height, with which the script of a coinbase must
+/// start (BIP34)
+pub fn get_coinbase_height_script(height: u64) -> Vec<u8> {
+
+    // small numbers are pushed with an opcode
+    if height == 0 {
+        return vec![0x00];
+    }
+    if height <= 16 {
+        return vec![0x50 + height as u8];
+    }
+
+    // otherwise as minimally encoded number; a set high bit would make it negative
+    let mut number: Vec<u8> = Vec::new();
+    let mut rest = height;
+    while rest > 0 {
+        number.push(rest as u8);
+        rest >>= 8;
+    }
+    if number[number.len() - 1] & 0x80 != 0 {
+        number.push(0);
+    }
+
+    let mut script = vec![number.len() as u8];
+    script.extend(number);
+    script
+}
+
+
+
+
+/// Verifies the witness commitment of a block with the given transactions (BIP141)
+///
+/// Once segwit is active, an output of the coinbase can commit to the witnesses of the block; if
+/// there are more, the last one is used. It holds the hash of the merkle root of the wtxids,
+/// with zeros for the coinbase, and the witness nonce, which must be the only item of the
+/// witness of the coinbase. Without a commitment, no transaction may have witness data
+///
+/// The threshold is the configured parallel_hashing_threshold of the merkle root
+pub fn verify_witness_commitment(txs: &[Transaction], segwit_active: bool, parallel_threshold: usize) -> BlockResult<()> {
+
+    let commitment = txs.first().and_then(|coinbase| coinbase.txs_out.iter()
+        .rev()
+        .find(|output| output.pk_script.len() >= 38 && output.pk_script.starts_with(&WITNESS_COMMITMENT_HEADER)));
+
+    if let (true, Some(commitment)) = (segwit_active, commitment) {
+
+        let nonce = match txs[0].witnesses.first() {
+            Some(witness) if witness.items.len() == 1 && witness.items[0].len() == 32 => witness.items[0],
+            _ => return Err(BlockError::InvalidWitnessNonce)
+        };
+
+        let wtxids: Vec<Hash32Buf> = Some(Hash32Buf::from_slice(&[0; 32])).into_iter()
+            .chain(txs[1..].iter().map(|tx| tx.wtxid()))
+            .collect();
+
+        let witness_root = merkle_tree::get_merkle_root(wtxids, parallel_threshold);
+        let expected     = Hash32Buf::double_sha256_from_parts(&[&witness_root.as_ref().0[..], nonce]);
+
+        return if &commitment.pk_script[6..38] == &expected.as_ref().0[..] {
+            Ok(())
+        }
+        else {
+            Err(BlockError::IncorrectWitnessCommitment)
+        };
+    }
+
+    if txs.iter().any(|tx| tx.has_witness()) {
+        Err(BlockError::UnexpectedWitness)
+    }
+    else {
+        Ok(())
+    }
+}
 
 
 /// Parsed block
@@ -121,20 +269,147 @@ impl<'a> Block<'a> {
         }
     }
 
-    /// Verifies the size of the block
+    /// Verifies that the first and only the first transaction is a coinbase
+    pub fn verify_coinbase(&self) -> BlockResult<()> {
+
+        if self.txs.is_empty() {
+            return Err(BlockError::NoTransanctions);
+        }
+
+        if !self.txs[0].is_coinbase() {
+            return Err(BlockError::FirstNotCoinbase);
+        }
+
+        if self.txs[1..].iter().any(|tx| tx.is_coinbase()) {
+            return Err(BlockError::DoubleCoinbase);
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the witness commitment of the block; see verify_witness_commitment
+    pub fn verify_witness_commitment(&self, segwit_active: bool, parallel_threshold: usize) -> BlockResult<()> {
+        verify_witness_commitment(&self.txs, segwit_active, parallel_threshold)
+    }
+
+    /// Returns the size of the block without witness data
+    pub fn get_stripped_size(&self) -> usize {
+
+        let witness_size: usize = self.txs.iter()
+            .map(|tx| tx.to_raw().len() - tx.stripped_size())
+            .sum();
+
+        self.raw.len() - witness_size
+    }
+
+    /// Returns the weight of the block; witness data counts once and other data four times
+    pub fn get_weight(&self) -> usize {
+        self.get_stripped_size() * (WITNESS_SCALE_FACTOR - 1) + self.raw.len()
+    }
+
+    /// Returns the number of signature operations in the block, counted by legacy rules
+    pub fn get_legacy_sigop_count(&self) -> usize {
+        self.txs.iter().map(|tx| tx.get_legacy_sigop_count()).sum()
+    }
+
+    /// Verifies the size of the block without witness data
     pub fn verify_block_size(&self) -> BlockResult<()> {
 
-        if self.to_raw().len() > MAX_BLOCK_SIZE {
+        if self.get_stripped_size() > MAX_BLOCK_SIZE {
             Err(BlockError::BlockTooLarge)
         }
         else {
             Ok(())
         }
     }
+
+    /// Verifies the weight of the block
+    pub fn verify_block_weight(&self) -> BlockResult<()> {
+
+        if self.get_weight() > MAX_BLOCK_WEIGHT {
+            Err(BlockError::BlockWeightTooHigh)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Verifies the number of signature operations that are known without the spent outputs
+    pub fn verify_legacy_sigops(&self) -> BlockResult<()> {
+
+        if self.get_legacy_sigop_count() > MAX_BLOCK_SIGOPS {
+            Err(BlockError::TooManySigops)
+        }
+        else {
+            Ok(())
+        }
+    }
 }
 
 
 
+impl<'a> BlockHeader<'a> {
+
+    /// The difficulty target in compact form
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn time(&self) -> u32 {
+        self.time
+    }
+
+    /// Verifies that the bits field is a valid target not above `pow_limit`, and that the given
+    /// hash of this header meets this target
+    pub fn verify_proof_of_work(&self, hash: Hash32, pow_limit: pow::U256) -> BlockResult<()> {
+
+        let target = pow::from_compact(self.bits);
+
+        if !pow::is_valid_compact(self.bits) || target.is_zero() || target > pow_limit {
+            Err(BlockError::InvalidDifficultyTarget)
+        }
+        else if pow::hash_to_u256(hash) > target {
+            Err(BlockError::HashAboveTarget)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Verifies that the time is not more than two hours after the given current time
+    pub fn verify_time_not_in_future(&self, now: u32) -> BlockResult<()> {
+
+        if self.time as u64 > now as u64 + MAX_FUTURE_BLOCK_TIME as u64 {
+            Err(BlockError::TimeTooNew)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Verifies that the time is after the median-time-past of the previous blocks
+    pub fn verify_time_after(&self, median_time_past: u32) -> BlockResult<()> {
+
+        if self.time <= median_time_past {
+            Err(BlockError::TimeTooOld)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Verifies that the bits field matches the expected difficulty
+    pub fn verify_difficulty(&self, expected_bits: u32) -> BlockResult<()> {
+
+        if self.bits != expected_bits {
+            Err(BlockError::IncorrectDifficulty { expected: expected_bits, found: self.bits })
+        }
+        else {
+            Ok(())
+        }
+    }
+}
+
 impl<'a> Parse<'a> for BlockHeader<'a> {
 
     /// Parses the block-header
@@ -179,6 +454,8 @@ mod tests {
     use buffer::Parse;
     use buffer;
     use transaction;
+    use chain_params::ChainParams;
+    use deployments::Chain;
 
     const BLOCK0: &'static str = "0100000000000000000000000000000000000000000000000000000000000000\
                    000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa\
@@ -212,4 +489,177 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_block_subsidy() {
+
+        assert_eq!(get_block_subsidy(0, 210_000), 50 * COIN);
+        assert_eq!(get_block_subsidy(209_999, 210_000), 50 * COIN);
+        assert_eq!(get_block_subsidy(210_000, 210_000), 25 * COIN);
+        assert_eq!(get_block_subsidy(420_000, 210_000), 1250_000_000);
+        assert_eq!(get_block_subsidy(210_000 * 33, 210_000), 0);
+        assert_eq!(get_block_subsidy(210_000 * 64, 210_000), 0);
+        assert_eq!(get_block_subsidy(150, 150), 25 * COIN);
+    }
+
+    #[test]
+    fn test_coinbase_height_script() {
+
+        assert_eq!(get_coinbase_height_script(0), vec![0x00]);
+        assert_eq!(get_coinbase_height_script(1), vec![0x51]);
+        assert_eq!(get_coinbase_height_script(16), vec![0x60]);
+        assert_eq!(get_coinbase_height_script(17), vec![0x01, 0x11]);
+        assert_eq!(get_coinbase_height_script(128), vec![0x02, 0x80, 0x00]);
+
+        // the first BIP34 block on main net
+        assert_eq!(get_coinbase_height_script(227_931), vec![0x03, 0x5b, 0x7a, 0x03]);
+    }
+
+    // Returns the genesis header followed by a coinbase with a witness item of the given size
+    fn segwit_block(witness_size: usize) -> Vec<u8> {
+
+        let mut block = from_hex(BLOCK0);
+        block.truncate(80);
+        block.push(1);
+
+        block.extend_from_slice(&[1, 0, 0, 0, 0, 1, 1]);
+        block.extend_from_slice(&[0; 32]);
+        block.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 2, 0x51, 0x51, 0, 0, 0, 0]);
+        block.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xac]);
+
+        block.extend_from_slice(&[1, 0xfe]);
+        block.extend((0..4).map(|n| (witness_size >> (n * 8)) as u8));
+        block.extend(vec![0; witness_size]);
+        block.extend_from_slice(&[0, 0, 0, 0]);
+        block
+    }
+
+    #[test]
+    fn test_block_weight_and_sigops() {
+
+        let raw = from_hex(BLOCK0);
+        let block = Block::new(&raw).unwrap();
+
+        assert_eq!(block.get_stripped_size(), raw.len());
+        assert_eq!(block.get_weight(), raw.len() * 4);
+        assert_eq!(block.get_legacy_sigop_count(), 1);
+
+        let raw = segwit_block(1000);
+        let block = Block::new(&raw).unwrap();
+
+        assert_eq!(block.get_stripped_size(), raw.len() - 1000 - 5 - 1 - 2);
+        assert_eq!(block.get_weight(), block.get_stripped_size() * 3 + raw.len());
+        assert_eq!(block.get_legacy_sigop_count(), 1);
+        assert_eq!(block.verify_block_weight(), Ok(()));
+
+        // the witness only counts once, but makes the block too heavy
+        let raw = segwit_block(MAX_BLOCK_WEIGHT);
+        let block = Block::new(&raw).unwrap();
+
+        assert!(raw.len() > MAX_BLOCK_SIZE);
+        assert_eq!(block.verify_block_size(), Ok(()));
+        assert_eq!(block.verify_block_weight(), Err(BlockError::BlockWeightTooHigh));
+    }
+
+    // Returns the genesis header followed by a coinbase with the given witness items, and with
+    // the given witness commitment output if any
+    fn commitment_block(commitment: Option<&[u8]>, witness: &[&[u8]]) -> Vec<u8> {
+
+        let mut block = from_hex(BLOCK0);
+        block.truncate(80);
+        block.push(1);
+
+        block.extend_from_slice(&[1, 0, 0, 0, 0, 1, 1]);
+        block.extend_from_slice(&[0; 32]);
+        block.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 2, 0x51, 0x51, 0, 0, 0, 0]);
+
+        match commitment {
+            None => block.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xac]),
+            Some(commitment) => {
+                block.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xac]);
+                block.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 38]);
+                block.extend_from_slice(&WITNESS_COMMITMENT_HEADER);
+                block.extend_from_slice(commitment);
+            }
+        }
+
+        block.push(witness.len() as u8);
+        for item in witness {
+            block.push(item.len() as u8);
+            block.extend_from_slice(item);
+        }
+        block.extend_from_slice(&[0, 0, 0, 0]);
+        block
+    }
+
+    #[test]
+    fn test_witness_commitment() {
+
+        // the witness root of a block with only a coinbase is zero
+        let nonce      = [7; 32];
+        let commitment = Hash32Buf::double_sha256_from_parts(&[&[0u8; 32][..], &nonce[..]]);
+
+        let raw = commitment_block(Some(&commitment.as_ref().0[..]), &[&nonce[..]]);
+        assert_eq!(Block::new(&raw).unwrap().verify_witness_commitment(true, 10), Ok(()));
+
+        // witness data needs an active and valid commitment
+        assert_eq!(Block::new(&raw).unwrap().verify_witness_commitment(false, 10),
+            Err(BlockError::UnexpectedWitness));
+
+        let raw = commitment_block(Some(&[0u8; 32][..]), &[&nonce[..]]);
+        assert_eq!(Block::new(&raw).unwrap().verify_witness_commitment(true, 10),
+            Err(BlockError::IncorrectWitnessCommitment));
+
+        let raw = commitment_block(Some(&commitment.as_ref().0[..]), &[&nonce[1..]]);
+        assert_eq!(Block::new(&raw).unwrap().verify_witness_commitment(true, 10),
+            Err(BlockError::InvalidWitnessNonce));
+
+        let raw = commitment_block(Some(&commitment.as_ref().0[..]), &[&nonce[..], &nonce[..]]);
+        assert_eq!(Block::new(&raw).unwrap().verify_witness_commitment(true, 10),
+            Err(BlockError::InvalidWitnessNonce));
+
+        let raw = commitment_block(None, &[&nonce[..]]);
+        assert_eq!(Block::new(&raw).unwrap().verify_witness_commitment(true, 10),
+            Err(BlockError::UnexpectedWitness));
+
+        // blocks without witness data need no commitment
+        let raw = from_hex(BLOCK0);
+        assert_eq!(Block::new(&raw).unwrap().verify_witness_commitment(true, 10), Ok(()));
+        assert_eq!(Block::new(&raw).unwrap().verify_witness_commitment(false, 10), Ok(()));
+    }
+
+    #[test]
+    fn test_blockheader_verify() {
+
+        let pow_limit = ChainParams::new(Chain::Main).pow_limit;
+
+        let mut raw = from_hex(BLOCK0);
+        raw.truncate(80);
+
+        {
+            let hdr = BlockHeader::parse(&mut buffer::Buffer::new(&raw)).unwrap();
+            let hash = Hash32Buf::double_sha256(&raw);
+
+            assert_eq!(hdr.verify_proof_of_work(hash.as_ref(), pow_limit), Ok(()));
+
+            // 2009-01-03 18:15:05
+            assert_eq!(hdr.time(), 1231006505);
+            assert_eq!(hdr.verify_time_not_in_future(1231006505 - MAX_FUTURE_BLOCK_TIME), Ok(()));
+            assert_eq!(hdr.verify_time_not_in_future(1231006505 - MAX_FUTURE_BLOCK_TIME - 1),
+                Err(BlockError::TimeTooNew));
+
+            assert_eq!(hdr.verify_time_after(1231006504), Ok(()));
+            assert_eq!(hdr.verify_time_after(1231006505), Err(BlockError::TimeTooOld));
+
+            assert_eq!(hdr.verify_difficulty(0x1d00ffff), Ok(()));
+            assert_eq!(hdr.verify_difficulty(0x1d00fffe),
+                Err(BlockError::IncorrectDifficulty { expected: 0x1d00fffe, found: 0x1d00ffff }));
+        }
+
+        // negative target
+        raw[74] = 0x80;
+        let hdr = BlockHeader::parse(&mut buffer::Buffer::new(&raw)).unwrap();
+        let hash = Hash32Buf::double_sha256(&raw);
+        assert_eq!(hdr.verify_proof_of_work(hash.as_ref(), pow_limit), Err(BlockError::InvalidDifficultyTarget));
+    }
+
 }
@@ -65,37 +65,376 @@ Block storing is a tricky part; blocks are stored in the spend-tree and referenc
     If this ^^ is only confusing; please ignore and check the code at connect_block below
 */
 
-use std::time::Instant;
+use std::time::{Instant,SystemTime,UNIX_EPOCH};
 use hash::*;
-use util::*;
 use buffer::*;
 use rayon::prelude::*;
 use slog ;
 
 use store::Store;
 use transaction;
-use transaction::TransactionStats;
+use transaction::{Transaction, TransactionError, TransactionStats, TxOutput};
 use merkle_tree;
 use block::*;
 use store::Record;
-use store::BlockPtr;
+use store::{BlockPtr, BlockInfo};
+use store::TxPtr;
 use store::HashIndexGuard;
 use store::tips;
+use pow;
+use chain_params;
 
 type BlockResult<T> = Result<T, BlockError>;
 
-// minimum number of hashes to use parallel hashing
-const PARALLEL_HASHING_THRESHOLD: usize = 10;
+/// The result of a block that is accepted by add_block
+#[derive(Debug, PartialEq)]
+pub enum BlockAddOk {
 
+    /// The block was already stored; nothing is done
+    AlreadyExists,
 
-/// Returns true if the given hash is the hash of a genesis block
-fn is_genesis_block(hash: Hash32) -> bool {
-    const HASH_GENESIS: &'static str =
-        "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+    /// The block is stored and connected to its previous block
+    Connected,
 
-    let genesis = Hash32Buf::from_slice(&from_hex_rev(HASH_GENESIS));
+    /// The block is stored, but its previous block is not yet in.
+    /// It is verified against the spend-tree and connected when the previous block comes in
+    Orphan
+}
+
+
+
+// number of blocks used for the median-time-past
+const MEDIAN_TIME_SPAN: usize = 11;
+
+/// Returns the parsed header of a stored block
+fn read_block_header(store: &mut Store, block: BlockPtr) -> BlockHeader<'static> {
+
+    let raw = store.get_block_header(block);
+
+    BlockHeader::parse(&mut Buffer::new(raw))
+        .expect("Stored block header must be valid")
+}
+
+/// Returns the height of a connected block
+pub fn get_height(store: &mut Store, block: BlockPtr) -> u64 {
+
+    store.get_block_info(block).height
+}
+
+/// Returns the bits of the last block at or below the given block that doesn't use the
+/// proof-of-work limit by the 20-minute rule, or that starts a difficulty period
+fn get_last_regular_bits(store: &mut Store, block: BlockPtr, height: u64) -> u32 {
+
+    let pow_limit_bits = store.params.pow_limit_bits();
+
+    let mut block  = block;
+    let mut height = height;
+    loop {
+        let bits = read_block_header(store, block).bits();
+        if height % pow::DIFFICULTY_ADJUSTMENT_INTERVAL == 0 || bits != pow_limit_bits {
+            return bits;
+        }
+
+        match store.spend_tree.get_previous_block(block) {
+            Some(previous) => { block = previous; height -= 1; },
+            None           => return bits
+        }
+    }
+}
+
+/// Verifies the header of a block against the chain it is connected to
+///
+/// The bits must match those of the previous block, or the retargeted difficulty at the start of
+/// a new difficulty period. On chains that allow minimum difficulty blocks, a block more than
+/// twice the target spacing after its previous block must use the proof-of-work limit, and other
+/// blocks the bits of the last block that doesn't. The time must be after the median time of the
+/// previous 11 blocks
+fn verify_header_context(store: &mut Store, block: BlockPtr, previous_block: BlockPtr) -> BlockResult<()> {
+
+    let header          = read_block_header(store, block);
+    let previous_header = read_block_header(store, previous_block);
+
+    let height = get_height(store, previous_block) + 1;
+
+    let expected_bits = if height % pow::DIFFICULTY_ADJUSTMENT_INTERVAL != 0 {
+
+        if !store.params.pow_allow_min_difficulty_blocks {
+            previous_header.bits()
+        }
+        else if header.time() > previous_header.time() + 2 * chain_params::TARGET_SPACING {
+            store.params.pow_limit_bits()
+        }
+        else {
+            get_last_regular_bits(store, previous_block, height - 1)
+        }
+    }
+    else if store.params.pow_no_retargeting {
+        previous_header.bits()
+    }
+    else {
+        // find the first block of the period that ends with the previous block
+        let mut first = previous_block;
+        for _ in 0..pow::DIFFICULTY_ADJUSTMENT_INTERVAL - 1 {
+            first = store.spend_tree.get_previous_block(first)
+                .expect("A difficulty period must be complete");
+        }
+        let first_time = read_block_header(store, first).time();
+
+        pow::calculate_next_target(previous_header.bits(), first_time, previous_header.time(),
+            store.params.pow_limit)
+    };
+
+    header.verify_difficulty(expected_bits)?;
+
+    // collect the times of the previous blocks for the median-time-past
+    let mut times    = vec![previous_header.time()];
+    let mut ancestor = previous_block;
+    while times.len() < MEDIAN_TIME_SPAN {
+        match store.spend_tree.get_previous_block(ancestor) {
+            Some(previous) => {
+                times.push(read_block_header(store, previous).time());
+                ancestor = previous;
+            },
+            None => break
+        }
+    }
+    times.sort();
+
+    header.verify_time_after(times[times.len() / 2])
+}
+
+/// Registers a connected block as tip, replacing the tip of its previous block
+fn add_tip(store: &mut Store, block_hash: Hash32Buf, block: BlockPtr, previous_block: Option<BlockPtr>) {
+
+    let previous_hash = previous_block.map(|previous_block| store.get_block_hash(previous_block));
+    let info          = store.get_block_info(block);
+
+    tips::add_tip(&store.tips, block_hash, previous_hash, info.work, info.height);
+}
+
+/// Moves the spend-index to the tip with the most work;
+/// this keeps the spend-index valid for the main chain
+///
+/// The spend-index lock must be held (see Store::lock_spend_index)
+pub fn update_main_chain(store: &mut Store) {
+
+    let best_tip = match store.tips.get_most_work_tip() {
+        Some(tip) => tip,
+        None      => return
+    };
+
+    let best_block = store.block_index
+        .get(best_tip.block_hash.as_ref())
+        .into_iter()
+        .find(|ptr| !ptr.is_guard());
+
+    if let Some(best_block) = best_block {
+        store.spend_tree.move_spend_index(&mut store.spend_index, &store.logger, best_block);
+    }
+}
+
+/// Verifies the amounts of the transactions in the block: the inputs of each transaction must
+/// cover its outputs, and the coinbase may not claim more than the subsidy plus the fees
+///
+/// The scripts of transactions that are not verified with the flags of the height of the block,
+/// such as those marked as unverified, are verified with these flags. Unless scripts are skipped
+/// during initial sync, these are then marked as verified with these flags
+///
+/// Returns the sigop cost of the block, which needs the same outputs as the amounts. The inputs
+/// of the block must be resolved (see revolve_orphan_pointers)
+fn verify_block_inputs(store: &mut Store, block: BlockPtr, height: u64) -> BlockResult<usize> {
+
+    let records = store.spend_tree.get_block_records(block);
+    let flags   = store.params.get_script_flags(height);
+
+    // the transaction being processed and the outputs spent by it so far
+    let mut current: Option<(TxPtr, Vec<u8>, Vec<Vec<u8>>)> = None;
+    let mut fees: i64         = 0;
+    let mut coinbase_value    = 0;
+    let mut sigop_cost: usize = 0;
+
+    for (n, rec) in records.iter().enumerate() {
+
+        if rec.is_output() {
+
+            let output = store.transactions.read_output(rec.get_transaction_ptr(), rec.get_output_index())
+                .ok_or(TransactionError::OutputIndexNotFound)?;
+
+            if let Some((_, _, ref mut spent)) = current {
+                spent.push(output);
+            }
+        }
+
+        if rec.is_transaction() {
+            let ptr = rec.get_transaction_ptr();
+            current = Some((ptr, store.transactions.read(ptr), Vec::new()));
+        }
+
+        // finish the transaction if this is its last record
+        let last = records.get(n+1).map_or(true, |next| next.is_transaction());
+        if !last {
+            continue;
+        }
+
+        if let Some((ptr, raw, spent)) = current.take() {
+
+            let tx = Transaction::parse(&mut Buffer::new(&raw))
+                .expect("Invalid tx data in database");
+
+            let spent: Vec<TxOutput> = spent.iter()
+                .map(|output| TxOutput::parse(&mut Buffer::new(output)).expect("Corrupt output data in store"))
+                .collect();
+
+            if !store.initial_sync && store.transactions.get_script_flags(ptr) != Some(flags) {
+                tx.verify_scripts(&spent, flags)?;
+                store.transactions.set_verified(ptr, flags);
+            }
+
+            sigop_cost += tx.get_sigop_cost(&spent, flags);
+
+            if tx.is_coinbase() {
+                coinbase_value = tx.get_output_value();
+                continue;
+            }
+
+            let input_value: i64 = spent.iter().map(|output| output.value).sum();
+            if input_value < tx.get_output_value() {
+                return Err(BlockError::TransactionError(TransactionError::InsufficientInputAmount));
+            }
+            fees += input_value - tx.get_output_value();
+        }
+    }
+
+    let allowed = store.params.get_block_subsidy(height) + fees;
+    if coinbase_value > allowed {
+        return Err(BlockError::CoinbaseAmountTooHigh { allowed: allowed, found: coinbase_value });
+    }
+
+    Ok(sigop_cost)
+}
+
+/// Returns the pointer and the raw data of the coinbase of a stored block
+fn read_coinbase(store: &mut Store, block: BlockPtr) -> (TxPtr, Vec<u8>) {
+
+    let ptr = store.spend_tree.get_block_records(block)[0].get_transaction_ptr();
+
+    (ptr, store.transactions.read(ptr))
+}
+
+/// Verifies that the coinbase script starts with the height of the block once BIP34 is active
+fn verify_coinbase_height(store: &mut Store, block: BlockPtr, height: u64) -> BlockResult<()> {
+
+    if height < store.params.bip34_height {
+        return Ok(());
+    }
+
+    let (_, raw) = read_coinbase(store, block);
+    let coinbase = Transaction::parse(&mut Buffer::new(&raw))
+        .expect("Invalid tx data in database");
+
+    if coinbase.txs_in[0].script.starts_with(&get_coinbase_height_script(height)) {
+        Ok(())
+    }
+    else {
+        Err(BlockError::IncorrectCoinbaseHeight)
+    }
+}
+
+/// Verifies that no other coinbase with the txid of the coinbase of the block is in the chain
+/// with unspent outputs (BIP30). The spend-index must be valid for the previous block
+///
+/// Other transactions cannot repeat a txid without spending the same outputs again. The blocks
+/// that are exempted keep their own coinbase, but bitcoin-core overwrites the outputs of the
+/// earlier one; the outputs of the txid are then those of the later coinbase. For this, the
+/// coinbase of the block is put in front of the earlier ones in the tx-index (see find_output)
+fn verify_unique_coinbase(store: &mut Store, block: BlockPtr) -> BlockResult<()> {
+
+    let (ptr, raw) = read_coinbase(store, block);
+    let coinbase = Transaction::parse(&mut Buffer::new(&raw))
+        .expect("Invalid tx data in database");
+
+    let txid   = coinbase.txid();
+    let values = store.tx_index.get(txid.as_ref());
+
+    let mut repeated = false;
+    for &other in values.iter().filter(|&&other| !other.is_guard() && other != ptr) {
+
+        if !store.spend_index.exists(Record::new_transaction(other).hash()) {
+            continue;
+        }
+
+        let unspent = (0..coinbase.txs_out.len() as u32)
+            .any(|n| !store.spend_index.exists(Record::new_output(other, n).hash()));
+
+        if unspent {
+            let block_hash = store.get_block_hash(block);
+            if !store.params.is_bip30_exception(block_hash.as_ref()) {
+                return Err(BlockError::DuplicateTransaction);
+            }
+        }
+        repeated = true;
+    }
+
+    if repeated && values.first() != Some(&ptr) {
+
+        let values: Vec<TxPtr> = Some(ptr).into_iter()
+            .chain(values.into_iter().filter(|&other| other != ptr))
+            .collect();
+
+        store.tx_index.replace(txid.as_ref(), &values);
+    }
+
+    Ok(())
+}
+
+/// Verifies a block against its previous block and connects them in the spend-tree
+///
+/// This checks the header context, the coinbase, the amounts, the sigop cost and double-spends.
+/// For a block that was added as orphan, the witness commitment is checked as well
+fn verify_and_connect_block(store: &mut Store, previous_block: BlockPtr, block: BlockPtr, orphan: bool) -> BlockResult<()> {
+
+    if store.verify_headers {
+        verify_header_context(store, block, previous_block)?;
+    }
+
+    let height = get_height(store, previous_block) + 1;
+    verify_coinbase_height(store, block, height)?;
+
+    if orphan {
+        verify_stored_witness_commitment(store, block, height)?;
+    }
+
+    // other stores may not move the spend-index until the block is connected
+    let _lock = store.lock_spend_index();
+
+    // the outputs and coinbases are looked up in the chain of the previous block
+    store.spend_tree.move_spend_index(&mut store.spend_index, &store.logger, previous_block);
+
+    // inputs that could not be matched when the transactions were stored,
+    // must be resolved now; otherwise they are spending non-existent outputs
+    store.spend_tree.revolve_orphan_pointers(
+        &mut store.transactions,
+        &mut store.tx_index,
+        &store.spend_index,
+        block
+    )?;
+
+    verify_unique_coinbase(store, block)?;
+
+    let sigop_cost = verify_block_inputs(store, block, height)?;
+    if sigop_cost > MAX_BLOCK_SIGOPS_COST {
+        return Err(BlockError::TooManySigops);
+    }
+
+    info!(store.logger, "connect_block - inputs verified"; "height" => height, "sigop_cost" => sigop_cost);
+
+    store.spend_tree.connect_block(&mut store.spend_index, &store.logger, previous_block, block)?;
 
-    genesis.as_ref() == hash
+    // only a connected block gets a height and work
+    let work = store.get_block_info(previous_block).work + store.get_block_work(block);
+    store.set_block_info(block, height, work);
+
+    Ok(())
 }
 
 // Connects two blocks (A,B) in the spend-tree and then stores the hash of B in the hash-index
@@ -132,10 +471,16 @@ fn connect_block(
     }
 
     // connect this block if not genesis...
-    if let Some(previous_block) = previous_block {
-        store.spend_tree.connect_block( &mut store.spend_index, & store.logger, previous_block, this_block) ?;
+    match previous_block {
+        Some(previous_block) => verify_and_connect_block(store, previous_block, this_block, false)?,
+        None => {
+            let work = store.get_block_work(this_block);
+            store.set_block_info(this_block, 0, work);
+        }
     }
 
+    add_tip(store, this_block_hash.as_buf(), this_block, previous_block);
+
     // The to_do list contains blocks that are connected to their previous but not yet added to the
     // block-index. Start with the one we just connected;
     let mut todo = vec![Connection {
@@ -187,14 +532,19 @@ fn connect_block(
                 "conn"  => format!("{:?}",   conn));
 
 
-            store.spend_tree.revolve_orphan_pointers(
-                &mut store.transactions,
-                &mut store.tx_index,
-                ptr
-            );
+            let connected = verify_and_connect_block(store, conn.block, ptr, true);
+
+            // A waiting block that fails to connect is invalid; this doesn't affect the block
+            // we are connecting. It stays an orphan and its guard is dropped
+            if let Err(err) = connected {
+                warn!(store.logger, "Connect block - rejected waiting block";
+                    "hash"  => format!("{:?}", hash),
+                    "error" => format!("{:?}", err));
 
-            store.spend_tree.connect_block(&mut store.spend_index, &store.logger, conn.block, ptr)?;
+                continue;
+            }
 
+            add_tip(store, hash, ptr, Some(conn.block));
 
             todo.push(Connection {
                 block: ptr,
@@ -212,6 +562,36 @@ fn connect_block(
 
 
 
+/// Returns the height of the block, which is only known if the previous block is connected
+fn get_block_height(store: &mut Store, block: &Block) -> Option<u64> {
+
+    let previous_block = store.block_index.get(block.header.prev_hash)
+        .into_iter()
+        .find(|ptr| !ptr.is_guard());
+
+    previous_block.map(|previous_block| get_height(store, previous_block) + 1)
+}
+
+/// Verifies the witness commitment of a stored block, of which the height was not known when it
+/// was added
+fn verify_stored_witness_commitment(store: &mut Store, block: BlockPtr, height: u64) -> BlockResult<()> {
+
+    let ptrs: Vec<TxPtr> = store.spend_tree.get_block_records(block).iter()
+        .filter(|rec| rec.is_transaction() && !rec.is_unmatched_input())
+        .map(|rec| rec.get_transaction_ptr())
+        .collect();
+
+    let raw_txs: Vec<Vec<u8>> = ptrs.into_iter()
+        .map(|ptr| store.transactions.read(ptr))
+        .collect();
+
+    let txs: Vec<Transaction> = raw_txs.iter()
+        .map(|raw| Transaction::parse(&mut Buffer::new(raw)).expect("Invalid tx data in database"))
+        .collect();
+
+    verify_witness_commitment(&txs, height >= store.params.segwit_height, store.config().parallel_hashing_threshold)
+}
+
 /// Returns true if the block is already stored
 fn block_exists(store: & mut Store, block_hash: Hash32) -> bool {
     let ptr = store.block_index.get(block_hash);
@@ -225,22 +605,37 @@ fn block_exists(store: & mut Store, block_hash: Hash32) -> bool {
 /// This does not yet check the order
 /// Also verifies the merkle_root & the amounts
 ///
+/// The merkle root is verified before anything is stored
+///
 /// Returns a list fileptrs to the transactions
 ///
-fn verify_and_store_transactions(store: &mut Store, block: &Block) -> BlockResult<Vec<Record>> {
+fn verify_and_store_transactions(store: &mut Store, block: &Block, script_flags: Option<u32>) -> BlockResult<Vec<Record>> {
 
     let timer = ::std::time::Instant::now();
 
+    // hash and check merkle roots
+    let p0 = Instant::now();
+    let hashes: Vec<Hash32Buf> = block.txs
+        .par_iter()
+        .map(|tx| tx.txid())
+        .collect();
+
+    let p1 = Instant::now();
+    let calculated_merkle_root = merkle_tree::get_merkle_root(hashes.clone(), store.config().parallel_hashing_threshold);
+    block.verify_merkle_root(calculated_merkle_root.as_ref())?;
+
+    let p2 = Instant::now();
+
     // We use chunked parallelization because otherwise we need to clone() the stores on each
     // iteration
-    // The main procedure here is to hash and call verify_and_store for each transaction
-    let chunks: Vec<_> =
-        block.txs.par_chunks(PARALLEL_HASHING_THRESHOLD).map(|chunk_tx| {
+    // The main procedure here is to call verify_and_store for each transaction
+    let chunk_size = store.config().parallel_hashing_threshold;
+    let chunks: Vec<BlockResult<_>> =
+        block.txs.par_chunks(chunk_size).enumerate().map(|(chunk_idx, chunk_tx)| {
 
         let len = chunk_tx.len();
         let p0  = Instant::now();
 
-        let mut hashes:  Vec<Hash32Buf> = Vec::with_capacity(len); // accurate
         let mut records: Vec<Record>    = Vec::with_capacity(len * 3); // estimate (guessing 2 in per tx)
 
         let ref mut tx_index = &mut store.tx_index.clone();
@@ -249,16 +644,11 @@ fn verify_and_store_transactions(store: &mut Store, block: &Block) -> BlockResul
         let cloning = Instant::now() - p0;
         let mut chunk_stats =   TransactionStats { cloning: cloning, ..Default::default() };
 
-        for tx in chunk_tx {
-
-            let p1  = Instant::now();
-
-            let hash = Hash32Buf::double_sha256(tx.to_raw());
-            hashes.push(hash);
+        let chunk_hashes = &hashes[chunk_idx * chunk_size..];
 
-            let p2  = Instant::now();
+        for (tx, hash) in chunk_tx.iter().zip(chunk_hashes) {
 
-            let res = tx.verify_and_store(tx_index, tx_store, store.initial_sync, hash.as_ref()).unwrap();
+            let res = tx.verify_and_store(tx_index, tx_store, store.initial_sync, script_flags, hash.as_ref())?;
 
             // AlreadyExists and VerifiedAndStored are both ok here;
             // Extract the TxPtr and the stats
@@ -268,37 +658,28 @@ fn verify_and_store_transactions(store: &mut Store, block: &Block) -> BlockResul
             };
 
             records.push(Record::new_transaction(ptr));
-            for rec in tx.get_output_records(tx_index) {
+            for rec in tx.get_output_records(tx_index, &store.spend_index) {
                 records.push(rec);
             }
 
             chunk_stats = chunk_stats + stats;
-            chunk_stats.hashing = chunk_stats.hashing + (p2 - p1);
         }
-        (chunk_stats, (hashes, records))
+        Ok((chunk_stats, records))
     }).collect();
 
-
-    let p3 = Instant::now();
-
     // split
-    let (stats, hashes_records): (Vec<_>, Vec<_>) = chunks.into_iter().unzip();
-    let (hashes, records):       (Vec<_>, Vec<_>) = hashes_records.into_iter().unzip();
+    let chunks: Vec<_> = chunks.into_iter().collect::<BlockResult<_>>()?;
+    let (stats, records): (Vec<_>, Vec<_>) = chunks.into_iter().unzip();
 
     // flatten
-    let hashes:  Vec<Hash32Buf> = hashes.into_iter().flat_map(|x| x).collect();
     let records: Vec<Record>    = records.into_iter().flat_map(|x| x).collect();
     let mut stats: TransactionStats = stats.into_iter().sum();
+    stats.hashing = p1 - p0;
+    stats.merkle  = p2 - p1;
 
     let rec_count: usize = records.len();
     let tx_count: usize  = hashes.len();
 
-
-    // check merkle roots
-    let calculated_merkle_root = merkle_tree::get_merkle_root(hashes);
-    block.verify_merkle_root(calculated_merkle_root.as_ref()).unwrap();
-    stats.merkle = Instant::now() - p3;
-
     let elapsed : usize = timer.elapsed().as_secs() as usize * 1000 +
         timer.elapsed().subsec_nanos() as usize / 1_000_000 as usize;
 
@@ -314,16 +695,19 @@ fn verify_and_store_transactions(store: &mut Store, block: &Block) -> BlockResul
 
 /// Validates and stores a block;
 ///
-/// For now; this panics on invalids; but all underlying functions
-/// propagate problems to jere
-pub fn add_block(store: &mut Store, buffer: &[u8]) {
+/// An invalid block is rejected with the corresponding BlockError. This leaves the block-index and
+/// the connections in the spend-tree untouched; at most some valid transactions are stored
+pub fn add_block(store: &mut Store, buffer: &[u8]) -> BlockResult<BlockAddOk> {
 
 
     let block_logger = slog::Logger::new(&store.logger, o!());
     info!(block_logger, "add_block - start");
 
+    // prevents concurrent pruning from replacing the tx-index
+    let _lock = store.lock_shared();
+
     // parse & hash block
-    let block      = Block::new(buffer) .unwrap();
+    let block      = Block::new(buffer)?;
     let block_hash = Hash32Buf::double_sha256( block.header.to_raw());
 
     info!(block_logger, "add_block - hashed"; "hash" => format!("{:?}", block_hash));
@@ -331,32 +715,59 @@ pub fn add_block(store: &mut Store, buffer: &[u8]) {
     // already done?
     if block_exists(store, block_hash.as_ref()) {
         info!(store.logger, "add_block - Block already exists");
-        return;
+        return Ok(BlockAddOk::AlreadyExists);
+    }
+
+    if store.verify_headers {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .expect("System time before 1970")
+            .as_secs();
+
+        block.header.verify_proof_of_work(block_hash.as_ref(), store.params.pow_limit)?;
+        block.header.verify_time_not_in_future(now as u32)?;
     }
 
-    block.verify_block_size().unwrap();
+    block.verify_block_size()?;
+    block.verify_block_weight()?;
+    block.verify_legacy_sigops()?;
+    block.verify_coinbase()?;
+
+    // the scripts and the witness commitment depend on the height; if this is not known, these
+    // are verified when the block is connected. Scripts are not verified during initial sync
+    let height = get_block_height(store, &block);
+
+    if let Some(height) = height {
+        block.verify_witness_commitment(height >= store.params.segwit_height, store.config().parallel_hashing_threshold)?;
+    }
+
+    let script_flags = if store.initial_sync {
+        Some(0)
+    }
+    else {
+        height.map(|height| store.params.get_script_flags(height))
+    };
 
     // check and store the transactions in block_content and check the merkle_root
-    let spend_tree_ptrs = verify_and_store_transactions(store, &block).unwrap();
 
-    // store the blockheader in block_content
-    let block_header_ptr = store.block_headers.write( &block.header.to_raw());
+    let spend_tree_ptrs = verify_and_store_transactions(store, &block, script_flags)?;
 
+    // store the blockheader in block_content
     // we also store the txcount, although we only use it for a reindex benchmark
-    let _ = store.block_headers.write_fixed( &block.txs.len());
+    let block_header_ptr = store.block_headers.write_with_fixed( &block.header.to_raw(), &BlockInfo::new(block.txs.len()));
 
     // store the block in the spend_tree
 
     let block_ptr       = store.spend_tree.store_block(block_header_ptr, spend_tree_ptrs);
 
-
-    if is_genesis_block(block_hash.as_ref()) {
+    let result = if store.params.is_genesis_block(block_hash.as_ref()) {
 
         info ! (block_logger, "add_block - storing genesis block");
 
         // there is None previous block, but we call connect_block anyway as this will also
         // connect to next blocks if they are already in
-        connect_block(store, block_hash.as_ref(), None, block_ptr).unwrap();
+        connect_block(store, block_hash.as_ref(), None, block_ptr)?;
+
+        BlockAddOk::Connected
     }
     else {
 
@@ -369,21 +780,35 @@ pub fn add_block(store: &mut Store, buffer: &[u8]) {
             "ptr" => format!("{:?}", previous_block));
 
         // if it is in, we will connect
-        if let Some(previous_block) = previous_block {
+        match previous_block {
+            Some(previous_block) => {
+
+                // the previous block may have been connected meanwhile
+                if height.is_none() {
+                    let height = get_height(store, previous_block) + 1;
+                    block.verify_witness_commitment(height >= store.params.segwit_height, store.config().parallel_hashing_threshold)?;
+                }
+
+                connect_block(store, block_hash.as_ref(), Some(previous_block), block_ptr)?;
 
-            connect_block(store, block_hash.as_ref(), Some(previous_block), block_ptr).unwrap();
+                BlockAddOk::Connected
+            },
+            None => BlockAddOk::Orphan
         }
+    };
 
+    if result == BlockAddOk::Connected {
+        let _lock = store.lock_spend_index();
+        update_main_chain(store);
     }
 
-    tips::add_tip(&store.tips, block_hash, None, 0, 0);
 
+    info!(block_logger, "add_block - done";
+        "result" => format!("{:?}", result),
+        "weight" => block.get_weight(),
+        "sigops" => block.get_legacy_sigop_count());
 
-    // TODO verify amounts
-    // TODO verify PoW
-    // TODO verify header-syntax
-
-    info!(block_logger, "add_block - done");
+    Ok(result)
 }
 
 
@@ -392,6 +817,10 @@ pub fn add_block(store: &mut Store, buffer: &[u8]) {
 mod tests {
 
     use store;
+    use store::SpendingError;
+    use deployments::Chain;
+    use script;
+    use util::*;
     use super::*;
 
 
@@ -419,6 +848,7 @@ mod tests {
     fn test_block_simple() {
 
         let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
 
         tx_builder!(bld);
 
@@ -434,9 +864,9 @@ mod tests {
             tx!(bld; c => g )
         );
 
-        add_block(&mut store, &block0);
-        add_block(&mut store, &block1);
-        add_block(&mut store, &block2);
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
 
     }
 
@@ -444,6 +874,7 @@ mod tests {
     fn test_blocks_reorder() {
 
         let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
 
         tx_builder!(bld);
 
@@ -462,10 +893,1043 @@ mod tests {
 
         println!("block1 = {:?}", block1);
         //println!("tx1 = {:?}", ::hash::Hash32Buf::double_sha256(&tx1));
-        add_block(&mut store, &block0);
-        add_block(&mut store, &block2);
-        add_block(&mut store, &block1);
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Orphan));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        // block2 is connected with block1
+        assert!(block_exists(&mut store, Hash32Buf::double_sha256(&block2[0..80]).as_ref()));
+
+    }
+
+    #[test]
+    fn test_block_parse_error() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        let block0 = genesis!();
+
+        assert_eq!(add_block(&mut store, &block0[0..100]), Err(BlockError::UnexpectedEndOfBuffer));
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+    }
+
+    #[test]
+    fn test_block_incorrect_merkle_root() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => b;11 ),
+            tx!(bld; b => c,e )
+        );
+
+        // corrupt the merkle root
+        let mut block1_invalid = block1.clone();
+        block1_invalid[36] ^= 1;
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1_invalid), Err(BlockError::IncorrectMerkleRoot));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+    }
+
+    #[test]
+    fn test_block_no_coinbase() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => b;11 ),
+            tx!(bld; b => c,e )
+        );
+        let block2 = blk!(prev = block1;
+            tx!(bld; c => d )
+        );
+        let block2b = blk!(prev = block1;
+            tx!(bld; coinbase => f;12 ),
+            tx!(bld; coinbase => g;13 )
+        );
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block2), Err(BlockError::FirstNotCoinbase));
+        assert_eq!(add_block(&mut store, &block2b), Err(BlockError::DoubleCoinbase));
+    }
+
+    #[test]
+    fn test_block_double_spend() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => b;11 ),
+            tx!(bld; b => c,e )
+        );
+
+        // b is already spent in block1
+        let block2_invalid = blk!(prev = block1;
+            tx!(bld; coinbase => f;12 ),
+            tx!(bld; b => g )
+        );
+
+        let block2 = blk!(prev = block1;
+            tx!(bld; coinbase => f;13 ),
+            tx!(bld; c => h )
+        );
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block2_invalid),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+        // the rejected block is not stored
+        assert!(!block_exists(&mut store, Hash32Buf::double_sha256(&block2_invalid[0..80]).as_ref()));
+
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+    }
+
+    #[test]
+    fn test_block_invalid_orphan() {
+
+        // an invalid block that waits for its previous should not cause
+        // the previous block to be rejected
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => b;11 ),
+            tx!(bld; b => c,e )
+        );
+
+        let block2_invalid = blk!(prev = block1;
+            tx!(bld; coinbase => f;12 ),
+            tx!(bld; b => g )
+        );
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block2_invalid), Ok(BlockAddOk::Orphan));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        assert!(block_exists(&mut store, Hash32Buf::double_sha256(&block1[0..80]).as_ref()));
+        assert!(!block_exists(&mut store, Hash32Buf::double_sha256(&block2_invalid[0..80]).as_ref()));
+    }
+
+    #[test]
+    fn test_block_competing_chains() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => a;10 ),
+            tx!(bld; a => b;5, c;5 )
+        );
+
+        // chain a spends b
+        let block2a = blk!(prev = block1;  tx!(bld; coinbase => d;20 ), tx!(bld; b => e;3 ));
+        let block3a = blk!(prev = block2a; tx!(bld; coinbase => f;21 ));
+        let block4a = blk!(prev = block3a; tx!(bld; coinbase => g;22 ));
+        let block5a = blk!(prev = block4a; tx!(bld; coinbase => h;23 ));
+
+        // chain b spends b as well, but more than 3 blocks deep; and spends c
+        let block2b = blk!(prev = block1;  tx!(bld; coinbase => i;30 ), tx!(bld; c => j ));
+        let block3b = blk!(prev = block2b; tx!(bld; coinbase => k;31 ));
+        let block4b = blk!(prev = block3b; tx!(bld; coinbase => l;32 ));
+        let block5b = blk!(prev = block4b; tx!(bld; coinbase => m;33 ));
+        let block6b = blk!(prev = block5b; tx!(bld; coinbase => n;34 ), tx!(bld; b => o;1 ));
+
+        // double spend of c within chain b
+        let block7b_invalid = blk!(prev = block6b; tx!(bld; coinbase => p;35 ), tx!(bld; c => q ));
+
+        // chain a continues; spending c is valid here but j is only on chain b
+        let block6a_invalid = blk!(prev = block5a; tx!(bld; coinbase => r;24 ), tx!(bld; j => s ));
+        let block6a = blk!(prev = block5a; tx!(bld; coinbase => t;25 ), tx!(bld; c => u;1 ));
+        let block7a_invalid = blk!(prev = block6a; tx!(bld; coinbase => v;26 ), tx!(bld; e => w;1 ), tx!(bld; e => x;2 ));
+
+        for block in [&block0, &block1, &block2a, &block3a, &block4a, &block5a,
+                      &block2b, &block3b, &block4b, &block5b, &block6b].iter() {
+
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+
+        assert_eq!(add_block(&mut store, &block7b_invalid),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+        assert_eq!(add_block(&mut store, &block6a_invalid),
+            Err(BlockError::SpendingError(SpendingError::OutputNotFound)));
+
+        assert_eq!(add_block(&mut store, &block6a), Ok(BlockAddOk::Connected));
+
+        assert_eq!(add_block(&mut store, &block7a_invalid),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+        let tips = store.tips.get_tips();
+        assert_eq!(tips.len(), 2);
+        assert!(tips.iter().all(|tip| tip.height == 6));
+    }
+
+    // mainnet blocks 1 and 2
+    const BLOCK1: &'static str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000\
+                   982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d\
+                   01e362990101000000010000000000000000000000000000000000000000000000000000000000\
+                   000000ffffffff0704ffff001d0104ffffffff0100f2052a0100000043410496b538e853519c72\
+                   6a2c91e61ec11600ae1390813a627c66fb8be7947be63c52da7589379515d4e0a604f8141781e6\
+                   2294721166bf621e73a82cbf2342c858eeac00000000";
+
+    const BLOCK2: &'static str = "010000004860eb18bf1b1620e37e9490fc8a427514416fd75159ab86688e9a8300000000\
+                   d5fdcc541e25de1c7a5addedf24858b8bb665c9f36ef744ee42c316022c90f9bb0bc6649ffff001d\
+                   08d2bd610101000000010000000000000000000000000000000000000000000000000000000000\
+                   000000ffffffff0704ffff001d010bffffffff0100f2052a010000004341047211a824f55b5052\
+                   28e4c3d5194c1fcfaa15a456abdf37f9b9d97a4040afc073dee6c89064984f03385237d92167c1\
+                   3e236446b417ab79a0fcae412ae3316b77ac00000000";
+
+    #[test]
+    fn test_block_proof_of_work() {
+
+        let mut store = store::Store::new(& test_cfg!());
+
+        let block0 = genesis!();
+        let block1 = from_hex(BLOCK1);
+        let block2 = from_hex(BLOCK2);
+
+        // a different nonce yields a hash above the target
+        let mut block1_nonce = block1.clone();
+        block1_nonce[76] ^= 1;
+
+        // target above pow-limit
+        let mut block1_bits = block1.clone();
+        block1_bits[75] = 0x1e;
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1_nonce), Err(BlockError::HashAboveTarget));
+        assert_eq!(add_block(&mut store, &block1_bits), Err(BlockError::InvalidDifficultyTarget));
+
+        // the header-context of block2 is verified when it is connected as orphan
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Orphan));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        let tip = store.tips.get_most_work_tip().unwrap();
+        assert_eq!(tip.block_hash, Hash32Buf::double_sha256(&block2[0..80]));
+        assert_eq!(tip.height, 2);
+    }
+
+    #[test]
+    fn test_block_header_context() {
+
+        let mut store = store::Store::new(& test_cfg!());
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+
+        // bits are 0
+        let block1 = blk!(prev = block0; tx!(bld; coinbase => a;10 ));
+
+        // bits are correct, time is 0
+        let mut block1_time = blk!(prev = block0; tx!(bld; coinbase => b;11 ));
+        block1_time[72..76].copy_from_slice(&block0[72..76]);
+
+        // bits and time are correct
+        let mut block1_valid = block1_time.clone();
+        block1_valid[68..72].copy_from_slice(&block0[68..72]);
+        block1_valid[68] += 1;
+
+        // store without verifying; we verify the context directly
+        store.verify_headers = false;
+
+        let get_ptr = |store: &mut store::Store, blk: &Vec<u8>| {
+            store.block_index.get(Hash32Buf::double_sha256(&blk[0..80]).as_ref())[0]
+        };
+
+        for blk in [&block1, &block1_time, &block1_valid].iter() {
+            assert_eq!(add_block(&mut store, blk), Ok(BlockAddOk::Connected));
+        }
+
+        let genesis_ptr = get_ptr(&mut store, &block0);
+
+        let ptr = get_ptr(&mut store, &block1);
+        assert_eq!(verify_header_context(&mut store, ptr, genesis_ptr),
+            Err(BlockError::IncorrectDifficulty { expected: 0x1d00ffff, found: 0 }));
+
+        let ptr = get_ptr(&mut store, &block1_time);
+        assert_eq!(verify_header_context(&mut store, ptr, genesis_ptr),
+            Err(BlockError::TimeTooOld));
+
+        let ptr = get_ptr(&mut store, &block1_valid);
+        assert_eq!(verify_header_context(&mut store, ptr, genesis_ptr), Ok(()));
+    }
+
+    // Sets the time and bits of the header of a block
+    fn set_header(block: &mut Vec<u8>, time: u32, bits: u32) {
+        for n in 0..4 {
+            block[68 + n] = (time >> (n * 8)) as u8;
+            block[72 + n] = (bits >> (n * 8)) as u8;
+        }
+    }
+
+    // Sets the time and bits, and increments the nonce until the hash meets the target
+    fn mine(block: &mut Vec<u8>, time: u32, bits: u32) {
+        set_header(block, time, bits);
+        while pow::hash_to_u256(Hash32Buf::double_sha256(&block[0..80]).as_ref()) > pow::from_compact(bits) {
+            block[76] += 1;
+        }
+    }
+
+    #[test]
+    fn test_regtest() {
+
+        let mut cfg = test_cfg!();
+        cfg.chain = Chain::Regtest;
+
+        let mut store = store::Store::new(&cfg);
+
+        tx_builder!(bld);
+
+        let block0 = store.params.genesis_block.clone();
+        let time   = 1296688602;
+
+        let mut block1 = blk!(prev = block0; tx!(bld; coinbase @ 1 => a;10 ));
+        mine(&mut block1, time + 600, 0x207fffff);
+
+        let mut block2 = blk!(prev = block1; tx!(bld; coinbase @ 2 => b;11 ), tx!(bld; a => c;10 ));
+        mine(&mut block2, time + 1200, 0x207fffff);
+
+        // BIP34 is active from height 1
+        let mut block2_height = blk!(prev = block1; tx!(bld; coinbase @ 1 => d;12 ));
+        mine(&mut block2_height, time + 1200, 0x207fffff);
+
+        // the main genesis block is an orphan on regtest
+        assert_eq!(add_block(&mut store, &genesis!()), Ok(BlockAddOk::Orphan));
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        let mut block2_bits = block2.clone();
+        mine(&mut block2_bits, time + 1200, 0x207ffffe);
+        assert_eq!(add_block(&mut store, &block2_bits),
+            Err(BlockError::IncorrectDifficulty { expected: 0x207fffff, found: 0x207ffffe }));
+        assert_eq!(add_block(&mut store, &block2_height), Err(BlockError::IncorrectCoinbaseHeight));
+
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+        assert_eq!(store.tips.get_most_work_tip().unwrap().height, 2);
+    }
+
+    #[test]
+    fn test_duplicate_coinbase() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        // coinbases with the same outputs have the same txid
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0; tx!(bld; coinbase => a;10 ));
+
+        // repeats the coinbase of block1 while its output is unspent
+        let block2_duplicate = blk!(prev = block1; tx!(bld; coinbase => b;10 ));
+
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => c;11 ), tx!(bld; a => d;10 ));
+
+        // repeats it after its output is spent
+        let block3 = blk!(prev = block2; tx!(bld; coinbase => e;10 ));
+
+        // spends the coinbase of block3, and then again
+        let block4 = blk!(prev = block3; tx!(bld; coinbase => f;12 ), tx!(bld; e => g;10 ));
+        let block5 = blk!(prev = block4; tx!(bld; coinbase => h;13 ), tx!(bld; e => i;10 ));
+
+        for block in [&block0, &block1].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+        assert_eq!(add_block(&mut store, &block2_duplicate), Err(BlockError::DuplicateTransaction));
+
+        // the coinbase of block1 is spent, not the one of the rejected block
+        for block in [&block2, &block3, &block4].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+        assert_eq!(add_block(&mut store, &block5),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+        // an exempted block replaces the unspent outputs of the earlier coinbase
+        let block5 = blk!(prev = block4; tx!(bld; coinbase => j;14 ));
+        let block6 = blk!(prev = block5; tx!(bld; coinbase => k;14 ));
+        let block7 = blk!(prev = block6; tx!(bld; coinbase => l;15 ), tx!(bld; j => m;14 ));
+        let block8 = blk!(prev = block7; tx!(bld; coinbase => n;16 ), tx!(bld; k => o;14 ));
+
+        store.params.bip30_exceptions.push(Hash32Buf::double_sha256(&block6[0..80]));
+
+        for block in [&block5, &block6, &block7].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+        assert_eq!(add_block(&mut store, &block8),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+        assert_eq!(store.verify_integrity(), vec![]);
+    }
+
+    #[test]
+    fn test_duplicate_coinbase_disconnect() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        // block2 is exempted, and repeats the coinbase of block1 while its output is unspent
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0; tx!(bld; coinbase => a;10 ));
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => b;10 ));
+        let block3 = blk!(prev = block2; tx!(bld; coinbase => c;11 ), tx!(bld; b => d;10 ));
+
+        store.params.bip30_exceptions.push(Hash32Buf::double_sha256(&block2[0..80]));
+
+        for block in [&block0, &block1, &block2, &block3].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+
+        // the coinbase of block1 is not spent by disconnecting the later one
+        let block2_hash = Hash32Buf::double_sha256(&block2[0..80]);
+        assert_eq!(store.disconnect_block(block2_hash.as_ref()), Ok(2));
+
+        let block2b = blk!(prev = block1; tx!(bld; coinbase => e;12 ), tx!(bld; a => f;10 ));
+        assert_eq!(add_block(&mut store, &block2b), Ok(BlockAddOk::Connected));
+        assert_eq!(store.verify_integrity(), vec![]);
+    }
+
+    #[test]
+    fn test_min_difficulty_blocks() {
+
+        let mut cfg = test_cfg!();
+        cfg.chain = Chain::Testnet;
+
+        let mut store = store::Store::new(&cfg);
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = store.params.genesis_block.clone();
+        let time   = 1296688602;
+
+        let mut block1 = blk!(prev = block0; tx!(bld; coinbase => a;10 ));
+        set_header(&mut block1, time + 600, 0x1c7fffff);
+
+        // more than 20 minutes after block1
+        let mut block2 = blk!(prev = block1; tx!(bld; coinbase => b;11 ));
+        set_header(&mut block2, time + 1801, 0x1d00ffff);
+
+        let mut block2_late = blk!(prev = block1; tx!(bld; coinbase => c;12 ));
+        set_header(&mut block2_late, time + 1801, 0x1c7fffff);
+
+        let mut block2_early = blk!(prev = block1; tx!(bld; coinbase => d;13 ));
+        set_header(&mut block2_early, time + 1800, 0x1d00ffff);
+
+        // block3 must use the bits of block1
+        let mut block3 = blk!(prev = block2; tx!(bld; coinbase => e;14 ));
+        set_header(&mut block3, time + 2400, 0x1c7fffff);
+
+        let mut block3_limit = blk!(prev = block2; tx!(bld; coinbase => f;15 ));
+        set_header(&mut block3_limit, time + 2400, 0x1d00ffff);
+
+        let blocks = [&block0, &block1, &block2, &block2_late, &block2_early, &block3, &block3_limit];
+        for blk in blocks.iter() {
+            assert_eq!(add_block(&mut store, blk), Ok(BlockAddOk::Connected));
+        }
+
+        let get_ptr = |store: &mut store::Store, blk: &Vec<u8>| {
+            store.block_index.get(Hash32Buf::double_sha256(&blk[0..80]).as_ref())[0]
+        };
+
+        let block1_ptr = get_ptr(&mut store, &block1);
+        let block2_ptr = get_ptr(&mut store, &block2);
+
+        let ptr = get_ptr(&mut store, &block2);
+        assert_eq!(verify_header_context(&mut store, ptr, block1_ptr), Ok(()));
+
+        let ptr = get_ptr(&mut store, &block2_late);
+        assert_eq!(verify_header_context(&mut store, ptr, block1_ptr),
+            Err(BlockError::IncorrectDifficulty { expected: 0x1d00ffff, found: 0x1c7fffff }));
+
+        let ptr = get_ptr(&mut store, &block2_early);
+        assert_eq!(verify_header_context(&mut store, ptr, block1_ptr),
+            Err(BlockError::IncorrectDifficulty { expected: 0x1c7fffff, found: 0x1d00ffff }));
+
+        let ptr = get_ptr(&mut store, &block3);
+        assert_eq!(verify_header_context(&mut store, ptr, block2_ptr), Ok(()));
+
+        let ptr = get_ptr(&mut store, &block3_limit);
+        assert_eq!(verify_header_context(&mut store, ptr, block2_ptr),
+            Err(BlockError::IncorrectDifficulty { expected: 0x1c7fffff, found: 0x1d00ffff }));
+    }
+
+    #[test]
+    fn test_block_amounts() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let subsidy = store.params.get_block_subsidy(2);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => a;10 )
+        );
+
+        // spends more than the input
+        let block2_invalid = blk!(prev = block1;
+            tx!(bld; coinbase => b;11 ),
+            tx!(bld; a => c;6, d;5 )
+        );
+
+        // claims the fee twice
+        let block2_fee_invalid = blk!(prev = block1;
+            tx!(bld; coinbase => e;subsidy + 7 ),
+            tx!(bld; a => f;6 )
+        );
+
+        let block2 = blk!(prev = block1;
+            tx!(bld; coinbase => g;subsidy + 4 ),
+            tx!(bld; a => h;6 )
+        );
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block2_invalid),
+            Err(BlockError::TransactionError(TransactionError::InsufficientInputAmount)));
+        assert_eq!(add_block(&mut store, &block2_fee_invalid),
+            Err(BlockError::CoinbaseAmountTooHigh { allowed: subsidy + 4, found: subsidy + 7 }));
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+    }
+
+    // Returns a transaction with the given inputs and script-sigs, and outputs of 10 satoshis
+    // with the given scripts
+    fn raw_tx(inputs: &[(Hash32Buf, u32, &[u8])], outputs: &[&[u8]]) -> Vec<u8> {
+
+        let mut tx = vec![1, 0, 0, 0, inputs.len() as u8];
+        for &(ref hash, index, script_sig) in inputs.iter() {
+            tx.extend_from_slice(hash.as_ref().0);
+            tx.extend((0..4).map(|n| (index >> (n * 8)) as u8));
+            write_compact_size(&mut tx, script_sig.len());
+            tx.extend_from_slice(script_sig);
+            tx.extend_from_slice(&[0; 4]);
+        }
+
+        tx.push(outputs.len() as u8);
+        for script in outputs.iter() {
+            tx.extend_from_slice(&[10, 0, 0, 0, 0, 0, 0, 0]);
+            write_compact_size(&mut tx, script.len());
+            tx.extend_from_slice(script);
+        }
+        tx.extend_from_slice(&[0; 4]);
+        tx
+    }
+
+    // Returns a coinbase with the nonce as witness, and an output with the witness commitment if
+    // given; the extra byte of the coinbase script makes its txid unique
+    fn witness_coinbase(extra: u8, commitment: Option<&[u8]>, nonce: &[u8]) -> Vec<u8> {
+
+        let mut outputs = vec![vec![0x51]];
+        if let Some(commitment) = commitment {
+            let mut script = vec![0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+            script.extend_from_slice(commitment);
+            outputs.push(script);
+        }
+        let outputs: Vec<&[u8]> = outputs.iter().map(|script| &script[..]).collect();
+        let tx = raw_tx(&[(Hash32Buf::from_slice(&[0; 32]), 0xffff_ffff, &[extra, 0x51])], &outputs);
+
+        with_witness(&tx, nonce)
+    }
+
+    // Returns the transaction of a single input with the given item as witness
+    fn with_witness(tx: &[u8], item: &[u8]) -> Vec<u8> {
+
+        // the extended serialization has a marker and flag, and the witness before the lock-time
+        let mut result = tx[0..4].to_vec();
+        result.extend_from_slice(&[0, 1]);
+        result.extend_from_slice(&tx[4..tx.len() - 4]);
+        result.extend_from_slice(&[1, item.len() as u8]);
+        result.extend_from_slice(item);
+        result.extend_from_slice(&tx[tx.len() - 4..]);
+        result
+    }
+
+    #[test]
+    fn test_block_witness_commitment() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+        store.params.segwit_height = 0;
+
+        tx_builder!(bld);
+
+        // the witness root of a block with only a coinbase is zero
+        let nonce      = [7u8; 32];
+        let commitment = Hash32Buf::double_sha256_from_parts(&[&[0u8; 32][..], &nonce[..]]);
+        let valid      = Some(&commitment.as_ref().0[..]);
+        let wrong      = Some(&[0u8; 32][..]);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0; tx!(bld; coinbase => a;10 ));
+
+        let block2_wrong   = blk!(prev = block1; witness_coinbase(1, wrong, &nonce));
+        let block2_missing = blk!(prev = block1; witness_coinbase(2, None, &nonce));
+        let block2         = blk!(prev = block1; witness_coinbase(3, valid, &nonce));
+
+        // the witness commitment of an orphan is verified when it is connected
+        let block3_wrong = blk!(prev = block2; witness_coinbase(4, wrong, &nonce));
+        let block3       = blk!(prev = block2; witness_coinbase(5, valid, &nonce));
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        assert_eq!(add_block(&mut store, &block2_wrong), Err(BlockError::IncorrectWitnessCommitment));
+        assert_eq!(add_block(&mut store, &block2_missing), Err(BlockError::UnexpectedWitness));
+
+        assert_eq!(add_block(&mut store, &block3_wrong), Ok(BlockAddOk::Orphan));
+        assert_eq!(add_block(&mut store, &block3), Ok(BlockAddOk::Orphan));
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+
+        let hash = |blk: &Vec<u8>| Hash32Buf::double_sha256(&blk[0..80]);
+        assert!(!block_exists(&mut store, hash(&block3_wrong).as_ref()));
+        assert_eq!(store.tips.get_most_work_tip().unwrap().block_hash, hash(&block3));
+
+        // witness data is not allowed before segwit is active
+        store.params.segwit_height = 5;
+        let block4 = blk!(prev = block3; witness_coinbase(6, valid, &nonce));
+        assert_eq!(add_block(&mut store, &block4), Err(BlockError::UnexpectedWitness));
+    }
+
+    #[test]
+    fn test_block_sigops() {
+
+        let mut cfg = test_cfg!();
+        cfg.chain = Chain::Regtest;
+
+        let mut store = store::Store::new(&cfg);
+        store.verify_headers = false;
+
+        let coinbase = |height: u64, outputs: &[&[u8]]| {
+            let mut script = get_coinbase_height_script(height);
+            script.push(0x51);
+            raw_tx(&[(Hash32Buf::from_slice(&[0; 32]), 0xffff_ffff, &script)], outputs)
+        };
+
+        // a redeem script of 520 multisigs has 20 sigops each; the scripts are not verified
+        let redeem_script = vec![script::opcode::OP_CHECKMULTISIG; 520];
+        let script_sig    = script::instruction::serialize_push(&redeem_script);
+        let p2sh          = from_hex("a914000000000000000000000000000000000000000087");
+
+        let block0 = store.params.genesis_block.clone();
+        let cb1    = coinbase(1, &[&p2sh, &p2sh]);
+        let block1 = blk!(prev = block0; cb1);
+        let hash1  = Hash32Buf::double_sha256(&cb1);
+
+        // too many legacy sigops
+        let checksigs = vec![script::opcode::OP_CHECKSIG; MAX_BLOCK_SIGOPS + 1];
+        let block2_legacy = blk!(prev = block1; coinbase(2, &[&checksigs]));
+
+        // each input has a sigop cost of 4 * 10400
+        let block2_p2sh = blk!(prev = block1;
+            coinbase(2, &[&[0x51]]),
+            raw_tx(&[(hash1.clone(), 0, &script_sig), (hash1.clone(), 1, &script_sig)], &[&[0x51]])
+        );
+        let block2 = blk!(prev = block1;
+            coinbase(2, &[&[0x51]]),
+            raw_tx(&[(hash1.clone(), 0, &script_sig)], &[&[0x51]])
+        );
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        assert_eq!(Block::new(&block2_legacy).unwrap().get_legacy_sigop_count(), MAX_BLOCK_SIGOPS + 1);
+        assert_eq!(add_block(&mut store, &block2_legacy), Err(BlockError::TooManySigops));
+
+        // the P2SH sigops are only known from the spent outputs
+        assert_eq!(Block::new(&block2_p2sh).unwrap().get_legacy_sigop_count(), 0);
+        assert_eq!(add_block(&mut store, &block2_p2sh), Err(BlockError::TooManySigops));
+
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+    }
+
+    #[test]
+    fn test_block_invalid_waiting_transaction() {
+
+        // a transaction that waits for a later transaction and fails against it, must not cause
+        // the block of the later transaction to be rejected
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+        store.initial_sync   = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let cb1    = tx!(bld; coinbase => a;10 );
+        let block1 = blk!(prev = block0; cb1.clone());
+
+        // the output of tx_valid requires two equal items; tx_invalid provides 1 and 2
+        let tx_valid   = raw_tx(&[(Hash32Buf::double_sha256(&cb1), 0, &[])], &[&[script::opcode::OP_EQUAL]]);
+        let tx_invalid = raw_tx(&[(Hash32Buf::double_sha256(&tx_valid), 0, &[0x51, 0x52])], &[&[0x51]]);
+
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => b;11 ), tx_valid.clone());
+
+        // an invalid block leaves tx_invalid waiting for tx_valid
+        let block2_invalid  = blk!(prev = block1; tx!(bld; coinbase => c;12 ), tx_invalid.clone());
+
+        // an orphan waiting for block2 contains tx_invalid as well
+        let block3_orphan   = blk!(prev = block2; tx!(bld; coinbase => d;13 ), tx_invalid.clone());
+        let block3_invalid  = blk!(prev = block2; tx!(bld; coinbase => e;14 ), tx_invalid.clone());
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block2_invalid),
+            Err(BlockError::SpendingError(SpendingError::OutputNotFound)));
+        assert_eq!(add_block(&mut store, &block3_orphan), Ok(BlockAddOk::Orphan));
+
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+
+        let hash = |blk: &Vec<u8>| Hash32Buf::double_sha256(&blk[0..80]);
+        assert!( block_exists(&mut store, hash(&block2).as_ref()));
+        assert!(!block_exists(&mut store, hash(&block3_orphan).as_ref()));
+        assert_eq!(store.tips.get_most_work_tip().unwrap().block_hash, hash(&block2));
+
+        // tx_invalid is verified again when it comes in another block
+        match add_block(&mut store, &block3_invalid) {
+            Err(BlockError::TransactionError(TransactionError::ScriptError { input: 0, .. })) => {},
+            x => panic!("Unexpected result {:?}", x)
+        }
+    }
+
+    #[test]
+    fn test_block_scripts_orphan() {
+
+        // the height of an orphan block is not known, so its scripts are verified when it is
+        // connected
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+        store.initial_sync   = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let cb1    = tx!(bld; coinbase => a;10 );
+        let block1 = blk!(prev = block0; cb1.clone());
+
+        let tx_valid   = raw_tx(&[(Hash32Buf::double_sha256(&cb1), 0, &[])], &[&[script::opcode::OP_EQUAL]]);
+        let tx_invalid = raw_tx(&[(Hash32Buf::double_sha256(&tx_valid), 0, &[0x51, 0x52])], &[&[0x51]]);
+        let tx_spend   = raw_tx(&[(Hash32Buf::double_sha256(&tx_valid), 0, &[0x51, 0x51])], &[&[0x52]]);
+
+        let block2         = blk!(prev = block1; tx!(bld; coinbase => b;11 ), tx_valid.clone());
+        let block3_invalid = blk!(prev = block2; tx!(bld; coinbase => c;12 ), tx_invalid.clone());
+        let block3         = blk!(prev = block2; tx!(bld; coinbase => d;13 ), tx_spend.clone());
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block3_invalid), Ok(BlockAddOk::Orphan));
+        assert_eq!(add_block(&mut store, &block3), Ok(BlockAddOk::Orphan));
+
+        let tx_ptr = |store: &mut store::Store, tx: &Vec<u8>| {
+            store.tx_index.get(Hash32Buf::double_sha256(tx).as_ref()).into_iter()
+                .find(|ptr| !ptr.is_guard())
+                .unwrap()
+        };
+
+        let ptr = tx_ptr(&mut store, &tx_spend);
+        assert!(!store.transactions.is_verified(ptr));
+
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+
+        let hash = |blk: &Vec<u8>| Hash32Buf::double_sha256(&blk[0..80]);
+        assert!(!block_exists(&mut store, hash(&block3_invalid).as_ref()));
+        assert_eq!(store.tips.get_most_work_tip().unwrap().block_hash, hash(&block3));
+
+        let ptr = tx_ptr(&mut store, &tx_spend);
+        assert!(store.transactions.is_verified(ptr));
+    }
+
+    #[test]
+    fn test_block_stored_transaction() {
+
+        // a block reuses a stored transaction only if it has the same witnesses, and verifies it
+        // again if it is verified with other flags
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+        store.initial_sync   = false;
+        store.params.bip65_height = 100;
+
+        tx_builder!(bld);
+
+        // the output of tx_cltv fails from the height at which CHECKLOCKTIMEVERIFY is enforced
+        let block0  = genesis!();
+        let cb1     = tx!(bld; coinbase => a;10, b;10 );
+        let tx_cltv = raw_tx(&[(Hash32Buf::double_sha256(&cb1), 0, &[])], &[&[script::opcode::OP_CHECKLOCKTIMEVERIFY, 0x51]]);
+        let tx_plain = raw_tx(&[(Hash32Buf::double_sha256(&cb1), 1, &[])], &[&[0x51]]);
+        let block1  = blk!(prev = block0; cb1.clone(), tx_cltv.clone(), tx_plain.clone());
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        let spend_cltv  = raw_tx(&[(Hash32Buf::double_sha256(&tx_cltv), 0, &[])], &[&[0x51]]);
+        let spend_plain = raw_tx(&[(Hash32Buf::double_sha256(&tx_plain), 0, &[])], &[&[0x52]]);
+
+        // the loose version of spend_plain has a witness, which is ignored without WITNESS
+        let ptr_cltv = match ::tx_add::add_transaction(&mut store, &spend_cltv) {
+            Ok(transaction::TransactionOk::VerifiedAndStored { ptr, .. }) => ptr,
+            x => panic!("Unexpected result {:?}", x)
+        };
+        let ptr_loose = match ::tx_add::add_transaction(&mut store, &with_witness(&spend_plain, &[1])) {
+            Ok(transaction::TransactionOk::VerifiedAndStored { ptr, .. }) => ptr,
+            x => panic!("Unexpected result {:?}", x)
+        };
+        assert_eq!(store.transactions.get_script_flags(ptr_cltv), Some(store.params.get_script_flags(2)));
+
+        // spend_cltv is verified with the flags of block2
+        store.params.bip65_height = 2;
+        let block2_invalid = blk!(prev = block1; tx!(bld; coinbase => c;11 ), spend_cltv.clone());
+        match add_block(&mut store, &block2_invalid) {
+            Err(BlockError::TransactionError(TransactionError::ScriptError { input: 0, .. })) => {},
+            x => panic!("Unexpected result {:?}", x)
+        }
+
+        // block2 stores its own version of spend_plain
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => d;12 ), spend_plain.clone());
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+
+        let block_ptr = store.block_index.get(Hash32Buf::double_sha256(&block2[0..80]).as_ref())[0];
+        let tx_ptrs: Vec<_> = store.spend_tree.get_block_records(block_ptr).iter()
+            .filter(|rec| rec.is_transaction())
+            .map(|rec| rec.get_transaction_ptr())
+            .collect();
+
+        assert!(tx_ptrs[1] != ptr_loose);
+        assert_eq!(store.transactions.read(tx_ptrs[1]), spend_plain);
+        assert_eq!(store.transactions.get_script_flags(tx_ptrs[1]), Some(store.params.get_script_flags(2)));
+        assert_eq!(store.verify_integrity(), vec![]);
+    }
+
+    #[test]
+    fn test_block_amounts_orphan() {
+
+        // the amounts of an orphan block are verified when it is connected
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => a;10 )
+        );
+        let block2_invalid = blk!(prev = block1;
+            tx!(bld; coinbase => b;11 ),
+            tx!(bld; a => c;11 )
+        );
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block2_invalid), Ok(BlockAddOk::Orphan));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        assert!(!block_exists(&mut store, Hash32Buf::double_sha256(&block2_invalid[0..80]).as_ref()));
+    }
+
+    #[test]
+    fn test_block_small_files() {
+
+        // with small files, the records of the spend-tree and the bits of the spend-index are
+        // written into more files; 64 records fit in the first file of the spend-tree, and 128
+        // bits in a file of the spend-index
+        let mut cfg = test_cfg!();
+        cfg.spend_tree_file_size  = ::config::FILE_RESERVE + 16 + 64 * 8;
+        cfg.spend_index_file_size = 16 + 2 * 8;
+
+        let mut store = store::Store::new(&cfg);
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase @ 1 => x;10, y;10 ),
+            tx!(bld; x => z;10 )
+        );
+
+        let mut blocks = vec![block0, block1];
+        assert_eq!(add_block(&mut store, &blocks[0]), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &blocks[1]), Ok(BlockAddOk::Connected));
+
+        for n in 2..30 {
+            let block = blk!(prev = blocks[n - 1];
+                tx!(bld; coinbase @ n => a;10 ),
+                tx!(bld; a => b;10 )
+            );
+            assert_eq!(add_block(&mut store, &block), Ok(BlockAddOk::Connected));
+            blocks.push(block);
+        }
+
+        let files = |dir: &str| ::std::fs::read_dir(cfg.root.join(dir)).unwrap().count();
+        assert!(files("spend-tree") > 1);
+        assert!(files("spend-index") > 1);
+
+        // the outputs of block1 are found from the last file
+        let block30 = blk!(prev = blocks[29];
+            tx!(bld; coinbase @ 30 => c;10 ),
+            tx!(bld; y => d;10 )
+        );
+        let block31 = blk!(prev = block30;
+            tx!(bld; coinbase @ 31 => e;10 ),
+            tx!(bld; x => f;5 )
+        );
+        assert_eq!(add_block(&mut store, &block30), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block31),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+        assert_eq!(store.tips.get_most_work_tip().unwrap().height, 30);
+        assert_eq!(store.verify_integrity(), vec![]);
+    }
+
+    #[test]
+    fn test_block_tips() {
+
+        let mut store = store::Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;  tx!(bld; coinbase => a;10 ));
+        let block2 = blk!(prev = block1;  tx!(bld; coinbase => b;11 ));
+        let block3 = blk!(prev = block2;  tx!(bld; coinbase => c;12 ));
+        let block2b = blk!(prev = block1; tx!(bld; coinbase => d;13 ));
+
+        let hash = |blk: &Vec<u8>| Hash32Buf::double_sha256(&blk[0..80]);
+
+        assert_eq!(store.tips.get_most_work_tip(), None);
+
+        add_block(&mut store, &block0).unwrap();
+        add_block(&mut store, &block1).unwrap();
+
+        // genesis is replaced by block1
+        let tips = store.tips.get_tips();
+        assert_eq!(tips.len(), 1);
+        assert_eq!(tips[0].block_hash, hash(&block1));
+        assert_eq!(tips[0].height, 1);
+        assert_eq!(tips[0].work, pow::difficulty_target_to_work(pow::from_compact(0x1d00ffff)));
+
+        // orphans are not tips; when connected the tip moves up
+        assert_eq!(add_block(&mut store, &block3), Ok(BlockAddOk::Orphan));
+        assert_eq!(store.tips.get_tips().len(), 1);
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+
+        let tip = store.tips.get_most_work_tip().unwrap();
+        assert_eq!(tip.block_hash, hash(&block3));
+        assert_eq!(tip.height, 3);
+
+        // a fork adds a tip
+        add_block(&mut store, &block2b).unwrap();
+        assert_eq!(store.tips.get_tips().len(), 2);
+        assert_eq!(store.tips.get_tip(hash(&block2b)).unwrap().height, 2);
+        assert_eq!(store.tips.get_most_work_tip().unwrap().block_hash, hash(&block3));
+
+        // the height and work of blocks that are not tips are stored with their headers
+        let block_ptr = |store: &mut store::Store, blk: &Vec<u8>| {
+            store.block_index.get(hash(blk).as_ref()).into_iter().find(|ptr| !ptr.is_guard()).unwrap()
+        };
+
+        let ptr1 = block_ptr(&mut store, &block1);
+        let ptr2 = block_ptr(&mut store, &block2);
+        let info1 = store.get_block_info(ptr1);
+        let info2 = store.get_block_info(ptr2);
+        assert_eq!(info1.height, 1);
+        assert_eq!(info1.work, tips[0].work);
+        assert_eq!(info2.height, 2);
+        assert_eq!(info2.tx_count, 1);
+        assert_eq!(info2.work, info1.work + store.get_block_work(ptr2));
+        assert_eq!(get_height(&mut store, ptr2), 2);
+    }
+
+    #[test]
+    fn test_block_concurrent_branches() {
+
+        // two stores connect competing branches at the same time; both move the shared
+        // spend-index, and each block must be verified against its own branch
+        let cfg = test_cfg!();
+        let mut store = store::Store::new(&cfg);
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0; tx!(bld; coinbase => a;10 ));
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        // both branches spend a, and then the outputs of their own previous block
+        let mut branch_a: Vec<Vec<u8>> = Vec::new();
+        let mut branch_b: Vec<Vec<u8>> = Vec::new();
+        for n in 0..20_u64 {
+            let (spend_a, spend_b) = if n == 0 {
+                (tx!(bld; a => c;5 ), tx!(bld; a => d;4 ))
+            } else {
+                (tx!(bld; c => c;5 ), tx!(bld; d => d;4 ))
+            };
+
+            let block_a = blk!(prev = branch_a.last().unwrap_or(&block1); tx!(bld; coinbase => e;100 + n ), spend_a);
+            let block_b = blk!(prev = branch_b.last().unwrap_or(&block1); tx!(bld; coinbase => f;200 + n ), spend_b);
+            branch_a.push(block_a);
+            branch_b.push(block_b);
+        }
+
+        let threads: Vec<_> = vec![branch_a.clone(), branch_b.clone()].into_iter().map(|branch| {
+            let cfg = cfg.clone();
+            ::std::thread::spawn(move || {
+                let mut store = store::Store::new(&cfg);
+                store.verify_headers = false;
+
+                branch.iter().map(|block| add_block(&mut store, block)).collect::<Vec<_>>()
+            })
+        }).collect();
+
+        for thread in threads {
+            let results = thread.join().unwrap();
+            assert!(results.iter().all(|result| *result == Ok(BlockAddOk::Connected)), "{:?}", results);
+        }
+
+        let tips = store.tips.get_tips();
+        assert_eq!(tips.len(), 2);
+        assert!(tips.iter().all(|tip| tip.height == 21));
+
+        // this store sees the spend-index as moved by the others
+        let block_a = blk!(prev = branch_a.last().unwrap(); tx!(bld; coinbase => g;300 ), tx!(bld; c => h ));
+        let block_b = blk!(prev = branch_b.last().unwrap(); tx!(bld; coinbase => i;301 ), tx!(bld; c => j ));
 
+        assert_eq!(add_block(&mut store, &block_b),
+            Err(BlockError::SpendingError(SpendingError::OutputNotFound)));
+        assert_eq!(add_block(&mut store, &block_a), Ok(BlockAddOk::Connected));
     }
 
 }
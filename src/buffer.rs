@@ -110,6 +110,19 @@ impl<'a> Buffer<'a> {
 }
 
 
+/// Writes a compact size, as read by Buffer::parse_compact_size
+pub fn write_compact_size(buf: &mut Vec<u8>, size: usize) {
+    let size = size as u64;
+    let len = match size {
+        0...0xfc        => { buf.push(size as u8); return; },
+        0xfd...0xffff   => { buf.push(0xfd); 2 },
+        0x1_0000...0xffff_ffff => { buf.push(0xfe); 4 },
+        _               => { buf.push(0xff); 8 }
+    };
+    buf.extend((0..len).map(|n| (size >> (n * 8)) as u8));
+}
+
+
 
 impl<'a, T : Parse<'a>> Parse<'a> for Vec<T> {
 
@@ -177,6 +190,19 @@ mod tests
         assert_eq!(buf.len(), 0);
         assert_eq!(buf.consumed_since(org_buf).len(), 4);
     }
+
+    #[test]
+    fn test_compact_size() {
+        for &(size, len) in [(0, 1), (0xfc, 1), (0xfd, 3), (0xffff, 3), (0x1_0000, 5), (0xffff_ffff, 5)].iter() {
+            let mut raw = Vec::new();
+            write_compact_size(&mut raw, size);
+            assert_eq!(raw.len(), len);
+
+            let mut buf = Buffer::new(&raw);
+            assert_eq!(buf.parse_compact_size().unwrap(), size);
+            assert_eq!(buf.len(), 0);
+        }
+    }
 }
 
 
@@ -35,6 +35,8 @@ macro_rules! tx_builder { ($b:ident ) =>
 ///
 /// The letters used have no other meaning then to link outputs to inputs
 ///
+/// Outputs can be given an amount in satoshis with `;`; the default is 0
+///
 /// Usage:
 ///
 /// ```no_test
@@ -47,12 +49,23 @@ macro_rules! tx_builder { ($b:ident ) =>
 ///    // transaction with input a and output b,c
 ///    tx!(bld, a -> b,c);
 ///
+///    // coinbase transaction of which the script starts with the height 2 (BIP34)
+///    let coinbase = tx!(bld; coinbase @ 2 => d);
+///
 /// ```
 #[macro_export]
 macro_rules! tx {
 
+    ( $bld:ident; coinbase @ $height:expr => $($output:ident $( ; $amount:expr )* ),+)
+    =>
+    ( tx!(@height Some($height as u64); $bld; coinbase => $($output $( ; $amount )* ),+) );
+
     ( $bld:ident;  $($input:ident),+     => $($output:ident $( ; $amount:expr )* ),+)
     =>
+    ( tx!(@height None::<u64>; $bld; $($input),+ => $($output $( ; $amount )* ),+) );
+
+    ( @height $height:expr; $bld:ident;  $($input:ident),+     => $($output:ident $( ; $amount:expr )* ),+)
+    =>
     ( {
         let b1 = $bld.clone();
 
@@ -64,12 +77,16 @@ macro_rules! tx {
                     let mut txin_cb: Vec<u8> = Vec::new();
                     txin_cb.extend([0u8;32].iter());   // previous output = 0
                     txin_cb.extend([0xffu8,0xffu8,0xffu8,0xffu8].iter()); // index  = -1
-                    txin_cb.extend([8u8].iter()); // script is 8 bytes
 
-                    // 1 as "extra-nonce". This should actually be made unique
-                    let bts = [1u8,0u8,0u8,0u8, 0u8,0u8,0u8,0u8];
+                    // the height, if given, followed by 1 as "extra-nonce". This should actually be made unique
+                    let mut script: Vec<u8> = match $height {
+                        Some(height) => ::block::get_coinbase_height_script(height),
+                        None         => Vec::new()
+                    };
+                    script.extend([1u8,0u8,0u8,0u8, 0u8,0u8,0u8,0u8].iter());
 
-                    txin_cb.extend(bts.iter());
+                    txin_cb.push(script.len() as u8);
+                    txin_cb.extend(script.iter());
                     txin_cb.extend([0u8;4].iter()); // sequence = 0
 
                     txin_cb
@@ -83,14 +100,14 @@ macro_rules! tx {
 
         let txs_out = vec![ $(
             {
-                const DEFAULT_AMOUNT: u8 = 50_u8;
-                let amount: u8 = * vec![$( $amount)*].get(0).unwrap_or(& DEFAULT_AMOUNT);
+                const DEFAULT_AMOUNT: u64 = 0_u64;
+                let amount: u64 = * vec![$( $amount as u64)*].get(0).unwrap_or(& DEFAULT_AMOUNT);
                 let _ = stringify!($output);
 
-                let outp = vec![
-                    amount, 0u8,0u8,0u8,0u8,0u8,0u8,0u8,  /* the amount as little endian i64 */
-                    1, 81 /* a one byte script consisting of OP_TRUE */
-                ];
+                /* the amount as little endian i64 */
+                let mut outp: Vec<u8> = (0..8).map(|n| (amount >> (n * 8)) as u8).collect();
+
+                outp.extend([1u8, 81u8].iter()); /* a one byte script consisting of OP_TRUE */
 
                 outp
             }
@@ -158,19 +175,20 @@ macro_rules! blk {
     {
         let mut block: Vec<u8> = vec![1_u8,0_u8,0_u8,0_u8]; // block version = 1
 
-        // hash of previous block
-        let hash = ::hash::Hash32Buf::double_sha256(& $prev);
+        // hash of previous block header
+        let hash = ::hash::Hash32Buf::double_sha256(& $prev[0..80]);
         block.extend(hash.as_ref().0.iter());
 
-        // calculate merkle root
+        // calculate merkle root; of transactions with witnesses, the txid excludes these
         let mut merkle = Vec::new();
         let mut count = 0_u8;
         $(
-            merkle.push(::hash::Hash32Buf::double_sha256(& $txvec) );
+            merkle.push(<::transaction::Transaction as ::buffer::Parse>::parse(
+                &mut ::buffer::Buffer::new(& $txvec)).unwrap().txid() );
             count += 1;
         )*
 
-        block.extend(::merkle_tree::get_merkle_root(merkle).as_ref().0.iter());
+        block.extend(::merkle_tree::get_merkle_root(merkle, ::config::Config::default().parallel_hashing_threshold).as_ref().0.iter());
 
         block.extend([0u8;4].iter()); // time = 0 for now
         block.extend([0u8;4].iter()); // bits = 0 for now
@@ -0,0 +1,246 @@
+//! Parameters of the chains
+//!
+//! A store verifies blocks against the parameters of the chain selected in its config: the
+//! genesis block, the magic number of the blk files, the proof-of-work limit and retarget rules,
+//! the activation heights of soft-forks and the subsidy halving interval.
+//!
+//! The values are those of bitcoin-core. The block signatures of signet are not verified.
+
+use hash::*;
+use pow;
+use pow::U256;
+use util::{from_hex, from_hex_rev};
+use block;
+use deployments;
+use deployments::Chain;
+
+
+/// The coinbase transaction of the genesis block; this is the same on all chains
+const GENESIS_COINBASE: &'static str = "01000000010000000000000000000000000000000000000000000000000000000000000000\
+                   ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c\
+                   6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffff\
+                   ffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea\
+                   1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+const POW_LIMIT_MAIN:    &'static str = "00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+const POW_LIMIT_REGTEST: &'static str = "7fffff0000000000000000000000000000000000000000000000000000000000";
+const POW_LIMIT_SIGNET:  &'static str = "00000377ae000000000000000000000000000000000000000000000000000000";
+
+/// The blocks on main net of which the coinbase has the txid of an earlier coinbase with
+/// unspent outputs (blocks 91842 and 91880)
+const BIP30_EXCEPTIONS_MAIN: [&'static str; 2] = [
+    "00000000000a4d0a398161ffc163c503763b1f4360639393e0e4c8e300e0caec",
+    "00000000000743f190a18c5577a3c2d2a1f610ae9601ac046a38084ccb7cd721"
+];
+
+/// The intended time between blocks in seconds
+pub const TARGET_SPACING: u32 = 10 * 60;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainParams {
+
+    pub chain: Chain,
+
+    /// The raw genesis block and its hash
+    pub genesis_block: Vec<u8>,
+    pub genesis_hash:  Hash32Buf,
+
+    /// The magic number that precedes each block in the blk files
+    pub magic: u32,
+
+    /// The highest allowed difficulty target
+    pub pow_limit: U256,
+
+    /// A block more than twice the target spacing after its previous block may use the
+    /// proof-of-work limit (testnet's 20-minute rule)
+    pub pow_allow_min_difficulty_blocks: bool,
+
+    /// The difficulty is never adjusted (regtest)
+    pub pow_no_retargeting: bool,
+
+    /// The height from which the coinbase must start with the height (BIP34)
+    pub bip34_height: u64,
+
+    /// The activation heights of the soft-forks that add script verification rules; see
+    /// deployments.rs
+    pub bip16_height:  u64,
+    pub bip65_height:  u64,
+    pub bip66_height:  u64,
+    pub csv_height:    u64,
+    pub segwit_height: u64,
+
+    /// The blocks that may repeat the txid of a transaction with unspent outputs (BIP30)
+    pub bip30_exceptions: Vec<Hash32Buf>,
+
+    /// Number of blocks after which the subsidy is halved
+    pub subsidy_halving_interval: u64
+}
+
+
+/// Creates the genesis block with the given header fields
+fn genesis_block(time: u32, bits: u32, nonce: u32) -> Vec<u8> {
+
+    let coinbase = from_hex(GENESIS_COINBASE);
+
+    let mut block = vec![1, 0, 0, 0]; // version
+    block.extend_from_slice(&[0; 32]);  // previous block
+    block.extend_from_slice(Hash32Buf::double_sha256(&coinbase).as_ref().0);
+
+    for &field in [time, bits, nonce].iter() {
+        block.extend((0..4).map(|n| (field >> (n * 8)) as u8));
+    }
+
+    block.push(1); // transaction count
+    block.extend(coinbase);
+    block
+}
+
+
+impl ChainParams {
+
+    pub fn new(chain: Chain) -> ChainParams {
+
+        let (genesis_block, magic, pow_limit) = match chain {
+            Chain::Main    => (genesis_block(1231006505, 0x1d00ffff, 2083236893), 0xD9B4BEF9, POW_LIMIT_MAIN),
+            Chain::Testnet => (genesis_block(1296688602, 0x1d00ffff, 414098458),  0x0709110B, POW_LIMIT_MAIN),
+            Chain::Regtest => (genesis_block(1296688602, 0x207fffff, 2),          0xDAB5BFFA, POW_LIMIT_REGTEST),
+            Chain::Signet  => (genesis_block(1598918400, 0x1e0377ae, 52613770),   0x40CF030A, POW_LIMIT_SIGNET)
+        };
+
+        ChainParams {
+            chain:         chain,
+            genesis_hash:  Hash32Buf::double_sha256(&genesis_block[0..80]),
+            genesis_block: genesis_block,
+            magic:         magic,
+            pow_limit:     U256::from(&from_hex(pow_limit)[..]),
+
+            pow_allow_min_difficulty_blocks: chain == Chain::Testnet || chain == Chain::Regtest,
+            pow_no_retargeting:              chain == Chain::Regtest,
+
+            bip34_height: match chain {
+                Chain::Main    => 227_931,
+                Chain::Testnet => 21_111,
+                Chain::Regtest => 1,
+                Chain::Signet  => 1
+            },
+
+            bip16_height: match chain {
+                Chain::Main    => 173_805,
+                Chain::Testnet => 514,
+                Chain::Regtest => 0,
+                Chain::Signet  => 0
+            },
+
+            bip65_height: match chain {
+                Chain::Main    => 388_381,
+                Chain::Testnet => 581_885,
+                Chain::Regtest => 1,
+                Chain::Signet  => 1
+            },
+
+            bip66_height: match chain {
+                Chain::Main    => 363_725,
+                Chain::Testnet => 330_776,
+                Chain::Regtest => 1,
+                Chain::Signet  => 1
+            },
+
+            csv_height: match chain {
+                Chain::Main    => 419_328,
+                Chain::Testnet => 770_112,
+                Chain::Regtest => 1,
+                Chain::Signet  => 1
+            },
+
+            segwit_height: match chain {
+                Chain::Main    => 481_824,
+                Chain::Testnet => 834_624,
+                Chain::Regtest => 0,
+                Chain::Signet  => 1
+            },
+
+            bip30_exceptions: if chain == Chain::Main {
+                BIP30_EXCEPTIONS_MAIN.iter()
+                    .map(|hash| Hash32Buf::from_slice(&from_hex_rev(hash)))
+                    .collect()
+            }
+            else {
+                Vec::new()
+            },
+
+            subsidy_halving_interval: if chain == Chain::Regtest { 150 } else { 210_000 }
+        }
+    }
+
+    /// Returns true if the given hash is the hash of the genesis block
+    pub fn is_genesis_block(&self, hash: Hash32) -> bool {
+        self.genesis_hash.as_ref() == hash
+    }
+
+    /// Returns true if the block with the given hash is exempted from BIP30
+    pub fn is_bip30_exception(&self, block_hash: Hash32) -> bool {
+        self.bip30_exceptions.iter().any(|hash| hash.as_ref() == block_hash)
+    }
+
+    /// Returns the proof-of-work limit in compact form
+    pub fn pow_limit_bits(&self) -> u32 {
+        pow::to_compact(self.pow_limit)
+    }
+
+    /// Returns the script verification flags for a block at the given height
+    pub fn get_script_flags(&self, height: u64) -> u32 {
+        deployments::get_script_flags(self, height)
+    }
+
+    /// Returns the amount of new coins a coinbase at the given height may create
+    pub fn get_block_subsidy(&self, height: u64) -> i64 {
+        block::get_block_subsidy(height, self.subsidy_halving_interval)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use transaction::COIN;
+
+    #[test]
+    fn test_genesis_blocks() {
+
+        let expected = [
+            (Chain::Main,    "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"),
+            (Chain::Testnet, "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943"),
+            (Chain::Regtest, "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206"),
+            (Chain::Signet,  "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6")
+        ];
+
+        for &(chain, hash) in expected.iter() {
+            let params = ChainParams::new(chain);
+            assert!(params.is_genesis_block(Hash32Buf::from_slice(&from_hex_rev(hash)).as_ref()));
+        }
+
+        assert_eq!(ChainParams::new(Chain::Main).genesis_block, genesis!());
+    }
+
+    #[test]
+    fn test_chain_params() {
+
+        let main    = ChainParams::new(Chain::Main);
+        let regtest = ChainParams::new(Chain::Regtest);
+
+        assert_eq!(main.pow_limit_bits(), 0x1d00ffff);
+        assert_eq!(regtest.pow_limit_bits(), 0x207fffff);
+        assert_eq!(ChainParams::new(Chain::Signet).pow_limit_bits(), 0x1e0377ae);
+
+        assert_eq!(main.get_block_subsidy(209_999), 50 * COIN);
+        assert_eq!(regtest.get_block_subsidy(150), 25 * COIN);
+        assert!(!main.is_genesis_block(regtest.genesis_hash.as_ref()));
+
+        let block91842 = Hash32Buf::from_slice(&from_hex_rev(BIP30_EXCEPTIONS_MAIN[0]));
+        assert!(main.is_bip30_exception(block91842.as_ref()));
+        assert!(!regtest.is_bip30_exception(block91842.as_ref()));
+        assert!(!main.is_bip30_exception(main.genesis_hash.as_ref()));
+    }
+}
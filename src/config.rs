@@ -1,9 +1,39 @@
-//! Stub configutation
-//! Meant to wrap toml-config files
+//! Configuration of a store
+//!
+//! A config is loaded from a TOML file, in which only `root` is required:
+//!
+//! ```toml
+//! root = "/var/lib/bitcrust"
+//!
+//! # optional; the defaults are shown
+//! chain                      = "main"     # main, testnet, regtest or signet
+//! initial_sync               = true
+//! parallel_hashing_threshold = 10
+//! transactions_file_size     = 2147483648
+//! block_headers_file_size    = 2147483648
+//! hash_index_file_size       = 1073741824
+//! spend_tree_file_size       = 1073741840
+//! spend_index_file_size      = 1073741840
+//! log_level                  = "info"     # critical, error, warning, info, debug or trace
+//! log_format                 = "compact"  # compact or full
+//! ```
+//!
+//! The file sizes must not be changed for an existing store, as its files are opened with the
+//! configured size. The records of the spend-tree and the words of the spend-index are addressed
+//! by an index in which each file spans a fixed range, so their files cannot be larger than the
+//! defaults.
 
+use std::fmt;
 use std::fs;
 use std::env;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use slog;
+use toml;
+
+use deployments::Chain;
+use store::RECORDS_FILE_SIZE;
 
 
 // Overrides the store directory to use
@@ -12,27 +42,189 @@ pub const ENV_BITCRUST_STORE: &'static str = "BITCRUST_STORE";
 // Set to "1" will prevent the data-folder to be cleared
 pub const ENV_BITCRUST_NOCLEAR: &'static str = "BITCRUST_NOCLEAR";
 
+const MB: u64 = 1024 * 1024;
+
+/// The space at the end of each file that is not filled, such that the largest write fits
+pub const FILE_RESERVE: u64 = 10 * MB;
 
+/// A file must hold more than the reserve, and positions in a file are 32-bit offsets
+const MIN_FILE_SIZE: u64 = 2 * FILE_RESERVE;
+const MAX_FILE_SIZE: u64 = 1 << 32;
 
-#[derive(Clone)]
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Critical,
+    Error,
+    Warning,
+    Info,
+    Debug,
+    Trace
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Compact,
+    Full
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
-    pub root: PathBuf
+
+    /// The directory of the store
+    pub root: PathBuf,
+
+    /// The chain of which the blocks are verified and stored
+    pub chain: Chain,
+
+    /// Skips script verification of the transactions in blocks
+    pub initial_sync: bool,
+
+    /// The number of transactions of a block that are verified per thread, and the number of
+    /// hashes of a level of a merkle tree above which these are hashed in parallel
+    pub parallel_hashing_threshold: usize,
+
+    /// The sizes in bytes of the files of the transactions, the block headers, the hash-indexes,
+    /// the spend-tree and the spend-index
+    pub transactions_file_size:  u64,
+    pub block_headers_file_size: u64,
+    pub hash_index_file_size:    u64,
+    pub spend_tree_file_size:    u64,
+    pub spend_index_file_size:   u64,
+
+    pub log_level:  LogLevel,
+    pub log_format: LogFormat
 }
 
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
 
-impl Config {
+    /// The config file cannot be read
+    Io(String),
+
+    /// The config is not valid TOML, or contains unknown or mistyped settings
+    Parse(String),
+
+    /// The setting with the given name has an invalid value
+    InvalidValue(&'static str)
+}
 
-    pub fn root() -> PathBuf {
-        unimplemented!()
+impl fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref err)              => write!(fmt, "cannot read config: {}", err),
+            ConfigError::Parse(ref err)           => write!(fmt, "invalid config: {}", err),
+            ConfigError::InvalidValue(setting)    => write!(fmt, "invalid value for {}", setting)
+        }
     }
 }
+
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            root:                       PathBuf::new(),
+            chain:                      Chain::Main,
+            initial_sync:               true,
+            parallel_hashing_threshold: 10,
+            transactions_file_size:     2 * 1024 * MB,
+            block_headers_file_size:    2 * 1024 * MB,
+            hash_index_file_size:       1 * 1024 * MB,
+            spend_tree_file_size:       RECORDS_FILE_SIZE,
+            spend_index_file_size:      RECORDS_FILE_SIZE,
+            log_level:                  LogLevel::Info,
+            log_format:                 LogFormat::Compact
+        }
+    }
+}
+
+/// Returns the size after which no more is written to a file of the given size
+pub fn max_content_size(file_size: u64) -> u64 {
+    file_size - FILE_RESERVE
+}
+
+
 impl Config {
 
-    pub fn new(path: &str) -> Config {
+    /// Loads and validates the config from a TOML file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
 
-        let path = PathBuf::from(path);
-        Config { root: path }
+        let mut toml = String::new();
+        fs::File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut toml))
+            .map_err(|err| ConfigError::Io(err.to_string()))?;
 
+        Config::from_toml(&toml)
+    }
+
+    /// Parses and validates the config from a TOML string
+    pub fn from_toml(toml: &str) -> Result<Config, ConfigError> {
+
+        let config: Config = toml::from_str(toml)
+            .map_err(|err| ConfigError::Parse(err.to_string()))?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Verifies the values of the settings
+    pub fn validate(&self) -> Result<(), ConfigError> {
+
+        if self.root.as_os_str().is_empty() {
+            return Err(ConfigError::InvalidValue("root"));
+        }
+
+        if self.parallel_hashing_threshold == 0 {
+            return Err(ConfigError::InvalidValue("parallel_hashing_threshold"));
+        }
+
+        let file_sizes = [
+            ("transactions_file_size",  self.transactions_file_size,  MAX_FILE_SIZE),
+            ("block_headers_file_size", self.block_headers_file_size, MAX_FILE_SIZE),
+            ("hash_index_file_size",    self.hash_index_file_size,    MAX_FILE_SIZE),
+            ("spend_tree_file_size",    self.spend_tree_file_size,    RECORDS_FILE_SIZE),
+            ("spend_index_file_size",   self.spend_index_file_size,   RECORDS_FILE_SIZE)
+        ];
+
+        for &(setting, size, max_size) in file_sizes.iter() {
+            if size < MIN_FILE_SIZE || size > max_size {
+                return Err(ConfigError::InvalidValue(setting));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates the root logger as configured
+    pub fn logger(&self) -> slog::Logger {
+
+        use slog::DrainExt;
+        use slog_term;
+
+        let level = match self.log_level {
+            LogLevel::Critical => slog::Level::Critical,
+            LogLevel::Error    => slog::Level::Error,
+            LogLevel::Warning  => slog::Level::Warning,
+            LogLevel::Info     => slog::Level::Info,
+            LogLevel::Debug    => slog::Level::Debug,
+            LogLevel::Trace    => slog::Level::Trace
+        };
+
+        let streamer = match self.log_format {
+            LogFormat::Compact => slog_term::streamer().compact(),
+            LogFormat::Full    => slog_term::streamer().full()
+        };
+
+        slog::Logger::root(slog::LevelFilter::new(streamer.build(), level).fuse(), o!())
+    }
+
+    /// Creates a config with the default settings for the store at the given path
+    pub fn new(path: &str) -> Config {
+
+        Config { root: PathBuf::from(path), ..Config::default() }
     }
 
     /// Creates and empties a store
@@ -59,15 +251,83 @@ impl Config {
         if env::var(ENV_BITCRUST_NOCLEAR).unwrap_or("0".to_string()) !=  "1" {
             let _ =  fs::remove_dir_all(path.clone());
         }
-        Config { root: path }
+        Config { root: path, ..Config::default() }
     }
 
 
     pub fn new_persist() -> Config {
 
-        let path = PathBuf::from("prs");
-        Config { root: path }
-
+        Config::new("prs")
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_from_toml() {
+
+        let cfg = Config::from_toml("root = \"store\"").unwrap();
+        assert_eq!(cfg, Config::new("store"));
+
+        let cfg = Config::from_toml("
+            root = \"store\"
+            chain = \"regtest\"
+            initial_sync = false
+            transactions_file_size = 104857600
+            spend_tree_file_size = 104857600
+            log_level = \"debug\"
+            log_format = \"full\"
+        ").unwrap();
+        assert_eq!(cfg.chain, Chain::Regtest);
+        assert_eq!(cfg.initial_sync, false);
+        assert_eq!(cfg.transactions_file_size, 100 * MB);
+        assert_eq!(cfg.spend_tree_file_size, 100 * MB);
+        assert_eq!(cfg.spend_index_file_size, Config::default().spend_index_file_size);
+        assert_eq!(cfg.hash_index_file_size, Config::default().hash_index_file_size);
+        assert_eq!(cfg.log_level, LogLevel::Debug);
+        assert_eq!(cfg.log_format, LogFormat::Full);
+
+        assert_eq!(Config::from_toml("initial_sync = true"),
+            Err(ConfigError::InvalidValue("root")));
+        assert_eq!(Config::from_toml("root = \"store\"\nhash_index_file_size = 1000"),
+            Err(ConfigError::InvalidValue("hash_index_file_size")));
+        assert_eq!(Config::from_toml("root = \"store\"\nspend_index_file_size = 2147483648"),
+            Err(ConfigError::InvalidValue("spend_index_file_size")));
+        assert_eq!(Config::from_toml("root = \"store\"\nparallel_hashing_threshold = 0"),
+            Err(ConfigError::InvalidValue("parallel_hashing_threshold")));
+
+        for toml in ["root = \"store\"\nlog_level = \"loud\"",
+                     "root = \"store\"\nchain = \"litecoin\"",
+                     "root = \"store\"\ninitial_sync = 1",
+                     "root = \"store\"\nfile_size = 1",
+                     "root = "].iter() {
+            match Config::from_toml(toml) {
+                Err(ConfigError::Parse(_)) => {},
+                x => panic!("Unexpected result {:?} for {}", x, toml)
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_file() {
+
+        let dir = PathBuf::from("tmp").join("config-test_from_file");
+        let _ = fs::create_dir_all(&dir);
+        let path = dir.join("bitcrust.toml");
+        fs::File::create(&path)
+            .and_then(|mut file| file.write_all(b"root = \"store\"\nblock_headers_file_size = 104857600\n"))
+            .unwrap();
+
+        let cfg = Config::from_file(&path).unwrap();
+        assert_eq!(cfg.block_headers_file_size, 100 * MB);
+
+        match Config::from_file(dir.join("missing.toml")) {
+            Err(ConfigError::Io(_)) => {},
+            x => panic!("Unexpected result {:?}", x)
+        }
+    }
+}
@@ -0,0 +1,9 @@
+//! Cryptographic primitives needed by the script interpreter
+//!
+//! SHA256 is provided by ring; the primitives that ring doesn't provide are implemented here.
+//! These are not constant time and must only be used for verification
+
+
+pub mod ripemd160;
+pub mod sha1;
+pub mod secp256k1;
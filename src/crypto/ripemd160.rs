@@ -0,0 +1,129 @@
+//! RIPEMD160 as used by OP_RIPEMD160 and OP_HASH160
+
+
+const R_LEFT: [usize; 80] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+    7, 4, 13, 1, 10, 6, 15, 3, 12, 0, 9, 5, 2, 14, 11, 8,
+    3, 10, 14, 4, 9, 15, 8, 1, 2, 7, 0, 6, 13, 11, 5, 12,
+    1, 9, 11, 10, 0, 8, 12, 4, 13, 3, 7, 15, 14, 5, 6, 2,
+    4, 0, 5, 9, 7, 12, 2, 10, 14, 1, 3, 8, 11, 6, 15, 13];
+
+const R_RIGHT: [usize; 80] = [
+    5, 14, 7, 0, 9, 2, 11, 4, 13, 6, 15, 8, 1, 10, 3, 12,
+    6, 11, 3, 7, 0, 13, 5, 10, 14, 15, 8, 12, 4, 9, 1, 2,
+    15, 5, 1, 3, 7, 14, 6, 9, 11, 8, 12, 2, 10, 0, 4, 13,
+    8, 6, 4, 1, 3, 11, 15, 0, 5, 12, 2, 13, 9, 7, 10, 14,
+    12, 15, 10, 4, 1, 5, 8, 7, 6, 2, 13, 14, 0, 3, 9, 11];
+
+const S_LEFT: [u32; 80] = [
+    11, 14, 15, 12, 5, 8, 7, 9, 11, 13, 14, 15, 6, 7, 9, 8,
+    7, 6, 8, 13, 11, 9, 7, 15, 7, 12, 15, 9, 11, 7, 13, 12,
+    11, 13, 6, 7, 14, 9, 13, 15, 14, 8, 13, 6, 5, 12, 7, 5,
+    11, 12, 14, 15, 14, 15, 9, 8, 9, 14, 5, 6, 8, 6, 5, 12,
+    9, 15, 5, 11, 6, 8, 13, 12, 5, 12, 13, 14, 11, 8, 5, 6];
+
+const S_RIGHT: [u32; 80] = [
+    8, 9, 9, 11, 13, 15, 15, 5, 7, 7, 8, 11, 14, 14, 12, 6,
+    9, 13, 15, 7, 12, 8, 9, 11, 7, 7, 12, 7, 6, 15, 13, 11,
+    9, 7, 15, 11, 8, 6, 6, 14, 12, 13, 5, 14, 13, 13, 7, 5,
+    15, 5, 8, 11, 14, 14, 6, 14, 6, 9, 12, 9, 12, 5, 15, 8,
+    8, 5, 12, 9, 12, 5, 14, 6, 8, 13, 6, 5, 15, 13, 11, 11];
+
+const K_LEFT:  [u32; 5] = [0x00000000, 0x5A827999, 0x6ED9EBA1, 0x8F1BBCDC, 0xA953FD4E];
+const K_RIGHT: [u32; 5] = [0x50A28BE6, 0x5C4DD124, 0x6D703EF3, 0x7A6D76E9, 0x00000000];
+
+
+fn f(round: usize, x: u32, y: u32, z: u32) -> u32 {
+    match round {
+        0 => x ^ y ^ z,
+        1 => (x & y) | (!x & z),
+        2 => (x | !y) ^ z,
+        3 => (x & z) | (y & !z),
+        _ => x ^ (y | !z)
+    }
+}
+
+/// Returns the RIPEMD160 digest of the input
+pub fn ripemd160(input: &[u8]) -> [u8; 20] {
+
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    // padding is like SHA1, but with a little-endian length
+    let bit_len = (input.len() as u64) * 8;
+    let mut data = input.to_vec();
+    data.push(0x80);
+    while data.len() % 64 != 56 {
+        data.push(0);
+    }
+    for i in 0..8 {
+        data.push((bit_len >> (i*8)) as u8);
+    }
+
+    for block in data.chunks(64) {
+
+        let mut x = [0u32; 16];
+        for i in 0..16 {
+            x[i] = block[i*4] as u32 | (block[i*4+1] as u32) << 8
+                | (block[i*4+2] as u32) << 16 | (block[i*4+3] as u32) << 24;
+        }
+
+        let (mut al, mut bl, mut cl, mut dl, mut el) = (h[0], h[1], h[2], h[3], h[4]);
+        let (mut ar, mut br, mut cr, mut dr, mut er) = (h[0], h[1], h[2], h[3], h[4]);
+
+        for j in 0..80 {
+            let round = j / 16;
+
+            let t = al.wrapping_add(f(round, bl, cl, dl))
+                .wrapping_add(x[R_LEFT[j]])
+                .wrapping_add(K_LEFT[round])
+                .rotate_left(S_LEFT[j])
+                .wrapping_add(el);
+            al = el;
+            el = dl;
+            dl = cl.rotate_left(10);
+            cl = bl;
+            bl = t;
+
+            let t = ar.wrapping_add(f(4 - round, br, cr, dr))
+                .wrapping_add(x[R_RIGHT[j]])
+                .wrapping_add(K_RIGHT[round])
+                .rotate_left(S_RIGHT[j])
+                .wrapping_add(er);
+            ar = er;
+            er = dr;
+            dr = cr.rotate_left(10);
+            cr = br;
+            br = t;
+        }
+
+        let t = h[1].wrapping_add(cl).wrapping_add(dr);
+        h[1]  = h[2].wrapping_add(dl).wrapping_add(er);
+        h[2]  = h[3].wrapping_add(el).wrapping_add(ar);
+        h[3]  = h[4].wrapping_add(al).wrapping_add(br);
+        h[4]  = h[0].wrapping_add(bl).wrapping_add(cr);
+        h[0]  = t;
+    }
+
+    let mut result = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        for j in 0..4 {
+            result[i*4 + j] = (word >> (j*8)) as u8;
+        }
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use util::*;
+    use super::*;
+
+    #[test]
+    fn test_ripemd160() {
+        assert_eq!(to_hex(&ripemd160(b"")), "9c1185a5c5e9fc54612808977ee8f548b2258d31");
+        assert_eq!(to_hex(&ripemd160(b"abc")), "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc");
+        assert_eq!(to_hex(&ripemd160(b"message digest")), "5d0689ef49d2fae572b881b123a85ffa21595f36");
+        assert_eq!(to_hex(&ripemd160(&[b'a'; 1000])), "aa69deee9a8922e92f8105e007f76110f381e9cf");
+    }
+}
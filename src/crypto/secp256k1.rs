@@ -0,0 +1,727 @@
+//! ECDSA signature verification on the secp256k1 curve
+//!
+//! This follows the behaviour of libsecp256k1 as used by bitcoin-core: public keys can be
+//! compressed, uncompressed or hybrid, signatures are parsed with the lax DER parser and
+//! high S values must be normalized by the caller before verification.
+//!
+//! Numbers are stored as eight little-endian 32-bit limbs; both the field prime and the group
+//! order are of the form 2^256 - c, which allows reduction by folding.
+
+use std::cmp::Ordering;
+
+
+/// Unsigned 256-bit number
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Num([u32; 8]);
+
+const ZERO: Num = Num([0; 8]);
+const ONE:  Num = Num([1, 0, 0, 0, 0, 0, 0, 0]);
+
+impl Num {
+
+    /// Reads a big-endian number of at most 32 bytes
+    fn from_be_bytes(bytes: &[u8]) -> Num {
+        debug_assert!(bytes.len() <= 32);
+
+        let mut result = ZERO;
+        for (i, byte) in bytes.iter().rev().enumerate() {
+            result.0[i / 4] |= (*byte as u32) << ((i % 4) * 8);
+        }
+        result
+    }
+
+    fn from_hex(hex: &str) -> Num {
+        let bytes: Vec<u8> = (0..hex.len() / 2)
+            .map(|i| u8::from_str_radix(&hex[i*2..i*2+2], 16).unwrap())
+            .collect();
+
+        Num::from_be_bytes(&bytes)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.iter().all(|limb| *limb == 0)
+    }
+
+    fn is_odd(&self) -> bool {
+        self.0[0] & 1 == 1
+    }
+
+    fn bit(&self, n: usize) -> bool {
+        (self.0[n / 32] >> (n % 32)) & 1 == 1
+    }
+
+    fn compare(&self, other: &Num) -> Ordering {
+        for i in (0..8).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ord             => return ord
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Returns the sum and whether it overflowed
+    fn add(&self, other: &Num) -> (Num, bool) {
+        let mut result = ZERO;
+        let mut carry  = 0u64;
+        for i in 0..8 {
+            let sum = self.0[i] as u64 + other.0[i] as u64 + carry;
+            result.0[i] = sum as u32;
+            carry = sum >> 32;
+        }
+        (result, carry != 0)
+    }
+
+    /// Returns the difference and whether it underflowed
+    fn sub(&self, other: &Num) -> (Num, bool) {
+        let mut result = ZERO;
+        let mut borrow = 0i64;
+        for i in 0..8 {
+            let diff = self.0[i] as i64 - other.0[i] as i64 - borrow;
+            result.0[i] = diff as u32;
+            borrow = if diff < 0 { 1 } else { 0 };
+        }
+        (result, borrow != 0)
+    }
+}
+
+
+/// A modulus m = 2^256 - c
+struct Modulus {
+    m: Num,
+    c: [u32; 5]
+}
+
+impl Modulus {
+
+    /// Reduces a 512-bit number by repeatedly replacing hi * 2^256 with hi * c
+    fn reduce(&self, wide: [u32; 16]) -> Num {
+
+        let mut w = wide;
+        while w[8..].iter().any(|limb| *limb != 0) {
+
+            let mut next = [0u64; 17];
+            for i in 0..8 {
+                next[i] = w[i] as u64;
+            }
+            for i in 0..8 {
+                for j in 0..5 {
+                    let product = w[8 + i] as u64 * self.c[j] as u64;
+                    next[i + j]     += product & 0xffff_ffff;
+                    next[i + j + 1] += product >> 32;
+                }
+            }
+
+            let mut carry = 0u64;
+            for i in 0..16 {
+                let sum = next[i] + carry;
+                w[i]  = sum as u32;
+                carry = sum >> 32;
+            }
+            debug_assert_eq!(carry, 0);
+        }
+
+        let mut result = Num([w[0], w[1], w[2], w[3], w[4], w[5], w[6], w[7]]);
+        while result.compare(&self.m) != Ordering::Less {
+            result = result.sub(&self.m).0;
+        }
+        result
+    }
+
+    fn add(&self, a: &Num, b: &Num) -> Num {
+        let (sum, overflow) = a.add(b);
+        if overflow || sum.compare(&self.m) != Ordering::Less {
+            sum.sub(&self.m).0
+        }
+        else {
+            sum
+        }
+    }
+
+    fn sub(&self, a: &Num, b: &Num) -> Num {
+        let (diff, underflow) = a.sub(b);
+        if underflow {
+            diff.add(&self.m).0
+        }
+        else {
+            diff
+        }
+    }
+
+    fn mul(&self, a: &Num, b: &Num) -> Num {
+        let mut wide = [0u64; 17];
+        for i in 0..8 {
+            for j in 0..8 {
+                let product = a.0[i] as u64 * b.0[j] as u64;
+                wide[i + j]     += product & 0xffff_ffff;
+                wide[i + j + 1] += product >> 32;
+            }
+        }
+
+        let mut result = [0u32; 16];
+        let mut carry  = 0u64;
+        for i in 0..16 {
+            let sum = wide[i] + carry;
+            result[i] = sum as u32;
+            carry     = sum >> 32;
+        }
+
+        self.reduce(result)
+    }
+
+    fn pow(&self, base: &Num, exponent: &Num) -> Num {
+        let mut result = ONE;
+        for n in (0..256).rev() {
+            result = self.mul(&result, &result);
+            if exponent.bit(n) {
+                result = self.mul(&result, base);
+            }
+        }
+        result
+    }
+
+    /// Inverse by Fermat's little theorem; both moduli are prime
+    fn inv(&self, a: &Num) -> Num {
+        let exponent = self.m.sub(&Num([2, 0, 0, 0, 0, 0, 0, 0])).0;
+        self.pow(a, &exponent)
+    }
+
+    /// Reduces a number below 2^256
+    fn normalize(&self, a: &Num) -> Num {
+        if a.compare(&self.m) != Ordering::Less {
+            a.sub(&self.m).0
+        }
+        else {
+            *a
+        }
+    }
+}
+
+
+/// The field prime p = 2^256 - 2^32 - 977
+fn field() -> Modulus {
+    Modulus {
+        m: Num::from_hex("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F"),
+        c: [0x000003D1, 0x00000001, 0, 0, 0]
+    }
+}
+
+/// The group order n
+fn order() -> Modulus {
+    Modulus {
+        m: Num::from_hex("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141"),
+        c: [0x2FC9BEBF, 0x402DA173, 0x50B75FC4, 0x45512319, 0x00000001]
+    }
+}
+
+
+/// A point in jacobian coordinates; z = 0 is the point at infinity
+#[derive(Clone, Copy, Debug)]
+struct Point {
+    x: Num,
+    y: Num,
+    z: Num
+}
+
+const INFINITY: Point = Point { x: ONE, y: ONE, z: ZERO };
+
+impl Point {
+
+    fn from_affine(x: Num, y: Num) -> Point {
+        Point { x: x, y: y, z: ONE }
+    }
+
+    fn generator() -> Point {
+        Point::from_affine(
+            Num::from_hex("79BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798"),
+            Num::from_hex("483ADA7726A3C4655DA4FBFC0E1108A8FD17B448A68554199C47D08FFB10D4B8"))
+    }
+
+    fn is_infinity(&self) -> bool {
+        self.z.is_zero()
+    }
+
+    fn double(&self, p: &Modulus) -> Point {
+        if self.is_infinity() || self.y.is_zero() {
+            return INFINITY;
+        }
+
+        let a  = p.mul(&self.x, &self.x);
+        let b  = p.mul(&self.y, &self.y);
+        let c  = p.mul(&b, &b);
+        let xb = p.add(&self.x, &b);
+        let d  = p.sub(&p.sub(&p.mul(&xb, &xb), &a), &c);
+        let d  = p.add(&d, &d);
+        let e  = p.add(&p.add(&a, &a), &a);
+        let f  = p.mul(&e, &e);
+
+        let x3 = p.sub(&f, &p.add(&d, &d));
+        let c8 = p.add(&c, &c);
+        let c8 = p.add(&c8, &c8);
+        let c8 = p.add(&c8, &c8);
+        let y3 = p.sub(&p.mul(&e, &p.sub(&d, &x3)), &c8);
+        let yz = p.mul(&self.y, &self.z);
+        let z3 = p.add(&yz, &yz);
+
+        Point { x: x3, y: y3, z: z3 }
+    }
+
+    fn add(&self, other: &Point, p: &Modulus) -> Point {
+        if self.is_infinity() {
+            return *other;
+        }
+        if other.is_infinity() {
+            return *self;
+        }
+
+        let z1z1 = p.mul(&self.z, &self.z);
+        let z2z2 = p.mul(&other.z, &other.z);
+        let u1   = p.mul(&self.x, &z2z2);
+        let u2   = p.mul(&other.x, &z1z1);
+        let s1   = p.mul(&p.mul(&self.y, &other.z), &z2z2);
+        let s2   = p.mul(&p.mul(&other.y, &self.z), &z1z1);
+
+        if u1 == u2 {
+            return if s1 == s2 { self.double(p) } else { INFINITY };
+        }
+
+        let h    = p.sub(&u2, &u1);
+        let r    = p.sub(&s2, &s1);
+        let h2   = p.mul(&h, &h);
+        let h3   = p.mul(&h, &h2);
+        let u1h2 = p.mul(&u1, &h2);
+
+        let x3 = p.sub(&p.sub(&p.mul(&r, &r), &h3), &p.add(&u1h2, &u1h2));
+        let y3 = p.sub(&p.mul(&r, &p.sub(&u1h2, &x3)), &p.mul(&s1, &h3));
+        let z3 = p.mul(&p.mul(&h, &self.z), &other.z);
+
+        Point { x: x3, y: y3, z: z3 }
+    }
+
+    /// Calculates a*self + b*other with a shared doubling chain
+    fn mul_add(&self, a: &Num, other: &Point, b: &Num, p: &Modulus) -> Point {
+        let both = self.add(other, p);
+
+        let mut result = INFINITY;
+        for n in (0..256).rev() {
+            result = result.double(p);
+            result = match (a.bit(n), b.bit(n)) {
+                (true,  true)  => result.add(&both, p),
+                (true,  false) => result.add(self, p),
+                (false, true)  => result.add(other, p),
+                (false, false) => result
+            };
+        }
+        result
+    }
+
+    /// Returns the affine x coordinate
+    fn affine_x(&self, p: &Modulus) -> Num {
+        let zinv = p.inv(&self.z);
+        p.mul(&self.x, &p.mul(&zinv, &zinv))
+    }
+}
+
+
+/// A public key on the curve
+#[derive(Clone, Copy, Debug)]
+pub struct PublicKey {
+    point: Point
+}
+
+impl PublicKey {
+
+    /// Parses a compressed (33 bytes), uncompressed or hybrid (65 bytes) public key
+    ///
+    /// Returns None if the encoding is invalid or the point is not on the curve
+    pub fn parse(bytes: &[u8]) -> Option<PublicKey> {
+        let p = field();
+
+        let on_curve = |x: &Num, y: &Num| {
+            let rhs = p.add(&p.mul(&p.mul(x, x), x), &Num([7, 0, 0, 0, 0, 0, 0, 0]));
+            p.mul(y, y) == rhs
+        };
+
+        let (x, y) = match (bytes.len(), bytes.first()) {
+            (33, Some(&prefix)) if prefix == 0x02 || prefix == 0x03 => {
+                let x = Num::from_be_bytes(&bytes[1..33]);
+                if x.compare(&p.m) != Ordering::Less {
+                    return None;
+                }
+
+                // y = sqrt(x^3 + 7) = (x^3 + 7)^((p+1)/4)
+                let rhs = p.add(&p.mul(&p.mul(&x, &x), &x), &Num([7, 0, 0, 0, 0, 0, 0, 0]));
+                let exponent = Num::from_hex(
+                    "3FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFBFFFFF0C");
+                let y = p.pow(&rhs, &exponent);
+                if p.mul(&y, &y) != rhs {
+                    return None;
+                }
+
+                let y = if y.is_odd() == (prefix == 0x03) { y } else { p.sub(&ZERO, &y) };
+                (x, y)
+            },
+
+            (65, Some(&prefix)) if prefix == 0x04 || prefix == 0x06 || prefix == 0x07 => {
+                let x = Num::from_be_bytes(&bytes[1..33]);
+                let y = Num::from_be_bytes(&bytes[33..65]);
+                if x.compare(&p.m) != Ordering::Less || y.compare(&p.m) != Ordering::Less {
+                    return None;
+                }
+                if prefix != 0x04 && y.is_odd() != (prefix == 0x07) {
+                    return None;
+                }
+                if !on_curve(&x, &y) {
+                    return None;
+                }
+                (x, y)
+            },
+
+            _ => return None
+        };
+
+        Some(PublicKey { point: Point::from_affine(x, y) })
+    }
+}
+
+
+/// An ECDSA signature
+#[derive(Clone, Copy, Debug)]
+pub struct Signature {
+    r: Num,
+    s: Num
+}
+
+impl Signature {
+
+    /// Parses a DER signature with the same leniency as bitcoin-core's
+    /// `ecdsa_signature_parse_der_lax`
+    ///
+    /// Lengths may use the long form and integers may be padded. Values that don't fit in 32
+    /// bytes or exceed the group order yield a zero signature, which never verifies.
+    pub fn parse_der_lax(sig: &[u8]) -> Option<Signature> {
+
+        let len = sig.len();
+        let mut pos = 0;
+
+        // sequence tag and length
+        if pos == len || sig[pos] != 0x30 {
+            return None;
+        }
+        pos += 1;
+
+        if pos == len {
+            return None;
+        }
+        let lenbyte = sig[pos] as usize;
+        pos += 1;
+        if lenbyte & 0x80 != 0 {
+            let lenbyte = lenbyte - 0x80;
+            if lenbyte > len - pos {
+                return None;
+            }
+            pos += lenbyte;
+        }
+
+        let (rpos, rlen) = match parse_der_lax_integer(sig, &mut pos) {
+            Some(x) => x,
+            None    => return None
+        };
+        let (spos, slen) = match parse_der_lax_integer(sig, &mut pos) {
+            Some(x) => x,
+            None    => return None
+        };
+
+        let n = order();
+        let to_num = |start: usize, len: usize| -> Option<Num> {
+
+            // ignore leading zeroes
+            let mut start = start;
+            let mut len   = len;
+            while len > 0 && sig[start] == 0 {
+                start += 1;
+                len   -= 1;
+            }
+            if len > 32 {
+                return None;
+            }
+            let num = Num::from_be_bytes(&sig[start..start + len]);
+            if num.compare(&n.m) != Ordering::Less {
+                None
+            }
+            else {
+                Some(num)
+            }
+        };
+
+        Some(match (to_num(rpos, rlen), to_num(spos, slen)) {
+            (Some(r), Some(s)) => Signature { r: r, s: s },
+            _                  => Signature { r: ZERO, s: ZERO }
+        })
+    }
+
+    /// Returns true if S is at most half the group order
+    pub fn has_low_s(&self) -> bool {
+        let half_order = Num::from_hex(
+            "7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0");
+        self.s.compare(&half_order) != Ordering::Greater
+    }
+
+    /// Replaces a high S by its complement, which is an equally valid signature
+    pub fn normalize_s(&mut self) {
+        if !self.has_low_s() {
+            self.s = order().sub(&ZERO, &self.s);
+        }
+    }
+
+    /// Verifies the signature of the 32-byte message hash
+    ///
+    /// Like libsecp256k1, this rejects signatures with a high S
+    pub fn verify(&self, msg: &[u8], key: &PublicKey) -> bool {
+        debug_assert_eq!(msg.len(), 32);
+
+        if self.r.is_zero() || self.s.is_zero() || !self.has_low_s() {
+            return false;
+        }
+
+        let n = order();
+        let p = field();
+        let z = n.normalize(&Num::from_be_bytes(msg));
+
+        let w  = n.inv(&self.s);
+        let u1 = n.mul(&z, &w);
+        let u2 = n.mul(&self.r, &w);
+
+        let point = Point::generator().mul_add(&u1, &key.point, &u2, &p);
+        if point.is_infinity() {
+            return false;
+        }
+
+        n.normalize(&point.affine_x(&p)) == self.r
+    }
+}
+
+/// Parses a DER integer tag and length at pos
+///
+/// Returns the start and length of the integer's content
+fn parse_der_lax_integer(sig: &[u8], pos: &mut usize) -> Option<(usize, usize)> {
+    let len = sig.len();
+
+    if *pos == len || sig[*pos] != 0x02 {
+        return None;
+    }
+    *pos += 1;
+
+    if *pos == len {
+        return None;
+    }
+    let mut lenbyte = sig[*pos] as usize;
+    *pos += 1;
+
+    let int_len = if lenbyte & 0x80 != 0 {
+        lenbyte -= 0x80;
+        if lenbyte > len - *pos {
+            return None;
+        }
+        while lenbyte > 0 && sig[*pos] == 0 {
+            *pos    += 1;
+            lenbyte -= 1;
+        }
+        if lenbyte >= 8 {
+            return None;
+        }
+        let mut int_len = 0usize;
+        while lenbyte > 0 {
+            int_len = (int_len << 8) + sig[*pos] as usize;
+            *pos    += 1;
+            lenbyte -= 1;
+        }
+        int_len
+    }
+    else {
+        lenbyte
+    };
+
+    if int_len > len - *pos {
+        return None;
+    }
+
+    let start = *pos;
+    *pos += int_len;
+    Some((start, int_len))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use util::*;
+    use super::*;
+
+    // Private key 1 with the generator as public key
+    const PUBKEY_G_COMPRESSED: &'static str =
+        "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const PUBKEY_G_UNCOMPRESSED: &'static str =
+        "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798\
+         483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+
+    #[test]
+    fn test_field() {
+        let p = field();
+        let a = Num::from_hex("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2E");
+
+        // (p-1)^2 = 1
+        assert_eq!(p.mul(&a, &a), ONE);
+        assert_eq!(p.add(&a, &ONE), ZERO);
+        assert_eq!(p.sub(&ZERO, &ONE), a);
+
+        let x = Num::from_hex("1234567890abcdef");
+        assert_eq!(p.mul(&x, &p.inv(&x)), ONE);
+
+        let n = order();
+        assert_eq!(n.mul(&x, &n.inv(&x)), ONE);
+    }
+
+    #[test]
+    fn test_point() {
+        let p = field();
+        let g = Point::generator();
+
+        // 2G = G + G, and n*G is infinity
+        let g2 = g.double(&p);
+        let two = Num([2, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(g.mul_add(&two, &INFINITY, &ZERO, &p).affine_x(&p), g2.affine_x(&p));
+        assert_eq!(g.mul_add(&ONE, &g, &ONE, &p).affine_x(&p), g2.affine_x(&p));
+
+        let n_minus_1 = order().m.sub(&ONE).0;
+        let x = g.mul_add(&n_minus_1, &g, &ONE, &p);
+        assert!(x.is_infinity());
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let compressed   = PublicKey::parse(&from_hex(PUBKEY_G_COMPRESSED)).unwrap();
+        let uncompressed = PublicKey::parse(&from_hex(PUBKEY_G_UNCOMPRESSED)).unwrap();
+        assert_eq!(compressed.point.y, uncompressed.point.y);
+
+        // hybrid with the matching parity; the y of G is even
+        let mut hybrid = from_hex(PUBKEY_G_UNCOMPRESSED);
+        hybrid[0] = 0x06;
+        assert!(PublicKey::parse(&hybrid).is_some());
+        hybrid[0] = 0x07;
+        assert!(PublicKey::parse(&hybrid).is_none());
+
+        // not on the curve
+        let mut invalid = from_hex(PUBKEY_G_UNCOMPRESSED);
+        invalid[64] ^= 1;
+        assert!(PublicKey::parse(&invalid).is_none());
+
+        assert!(PublicKey::parse(&[]).is_none());
+        assert!(PublicKey::parse(&from_hex(PUBKEY_G_COMPRESSED)[..32]).is_none());
+
+        let mut prefix = from_hex(PUBKEY_G_COMPRESSED);
+        prefix[0] = 0x05;
+        assert!(PublicKey::parse(&prefix).is_none());
+    }
+
+    #[test]
+    fn test_parse_public_key_x() {
+        let compressed = |x: &str| {
+            let mut key = vec![0x02];
+            key.extend(from_hex(x));
+            PublicKey::parse(&key)
+        };
+
+        assert!(compressed("0000000000000000000000000000000000000000000000000000000000000001").is_some());
+
+        // x^3 + 7 has no square root for x = 0 and x = 5
+        assert!(compressed("0000000000000000000000000000000000000000000000000000000000000000").is_none());
+        assert!(compressed("0000000000000000000000000000000000000000000000000000000000000005").is_none());
+
+        // x = p is out of range, although it is equal to x = 0
+        assert!(compressed("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F").is_none());
+
+        // coordinates must be below p
+        let p = from_hex("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC2F");
+        let mut uncompressed = from_hex(PUBKEY_G_UNCOMPRESSED);
+        uncompressed[33..65].copy_from_slice(&p);
+        assert!(PublicKey::parse(&uncompressed).is_none());
+    }
+
+    #[test]
+    fn test_verify() {
+
+        // sha256("bitcrust") signed with private key 1
+        let msg = from_hex("\
+            a64fa51c308dcdad6c884c8ee6e2e96ca561da3c4110b2d4a0ece97629e975f1");
+        let sig = from_hex("\
+            304502210088879de50185e78a9a92f2ca3596b4addd48592ad8fdd67763d88661cdf80d27\
+            02206735ec4aafea1c39f7460a85cc1a5b7337e59ac6f68f8fc923e26bf3644d5403");
+
+        let key = PublicKey::parse(&from_hex(PUBKEY_G_COMPRESSED)).unwrap();
+        let signature = Signature::parse_der_lax(&sig).unwrap();
+        assert!(signature.verify(&msg, &key));
+
+        let mut other_msg = msg.clone();
+        other_msg[0] ^= 1;
+        assert!(!signature.verify(&other_msg, &key));
+
+        // the high-S complement is only valid after normalization
+        let mut high = Signature { r: signature.r, s: order().sub(&ZERO, &signature.s) };
+        assert!(!high.has_low_s());
+        assert!(!high.verify(&msg, &key));
+        high.normalize_s();
+        assert!(high.verify(&msg, &key));
+    }
+
+    #[test]
+    fn test_parse_der_lax() {
+        assert!(Signature::parse_der_lax(&[]).is_none());
+        assert!(Signature::parse_der_lax(&from_hex("3006020101020101")).is_some());
+
+        // padded and long-form lengths are accepted
+        let sig = Signature::parse_der_lax(&from_hex("30810802820001010203000002")).unwrap();
+        assert_eq!(sig.r, ONE);
+        assert_eq!(sig.s, Num([2, 0, 0, 0, 0, 0, 0, 0]));
+
+        // truncated
+        assert!(Signature::parse_der_lax(&from_hex("3006020101020201")).is_none());
+    }
+
+    #[test]
+    fn test_signature_range() {
+
+        // the message and signature of test_verify
+        let msg = from_hex("\
+            a64fa51c308dcdad6c884c8ee6e2e96ca561da3c4110b2d4a0ece97629e975f1");
+        let r = "0088879de50185e78a9a92f2ca3596b4addd48592ad8fdd67763d88661cdf80d27";
+        let s = "6735ec4aafea1c39f7460a85cc1a5b7337e59ac6f68f8fc923e26bf3644d5403";
+        let n = "00FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141";
+
+        let key = PublicKey::parse(&from_hex(PUBKEY_G_COMPRESSED)).unwrap();
+        let verify = |r: &str, s: &str| {
+            let mut sig = vec![0x30, 0];
+            for int in [r, s].iter() {
+                let int = from_hex(int);
+                sig.push(0x02);
+                sig.push(int.len() as u8);
+                sig.extend(int);
+            }
+            sig[1] = (sig.len() - 2) as u8;
+            Signature::parse_der_lax(&sig).unwrap().verify(&msg, &key)
+        };
+
+        assert!(verify(r, s));
+
+        // r and s must be in 1..n
+        assert!(!verify(n, s));
+        assert!(!verify(r, n));
+        assert!(!verify("00", s));
+        assert!(!verify(r, "00"));
+
+        // n + 1 is 1 modulo n
+        assert!(!verify(r, "00FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364142"));
+
+        // more than 32 bytes
+        assert!(!verify(&format!("01{}", &r[2..])[..], s));
+    }
+}
@@ -0,0 +1,83 @@
+//! SHA1 as used by OP_SHA1
+
+
+/// Returns the SHA1 digest of the input
+pub fn sha1(input: &[u8]) -> [u8; 20] {
+
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    for block in padded(input).chunks(64) {
+
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = (block[i*4] as u32) << 24 | (block[i*4+1] as u32) << 16
+                | (block[i*4+2] as u32) << 8 | block[i*4+3] as u32;
+        }
+        for i in 16..80 {
+            w[i] = (w[i-3] ^ w[i-8] ^ w[i-14] ^ w[i-16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+
+        for i in 0..80 {
+            let (f, k) = match i {
+                0...19  => ((b & c) | (!b & d),          0x5A827999),
+                20...39 => (b ^ c ^ d,                   0x6ED9EBA1),
+                40...59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _       => (b ^ c ^ d,                   0xCA62C1D6)
+            };
+
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e)
+                .wrapping_add(k).wrapping_add(w[i]);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut result = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        for j in 0..4 {
+            result[i*4 + j] = (word >> (24 - j*8)) as u8;
+        }
+    }
+    result
+}
+
+/// Pads the input to a multiple of 64 bytes with the big-endian bit length at the end
+fn padded(input: &[u8]) -> Vec<u8> {
+
+    let bit_len = (input.len() as u64) * 8;
+    let mut data = input.to_vec();
+    data.push(0x80);
+    while data.len() % 64 != 56 {
+        data.push(0);
+    }
+    for i in 0..8 {
+        data.push((bit_len >> (56 - i*8)) as u8);
+    }
+    data
+}
+
+
+#[cfg(test)]
+mod tests {
+    use util::*;
+    use super::*;
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(to_hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(to_hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(to_hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+                   "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+    }
+}
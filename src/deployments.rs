@@ -0,0 +1,87 @@
+//! Soft-fork deployments
+//!
+//! Soft-forks add script verification rules from a given height. The deployments of a chain map
+//! the activation heights of its parameters (see chain_params.rs) to the set of flags passed to
+//! the script verification.
+//!
+//! The heights are the buried activation heights as used by bitcoin-core
+//!
+//! The following soft-forks are not supported, and blocks that violate them are accepted:
+//!
+//! * BIP68 and BIP113: the relative lock-times of the sequence numbers and the median-time-past
+//!   lock-times are not enforced (nor is the lock-time of transactions at all); only the
+//!   CHECKSEQUENCEVERIFY opcode of the same deployment (BIP112) is,
+//! * BIP341 and BIP342 (taproot; main net height 709,632): the interpreter treats version 1
+//!   witness programs as any other upgradable witness program. Only the signature hash of BIP341
+//!   is implemented, in sighash.rs.
+
+use script;
+use chain_params::ChainParams;
+
+/// The chain of which the parameters are used; see chain_params.rs
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chain {
+    Main,
+    Testnet,
+    Regtest,
+    Signet
+}
+
+/// A soft-fork that enables the given script verification flags from the given height
+pub struct Deployment {
+    pub height: u64,
+    pub flags:  u32
+}
+
+/// Returns the deployments of the chain, with the activation heights of its parameters
+pub fn deployments(params: &ChainParams) -> [Deployment; 5] {
+    [
+        Deployment { height: params.bip16_height,  flags: script::VERIFY_P2SH },
+        Deployment { height: params.bip66_height,  flags: script::VERIFY_DERSIG },
+        Deployment { height: params.bip65_height,  flags: script::VERIFY_CHECKLOCKTIMEVERIFY },
+
+        // BIP112; BIP68 and BIP113 are part of the same deployment but are not supported
+        Deployment { height: params.csv_height,    flags: script::VERIFY_CHECKSEQUENCEVERIFY },
+
+        // BIP141, BIP143 and BIP147
+        Deployment { height: params.segwit_height, flags: script::VERIFY_WITNESS | script::VERIFY_NULLDUMMY },
+    ]
+}
+
+/// Returns the script verification flags for a block at the given height
+pub fn get_script_flags(params: &ChainParams, height: u64) -> u32 {
+
+    deployments(params)
+        .iter()
+        .filter(|deployment| height >= deployment.height)
+        .fold(script::VERIFY_NONE, |flags, deployment| flags | deployment.flags)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use script::*;
+
+    #[test]
+    fn test_script_flags() {
+
+        let main = ChainParams::new(Chain::Main);
+        assert_eq!(get_script_flags(&main, 0), VERIFY_NONE);
+        assert_eq!(get_script_flags(&main, 173_804), VERIFY_NONE);
+        assert_eq!(get_script_flags(&main, 173_805), VERIFY_P2SH);
+        assert_eq!(get_script_flags(&main, 400_000),
+            VERIFY_P2SH | VERIFY_DERSIG | VERIFY_CHECKLOCKTIMEVERIFY);
+        assert_eq!(get_script_flags(&main, 481_824),
+            VERIFY_P2SH | VERIFY_DERSIG | VERIFY_CHECKLOCKTIMEVERIFY | VERIFY_CHECKSEQUENCEVERIFY
+            | VERIFY_WITNESS | VERIFY_NULLDUMMY);
+
+        assert_eq!(get_script_flags(&ChainParams::new(Chain::Testnet), 514), VERIFY_P2SH);
+
+        let regtest = ChainParams::new(Chain::Regtest);
+        assert_eq!(get_script_flags(&regtest, 0), VERIFY_P2SH | VERIFY_WITNESS | VERIFY_NULLDUMMY);
+        assert_eq!(get_script_flags(&ChainParams::new(Chain::Signet), 1), get_script_flags(&main, 481_824));
+    }
+}
@@ -5,6 +5,8 @@
 
 extern crate libc;
 
+use script::ScriptError;
+
 #[link(name = "bitcoinconsensus")]
 extern {
 
@@ -24,8 +26,28 @@ extern {
     )
 
         -> i32;
+
+/* EXPORT_SYMBOL int bitcoinconsensus_verify_script_with_amount(const unsigned char *scriptPubKey, unsigned int scriptPubKeyLen, int64_t amount,
+                                    const unsigned char *txTo        , unsigned int txToLen,
+                                    unsigned int nIn, unsigned int flags, bitcoinconsensus_error* err);
+*/
+
+    pub fn bitcoinconsensus_verify_script_with_amount(
+        prevout_script:      *const u8,
+        prevout_script_size: u32,
+        amount:              i64,
+        transaction:         *const u8,
+        transaction_size:    u32,
+        tx_input_index:      u32,
+        flags:               u32,
+        err:                 *mut i32
+    )
+
+        -> i32;
 }
 
+// The script verification flags are defined in `script`
+
 // typedef enum bitcoinconsensus_error_t
 // {
 //     bitcoinconsensus_ERR_OK = 0,
@@ -36,8 +58,11 @@ extern {
 //     bitcoinconsensus_ERR_INVALID_FLAGS,
 // } bitcoinconsensus_error;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum VerifyScriptError {
+    /// The script was evaluated but did not succeed
+    Invalid,
+
     Index,
     SizeMismatch,
     Deserialize,
@@ -45,11 +70,20 @@ pub enum VerifyScriptError {
     InvalidFlags,
 }
 
+/// libbitcoinconsensus doesn't report why a script fails
+impl From<VerifyScriptError> for ScriptError {
+    fn from(_: VerifyScriptError) -> ScriptError {
+        ScriptError::Unknown
+    }
+}
+
 /// Verifies whether the given `input` of the transaction spends the given `output`
 /// using libbitcoin-consensus
+///
+/// This fails with AmountRequired if the flags include VERIFY_WITNESS
+pub fn verify_script(previous_tx_out: &[u8], transaction: &[u8], input: u32, flags: u32)
+    -> Result<(), VerifyScriptError> {
 
-pub fn verify_script(previous_tx_out: &[u8], transaction: &[u8], input: u32) -> Result<(), VerifyScriptError> {
-    let flags = 0;
     let mut err: i32 = 0;
     let result = unsafe { bitcoinconsensus_verify_script(
         previous_tx_out.as_ptr(),
@@ -61,16 +95,45 @@ pub fn verify_script(previous_tx_out: &[u8], transaction: &[u8], input: u32) ->
         &mut err
     ) };
 
+    to_result(result, err)
+}
+
+/// Verifies whether the given `input` of the transaction spends the given `output` with the given
+/// amount using libbitcoin-consensus
+///
+/// The amount is needed to verify witness programs
+pub fn verify_script_with_amount(previous_tx_out: &[u8], amount: i64, transaction: &[u8], input: u32, flags: u32)
+    -> Result<(), VerifyScriptError> {
+
+    let mut err: i32 = 0;
+    let result = unsafe { bitcoinconsensus_verify_script_with_amount(
+        previous_tx_out.as_ptr(),
+        previous_tx_out.len()  as u32,
+        amount,
+        transaction.as_ptr(),
+        transaction.len() as u32,
+        input as u32,
+        flags,
+        &mut err
+    ) };
+
+    to_result(result, err)
+}
+
+// Converts the result and error code of libbitcoin-consensus
+fn to_result(result: i32, err: i32) -> Result<(), VerifyScriptError> {
+
     if result == 1 {
         Ok(())
     }
     else {
         Err(match err {
-            0 => VerifyScriptError::Index,
-            1 => VerifyScriptError::SizeMismatch,
-            2  => VerifyScriptError::Deserialize,
-            3  => VerifyScriptError::AmountRequired,
-            4  => VerifyScriptError::InvalidFlags,
+            0 => VerifyScriptError::Invalid,
+            1 => VerifyScriptError::Index,
+            2 => VerifyScriptError::SizeMismatch,
+            3 => VerifyScriptError::Deserialize,
+            4 => VerifyScriptError::AmountRequired,
+            5 => VerifyScriptError::InvalidFlags,
             _ => unreachable!()
         })
     }
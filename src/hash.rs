@@ -39,6 +39,19 @@ impl Hash32Buf {
         Hash32Buf::from_slice(digest2.as_ref())
     }
 
+    /// Hashes the concatenation of the parts twice with SHA256 and returns an owned buffer;
+    /// This saves copying the parts into a single buffer
+    pub fn double_sha256_from_parts(parts: &[&[u8]]) -> Hash32Buf {
+        let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+        for part in parts {
+            ctx.update(part);
+        }
+        let digest1 = ctx.finish();
+        let digest2 = ring::digest::digest(&ring::digest::SHA256, digest1.as_ref());
+
+        Hash32Buf::from_slice(digest2.as_ref())
+    }
+
     /// Hashes the input twice with SHA256 and returns an owned buffer;
  /// Can be extracted as an Hash32 using as_ref()
     pub fn double_sha256_from_pair(first: Hash32, second: Hash32) -> Hash32Buf {
@@ -150,5 +163,15 @@ mod tests {
         assert_eq!(hash3, paired);
 
     }
+
+    #[test]
+    fn test_double_hash_parts() {
+
+        let input = from_hex("0102030405060708090a");
+
+        assert_eq!(
+            Hash32Buf::double_sha256_from_parts(&[&input[..3], &input[3..3], &input[3..]]),
+            Hash32Buf::double_sha256(&input));
+    }
 }
 
@@ -0,0 +1,592 @@
+//! Import of blocks from bitcoin-core style blk files
+//!
+//! A blocks directory holds the files `blk00000.dat`, `blk00001.dat`, ... in which each block is
+//! stored as a magic number, a length and the raw block. Blocks are stored in the order they
+//! were downloaded, which is not the order of the chain: a block often comes before its previous
+//! block. The blocks are added to the store by multiple worker threads that each have their
+//! own store, such that orphan blocks are connected as their previous block comes in. The
+//! workers verify and store transactions concurrently; connecting a block is serialized by the
+//! spend-index lock (see store/lock.rs), such that the resulting chain doesn't depend on the
+//! order in which the workers finish.
+//!
+//! The position up to which all blocks are added is saved as a checkpoint in the store
+//! directory; a next import of the same directory resumes from there. The files can therefore be
+//! imported again while bitcoin-core appends to them.
+
+use std::cmp;
+use std::fs;
+use std::fmt;
+use std::io;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::collections::BTreeMap;
+use std::thread;
+
+use byteorder::{ReadBytesExt, LittleEndian};
+
+use block::BlockError;
+use block_add::{add_block, BlockAddOk};
+use store::Store;
+
+
+/// The name of the checkpoint file in the store directory
+const CHECKPOINT_FILE: &'static str = "import-checkpoint";
+
+/// The number of blocks after which the checkpoint is saved
+const CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// The number of blocks per worker thread that are read ahead
+const QUEUE_SIZE: usize = 16;
+
+
+/// A position in the blk files
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BlkPos {
+    pub file_number:   u32,
+    pub file_position: u64
+}
+
+impl fmt::Display for BlkPos {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "blk{:05}.dat:{}", self.file_number, self.file_position)
+    }
+}
+
+/// The progress of an import
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImportStats {
+
+    /// Number of blocks read from the blk files
+    pub blocks:    u64,
+
+    /// Number of blocks connected to their previous block when added
+    pub connected: u64,
+
+    /// Number of blocks added before their previous block
+    pub orphans:   u64,
+
+    /// Number of blocks that were already stored
+    pub existing:  u64,
+
+    /// Number of blocks that failed verification
+    pub rejected:  u64,
+
+    /// The position up to which all blocks are added
+    pub checkpoint: BlkPos
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ImportError {
+
+    /// A blk file cannot be read, or the checkpoint cannot be read or written
+    Io(String),
+
+    /// The data at the given position doesn't start with the magic number
+    InvalidData(BlkPos),
+
+    /// The block at the given position is cut off, while the next blk file exists
+    Truncated(BlkPos),
+
+    /// The first blk file to import is not found
+    NoBlockFiles(PathBuf)
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImportError::Io(ref err)          => write!(fmt, "cannot import blocks: {}", err),
+            ImportError::InvalidData(pos)     => write!(fmt, "no block found at {}", pos),
+            ImportError::Truncated(pos)       => write!(fmt, "block at {} is cut off", pos),
+            ImportError::NoBlockFiles(ref p)  => write!(fmt, "cannot find {}", p.display())
+        }
+    }
+}
+
+
+/// Returns the path of the blk file with the given number
+pub fn blk_file_name(dir: &Path, file_number: u32) -> PathBuf {
+    dir.join(format!("blk{:05}.dat", file_number))
+}
+
+
+/// Reads a block from a blk file as used by bitcoin-core and various other implementations
+///
+/// Each block must be preceded by the given magic number of the chain. Returns None at the end
+/// of the data
+pub fn read_block(rdr: &mut io::Read, magic: u32) -> Result<Option<Vec<u8>>, io::Error> {
+
+    match read_block_with_size(rdr, magic) {
+        Ok(blk) => Ok(blk.map(|(block, _)| block)),
+
+        // a block that is cut off is treated as the end of the data
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e)
+    }
+}
+
+/// Reads a block with the given magic number, and returns it with the number of bytes read
+///
+/// Returns None at the end of the data, and an UnexpectedEof error if the block is cut off
+fn read_block_with_size(rdr: &mut io::Read, magic: u32) -> Result<Option<(Vec<u8>, u64)>, io::Error> {
+
+    let mut size = 0;
+    loop {
+        let magicnr = rdr.read_u32::<LittleEndian>();
+        size += 4;
+        match magicnr {
+            Err(_)     => return Ok(None), // assume EOF
+            Ok(m) => match m {
+
+                // TODO investigate; // Can't really find it in the cpp.
+                // this happens on bitcrust-1 at block  451327
+                // file blk000760, file pos 54391594
+                // first 8 zero-bytes before magicnr
+                // for now we skip them; the unused end of a file is zero-padded as well
+                0     => continue,
+
+                m if m == magic => break,
+                _     => return Err(io::Error::new(io::ErrorKind::InvalidData, "Incorrect magic number"))
+            }
+        }
+    }
+
+    let length = rdr.read_u32::<LittleEndian>()?;
+
+    let mut buffer = vec![0; length as usize];
+    rdr.read_exact(&mut buffer)?;
+
+    Ok(Some((buffer, size + 4 + length as u64)))
+}
+
+
+/// Streams the blocks of the blk files in a directory, from a given position
+///
+/// Each block is returned with the position after it
+pub struct BlkFiles {
+    dir:    PathBuf,
+    magic:  u32,
+    pos:    BlkPos,
+    reader: Option<io::BufReader<fs::File>>
+}
+
+impl BlkFiles {
+
+    pub fn new(dir: &Path, magic: u32, start: BlkPos) -> BlkFiles {
+        BlkFiles {
+            dir:    dir.to_path_buf(),
+            magic:  magic,
+            pos:    start,
+            reader: None
+        }
+    }
+
+    /// Opens the file at the current position; returns false if it doesn't exist
+    fn open(&mut self) -> Result<bool, ImportError> {
+
+        let mut file = match fs::File::open(blk_file_name(&self.dir, self.pos.file_number)) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(ImportError::Io(e.to_string()))
+        };
+
+        file.seek(io::SeekFrom::Start(self.pos.file_position))
+            .map_err(|e| ImportError::Io(e.to_string()))?;
+
+        self.reader = Some(io::BufReader::new(file));
+        Ok(true)
+    }
+}
+
+impl Iterator for BlkFiles {
+    type Item = Result<(BlkPos, Vec<u8>), ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        loop {
+            if self.reader.is_none() {
+                match self.open() {
+                    Ok(true)  => {},
+                    Ok(false) => return None,
+                    Err(e)    => return Some(Err(e))
+                }
+            }
+
+            let block = read_block_with_size(self.reader.as_mut().unwrap(), self.magic);
+
+            let truncated = match block {
+                Ok(Some((block, size))) => {
+                    self.pos.file_position += size;
+                    return Some(Ok((self.pos, block)));
+                },
+                Ok(None) => false,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => true,
+                Err(e) => {
+                    self.reader = None;
+                    return Some(Err(match e.kind() {
+                        io::ErrorKind::InvalidData => ImportError::InvalidData(self.pos),
+                        _                          => ImportError::Io(e.to_string())
+                    }));
+                }
+            };
+
+            // the next file is only started when it exists, such that a file that is still
+            // being written is continued on the next call
+            self.reader = None;
+            let next = BlkPos { file_number: self.pos.file_number + 1, file_position: 0 };
+            if fs::metadata(blk_file_name(&self.dir, next.file_number)).is_err() {
+                return None;
+            }
+
+            // bitcoin-core only writes to the last file, so a block that is cut off before
+            // another file is not going to be completed
+            if truncated {
+                return Some(Err(ImportError::Truncated(self.pos)));
+            }
+            self.pos = next;
+        }
+    }
+}
+
+
+/// Returns the position up to which the blocks are imported into the store
+pub fn get_checkpoint(store: &Store) -> Result<BlkPos, ImportError> {
+
+    let mut content = String::new();
+    match fs::File::open(store.config().root.join(CHECKPOINT_FILE)) {
+        Ok(mut file) => { file.read_to_string(&mut content).map_err(|e| ImportError::Io(e.to_string()))?; },
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BlkPos::default()),
+        Err(e) => return Err(ImportError::Io(e.to_string()))
+    };
+
+    let invalid = || ImportError::Io(format!("invalid checkpoint '{}'", content.trim()));
+
+    let mut parts = content.trim().split(',');
+    let file_number   = parts.next().and_then(|s| s.parse().ok());
+    let file_position = parts.next().and_then(|s| s.parse().ok());
+
+    match (file_number, file_position, parts.next()) {
+        (Some(file_number), Some(file_position), None) =>
+            Ok(BlkPos { file_number: file_number, file_position: file_position }),
+        _ => Err(invalid())
+    }
+}
+
+/// Saves the checkpoint; it is replaced at once such that a crash doesn't leave a partial file
+fn set_checkpoint(store: &Store, pos: BlkPos) -> Result<(), ImportError> {
+
+    let path = store.config().root.join(CHECKPOINT_FILE);
+    let tmp  = path.with_extension("tmp");
+
+    fs::File::create(&tmp)
+        .and_then(|mut file| file.write_all(format!("{},{}\n", pos.file_number, pos.file_position).as_bytes()))
+        .and_then(|_| fs::rename(&tmp, &path))
+        .map_err(|e| ImportError::Io(e.to_string()))
+}
+
+
+/// Keeps the statistics and the checkpoint of an import as blocks are added
+struct Tracker<F> {
+    stats:      ImportStats,
+    progress:   F,
+
+    // the positions of the blocks that are added while a block before them in the files isn't
+    // yet, by sequence number; the checkpoint moves past them once that block is added
+    done:       BTreeMap<u64, BlkPos>,
+    next_seq:   u64,
+    last_saved: u64
+}
+
+impl<F: FnMut(&ImportStats)> Tracker<F> {
+
+    /// Registers the result of adding the block with the given sequence number
+    fn added(&mut self, store: &Store, seq: u64, pos: BlkPos, result: Result<BlockAddOk, BlockError>)
+        -> Result<(), ImportError>
+    {
+        match result {
+            Ok(BlockAddOk::Connected)     => self.stats.connected += 1,
+            Ok(BlockAddOk::Orphan)        => self.stats.orphans   += 1,
+            Ok(BlockAddOk::AlreadyExists) => self.stats.existing  += 1,
+            Err(err) => {
+                warn!(store.logger, "import_blocks - block rejected";
+                    "pos" => format!("{}", pos), "error" => format!("{:?}", err));
+                self.stats.rejected += 1
+            }
+        }
+
+        self.done.insert(seq, pos);
+        while let Some(pos) = self.done.remove(&self.next_seq) {
+            self.stats.checkpoint = pos;
+            self.next_seq += 1;
+        }
+
+        if self.next_seq >= self.last_saved + CHECKPOINT_INTERVAL {
+            self.last_saved = self.next_seq;
+            set_checkpoint(store, self.stats.checkpoint)?;
+            (self.progress)(&self.stats);
+        }
+        Ok(())
+    }
+}
+
+
+/// Adds the blocks of the blk files in `dir` to the store, using the given number of threads
+///
+/// The import starts at the checkpoint of a previous import; `progress` is called each time the
+/// checkpoint is saved. On an error, the checkpoint is saved up to the last block added
+pub fn import_blocks<F>(store: &mut Store, dir: &Path, threads: usize, progress: F)
+    -> Result<ImportStats, ImportError>
+    where F: FnMut(&ImportStats)
+{
+    let start = get_checkpoint(store)?;
+
+    if fs::metadata(blk_file_name(dir, start.file_number)).is_err() {
+        return Err(ImportError::NoBlockFiles(blk_file_name(dir, start.file_number)));
+    }
+
+    info!(store.logger, "import_blocks - start"; "dir" => format!("{}", dir.display()), "from" => format!("{}", start));
+
+    let (block_tx, block_rx) = mpsc::sync_channel::<(u64, BlkPos, Vec<u8>)>(cmp::max(threads, 1) * QUEUE_SIZE);
+    let (result_tx, result_rx) = mpsc::channel::<(u64, BlkPos, Result<BlockAddOk, BlockError>)>();
+
+    let block_rx = Arc::new(Mutex::new(block_rx));
+
+    let workers: Vec<_> = (0..cmp::max(threads, 1)).map(|_| {
+
+        let block_rx  = block_rx.clone();
+        let result_tx = result_tx.clone();
+
+        let cfg            = store.config().clone();
+        let initial_sync   = store.initial_sync;
+        let verify_headers = store.verify_headers;
+
+        thread::spawn(move || {
+
+            let mut store = Store::new(&cfg);
+            store.initial_sync   = initial_sync;
+            store.verify_headers = verify_headers;
+
+            loop {
+                let next = block_rx.lock().unwrap().recv();
+                let (seq, pos, block) = match next {
+                    Ok(next) => next,
+                    Err(_)   => break
+                };
+
+                let result = add_block(&mut store, &block);
+                if result_tx.send((seq, pos, result)).is_err() {
+                    break;
+                }
+            }
+        })
+    }).collect();
+
+    drop(result_tx);
+
+    let mut tracker = Tracker {
+        stats:      ImportStats { checkpoint: start, ..ImportStats::default() },
+        progress:   progress,
+        done:       BTreeMap::new(),
+        next_seq:   0,
+        last_saved: 0
+    };
+
+    let mut result = Ok(());
+
+    for (seq, block) in BlkFiles::new(dir, store.params.magic, start).enumerate() {
+
+        let (pos, block) = match block {
+            Ok(block) => block,
+            Err(e)    => { result = Err(e); break; }
+        };
+
+        // this only fails if all workers have stopped
+        if block_tx.send((seq as u64, pos, block)).is_err() {
+            break;
+        }
+        tracker.stats.blocks += 1;
+
+        while let Ok((seq, pos, added)) = result_rx.try_recv() {
+            if let Err(e) = tracker.added(store, seq, pos, added) {
+                result = Err(e);
+            }
+        }
+        if result.is_err() {
+            break;
+        }
+    }
+
+    drop(block_tx);
+
+    for (seq, pos, added) in result_rx.iter() {
+        if let Err(e) = tracker.added(store, seq, pos, added) {
+            result = Err(e);
+        }
+    }
+
+    for worker in workers {
+        worker.join().expect("Import worker failed");
+    }
+
+    let saved = set_checkpoint(store, tracker.stats.checkpoint);
+    result?;
+    saved?;
+
+    let stats = tracker.stats;
+    (tracker.progress)(&stats);
+
+    info!(store.logger, "import_blocks - done";
+        "blocks"    => stats.blocks,
+        "connected" => stats.connected,
+        "orphans"   => stats.orphans,
+        "existing"  => stats.existing,
+        "rejected"  => stats.rejected,
+        "to"        => format!("{}", stats.checkpoint));
+
+    Ok(stats)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use hash::Hash32Buf;
+
+    // Writes the blocks as a blk file, with zero-padding before the second block and at the end
+    fn write_blk_file(dir: &Path, magic: u32, file_number: u32, blocks: &[&Vec<u8>]) -> u64 {
+
+        let mut content = Vec::new();
+        for (n, block) in blocks.iter().enumerate() {
+            if n == 1 {
+                content.extend_from_slice(&[0; 8]);
+            }
+            let len = block.len() as u32;
+            content.extend((0..4).map(|n| (magic >> (n * 8)) as u8));
+            content.extend((0..4).map(|n| (len >> (n * 8)) as u8));
+            content.extend_from_slice(block);
+        }
+        let size = content.len() as u64;
+        content.extend_from_slice(&[0; 64]);
+
+        fs::File::create(blk_file_name(dir, file_number))
+            .and_then(|mut file| file.write_all(&content))
+            .unwrap();
+        size
+    }
+
+    #[test]
+    fn test_import_blocks() {
+
+        let cfg = test_cfg!();
+        let mut store = Store::new(&cfg);
+        store.verify_headers = false;
+
+        let dir = PathBuf::from(format!("{}-blocks", cfg.root.display()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(import_blocks(&mut store, &dir, 2, |_| {}),
+            Err(ImportError::NoBlockFiles(blk_file_name(&dir, 0))));
+
+        tx_builder!(bld);
+
+        let magic = store.params.magic;
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0; tx!(bld; coinbase => a;10, b;10 ));
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => c;11 ), tx!(bld; a => d ));
+        let block3 = blk!(prev = block2; tx!(bld; coinbase => e;12 ), tx!(bld; b => f ));
+        let block4 = blk!(prev = block3; tx!(bld; coinbase => g;13 ), tx!(bld; d => h ));
+        let block5 = blk!(prev = block4; tx!(bld; coinbase => i;14 ), tx!(bld; c, f => j ));
+
+        // the blocks are stored out of order
+        write_blk_file(&dir, magic, 0, &[&block0, &block3, &block1, &block2]);
+        let size1 = write_blk_file(&dir, magic, 1, &[&block5, &block4]);
+
+        let mut saved = Vec::new();
+        let stats = import_blocks(&mut store, &dir, 3, |stats| saved.push(stats.checkpoint)).unwrap();
+
+        let end = BlkPos { file_number: 1, file_position: size1 };
+        assert_eq!(saved, vec![end]);
+        assert_eq!(get_checkpoint(&store), Ok(end));
+
+        // which blocks are orphans when added depends on the order in which the workers take
+        // them, but not the resulting chain
+        assert_eq!(stats.blocks, 6);
+        assert_eq!(stats.existing, 0);
+        assert_eq!(stats.rejected, 0);
+        assert_eq!(stats.connected + stats.orphans, 6);
+        assert_eq!(stats.checkpoint, end);
+
+        let tip = store.tips.get_most_work_tip().unwrap();
+        assert_eq!(tip.block_hash, Hash32Buf::double_sha256(&block5[0..80]));
+        assert_eq!(tip.height, 5);
+        assert_eq!(store.verify_integrity(), vec![]);
+
+        // the import resumes at the checkpoint
+        let stats = import_blocks(&mut store, &dir, 3, |_| {}).unwrap();
+        assert_eq!(stats, ImportStats { checkpoint: end, ..ImportStats::default() });
+
+        let block6 = blk!(prev = block5; tx!(bld; coinbase => m;16 ), tx!(bld; e => n ));
+
+        // spends a again
+        let rejected = blk!(prev = block6; tx!(bld; coinbase => k;15 ), tx!(bld; a => l ));
+        let size2 = write_blk_file(&dir, magic, 2, &[&block6, &rejected]);
+
+        // a single worker adds the blocks in order
+        let stats = import_blocks(&mut store, &dir, 1, |_| {}).unwrap();
+        assert_eq!(stats, ImportStats {
+            blocks:     2,
+            connected:  1,
+            rejected:   1,
+            checkpoint: BlkPos { file_number: 2, file_position: size2 },
+            ..ImportStats::default()
+        });
+
+        let tip = store.tips.get_most_work_tip().unwrap();
+        assert_eq!(tip.block_hash, Hash32Buf::double_sha256(&block6[0..80]));
+        assert_eq!(tip.height, 6);
+        assert_eq!(store.verify_integrity(), vec![]);
+
+        // a file with another magic number
+        fs::File::create(blk_file_name(&dir, 3))
+            .and_then(|mut file| file.write_all(&[1, 2, 3, 4, 0, 0, 0, 0]))
+            .unwrap();
+
+        assert_eq!(import_blocks(&mut store, &dir, 3, |_| {}),
+            Err(ImportError::InvalidData(BlkPos { file_number: 3, file_position: 0 })));
+        assert_eq!(get_checkpoint(&store), Ok(BlkPos { file_number: 2, file_position: size2 }));
+    }
+
+    #[test]
+    fn test_truncated_block() {
+
+        let cfg = test_cfg!();
+        let magic = 0xd9b4bef9;
+
+        let dir = PathBuf::from(format!("{}-blocks", cfg.root.display()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // a block of 4 bytes, followed by a block of 100 bytes that is cut off after 3
+        let mut content = Vec::new();
+        content.extend_from_slice(&[0xf9, 0xbe, 0xb4, 0xd9, 4, 0, 0, 0, 1, 2, 3, 4]);
+        content.extend_from_slice(&[0xf9, 0xbe, 0xb4, 0xd9, 100, 0, 0, 0, 1, 2, 3]);
+        fs::File::create(blk_file_name(&dir, 0))
+            .and_then(|mut file| file.write_all(&content))
+            .unwrap();
+
+        assert_eq!(read_block(&mut &content[12..], magic).unwrap(), None);
+
+        // the last file may still be written
+        let blocks: Vec<_> = BlkFiles::new(&dir, magic, BlkPos::default()).collect();
+        let after = BlkPos { file_number: 0, file_position: 12 };
+        assert_eq!(blocks, vec![Ok((after, vec![1, 2, 3, 4]))]);
+
+        // but not once the next file exists
+        fs::File::create(blk_file_name(&dir, 1)).unwrap();
+        let blocks: Vec<_> = BlkFiles::new(&dir, magic, BlkPos::default()).take(2).collect();
+        assert_eq!(blocks, vec![Ok((after, vec![1, 2, 3, 4])), Err(ImportError::Truncated(after))]);
+    }
+}
@@ -8,15 +8,24 @@
 
 
 extern crate memmap;
+extern crate libc;
 extern crate itertools;
 extern crate rand;
 extern crate ring;
 extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+extern crate byteorder;
 
 #[macro_use]
 pub extern crate slog ;
 extern crate slog_term ;
 
+#[cfg(test)]
+extern crate serde_json;
+
 
 /// Macro to create and empty a storage folder; used by tests
 macro_rules! test_cfg {
@@ -34,18 +43,30 @@ pub mod metrics;
 pub mod transaction;
 pub mod block;
 pub mod script;
+pub mod sighash;
 
+#[cfg(feature = "bitcoinconsensus")]
 mod ffi;
+mod crypto;
 mod buffer;
 mod util;
 mod store;
 mod config;
 mod merkle_tree;
+mod pow;
+pub mod deployments;
+pub mod chain_params;
 mod block_add;
+mod tx_add;
 mod api;
+pub mod import;
 
 
-pub use store::Store;
+pub use store::{Store, PruneProgress, Inconsistency, InconsistencyKind, Location};
+pub use store::{ReindexStats, ReindexError, DisconnectError};
+pub use config::{Config, ConfigError};
+pub use chain_params::ChainParams;
+pub use deployments::Chain;
 
 
 pub use api::*;
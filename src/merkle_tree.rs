@@ -1,16 +1,14 @@
 //! Merkle tree implementation
 //!
 
-// minimum number of hashes to use parallel hashing
-const PARALLEL_HASHING_THRESHOLD: usize = 60;
-
 use rayon::prelude::*;
 use hash::*;
 
 /// This halves the merkle tree leaves, taking it one level up
 ///
-/// Calls itself recursively until one is left
-fn shrink_merkle_tree(hashes: Vec<Hash32Buf>) -> Vec<Hash32Buf> {
+/// Calls itself recursively until one is left; a level of more than `parallel_threshold` hashes
+/// is hashed in parallel
+fn shrink_merkle_tree(hashes: Vec<Hash32Buf>, parallel_threshold: usize) -> Vec<Hash32Buf> {
 
     if hashes.len() == 1 {
         return hashes;
@@ -33,18 +31,20 @@ fn shrink_merkle_tree(hashes: Vec<Hash32Buf>) -> Vec<Hash32Buf> {
         )
     };
 
-    let result = if count > PARALLEL_HASHING_THRESHOLD
+    let result = if count > parallel_threshold
         { (0..count).into_par_iter().map(reduce).collect() }
     else
         { (0..count).into_iter().map(reduce).collect() };
 
-    shrink_merkle_tree(result)
+    shrink_merkle_tree(result, parallel_threshold)
 }
 
 /// Calculates the merkle root for the given set of hashes
-pub fn get_merkle_root(hashes: Vec<Hash32Buf>) -> Hash32Buf {
+///
+/// The threshold is the configured parallel_hashing_threshold
+pub fn get_merkle_root(hashes: Vec<Hash32Buf>, parallel_threshold: usize) -> Hash32Buf {
 
-    shrink_merkle_tree(hashes)[0]
+    shrink_merkle_tree(hashes, parallel_threshold)[0]
 }
 
 
@@ -74,7 +74,7 @@ mod tests {
         merkle.push(hash2);
 
 
-        let merkle_root = get_merkle_root(merkle);
+        let merkle_root = get_merkle_root(merkle, 10);
 
         assert_eq!(exp_merkle, merkle_root);
 
@@ -110,9 +110,9 @@ mod tests {
             merkle.push(txh);
         }
 
-        let merkle_root = get_merkle_root(merkle);
-
-        assert_eq!(exp_merkle, merkle_root);
+        // the same hashes in parallel and sequentially
+        assert_eq!(exp_merkle, get_merkle_root(merkle.clone(), 1));
+        assert_eq!(exp_merkle, get_merkle_root(merkle, usize::max_value()));
 
 
     }
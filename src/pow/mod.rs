@@ -0,0 +1,163 @@
+//! Proof of work calculations
+//!
+//! Difficulty targets are stored in the block header in compact form ("nbits");
+//! the work of a block is the expected number of hashes needed to find it
+
+mod u256;
+
+pub use self::u256::U256;
+
+use std::cmp;
+
+use hash::Hash32;
+
+/// Blocks per difficulty period
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: u64 = 2016;
+
+/// The intended duration of a difficulty period in seconds
+pub const TARGET_TIMESPAN: u64 = 14 * 24 * 60 * 60;
+
+/// Converts a header "nbits" representation to a U256 difficulty target
+///
+/// This doesn't check errors; the sign bit is ignored. Use is_valid_compact to check
+pub fn from_compact(compact_target: u32) -> U256 {
+
+    let size = compact_target as usize >> 24;
+    let word = U256::from((compact_target as u64) & 0x007f_ffff);
+
+    if size <= 3 {
+        word >> (8 * (3 - size))
+    }
+    else {
+        word << (8 * (size - 3))
+    }
+}
+
+/// Returns false if the compact target is negative or doesn't fit in 256 bits
+pub fn is_valid_compact(compact_target: u32) -> bool {
+
+    let size = compact_target >> 24;
+    let word = compact_target & 0x007f_ffff;
+
+    let negative = word != 0 && (compact_target & 0x0080_0000) != 0;
+    let overflow = word != 0 && (size > 34 ||
+        (word > 0xff && size > 33) ||
+        (word > 0xffff && size > 32));
+
+    !negative && !overflow
+}
+
+/// Converts a difficulty target to its compact "nbits" representation
+///
+/// This loses precision; only the highest 3 bytes are kept
+pub fn to_compact(target: U256) -> u32 {
+
+    let mut size = (target.bits() + 7) / 8;
+    let mut compact = if size <= 3 {
+        target.low_u64() << (8 * (3 - size))
+    }
+    else {
+        (target >> (8 * (size - 3))).low_u64()
+    };
+
+    // the 0x00800000 bit denotes the sign; if it is set divide the mantissa by 256
+    if compact & 0x0080_0000 != 0 {
+        compact >>= 8;
+        size += 1;
+    }
+
+    (compact as u32) | ((size as u32) << 24)
+}
+
+/// Converts a block hash to a number that can be compared against the target
+pub fn hash_to_u256(hash: Hash32) -> U256 {
+
+    let mut bytes = hash.0.clone();
+    bytes.reverse();
+    U256::from(&bytes[..])
+}
+
+/// Calculates the compact target of the first block of a new difficulty period
+///
+/// `last_bits` and `last_time` are of the last block of the previous period and `first_time` is of
+/// its first block. The adjustment is limited to a factor 4 and the target to `pow_limit`
+pub fn calculate_next_target(last_bits: u32, first_time: u32, last_time: u32, pow_limit: U256) -> u32 {
+
+    let actual_timespan = last_time as i64 - first_time as i64;
+    let actual_timespan = cmp::max(actual_timespan, TARGET_TIMESPAN as i64 / 4);
+    let actual_timespan = cmp::min(actual_timespan, TARGET_TIMESPAN as i64 * 4);
+
+    let target = from_compact(last_bits) * U256::from(actual_timespan as u64)
+        / U256::from(TARGET_TIMESPAN);
+
+    to_compact(if target > pow_limit { pow_limit } else { target })
+}
+
+/// Converts the difficulty target (= maximum hash to find) to work,
+/// which is its reciprocal.
+///
+/// We multiply by constant 2^256 to keep ensure the results are integral
+/// A zero target yields zero work
+pub fn difficulty_target_to_work(target: U256) -> U256 {
+
+    if target.is_zero() {
+        return U256::zero();
+    }
+
+    // We find:
+    // (2^256) / (target+1)
+    // = ((2^256 - (target+1))/ (target+1)) - 1
+    // = (!target / (target+1)) + 1
+    ((!target) / (target + U256::one())) + U256::one()
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use chain_params::ChainParams;
+    use deployments::Chain;
+
+    #[test]
+    fn test_work_genesis() {
+
+        let target = from_compact(0x1d00ffff);
+        assert_eq!(format!("{}", target),
+            "00000000ffff0000000000000000000000000000000000000000000000000000");
+
+        assert_eq!(difficulty_target_to_work(target), U256::from(0x1_0001_0001u64));
+        assert_eq!(difficulty_target_to_work(from_compact(0)), U256::zero());
+    }
+
+    #[test]
+    fn test_compact() {
+
+        assert_eq!(from_compact(0x01003456), U256::zero());
+        assert_eq!(from_compact(0x01123456), U256::from(0x12u64));
+        assert_eq!(to_compact(U256::from(0x12u64)), 0x01120000);
+        assert_eq!(from_compact(0x05009234), U256::from(0x9234_0000u64));
+        assert_eq!(to_compact(U256::from(0x9234_0000u64)), 0x05009234);
+
+        assert!( is_valid_compact(0x1d00ffff));
+        assert!( is_valid_compact(0x04800000)); // zero mantissa is not negative
+        assert!(!is_valid_compact(0x04923456));
+        assert!(!is_valid_compact(0xff123456));
+    }
+
+    #[test]
+    fn test_calculate_next_target() {
+
+        let pow_limit = ChainParams::new(Chain::Main).pow_limit;
+
+        // test vectors from bitcoin-core's pow_tests
+        assert_eq!(calculate_next_target(0x1d00ffff, 1261130161, 1262152739, pow_limit), 0x1d00d86a);
+
+        // limited by pow-limit
+        assert_eq!(calculate_next_target(0x1d00ffff, 1231006505, 1233061996, pow_limit), 0x1d00ffff);
+
+        // limited to a factor 4
+        assert_eq!(calculate_next_target(0x1c05a3f4, 1279008237, 1279297671, pow_limit), 0x1c0168fd);
+        assert_eq!(calculate_next_target(0x1c387f6f, 1263163443, 1269211443, pow_limit), 0x1d00e1fd);
+    }
+}
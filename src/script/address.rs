@@ -0,0 +1,419 @@
+//! Addresses of script-pubkeys
+//!
+//! P2PKH and P2SH outputs have a Base58Check address; witness programs have a Bech32 (version 0,
+//! BIP173) or Bech32m (version 1 and up, BIP350) address. P2PK outputs are shown with the
+//! P2PKH address of their key, like bitcoin-core does.
+
+
+use std::fmt;
+
+use ring;
+
+use crypto::ripemd160::ripemd160;
+use hash::Hash32Buf;
+use script::instruction::serialize_push;
+use script::opcode::*;
+use script::standard::{classify, ScriptType};
+
+
+/// The network determines the prefixes of addresses
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Network {
+    Main,
+    Test,
+    Signet,
+    Regtest
+}
+
+impl Network {
+
+    /// Returns the network with the given name: main, test, signet or regtest
+    pub fn from_name(name: &str) -> Option<Network> {
+        match name {
+            "main"    => Some(Network::Main),
+            "test"    => Some(Network::Test),
+            "signet"  => Some(Network::Signet),
+            "regtest" => Some(Network::Regtest),
+            _         => None
+        }
+    }
+
+    fn pubkey_hash_prefix(self) -> u8 {
+        if self == Network::Main { 0x00 } else { 0x6f }
+    }
+
+    fn script_hash_prefix(self) -> u8 {
+        if self == Network::Main { 0x05 } else { 0xc4 }
+    }
+
+    /// The human readable part of Bech32 addresses
+    fn hrp(self) -> &'static str {
+        match self {
+            Network::Main    => "bc",
+            Network::Test    => "tb",
+            Network::Signet  => "tb",
+            Network::Regtest => "bcrt"
+        }
+    }
+}
+
+
+#[derive(Debug, PartialEq)]
+pub enum AddressError {
+
+    /// The address contains characters outside of its encoding
+    InvalidCharacter,
+
+    /// The checksum doesn't match; this includes a Bech32 checksum used for a Bech32m address
+    /// and vice versa
+    InvalidChecksum,
+
+    /// The address is encoded correctly but is not a P2PKH, P2SH or witness program address
+    InvalidFormat,
+
+    /// The address is for another network
+    WrongNetwork
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            AddressError::InvalidCharacter => "Invalid character",
+            AddressError::InvalidChecksum  => "Invalid checksum",
+            AddressError::InvalidFormat    => "Invalid address format",
+            AddressError::WrongNetwork     => "Address is for another network"
+        };
+        write!(fmt, "{}", msg)
+    }
+}
+
+
+/// Returns the address of the script-pubkey, or None if it has no address
+pub fn to_address(script_pubkey: &[u8], network: Network) -> Option<String> {
+    match classify(script_pubkey) {
+        ScriptType::PubKey(pubkey)               => Some(base58check(network.pubkey_hash_prefix(), &hash160(pubkey))),
+        ScriptType::PubKeyHash(hash)             => Some(base58check(network.pubkey_hash_prefix(), hash)),
+        ScriptType::ScriptHash(hash)             => Some(base58check(network.script_hash_prefix(), hash)),
+        ScriptType::WitnessPubKeyHash(program)   |
+        ScriptType::WitnessScriptHash(program)   => Some(segwit_address(network.hrp(), 0, program)),
+        ScriptType::Taproot(program)             => Some(segwit_address(network.hrp(), 1, program)),
+        ScriptType::WitnessUnknown { version, program } => Some(segwit_address(network.hrp(), version, program)),
+        _                                        => None
+    }
+}
+
+/// Returns the script-pubkey that pays to the address
+pub fn from_address(address: &str, network: Network) -> Result<Vec<u8>, AddressError> {
+
+    // the prefixes are compared by byte position, which may not be at a character boundary
+    let starts_with = |prefix: &str| {
+        address.get(..prefix.len()).map_or(false, |start| start.eq_ignore_ascii_case(prefix))
+    };
+
+    let hrp = network.hrp();
+    if starts_with(&format!("{}1", hrp)) {
+        return from_segwit_address(address, hrp);
+    }
+
+    // regtest addresses of another network start with bc or tb
+    if starts_with("bc1") || starts_with("tb1") {
+        return Err(AddressError::WrongNetwork);
+    }
+
+    let payload = from_base58check(address)?;
+    if payload.len() != 21 {
+        return Err(AddressError::InvalidFormat);
+    }
+
+    let mut script = Vec::with_capacity(25);
+    if payload[0] == network.pubkey_hash_prefix() {
+        script.extend_from_slice(&[OP_DUP, OP_HASH160, 20]);
+        script.extend_from_slice(&payload[1..]);
+        script.extend_from_slice(&[OP_EQUALVERIFY, OP_CHECKSIG]);
+    }
+    else if payload[0] == network.script_hash_prefix() {
+        script.extend_from_slice(&[OP_HASH160, 20]);
+        script.extend_from_slice(&payload[1..]);
+        script.push(OP_EQUAL);
+    }
+    else if [0x00, 0x05, 0x6f, 0xc4].contains(&payload[0]) {
+        return Err(AddressError::WrongNetwork);
+    }
+    else {
+        return Err(AddressError::InvalidFormat);
+    }
+    Ok(script)
+}
+
+
+fn hash160(data: &[u8]) -> [u8; 20] {
+    ripemd160(ring::digest::digest(&ring::digest::SHA256, data).as_ref())
+}
+
+
+const BASE58_CHARS: &'static [u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Encodes the prefix and payload followed by the first 4 bytes of their double SHA256
+fn base58check(prefix: u8, payload: &[u8]) -> String {
+
+    let mut data = vec![prefix];
+    data.extend_from_slice(payload);
+    let checksum = Hash32Buf::double_sha256(&data);
+    data.extend_from_slice(&checksum.as_ref().0[..4]);
+
+    // repeated division of the big-endian number by 58; digits are little-endian
+    let mut digits: Vec<u8> = Vec::with_capacity(data.len() * 138 / 100 + 1);
+    for byte in data.iter() {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    // leading zero bytes are encoded as ones
+    let zeros = data.iter().take_while(|byte| **byte == 0).count();
+    let mut result: String = (0..zeros).map(|_| '1').collect();
+    result.extend(digits.iter().rev().map(|digit| BASE58_CHARS[*digit as usize] as char));
+    result
+}
+
+/// Decodes a Base58Check string and returns the prefix and payload
+fn from_base58check(address: &str) -> Result<Vec<u8>, AddressError> {
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(address.len());
+    for c in address.bytes() {
+        let mut carry = match BASE58_CHARS.iter().position(|b| *b == c) {
+            Some(value) => value as u32,
+            None        => return Err(AddressError::InvalidCharacter)
+        };
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let zeros = address.bytes().take_while(|c| *c == b'1').count();
+    let mut data = vec![0u8; zeros];
+    data.extend(bytes.iter().rev());
+
+    if data.len() < 4 {
+        return Err(AddressError::InvalidFormat);
+    }
+    let (payload, checksum) = data.split_at(data.len() - 4);
+    if &Hash32Buf::double_sha256(payload).as_ref().0[..4] != checksum {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok(payload.to_vec())
+}
+
+
+const BECH32_CHARS: &'static [u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// The checksum constant of Bech32 (version 0) and Bech32m (other versions)
+const BECH32_CONST:  u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a_57b2, 0x2650_8e6d, 0x1ea1_19fa, 0x3d42_33dd, 0x2a14_62b3];
+
+    let mut chk = 1u32;
+    for value in values {
+        let top = chk >> 25;
+        chk = (chk & 0x1ff_ffff) << 5 ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 != 0 {
+                chk ^= *generator;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut result: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    result.push(0);
+    result.extend(hrp.bytes().map(|c| c & 31));
+    result
+}
+
+/// Regroups the bits of `data` from groups of `from` bits to groups of `to` bits
+///
+/// Returns None if padding is not allowed and the remaining bits are not zero
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc  = 0u32;
+    let mut bits = 0u32;
+    let max = (1u32 << to) - 1;
+    let mut result = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+
+    for value in data {
+        acc = (acc << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            result.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            result.push(((acc << (to - bits)) & max) as u8);
+        }
+    }
+    else if bits >= from || ((acc << (to - bits)) & max) != 0 {
+        return None;
+    }
+    Some(result)
+}
+
+fn segwit_address(hrp: &str, version: u8, program: &[u8]) -> String {
+
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).expect("Padding is allowed"));
+
+    let constant = if version == 0 { BECH32_CONST } else { BECH32M_CONST };
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(&data);
+    values.extend_from_slice(&[0; 6]);
+    let polymod = bech32_polymod(&values) ^ constant;
+    data.extend((0..6).map(|i| ((polymod >> (5 * (5 - i))) & 31) as u8));
+
+    let mut result = format!("{}1", hrp);
+    result.extend(data.iter().map(|value| BECH32_CHARS[*value as usize] as char));
+    result
+}
+
+fn from_segwit_address(address: &str, hrp: &str) -> Result<Vec<u8>, AddressError> {
+
+    // addresses are either all lowercase or all uppercase
+    if address.len() > 90 || (address.to_lowercase() != address && address.to_uppercase() != address) {
+        return Err(AddressError::InvalidFormat);
+    }
+    let address = address.to_lowercase();
+
+    let mut data = Vec::with_capacity(address.len());
+    for c in address[hrp.len() + 1..].bytes() {
+        match BECH32_CHARS.iter().position(|b| *b == c) {
+            Some(value) => data.push(value as u8),
+            None        => return Err(AddressError::InvalidCharacter)
+        }
+    }
+    if data.len() < 7 {
+        return Err(AddressError::InvalidFormat);
+    }
+
+    let version = data[0];
+    let constant = if version == 0 { BECH32_CONST } else { BECH32M_CONST };
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(&data);
+    if bech32_polymod(&values) != constant {
+        return Err(AddressError::InvalidChecksum);
+    }
+
+    let program = match convert_bits(&data[1..data.len() - 6], 5, 8, false) {
+        Some(program) => program,
+        None          => return Err(AddressError::InvalidFormat)
+    };
+    if version > 16 || program.len() < 2 || program.len() > 40
+        || (version == 0 && program.len() != 20 && program.len() != 32) {
+        return Err(AddressError::InvalidFormat);
+    }
+
+    let mut script = vec![if version == 0 { OP_0 } else { OP_1 + version - 1 }];
+    script.extend(serialize_push(&program));
+    Ok(script)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::*;
+
+    fn address(script: &str, network: Network) -> Option<String> {
+        to_address(&from_hex(script), network)
+    }
+
+    #[test]
+    fn test_base58_addresses() {
+
+        // the output of the genesis block pays to a key
+        let genesis = "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f3\
+                       5504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac";
+        assert_eq!(address(genesis, Network::Main), Some("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa".to_string()));
+
+        let p2pkh = "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac";
+        assert_eq!(address(p2pkh, Network::Main), Some("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa".to_string()));
+        assert_eq!(from_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", Network::Main), Ok(from_hex(p2pkh)));
+
+        let p2sh = "a91489abcdefabbaabbaabbaabbaabbaabbaabbaabba87";
+        assert_eq!(address(p2sh, Network::Main), Some("3EExK1K1TF3v7zsFtQHt14XqexCwgmXM1y".to_string()));
+        assert_eq!(address(p2sh, Network::Test), Some("2N5oANkF34hZGKnVoZXukd1X6sJR7ayZPad".to_string()));
+        assert_eq!(from_address("2N5oANkF34hZGKnVoZXukd1X6sJR7ayZPad", Network::Regtest), Ok(from_hex(p2sh)));
+
+        assert_eq!(from_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Test),
+            Ok(from_hex("76a914243f1394f44554f4ce3fd68649c19adc483ce92488ac")));
+        assert_eq!(address("76a914000000000000000000000000000000000000000088ac", Network::Main),
+            Some("1111111111111111111114oLvT2".to_string()));
+
+        assert_eq!(from_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Main), Err(AddressError::WrongNetwork));
+        assert_eq!(from_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb", Network::Main), Err(AddressError::InvalidChecksum));
+        assert_eq!(from_address("1A1zP1eP5QGefi2DMPTfTL5SLmv7Divf0a", Network::Main), Err(AddressError::InvalidCharacter));
+    }
+
+    #[test]
+    fn test_segwit_addresses() {
+
+        // vectors of BIP173 and BIP350
+        let vectors = [
+            ("BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4", Network::Main,
+             "0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            ("tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7", Network::Test,
+             "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262"),
+            ("bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y", Network::Main,
+             "5128751e76e8199196d454941c45d1b3a323f1433bd6751e76e8199196d454941c45d1b3a323f1433bd6"),
+            ("BC1SW50QGDZ25J", Network::Main, "6002751e"),
+            ("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0", Network::Main,
+             "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+            ("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080", Network::Regtest,
+             "0014751e76e8199196d454941c45d1b3a323f1433bd6")
+        ];
+
+        for &(addr, network, script) in vectors.iter() {
+            assert_eq!(from_address(addr, network), Ok(from_hex(script)), "{}", addr);
+            assert_eq!(address(script, network), Some(addr.to_lowercase()));
+        }
+
+        // version 0 with a Bech32m checksum and version 1 with a Bech32 checksum
+        assert_eq!(from_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh", Network::Main),
+            Err(AddressError::InvalidChecksum));
+        assert_eq!(from_address("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd", Network::Main),
+            Err(AddressError::InvalidChecksum));
+
+        // mixed case and an invalid program length
+        assert_eq!(from_address("bc1QW508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", Network::Main),
+            Err(AddressError::InvalidFormat));
+        assert_eq!(from_address("bc1pw5dgrnzv", Network::Main), Err(AddressError::InvalidFormat));
+
+        assert_eq!(from_address("tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7", Network::Main),
+            Err(AddressError::WrongNetwork));
+
+        // a multi-byte character where a prefix ends
+        assert_eq!(from_address("bcé1qqq", Network::Main), Err(AddressError::InvalidCharacter));
+        assert_eq!(from_address("bcrté1qqq", Network::Regtest), Err(AddressError::InvalidCharacter));
+        assert_eq!(from_address("é", Network::Main), Err(AddressError::InvalidCharacter));
+
+        // outputs without an address
+        assert_eq!(address("6a0401020304", Network::Main), None);
+        assert_eq!(address("00150000000000000000000000000000000000000000", Network::Main), None);
+    }
+}
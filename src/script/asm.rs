@@ -0,0 +1,272 @@
+//! Script assembly
+//!
+//! `to_asm` renders a script in the notation of bitcoin-core's `ScriptToAsmStr`: pushes of up
+//! to 4 bytes are shown as numbers, larger pushes as hex and other opcodes by name.
+//!
+//! That notation loses information for some scripts. Instructions that it cannot represent
+//! exactly are rendered such that `from_asm` restores the same bytes:
+//!
+//! * Pushes that don't use the smallest opcode, or whose data is not a minimally encoded
+//!   number, are shown as raw bytes: `0x` followed by the opcode, any length and the data
+//! * Opcodes that are not defined are shown as `[UNKNOWN:0xba]`
+//! * A truncated push at the end of the script is shown as `[error:0x4c05aa]`
+//!
+//! `from_asm` also accepts the notation of bitcoin-core's test vectors: opcode names without
+//! the OP_ prefix, 'text' pushes and numbers of up to 5 bytes.
+
+
+use std::fmt;
+
+use script::instruction::{instructions, Instruction, serialize_push};
+use script::opcode::*;
+use script::stack::{decode_scriptnum, encode_scriptnum};
+use util::to_hex;
+
+
+#[derive(Debug, PartialEq)]
+pub enum AsmError {
+
+    /// The token is not a number, data, marker or opcode name
+    UnknownToken(String),
+
+    /// The number is outside the range of 5 byte script numbers
+    NumberOutOfRange(String),
+
+    /// The token has an odd number of hex digits or a non-hex digit
+    InvalidHex(String)
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsmError::UnknownToken(ref token)     => write!(fmt, "Unknown token {}", token),
+            AsmError::NumberOutOfRange(ref token) => write!(fmt, "Number out of range {}", token),
+            AsmError::InvalidHex(ref token)       => write!(fmt, "Invalid hex {}", token)
+        }
+    }
+}
+
+
+/// Renders the script as assembly
+pub fn to_asm(script: &[u8]) -> String {
+
+    let mut tokens = Vec::new();
+    let mut iter = instructions(script);
+    loop {
+        let start = iter.position();
+        match iter.next() {
+            Some(Ok(op))  => tokens.push(instruction_to_asm(&op, &script[start..iter.position()])),
+            Some(Err(_))  => tokens.push(format!("[error:0x{}]", to_hex(&script[start..]))),
+            None          => break
+        }
+    }
+    tokens.join(" ")
+}
+
+/// Assembles the script from its tokens, which are separated by whitespace
+pub fn from_asm(asm: &str) -> Result<Vec<u8>, AsmError> {
+
+    let mut result = Vec::new();
+    for token in asm.split_whitespace() {
+        result.extend(assemble_token(token)?);
+    }
+    Ok(result)
+}
+
+
+/// Renders a single instruction; `raw` are the bytes of the instruction
+fn instruction_to_asm(op: &Instruction, raw: &[u8]) -> String {
+
+    let token = if op.is_push() {
+        if op.data.len() <= 4 {
+            // like bitcoin-core, the data is decoded as number even if it is not minimal
+            decode_scriptnum(op.data, 4, false)
+                .expect("At most 4 bytes can always be decoded")
+                .to_string()
+        }
+        else {
+            to_hex(op.data)
+        }
+    }
+    else if op.opcode == OP_1NEGATE || (op.opcode >= OP_1 && op.opcode <= OP_16) {
+        (op.opcode as i64 - (OP_1 as i64 - 1)).to_string()
+    }
+    else {
+        match opcode_name(op.opcode) {
+            Some(name) => name.to_string(),
+            None       => return format!("[UNKNOWN:0x{:02x}]", op.opcode)
+        }
+    };
+
+    // fall back to the raw bytes if the token would assemble into something else
+    match assemble_token(&token) {
+        Ok(ref bytes) if &bytes[..] == raw => token,
+        _                                  => format!("0x{}", to_hex(raw))
+    }
+}
+
+/// Returns the name of opcodes that are not pushes or numbers
+fn opcode_name(opcode: u8) -> Option<&'static str> {
+    let name = OPCODES[opcode as usize].name;
+    if (opcode > OP_16 || opcode == OP_RESERVED) && !name.is_empty() {
+        Some(name)
+    }
+    else {
+        None
+    }
+}
+
+/// Returns the opcode for a name with or without OP_ prefix
+fn opcode_by_name(token: &str) -> Option<u8> {
+    let name = if token.starts_with("OP_") { &token[3..] } else { token };
+
+    // the names these opcodes had before BIP65 and BIP112
+    match name {
+        "NOP2" => return Some(OP_CHECKLOCKTIMEVERIFY),
+        "NOP3" => return Some(OP_CHECKSEQUENCEVERIFY),
+        _      => {}
+    }
+
+    (0..256)
+        .map(|opcode| opcode as u8)
+        .find(|opcode| opcode_name(*opcode).map_or(false, |op_name| &op_name[3..] == name))
+}
+
+fn is_number(token: &str) -> bool {
+    let digits = if token.starts_with('-') { &token[1..] } else { token };
+    !digits.is_empty() && digits.bytes().all(|b| b >= b'0' && b <= b'9')
+}
+
+fn parse_hex(hex: &str, token: &str) -> Result<Vec<u8>, AsmError> {
+    if hex.is_empty() || hex.len() % 2 != 0 || !hex.bytes().all(|b| (b as char).is_digit(16)) {
+        return Err(AsmError::InvalidHex(token.to_string()));
+    }
+    Ok((0..hex.len() / 2)
+        .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap())
+        .collect())
+}
+
+/// Returns the instruction that pushes `n`; this uses OP_0, OP_1NEGATE and OP_1 to OP_16
+/// where possible
+fn push_number(n: i64) -> Vec<u8> {
+    if n == 0 {
+        vec![OP_0]
+    }
+    else if n == -1 || (n >= 1 && n <= 16) {
+        vec![(OP_1 as i64 + n - 1) as u8]
+    }
+    else {
+        serialize_push(&encode_scriptnum(n))
+    }
+}
+
+fn assemble_token(token: &str) -> Result<Vec<u8>, AsmError> {
+
+    if is_number(token) {
+        match token.parse::<i64>() {
+            Ok(n) if n >= -0xffff_ffff && n <= 0xffff_ffff => Ok(push_number(n)),
+            _ => Err(AsmError::NumberOutOfRange(token.to_string()))
+        }
+    }
+    else if token.starts_with("0x") {
+        parse_hex(&token[2..], token)
+    }
+    else if token.len() >= 2 && token.starts_with('\'') && token.ends_with('\'') {
+        Ok(serialize_push(token[1..token.len() - 1].as_bytes()))
+    }
+    else if token.starts_with("[UNKNOWN:0x") && token.ends_with(']') {
+        parse_hex(&token[11..token.len() - 1], token)
+    }
+    else if token.starts_with("[error:0x") && token.ends_with(']') {
+        parse_hex(&token[9..token.len() - 1], token)
+    }
+    else if let Some(opcode) = opcode_by_name(token) {
+        Ok(vec![opcode])
+    }
+    else if let Ok(data) = parse_hex(token, token) {
+        Ok(serialize_push(&data))
+    }
+    else {
+        Err(AsmError::UnknownToken(token.to_string()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate rand;
+
+    use super::*;
+    use self::rand::Rng;
+    use util::*;
+
+    #[test]
+    fn test_to_asm() {
+
+        // P2PKH
+        let script = from_hex("76a91489abcdefabbaabbaabbaabbaabbaabbaabbaabba88ac");
+        assert_eq!(to_asm(&script),
+            "OP_DUP OP_HASH160 89abcdefabbaabbaabbaabbaabbaabbaabbaabba OP_EQUALVERIFY OP_CHECKSIG");
+
+        // small pushes and numbers
+        assert_eq!(to_asm(&[OP_0, OP_1NEGATE, OP_1, OP_16, 0x01, 0x11, 0x02, 0xe8, 0x03, 0x01, 0x81]),
+            "0 -1 1 16 17 1000 0x0181");
+        assert_eq!(to_asm(&[0x04, 0xff, 0xff, 0xff, 0xff]), "-2147483647");
+
+        // hex that would be read as a number
+        assert_eq!(to_asm(&[0x05, 0x01, 0x02, 0x03, 0x04, 0x05]), "0x050102030405");
+
+        // non-minimal pushes are shown raw
+        assert_eq!(to_asm(&[OP_PUSHDATA1, 0x01, 0x11, 0x01, 0x05, 0x02, 0x11, 0x00]),
+            "0x4c0111 0x0105 0x021100");
+
+        // unknown opcodes and truncated pushes
+        assert_eq!(to_asm(&[OP_NOP, 0xba, 0xff, OP_PUSHDATA1, 0x05, 0xaa]),
+            "OP_NOP [UNKNOWN:0xba] [UNKNOWN:0xff] [error:0x4c05aa]");
+
+        assert_eq!(to_asm(&[OP_RESERVED, OP_CHECKLOCKTIMEVERIFY, OP_NOP10]),
+            "OP_RESERVED OP_CHECKLOCKTIMEVERIFY OP_NOP10");
+        assert_eq!(to_asm(&[]), "");
+    }
+
+    #[test]
+    fn test_from_asm() {
+        assert_eq!(from_asm("0 -1 16 17 DUP OP_DUP 'ab' 0x4c01 0x07"),
+            Ok(vec![OP_0, OP_1NEGATE, OP_16, 0x01, 17, OP_DUP, OP_DUP, 0x02, b'a', b'b', 0x4c, 0x01, 0x07]));
+
+        assert_eq!(from_asm("NOP2 OP_NOP3 4294967295"),
+            Ok(vec![OP_CHECKLOCKTIMEVERIFY, OP_CHECKSEQUENCEVERIFY, 0x05, 0xff, 0xff, 0xff, 0xff, 0x00]));
+        assert_eq!(from_asm("  0a0b0c0d0e \n [UNKNOWN:0xba] "),
+            Ok(vec![0x05, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0xba]));
+
+        assert_eq!(from_asm("4294967296"), Err(AsmError::NumberOutOfRange("4294967296".to_string())));
+        assert_eq!(from_asm("0x123"), Err(AsmError::InvalidHex("0x123".to_string())));
+        assert_eq!(from_asm("OP_FOO"), Err(AsmError::UnknownToken("OP_FOO".to_string())));
+
+        // pushes and numbers are only written as the numbers they are
+        assert_eq!(from_asm("OP_1"), Err(AsmError::UnknownToken("OP_1".to_string())));
+    }
+
+    #[test]
+    fn test_round_trip() {
+
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..2000 {
+
+            let len = rng.gen_range(0, 40);
+            let mut script: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+
+            // make sure pushes of various sizes and opcodes around them are well represented
+            if rng.gen() {
+                let data_len = rng.gen_range(0, 300);
+                let mut push = serialize_push(&(0..data_len).map(|_| rng.gen()).collect::<Vec<u8>>());
+                push.extend(script);
+                script = push;
+            }
+
+            let asm = to_asm(&script);
+            assert_eq!(from_asm(&asm), Ok(script.clone()), "Failed round-trip of {}", asm);
+        }
+    }
+}
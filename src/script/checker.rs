@@ -0,0 +1,192 @@
+//! Verification of signatures and locktimes against a transaction
+//!
+//! The interpreter uses a `SignatureChecker` for CHECKSIG, CHECKMULTISIG, CHECKLOCKTIMEVERIFY
+//! and CHECKSEQUENCEVERIFY; the `TransactionSignatureChecker` implements these for an input
+//! of a transaction
+
+
+use crypto::secp256k1;
+use script::*;
+use script::opcode_locktime::SEQUENCE_LOCKTIME_DISABLE_FLAG;
+use sighash::SighashCache;
+
+
+/// Locktimes below this are block heights; others are timestamps
+const LOCKTIME_THRESHOLD: i64 = 500_000_000;
+
+/// If set, the relative locktime of CHECKSEQUENCEVERIFY is in units of 512 seconds
+const SEQUENCE_LOCKTIME_TYPE_FLAG: i64 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK:      i64 = 0x0000_ffff;
+
+
+pub trait SignatureChecker {
+
+    /// Returns true if `sig` (including the hashtype byte) is a valid signature for `pubkey`
+    /// of the transaction with the given script-code
+    fn check_sig(&self, sig: &[u8], pubkey: &[u8], script_code: &[u8], sig_version: SigVersion) -> bool;
+
+    /// Returns true if the transaction satisfies the absolute locktime (BIP65)
+    fn check_lock_time(&self, lock_time: i64) -> bool;
+
+    /// Returns true if the input satisfies the relative locktime (BIP112)
+    fn check_sequence(&self, sequence: i64) -> bool;
+}
+
+
+/// Checker for scripts that are executed without a transaction; all checks fail
+pub struct NoChecker;
+
+pub static NO_CHECKER: NoChecker = NoChecker;
+
+impl SignatureChecker for NoChecker {
+    fn check_sig(&self, _: &[u8], _: &[u8], _: &[u8], _: SigVersion) -> bool {
+        false
+    }
+
+    fn check_lock_time(&self, _: i64) -> bool {
+        false
+    }
+
+    fn check_sequence(&self, _: i64) -> bool {
+        false
+    }
+}
+
+
+/// Checks signatures and locktimes for the given input of a transaction
+///
+/// The signature hashes are computed by a `SighashCache` that is shared by all inputs
+pub struct TransactionSignatureChecker<'a> {
+    cache:  &'a SighashCache<'a>,
+    input:  usize,
+
+    /// The value of the output that is spent; only used for witness programs
+    amount: i64
+}
+
+impl<'a> TransactionSignatureChecker<'a> {
+
+    pub fn new(cache: &'a SighashCache<'a>, input: usize, amount: i64) -> TransactionSignatureChecker<'a> {
+        TransactionSignatureChecker {
+            cache:  cache,
+            input:  input,
+            amount: amount
+        }
+    }
+}
+
+impl<'a> SignatureChecker for TransactionSignatureChecker<'a> {
+
+    fn check_sig(&self, sig: &[u8], pubkey: &[u8], script_code: &[u8], sig_version: SigVersion) -> bool {
+
+        let (hashtype, sig) = match sig.split_last() {
+            Some((hashtype, sig)) => (*hashtype, sig),
+            None                  => return false
+        };
+
+        let pubkey = match secp256k1::PublicKey::parse(pubkey) {
+            Some(pubkey) => pubkey,
+            None         => return false
+        };
+
+        // signatures that were valid before BIP66 may have a high S
+        let mut sig = match secp256k1::Signature::parse_der_lax(sig) {
+            Some(sig) => sig,
+            None      => return false
+        };
+        sig.normalize_s();
+
+        let hash = self.cache.signature_hash(self.input, script_code, self.amount, hashtype as u32, sig_version);
+        sig.verify(hash.as_ref().0, &pubkey)
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+
+        let tx_lock_time = self.cache.transaction().lock_time as i64;
+
+        // the locktime must be of the same type as that of the transaction
+        if (tx_lock_time < LOCKTIME_THRESHOLD) != (lock_time < LOCKTIME_THRESHOLD) {
+            return false;
+        }
+        if lock_time > tx_lock_time {
+            return false;
+        }
+
+        // the locktime of the transaction is ignored if the input is final
+        self.cache.transaction().txs_in[self.input].sequence != 0xffff_ffff
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+
+        let tx_sequence = self.cache.transaction().txs_in[self.input].sequence as i64;
+
+        // relative locktimes are only enforced from version 2
+        if (self.cache.transaction().version as u32) < 2 {
+            return false;
+        }
+        if tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return false;
+        }
+
+        let mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+        let tx_sequence = tx_sequence & mask;
+        let sequence    = sequence & mask;
+
+        if (tx_sequence < SEQUENCE_LOCKTIME_TYPE_FLAG) != (sequence < SEQUENCE_LOCKTIME_TYPE_FLAG) {
+            return false;
+        }
+
+        sequence <= tx_sequence
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buffer::{Buffer, Parse};
+    use sighash::*;
+    use transaction::Transaction;
+    use util::*;
+
+    #[test]
+    fn test_check_sig() {
+
+        // Native P2WPKH example of BIP143
+        let raw = from_hex("01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00\
+                      000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cd\
+                      d30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeff\
+                      ffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000f\
+                      fffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac90\
+                      93510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402\
+                      203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c\
+                      4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368d\
+                      a1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000");
+        let tx = Transaction::parse(&mut Buffer::new(&raw)).unwrap();
+        let cache = SighashCache::new(&tx);
+
+        let checker = TransactionSignatureChecker::new(&cache, 1, 600_000_000);
+        let script_code = from_hex("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac");
+        assert_eq!(to_hex(cache.signature_hash(1, &script_code, 600_000_000, SIGHASH_ALL as u32, SigVersion::WitnessV0).as_ref().0),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670");
+
+        let witness = &tx.witnesses[1].items;
+        assert!(checker.check_sig(witness[0], witness[1], &script_code, SigVersion::WitnessV0));
+        assert!(!checker.check_sig(witness[0], witness[1], &script_code, SigVersion::Base));
+
+        // the first input spends a P2PK output
+        let checker = TransactionSignatureChecker::new(&cache, 0, 625_000_000);
+        let pubkey = from_hex("03c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432");
+        let script_code = from_hex("2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac");
+        assert_eq!(to_hex(cache.signature_hash(0, &script_code, 0, SIGHASH_ALL as u32, SigVersion::Base).as_ref().0),
+            "63cec688ee06a91e913875356dd4dea2f8e0f2a2659885372da2a37e32c7532e");
+
+        let sig = &tx.txs_in[0].script[1..];
+        assert!(checker.check_sig(sig, &pubkey, &script_code, SigVersion::Base));
+        assert!(!checker.check_sig(sig, &pubkey, &script_code[1..], SigVersion::Base));
+
+        // SIGHASH_SINGLE without a matching output
+        assert_eq!(to_hex(cache.signature_hash(2, &script_code, 0, SIGHASH_SINGLE as u32, SigVersion::Base).as_ref().0),
+            "0100000000000000000000000000000000000000000000000000000000000000");
+    }
+}
@@ -1,38 +1,136 @@
-
 /// Context provides an execution environment for scripts
-/// Currently unused
+///
+/// The opcodes are executed through the `OPCODES` table; each opcode has an `execute` function
+/// that is called in an executed branch and a `skip` function that is called in a branch that
+/// is not executed
 
 use super::stack;
 use super::ScriptError;
+use super::SigVersion;
+use super::checker::{SignatureChecker, NO_CHECKER};
 use std::fmt;
 use std::io;
 use std::io::Write;
 
-use script::opcode::OPCODES;
+use script::*;
+use script::opcode::{OPCODES, OP_16};
 
 pub struct Context<'a> {
     pub stack:     stack::Stack,
     pub alt_stack: stack::Stack,
-    
+
     pub script1:   &'a[u8],
-    pub ip:        usize   
+    pub ip:        usize,
+
+    /// Verification flags
+    pub flags:       u32,
+    pub sig_version: SigVersion,
+    pub checker:     &'a SignatureChecker,
+
+    /// One item per nested OP_IF; false if that branch is not executed
+    pub exec_stack:  Vec<bool>,
+
+    /// Number of non-push operations executed so far
+    pub op_count:    usize,
+
+    /// Offset in the script after the last OP_CODESEPARATOR
+    pub code_separator: usize
 }
 
 
 impl<'a> Context<'a> {
 
     pub fn new(script:  &'a[u8]) -> Context<'a>
+    {
+        Context::with_checker(script, VERIFY_NONE, SigVersion::Base, &NO_CHECKER)
+    }
+
+    /// Creates a context to execute the script with the given flags
+    ///
+    /// The checker is used for the signature and locktime opcodes
+    pub fn with_checker(script:      &'a[u8],
+                        flags:       u32,
+                        sig_version: SigVersion,
+                        checker:     &'a SignatureChecker) -> Context<'a>
     {
         Context {
-            stack:     stack::Stack::new(),
-            alt_stack: stack::Stack::new(),
-            script1:   script,
-            ip:        0
+            stack:          stack::Stack::new(),
+            alt_stack:      stack::Stack::new(),
+            script1:        script,
+            ip:             0,
+            flags:          flags,
+            sig_version:    sig_version,
+            checker:        checker,
+            exec_stack:     Vec::new(),
+            op_count:       0,
+            code_separator: 0
         }
     }
-    
+
+    /// Executes the script on the current stack
+    ///
+    /// On success, the resulting stack is left in `stack`; whether it represents a
+    /// successful verification is up to the caller
     pub fn run(&mut self) -> Result<(), ScriptError> {
-        unimplemented!()
+
+        if self.script1.len() > MAX_SCRIPT_SIZE {
+            return Err(ScriptError::ScriptSize);
+        }
+
+        self.ip = 0;
+        while self.ip < self.script1.len() {
+
+            let opcode = self.script1[self.ip];
+
+            if opcode > OP_16 {
+                self.op_count += 1;
+                if self.op_count > MAX_OPS_PER_SCRIPT {
+                    return Err(ScriptError::OpCount);
+                }
+            }
+
+            let op = &OPCODES[opcode as usize];
+            if self.is_executing() {
+                (op.execute)(self)?;
+            }
+            else {
+                (op.skip)(self)?;
+            }
+
+            if self.stack.len() + self.alt_stack.len() > MAX_STACK_SIZE {
+                return Err(ScriptError::StackSize);
+            }
+
+            self.ip += 1;
+        }
+
+        if !self.exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the current branch is executed
+    pub fn is_executing(&self) -> bool {
+        self.exec_stack.iter().all(|exec| *exec)
+    }
+
+    /// Returns true if the given verification flag is set
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Pops a number of at most 4 bytes; this must be minimally encoded if MINIMALDATA is set
+    pub fn pop_scriptnum(&mut self) -> Result<i64, ScriptError> {
+        let require_minimal = self.has_flag(VERIFY_MINIMALDATA);
+        self.stack.pop_scriptnum_ext(4, require_minimal)
+    }
+
+    /// Returns the part of the script that is signed; this starts after the last
+    /// executed OP_CODESEPARATOR
+    pub fn script_code(&self) -> &'a[u8] {
+        &self.script1[self.code_separator..]
     }
 
 
@@ -40,23 +138,23 @@ impl<'a> Context<'a> {
     /// ip (instruction pointer), and increases the ip to the last
     /// byte returned
     ///
-    /// Can return a UnexpectedEndOfScript if not enough bytes are available  
-    pub fn next_bytes(&mut self, count: u64) -> Result<&[u8], ScriptError> { 
-        if self.script1.len() < self.ip + count  as usize + 1 {
+    /// Can return a UnexpectedEndOfScript if not enough bytes are available
+    pub fn next_bytes(&mut self, count: u64) -> Result<&'a[u8], ScriptError> {
+        if (self.script1.len() as u64) < self.ip as u64 + count + 1 {
             return Err(ScriptError::UnexpectedEndOfScript);
         }
 
         let old_ip = self.ip;
         self.ip += count as usize;
         Ok(&self.script1[old_ip + 1 .. self.ip + 1])
-        
+
     }
 
-    pub fn next_uint(&mut self, count: u64) -> Result<u64, ScriptError> { 
+    pub fn next_uint(&mut self, count: u64) -> Result<u64, ScriptError> {
         let bytes = try!(self.next_bytes(count));
 
         // parse as little endian
-        Ok(bytes.iter().enumerate().fold(0, 
+        Ok(bytes.iter().enumerate().fold(0,
             |sum, (n, byte)| sum + ((*byte as u64) << (n * 8))
         ))
     }
@@ -65,16 +163,16 @@ impl<'a> Context<'a> {
 
 
 impl<'a> fmt::Debug for Context<'a> {
-    
+
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        
+
         // create mutable copy
         let mut copied_context = Context::new(self.script1);
 
         // target to write the script to
         let buf: Vec<u8> = Vec::new();
         let mut cursor = io::Cursor::new(buf);
-        
+
 
         while copied_context.ip < copied_context.script1.len() {
             let opcode = copied_context.script1[copied_context.ip] as usize;
@@ -88,8 +186,8 @@ impl<'a> fmt::Debug for Context<'a> {
 
         // write to output
         // we know we're not writing invalid utf so we can unwrap
-        write!(fmt, "{}", &String::from_utf8(cursor.into_inner()).unwrap())    
-        
+        write!(fmt, "{}", &String::from_utf8(cursor.into_inner()).unwrap())
+
     }
 }
 
@@ -98,16 +196,61 @@ impl<'a> fmt::Debug for Context<'a> {
 mod tests {
     #![cfg(test)]
     use ::script::context::Context;
+    use ::script::ScriptError;
+    use ::script::opcode::*;
+
 
-    
     #[test]
     fn test_op_false()
     {
         let script = vec![0x00];
-        let _ = Context::new(&script);
-        
-        
-        
-        
+        let mut ctx = Context::new(&script);
+        ctx.run().unwrap();
+        assert_eq!(ctx.stack.len(), 1);
+        assert_eq!(ctx.stack.top(0).unwrap(), &[]);
+    }
+
+    #[test]
+    fn test_run()
+    {
+        // 2 3 ADD 5 EQUAL
+        let script = vec![OP_2, OP_3, OP_ADD, OP_5, OP_EQUAL];
+        let mut ctx = Context::new(&script);
+        ctx.run().unwrap();
+        assert_eq!(ctx.stack.top(0).unwrap(), &[1]);
+
+        // IF 2 ELSE 3 ENDIF; the branch taken depends on the item on the stack
+        let script = vec![OP_IF, OP_2, OP_ELSE, OP_3, OP_ENDIF];
+        let mut ctx = Context::new(&script);
+        ctx.stack.push_bool(false).unwrap();
+        ctx.run().unwrap();
+        assert_eq!(ctx.stack.top(0).unwrap(), &[3]);
+
+        let script = vec![OP_1, OP_IF, OP_2];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::UnbalancedConditional));
+
+        // disabled opcodes fail even if not executed
+        let script = vec![OP_0, OP_IF, OP_CAT, OP_ENDIF];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::DisabledOpcode));
+
+        // as do truncated pushes
+        let script = vec![OP_0, OP_IF, OP_PUSHDATA1, 0x05, 0x00, OP_ENDIF];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::UnexpectedEndOfScript));
+
+        // but other invalid opcodes only fail if they are executed
+        let script = vec![OP_0, OP_IF, 0xff, OP_ENDIF];
+        assert_eq!(Context::new(&script).run(), Ok(()));
+        let script = vec![OP_1, OP_IF, 0xff, OP_ENDIF];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::InvalidOpcode));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_op_count()
+    {
+        let script = vec![OP_NOP; 201];
+        assert_eq!(Context::new(&script).run(), Ok(()));
+
+        let script = vec![OP_NOP; 202];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::OpCount));
+    }
+}
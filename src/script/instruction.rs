@@ -0,0 +1,168 @@
+//! Iterating over the instructions of a script
+//!
+//! This is used where a script needs to be inspected without executing it
+
+
+use script::ScriptError;
+use script::opcode::*;
+
+
+/// A single opcode with the data it pushes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction<'a> {
+    pub opcode:   u8,
+
+    /// The data pushed by OP_0 to OP_PUSHDATA4; empty for other opcodes
+    pub data:     &'a[u8],
+
+    /// The offset of the opcode in the script
+    pub position: usize
+}
+
+impl<'a> Instruction<'a> {
+
+    /// Returns true if this is one of the opcodes that push data from the script
+    pub fn is_push(&self) -> bool {
+        self.opcode <= OP_PUSHDATA4
+    }
+}
+
+
+/// Iterator over the instructions of a script
+///
+/// A truncated push yields an UnexpectedEndOfScript error and ends the iteration
+pub struct Instructions<'a> {
+    script: &'a[u8],
+    pos:    usize
+}
+
+/// Returns an iterator over the instructions of the script
+pub fn instructions(script: &[u8]) -> Instructions {
+    Instructions {
+        script: script,
+        pos:    0
+    }
+}
+
+impl<'a> Instructions<'a> {
+
+    /// Returns the offset of the next instruction
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn read_uint(&mut self, size: usize) -> Option<usize> {
+        if self.script.len() < self.pos + size {
+            return None;
+        }
+        let value = self.script[self.pos..self.pos + size].iter().enumerate()
+            .fold(0usize, |sum, (n, byte)| sum | ((*byte as usize) << (n * 8)));
+
+        self.pos += size;
+        Some(value)
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, ScriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.pos >= self.script.len() {
+            return None;
+        }
+
+        let position = self.pos;
+        let opcode   = self.script[position];
+        self.pos += 1;
+
+        let count = match opcode {
+            OP_PUSHDATA1 => self.read_uint(1),
+            OP_PUSHDATA2 => self.read_uint(2),
+            OP_PUSHDATA4 => self.read_uint(4),
+            n if n < OP_PUSHDATA1 => Some(n as usize),
+            _ => Some(0)
+        };
+
+        let count = match count {
+            Some(count) if self.script.len() - self.pos >= count => count,
+            _ => {
+                self.pos = self.script.len();
+                return Some(Err(ScriptError::UnexpectedEndOfScript));
+            }
+        };
+
+        let data = &self.script[self.pos..self.pos + count];
+        self.pos += count;
+
+        Some(Ok(Instruction {
+            opcode:   opcode,
+            data:     data,
+            position: position
+        }))
+    }
+}
+
+
+/// Returns the serialization of a push of `data`
+///
+/// This uses the smallest of the direct push and OP_PUSHDATA1/2/4, but never OP_1 to OP_16
+pub fn serialize_push(data: &[u8]) -> Vec<u8> {
+
+    let len = data.len();
+    let mut result = Vec::with_capacity(len + 5);
+
+    if len < OP_PUSHDATA1 as usize {
+        result.push(len as u8);
+    }
+    else if len <= 0xff {
+        result.push(OP_PUSHDATA1);
+        result.push(len as u8);
+    }
+    else if len <= 0xffff {
+        result.push(OP_PUSHDATA2);
+        result.push(len as u8);
+        result.push((len >> 8) as u8);
+    }
+    else {
+        result.push(OP_PUSHDATA4);
+        for n in 0..4 {
+            result.push((len >> (n * 8)) as u8);
+        }
+    }
+
+    result.extend_from_slice(data);
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instructions() {
+        let script = [OP_DUP, 0x02, 0xaa, 0xbb, OP_PUSHDATA1, 0x01, 0xcc, OP_EQUAL];
+        let ops: Vec<_> = instructions(&script).map(|op| op.unwrap()).collect();
+
+        assert_eq!(ops.len(), 4);
+        assert_eq!(ops[1], Instruction { opcode: 0x02, data: &[0xaa, 0xbb], position: 1 });
+        assert_eq!(ops[2], Instruction { opcode: OP_PUSHDATA1, data: &[0xcc], position: 4 });
+        assert_eq!(ops[3].position, 7);
+
+        let truncated = [OP_DUP, OP_PUSHDATA2, 0x05, 0x00, 0x01];
+        let mut iter = instructions(&truncated);
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(iter.next(), Some(Err(ScriptError::UnexpectedEndOfScript)));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_serialize_push() {
+        assert_eq!(serialize_push(&[]), vec![0x00]);
+        assert_eq!(serialize_push(&[0x07]), vec![0x01, 0x07]);
+        assert_eq!(serialize_push(&[0; 75])[0], 75);
+        assert_eq!(serialize_push(&[0; 76])[..2], [OP_PUSHDATA1, 76]);
+        assert_eq!(serialize_push(&[0; 256])[..3], [OP_PUSHDATA2, 0x00, 0x01]);
+    }
+}
@@ -0,0 +1,487 @@
+//! Verification of a script-sig against the script-pubkey it spends
+//!
+//! This follows bitcoin-core's VerifyScript: the script-sig is executed, and the resulting
+//! stack is used to execute the script-pubkey. Depending on the flags, this is followed by the
+//! P2SH redeem script (BIP16) and the witness program (BIP141).
+
+
+use ring;
+
+use script::*;
+use script::context::Context;
+use script::stack::{Stack, as_bool};
+use script::instruction::{instructions, serialize_push};
+use script::checker::SignatureChecker;
+use script::opcode::*;
+
+
+/// Verifies whether the script-sig and witness satisfy the script-pubkey
+///
+/// The checker is used to verify signatures and locktimes against the spending transaction
+pub fn verify_script(script_sig:    &[u8],
+                     script_pubkey: &[u8],
+                     witness:       &[&[u8]],
+                     flags:         u32,
+                     checker:       &SignatureChecker) -> Result<(), ScriptError> {
+
+    let mut had_witness = false;
+
+    if flags & VERIFY_SIGPUSHONLY != 0 && !is_push_only(script_sig) {
+        return Err(ScriptError::SigPushOnly);
+    }
+
+    let stack = eval(Stack::new(), script_sig, flags, SigVersion::Base, checker)?;
+
+    // P2SH needs the stack as it was after the script-sig
+    let stack_copy = if flags & VERIFY_P2SH != 0 { Some(stack.clone()) } else { None };
+
+    let mut stack = eval(stack, script_pubkey, flags, SigVersion::Base, checker)?;
+    verify_top(&stack)?;
+
+    if flags & VERIFY_WITNESS != 0 {
+        if let Some((version, program)) = witness_program(script_pubkey) {
+            had_witness = true;
+
+            // the script-sig must be empty to prevent malleability
+            if !script_sig.is_empty() {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            verify_witness_program(witness, version, program, flags, checker)?;
+
+            // the stack of the script-pubkey is no longer relevant
+            stack.truncate(1);
+        }
+    }
+
+    if let Some(stack_copy) = stack_copy {
+        if is_p2sh(script_pubkey) {
+
+            if !is_push_only(script_sig) {
+                return Err(ScriptError::SigPushOnly);
+            }
+
+            // the top of the stack is the redeem script
+            stack = stack_copy;
+            let redeem_script = stack.pop()
+                .expect("Stack cannot be empty as the script-pubkey succeeded");
+
+            stack = eval(stack, &redeem_script, flags, SigVersion::Base, checker)?;
+            verify_top(&stack)?;
+
+            if flags & VERIFY_WITNESS != 0 {
+                if let Some((version, program)) = witness_program(&redeem_script) {
+                    had_witness = true;
+
+                    // the script-sig must be exactly the push of the redeem script
+                    if script_sig != &serialize_push(&redeem_script)[..] {
+                        return Err(ScriptError::WitnessMalleatedP2SH);
+                    }
+                    verify_witness_program(witness, version, program, flags, checker)?;
+
+                    stack.truncate(1);
+                }
+            }
+        }
+    }
+
+    if flags & VERIFY_CLEANSTACK != 0 && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+
+    if flags & VERIFY_WITNESS != 0 && !had_witness && !witness.is_empty() {
+        return Err(ScriptError::WitnessUnexpected);
+    }
+
+    Ok(())
+}
+
+
+/// Returns true if the script consists only of push operations
+///
+/// Like in bitcoin-core, OP_RESERVED counts as a push
+pub fn is_push_only(script: &[u8]) -> bool {
+    instructions(script).all(|op| match op {
+        Ok(op) => op.opcode <= OP_16,
+        Err(_) => false
+    })
+}
+
+/// Returns true if the script is a P2SH script-pubkey: HASH160 <20 bytes> EQUAL
+pub fn is_p2sh(script: &[u8]) -> bool {
+    script.len() == 23
+        && script[0] == OP_HASH160
+        && script[1] == 20
+        && script[22] == OP_EQUAL
+}
+
+/// Returns the version and program if the script is a witness program
+///
+/// This is a script of a version opcode followed by a single push of 2 to 40 bytes
+pub fn witness_program(script: &[u8]) -> Option<(u8, &[u8])> {
+    if script.len() < 4 || script.len() > 42 {
+        return None;
+    }
+
+    let version = match script[0] {
+        OP_0                          => 0,
+        op if op >= OP_1 && op <= OP_16 => op - OP_1 + 1,
+        _                             => return None
+    };
+
+    if script[1] as usize + 2 != script.len() {
+        return None;
+    }
+    Some((version, &script[2..]))
+}
+
+
+/// Executes the script with the given initial stack and returns the resulting stack
+fn eval(stack:       Stack,
+        script:      &[u8],
+        flags:       u32,
+        sig_version: SigVersion,
+        checker:     &SignatureChecker) -> Result<Stack, ScriptError> {
+
+    let mut ctx = Context::with_checker(script, flags, sig_version, checker);
+    ctx.stack = stack;
+    ctx.run()?;
+    Ok(ctx.stack)
+}
+
+/// Succeeds if the top of the stack is true
+fn verify_top(stack: &Stack) -> Result<(), ScriptError> {
+    match stack.top(0) {
+        Ok(top) if as_bool(top) => Ok(()),
+        _                       => Err(ScriptError::EvalFalse)
+    }
+}
+
+fn verify_witness_program(witness: &[&[u8]],
+                          version: u8,
+                          program: &[u8],
+                          flags:   u32,
+                          checker: &SignatureChecker) -> Result<(), ScriptError> {
+
+    let (script, items) = if version == 0 {
+
+        if program.len() == 32 {
+            // P2WSH: the last item is the witness script
+            let (script, items) = match witness.split_last() {
+                Some((script, items)) => (script.to_vec(), items),
+                None                  => return Err(ScriptError::WitnessProgramWitnessEmpty)
+            };
+
+            if ring::digest::digest(&ring::digest::SHA256, &script).as_ref() != program {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            (script, items)
+        }
+        else if program.len() == 20 {
+            // P2WPKH: the witness is a signature and a key for an implied P2PKH script
+            if witness.len() != 2 {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+
+            let mut script = vec![OP_DUP, OP_HASH160];
+            script.extend(serialize_push(program));
+            script.extend_from_slice(&[OP_EQUALVERIFY, OP_CHECKSIG]);
+            (script, witness)
+        }
+        else {
+            return Err(ScriptError::WitnessProgramWrongLength);
+        }
+    }
+    else if flags & VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM != 0 {
+        return Err(ScriptError::DiscourageUpgradableWitnessProgram);
+    }
+    else {
+        // future versions are anyone-can-spend until a soft-fork gives them a meaning
+        return Ok(());
+    };
+
+    if items.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return Err(ScriptError::PushdataTooLarge);
+    }
+
+    let stack = Stack::from_items(items.iter().map(|item| item.to_vec().into_boxed_slice()).collect());
+    let stack = eval(stack, &script, flags, SigVersion::WitnessV0, checker)?;
+
+    // witness scripts implicitly require a clean stack
+    if stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    verify_top(&stack)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::*;
+    use buffer::{Buffer, Parse, write_compact_size};
+    use hash::Hash32Buf;
+    use script::checker::TransactionSignatureChecker;
+    use sighash::SighashCache;
+    use transaction::Transaction;
+    use util::*;
+
+
+    fn parse_script(asm: &str) -> Vec<u8> {
+        asm::from_asm(asm).unwrap()
+    }
+
+    /// The flags of bitcoin-core's vectors for taproot (BIP341/BIP342), which is not
+    /// implemented; vectors that use these are skipped. Any other unknown flag fails the test
+    const TAPROOT_FLAGS: &'static [&'static str] = &[
+        "TAPROOT",
+        "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+        "DISCOURAGE_OP_SUCCESS",
+        "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+    ];
+
+    /// The number of vectors in tests/data/script_tests.json that are verified, and that are
+    /// skipped as these use TAPROOT_FLAGS; updated with the vectors
+    const VERIFIED_VECTORS: usize = 128;
+    const TAPROOT_VECTORS:  usize = 0;
+
+    /// Parses the flags of a vector
+    ///
+    /// Returns None if the vector uses taproot
+    fn parse_flags(flags: &str) -> Option<u32> {
+        let mut result = VERIFY_NONE;
+        for flag in flags.split(',').filter(|flag| !flag.is_empty()) {
+
+            if TAPROOT_FLAGS.contains(&flag) {
+                return None;
+            }
+
+            result |= match flag {
+                "NONE"                                  => VERIFY_NONE,
+                "P2SH"                                  => VERIFY_P2SH,
+                "STRICTENC"                             => VERIFY_STRICTENC,
+                "DERSIG"                                => VERIFY_DERSIG,
+                "LOW_S"                                 => VERIFY_LOW_S,
+                "NULLDUMMY"                             => VERIFY_NULLDUMMY,
+                "SIGPUSHONLY"                           => VERIFY_SIGPUSHONLY,
+                "MINIMALDATA"                           => VERIFY_MINIMALDATA,
+                "DISCOURAGE_UPGRADABLE_NOPS"            => VERIFY_DISCOURAGE_UPGRADABLE_NOPS,
+                "CLEANSTACK"                            => VERIFY_CLEANSTACK,
+                "CHECKLOCKTIMEVERIFY"                   => VERIFY_CHECKLOCKTIMEVERIFY,
+                "CHECKSEQUENCEVERIFY"                   => VERIFY_CHECKSEQUENCEVERIFY,
+                "WITNESS"                               => VERIFY_WITNESS,
+                "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM" => VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM,
+                "MINIMALIF"                             => VERIFY_MINIMALIF,
+                "NULLFAIL"                              => VERIFY_NULLFAIL,
+                "WITNESS_PUBKEYTYPE"                    => VERIFY_WITNESS_PUBKEYTYPE,
+                "CONST_SCRIPTCODE"                      => VERIFY_CONST_SCRIPTCODE,
+                _                                       => panic!("Unknown flag {}", flag)
+            };
+        }
+        Some(result)
+    }
+
+    /// Returns the name bitcoin-core uses for the result
+    fn result_name(result: Result<(), ScriptError>) -> &'static str {
+        match result {
+            Ok(()) => "OK",
+            Err(err) => match err {
+                ScriptError::Unknown                 => "UNKNOWN_ERROR",
+                ScriptError::EvalFalse               => "EVAL_FALSE",
+                ScriptError::OpReturn                => "OP_RETURN",
+                ScriptError::ScriptSize              => "SCRIPT_SIZE",
+                ScriptError::PushdataTooLarge        => "PUSH_SIZE",
+                ScriptError::OpCount                 => "OP_COUNT",
+                ScriptError::StackSize               => "STACK_SIZE",
+                ScriptError::SigCount                => "SIG_COUNT",
+                ScriptError::PubkeyCount             => "PUBKEY_COUNT",
+                ScriptError::Verify                  => "VERIFY",
+                ScriptError::EqualVerify             => "EQUALVERIFY",
+                ScriptError::CheckSigVerify          => "CHECKSIGVERIFY",
+                ScriptError::CheckMultiSigVerify     => "CHECKMULTISIGVERIFY",
+                ScriptError::NumEqualVerify          => "NUMEQUALVERIFY",
+                ScriptError::InvalidOpcode           => "BAD_OPCODE",
+                ScriptError::DisabledOpcode          => "DISABLED_OPCODE",
+                ScriptError::StackUnderflow          => "INVALID_STACK_OPERATION",
+                ScriptError::AltStackUnderflow       => "INVALID_ALTSTACK_OPERATION",
+                ScriptError::UnbalancedConditional   => "UNBALANCED_CONDITIONAL",
+                ScriptError::UnexpectedEndOfScript   => "BAD_OPCODE",
+
+                // bitcoin-core throws an exception for these
+                ScriptError::NumericOverflow         => "UNKNOWN_ERROR",
+                ScriptError::NonMinimalNumber        => "UNKNOWN_ERROR",
+
+                ScriptError::NegativeLocktime        => "NEGATIVE_LOCKTIME",
+                ScriptError::UnsatisfiedLocktime     => "UNSATISFIED_LOCKTIME",
+                ScriptError::SigHashType             => "SIG_HASHTYPE",
+                ScriptError::SigDer                  => "SIG_DER",
+                ScriptError::MinimalData             => "MINIMALDATA",
+                ScriptError::SigPushOnly             => "SIG_PUSHONLY",
+                ScriptError::SigHighS                => "SIG_HIGH_S",
+                ScriptError::SigNullDummy            => "SIG_NULLDUMMY",
+                ScriptError::PubkeyType              => "PUBKEYTYPE",
+                ScriptError::CleanStack              => "CLEANSTACK",
+                ScriptError::MinimalIf               => "MINIMALIF",
+                ScriptError::SigNullFail             => "NULLFAIL",
+                ScriptError::SigFindAndDelete        => "SIG_FINDANDDELETE",
+                ScriptError::OpCodeSeparator         => "OP_CODESEPARATOR",
+                ScriptError::DiscourageUpgradableNops           => "DISCOURAGE_UPGRADABLE_NOPS",
+                ScriptError::DiscourageUpgradableWitnessProgram => "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+                ScriptError::WitnessProgramWrongLength  => "WITNESS_PROGRAM_WRONG_LENGTH",
+                ScriptError::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+                ScriptError::WitnessProgramMismatch     => "WITNESS_PROGRAM_MISMATCH",
+                ScriptError::WitnessMalleated           => "WITNESS_MALLEATED",
+                ScriptError::WitnessMalleatedP2SH       => "WITNESS_MALLEATED_P2SH",
+                ScriptError::WitnessUnexpected          => "WITNESS_UNEXPECTED",
+                ScriptError::WitnessPubkeyType          => "WITNESS_PUBKEYTYPE",
+            }
+        }
+    }
+
+    fn push_output(buf: &mut Vec<u8>, amount: i64, script: &[u8]) {
+        buf.extend((0..8).map(|n| (amount >> (n * 8)) as u8));
+        write_compact_size(buf, script.len());
+        buf.extend_from_slice(script);
+    }
+
+    /// The transaction that creates the output that is spent, as in bitcoin-core's test
+    fn crediting_tx(script_pubkey: &[u8], amount: i64) -> Vec<u8> {
+        let mut tx = vec![1, 0, 0, 0, 1];
+        tx.extend_from_slice(&[0; 32]);
+        tx.extend_from_slice(&[0xff; 4]);
+        tx.extend_from_slice(&[2, OP_0, OP_0]);
+        tx.extend_from_slice(&[0xff; 4]);
+        tx.push(1);
+        push_output(&mut tx, amount, script_pubkey);
+        tx.extend_from_slice(&[0; 4]);
+        tx
+    }
+
+    /// The transaction that spends the only output of the crediting transaction
+    fn spending_tx(credit: &[u8], script_sig: &[u8], witness: &[Vec<u8>], amount: i64) -> Vec<u8> {
+        let mut tx = vec![1, 0, 0, 0];
+        if !witness.is_empty() {
+            tx.extend_from_slice(&[0, 1]);
+        }
+        tx.push(1);
+        tx.extend_from_slice(Hash32Buf::double_sha256(credit).as_ref().0);
+        tx.extend_from_slice(&[0; 4]);
+        write_compact_size(&mut tx, script_sig.len());
+        tx.extend_from_slice(script_sig);
+        tx.extend_from_slice(&[0xff; 4]);
+        tx.push(1);
+        push_output(&mut tx, amount, &[]);
+        if !witness.is_empty() {
+            write_compact_size(&mut tx, witness.len());
+            for item in witness {
+                write_compact_size(&mut tx, item.len());
+                tx.extend_from_slice(item);
+            }
+        }
+        tx.extend_from_slice(&[0; 4]);
+        tx
+    }
+
+    #[test]
+    fn test_script_vectors() {
+
+        let vectors: serde_json::Value = serde_json::from_str(
+            include_str!("../../tests/data/script_tests.json")).unwrap();
+
+        let mut count   = 0;
+        let mut skipped = 0;
+        for vector in vectors.as_array().unwrap() {
+            let mut vector = vector.as_array().unwrap().clone();
+
+            // the witness and amount are optional
+            let (witness, amount) = if vector[0].is_array() {
+                let mut witness = vector.remove(0).as_array().unwrap().clone();
+                let amount = witness.pop().unwrap().as_f64().unwrap();
+                (witness, (amount * 100_000_000.0).round() as i64)
+            }
+            else {
+                (vec![], 0)
+            };
+
+            // skip comments
+            if vector.len() < 4 {
+                continue;
+            }
+
+            // the scripts and witnesses of taproot vectors contain placeholders, so the flags are
+            // parsed first
+            let flags = match parse_flags(vector[2].as_str().unwrap()) {
+                Some(flags) => flags,
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            let witness: Vec<Vec<u8>> = witness.iter()
+                .map(|item| from_hex(item.as_str().unwrap()))
+                .collect();
+
+            let script_sig    = parse_script(vector[0].as_str().unwrap());
+            let script_pubkey = parse_script(vector[1].as_str().unwrap());
+            let expected      = vector[3].as_str().unwrap();
+
+            let credit = crediting_tx(&script_pubkey, amount);
+            let spend  = spending_tx(&credit, &script_sig, &witness, amount);
+            let tx     = Transaction::parse(&mut Buffer::new(&spend)).unwrap();
+
+            let cache   = SighashCache::new(&tx);
+            let checker = TransactionSignatureChecker::new(&cache, 0, amount);
+            let witness: Vec<&[u8]> = witness.iter().map(|item| &item[..]).collect();
+            let result = verify_script(&script_sig, &script_pubkey, &witness, flags, &checker);
+
+            assert_eq!(result_name(result), expected, "Failed vector {:?}", vector);
+            count += 1;
+        }
+        assert_eq!(count, VERIFIED_VECTORS);
+        assert_eq!(skipped, TAPROOT_VECTORS);
+    }
+
+    #[test]
+    fn test_parse_script() {
+        assert_eq!(parse_script("0 -1 16 17 DUP OP_DUP 'ab' 0x4c01 0x07"),
+            vec![OP_0, OP_1NEGATE, OP_16, 0x01, 17, OP_DUP, OP_DUP, 0x02, b'a', b'b', 0x4c, 0x01, 0x07]);
+    }
+
+    #[test]
+    fn test_witness_program() {
+        assert_eq!(witness_program(&parse_script("0 0x14 0x0000000000000000000000000000000000000000")),
+            Some((0, &[0u8; 20][..])));
+        assert_eq!(witness_program(&parse_script("16 0x02 0x0000")), Some((16, &[0u8; 2][..])));
+        assert_eq!(witness_program(&parse_script("-1 0x02 0x0000")), None);
+        assert_eq!(witness_program(&parse_script("0 0x01 0x00")), None);
+    }
+
+    #[test]
+    fn test_first_transaction() {
+
+        // The first bitcoin transaction (block 170) spends the coinbase of block 9
+        let tx = from_hex("0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd37040000\
+                           00004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd41\
+                           0220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff02\
+                           00ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302f\
+                           a28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee\
+                           0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2\
+                           e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000");
+        let tx = Transaction::parse(&mut Buffer::new(&tx)).unwrap();
+
+        let script_pubkey = from_hex("410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2\
+                                      e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac");
+
+        let cache = SighashCache::new(&tx);
+        let checker = TransactionSignatureChecker::new(&cache, 0, 50_0000_0000);
+        let flags = VERIFY_P2SH | VERIFY_DERSIG | VERIFY_LOW_S | VERIFY_STRICTENC;
+        assert_eq!(verify_script(tx.txs_in[0].script, &script_pubkey, &[], flags, &checker), Ok(()));
+
+        // the signature doesn't match another key
+        let mut other_pubkey = script_pubkey.clone();
+        other_pubkey[1..66].copy_from_slice(&tx.txs_out[0].pk_script[1..66]);
+        assert_eq!(verify_script(tx.txs_in[0].script, &other_pubkey, &[], flags, &checker),
+            Err(ScriptError::EvalFalse));
+    }
+}
@@ -1,8 +1,11 @@
 //! SCRIPTING interface
 //!
-//! CURRENTLY ONLY USED FOR DISPLAY
+//! The native interpreter in `interpreter` executes the opcodes of the `OPCODES` table through
+//! `context::Context::run`.
 //!
-//! libbitcoinconsensus is used for script verification
+//! With the `bitcoinconsensus` feature, which is enabled by default, consensus verification of
+//! scripts is done by libbitcoinconsensus instead; the native interpreter is only used for blocks
+//! and transactions if the crate is built without default features
 
 
 pub mod context;
@@ -10,17 +13,175 @@ pub mod context;
 pub mod opcode;
 
 mod opcode_pushdata;
+mod opcode_flow;
+mod opcode_stack;
+mod opcode_arithmetic;
+mod opcode_crypto;
+mod opcode_locktime;
 
 pub mod stack;
 
-#[derive(Debug, PartialEq, Eq)]
+pub mod instruction;
+
+pub mod asm;
+
+pub mod standard;
+
+pub mod sigops;
+
+pub mod address;
+
+pub mod signature;
+
+pub mod checker;
+
+pub mod interpreter;
+
+pub use self::interpreter::verify_script;
+pub use self::checker::{SignatureChecker, TransactionSignatureChecker};
+
+
+/// Maximum number of bytes to push to the stack at once
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
+/// Maximum size of a script
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Maximum number of non-push operations per script
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+
+/// Maximum number of public keys per multisig
+pub const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+
+/// Maximum number of items on the stack and the alt-stack combined
+pub const MAX_STACK_SIZE: usize = 1000;
+
+
+// Script verification flags; these have the same values as in bitcoinconsensus.h and
+// bitcoin-core's interpreter.h
+
+pub const VERIFY_NONE:                u32 = 0;
+
+/// evaluate P2SH (BIP16) subscripts
+pub const VERIFY_P2SH:                u32 = 1 << 0;
+
+/// only allow defined signature hash types and compressed or uncompressed public keys
+pub const VERIFY_STRICTENC:           u32 = 1 << 1;
+
+/// enforce strict DER (BIP66) compliance
+pub const VERIFY_DERSIG:              u32 = 1 << 2;
+
+/// only allow signatures with an S value of at most half the group order
+pub const VERIFY_LOW_S:               u32 = 1 << 3;
+
+/// enforce NULLDUMMY (BIP147)
+pub const VERIFY_NULLDUMMY:           u32 = 1 << 4;
+
+/// only allow push operations in the script-sig
+pub const VERIFY_SIGPUSHONLY:         u32 = 1 << 5;
+
+/// require the smallest possible encoding of pushes and numbers
+pub const VERIFY_MINIMALDATA:         u32 = 1 << 6;
+
+/// fail on the NOPs that are reserved for soft-forks
+pub const VERIFY_DISCOURAGE_UPGRADABLE_NOPS: u32 = 1 << 7;
+
+/// require exactly one item on the stack after evaluation
+pub const VERIFY_CLEANSTACK:          u32 = 1 << 8;
+
+/// enable CHECKLOCKTIMEVERIFY (BIP65)
+pub const VERIFY_CHECKLOCKTIMEVERIFY: u32 = 1 << 9;
+
+/// enable CHECKSEQUENCEVERIFY (BIP112)
+pub const VERIFY_CHECKSEQUENCEVERIFY: u32 = 1 << 10;
+
+/// enable WITNESS (BIP141); this requires the amount of the output
+pub const VERIFY_WITNESS:             u32 = 1 << 11;
+
+/// fail on witness programs with a version reserved for soft-forks
+pub const VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: u32 = 1 << 12;
+
+/// require the argument of OP_IF and OP_NOTIF to be empty or 1 in witness scripts
+pub const VERIFY_MINIMALIF:           u32 = 1 << 13;
+
+/// require failing signatures to be empty
+pub const VERIFY_NULLFAIL:            u32 = 1 << 14;
+
+/// only allow compressed public keys in witness scripts
+pub const VERIFY_WITNESS_PUBKEYTYPE:  u32 = 1 << 15;
+
+/// fail on OP_CODESEPARATOR and signatures in the script-code of legacy scripts
+pub const VERIFY_CONST_SCRIPTCODE:    u32 = 1 << 16;
+
+
+/// The context in which a script is executed; this determines the signature hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    Base,
+    WitnessV0
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ScriptError {
+
+    /// Used for failures reported by libbitcoinconsensus, which doesn't report the reason
+    Unknown,
+
+    /// The script finished with an empty stack or a false value on top
+    EvalFalse,
+    OpReturn,
+
+    ScriptSize,
+    PushdataTooLarge,
+    OpCount,
+    StackSize,
+    SigCount,
+    PubkeyCount,
+
+    Verify,
+    EqualVerify,
+    CheckSigVerify,
+    CheckMultiSigVerify,
+    NumEqualVerify,
+
+    InvalidOpcode,
+    DisabledOpcode,
     StackUnderflow,
+    AltStackUnderflow,
+    UnbalancedConditional,
+    UnexpectedEndOfScript,
+
+    /// A number is larger then allowed for the operation
     NumericOverflow,
 
-    UnexpectedEndOfScript,
+    /// A number is not minimally encoded while MINIMALDATA is set
+    NonMinimalNumber,
 
-    InvalidOpcode,
+    NegativeLocktime,
+    UnsatisfiedLocktime,
+
+    SigHashType,
+    SigDer,
+    MinimalData,
+    SigPushOnly,
+    SigHighS,
+    SigNullDummy,
+    PubkeyType,
+    CleanStack,
+    MinimalIf,
+    SigNullFail,
+    SigFindAndDelete,
+    OpCodeSeparator,
+
+    DiscourageUpgradableNops,
+    DiscourageUpgradableWitnessProgram,
 
-    PushdataTooLarge
+    WitnessProgramWrongLength,
+    WitnessProgramWitnessEmpty,
+    WitnessProgramMismatch,
+    WitnessMalleated,
+    WitnessMalleatedP2SH,
+    WitnessUnexpected,
+    WitnessPubkeyType,
 }
@@ -1,19 +1,26 @@
 /*
  * 2016 Tomas van der Wansem
-
- * CURRENTLY ONLY USED FOR DEBUG-RENDERING
- * Actual script processing is done in libbitcoinconsensus
  */
+
+//! The opcode table
+//!
+//! Each opcode has functions to execute it, to skip it in a branch that is not executed, and to
+//! display it. The implementations are grouped per kind in the opcode_* modules
  
 
 use std::io;
 
 
-use script::ScriptError;
+use script::*;
 use script::context::Context;
 
 
 use super::opcode_pushdata::*;
+use super::opcode_flow::*;
+use super::opcode_stack::*;
+use super::opcode_arithmetic::*;
+use super::opcode_crypto::*;
+use super::opcode_locktime::*;
 
 
 
@@ -26,6 +33,121 @@ pub struct OpCode {
 }
 
 
+pub const OP_0:                      u8 = 0x00;
+pub const OP_FALSE:                  u8 = 0x00;
+pub const OP_PUSHDATA1:              u8 = 0x4c;
+pub const OP_PUSHDATA2:              u8 = 0x4d;
+pub const OP_PUSHDATA4:              u8 = 0x4e;
+pub const OP_1NEGATE:                u8 = 0x4f;
+pub const OP_RESERVED:               u8 = 0x50;
+pub const OP_1:                      u8 = 0x51;
+pub const OP_TRUE:                   u8 = 0x51;
+pub const OP_2:                      u8 = 0x52;
+pub const OP_3:                      u8 = 0x53;
+pub const OP_4:                      u8 = 0x54;
+pub const OP_5:                      u8 = 0x55;
+pub const OP_6:                      u8 = 0x56;
+pub const OP_7:                      u8 = 0x57;
+pub const OP_8:                      u8 = 0x58;
+pub const OP_9:                      u8 = 0x59;
+pub const OP_10:                     u8 = 0x5a;
+pub const OP_11:                     u8 = 0x5b;
+pub const OP_12:                     u8 = 0x5c;
+pub const OP_13:                     u8 = 0x5d;
+pub const OP_14:                     u8 = 0x5e;
+pub const OP_15:                     u8 = 0x5f;
+pub const OP_16:                     u8 = 0x60;
+pub const OP_NOP:                    u8 = 0x61;
+pub const OP_VER:                    u8 = 0x62;
+pub const OP_IF:                     u8 = 0x63;
+pub const OP_NOTIF:                  u8 = 0x64;
+pub const OP_VERIF:                  u8 = 0x65;
+pub const OP_VERNOTIF:               u8 = 0x66;
+pub const OP_ELSE:                   u8 = 0x67;
+pub const OP_ENDIF:                  u8 = 0x68;
+pub const OP_VERIFY:                 u8 = 0x69;
+pub const OP_RETURN:                 u8 = 0x6a;
+pub const OP_TOALTSTACK:             u8 = 0x6b;
+pub const OP_FROMALTSTACK:           u8 = 0x6c;
+pub const OP_2DROP:                  u8 = 0x6d;
+pub const OP_2DUP:                   u8 = 0x6e;
+pub const OP_3DUP:                   u8 = 0x6f;
+pub const OP_2OVER:                  u8 = 0x70;
+pub const OP_2ROT:                   u8 = 0x71;
+pub const OP_2SWAP:                  u8 = 0x72;
+pub const OP_IFDUP:                  u8 = 0x73;
+pub const OP_DEPTH:                  u8 = 0x74;
+pub const OP_DROP:                   u8 = 0x75;
+pub const OP_DUP:                    u8 = 0x76;
+pub const OP_NIP:                    u8 = 0x77;
+pub const OP_OVER:                   u8 = 0x78;
+pub const OP_PICK:                   u8 = 0x79;
+pub const OP_ROLL:                   u8 = 0x7a;
+pub const OP_ROT:                    u8 = 0x7b;
+pub const OP_SWAP:                   u8 = 0x7c;
+pub const OP_TUCK:                   u8 = 0x7d;
+pub const OP_CAT:                    u8 = 0x7e;
+pub const OP_SUBSTR:                 u8 = 0x7f;
+pub const OP_LEFT:                   u8 = 0x80;
+pub const OP_RIGHT:                  u8 = 0x81;
+pub const OP_SIZE:                   u8 = 0x82;
+pub const OP_INVERT:                 u8 = 0x83;
+pub const OP_AND:                    u8 = 0x84;
+pub const OP_OR:                     u8 = 0x85;
+pub const OP_XOR:                    u8 = 0x86;
+pub const OP_EQUAL:                  u8 = 0x87;
+pub const OP_EQUALVERIFY:            u8 = 0x88;
+pub const OP_RESERVED1:              u8 = 0x89;
+pub const OP_RESERVED2:              u8 = 0x8a;
+pub const OP_1ADD:                   u8 = 0x8b;
+pub const OP_1SUB:                   u8 = 0x8c;
+pub const OP_2MUL:                   u8 = 0x8d;
+pub const OP_2DIV:                   u8 = 0x8e;
+pub const OP_NEGATE:                 u8 = 0x8f;
+pub const OP_ABS:                    u8 = 0x90;
+pub const OP_NOT:                    u8 = 0x91;
+pub const OP_0NOTEQUAL:              u8 = 0x92;
+pub const OP_ADD:                    u8 = 0x93;
+pub const OP_SUB:                    u8 = 0x94;
+pub const OP_MUL:                    u8 = 0x95;
+pub const OP_DIV:                    u8 = 0x96;
+pub const OP_MOD:                    u8 = 0x97;
+pub const OP_LSHIFT:                 u8 = 0x98;
+pub const OP_RSHIFT:                 u8 = 0x99;
+pub const OP_BOOLAND:                u8 = 0x9a;
+pub const OP_BOOLOR:                 u8 = 0x9b;
+pub const OP_NUMEQUAL:               u8 = 0x9c;
+pub const OP_NUMEQUALVERIFY:         u8 = 0x9d;
+pub const OP_NUMNOTEQUAL:            u8 = 0x9e;
+pub const OP_LESSTHAN:               u8 = 0x9f;
+pub const OP_GREATERTHAN:            u8 = 0xa0;
+pub const OP_LESSTHANOREQUAL:        u8 = 0xa1;
+pub const OP_GREATERTHANOREQUAL:     u8 = 0xa2;
+pub const OP_MIN:                    u8 = 0xa3;
+pub const OP_MAX:                    u8 = 0xa4;
+pub const OP_WITHIN:                 u8 = 0xa5;
+pub const OP_RIPEMD160:              u8 = 0xa6;
+pub const OP_SHA1:                   u8 = 0xa7;
+pub const OP_SHA256:                 u8 = 0xa8;
+pub const OP_HASH160:                u8 = 0xa9;
+pub const OP_HASH256:                u8 = 0xaa;
+pub const OP_CODESEPARATOR:          u8 = 0xab;
+pub const OP_CHECKSIG:               u8 = 0xac;
+pub const OP_CHECKSIGVERIFY:         u8 = 0xad;
+pub const OP_CHECKMULTISIG:          u8 = 0xae;
+pub const OP_CHECKMULTISIGVERIFY:    u8 = 0xaf;
+pub const OP_NOP1:                   u8 = 0xb0;
+pub const OP_CHECKLOCKTIMEVERIFY:    u8 = 0xb1;
+pub const OP_CHECKSEQUENCEVERIFY:    u8 = 0xb2;
+pub const OP_NOP4:                   u8 = 0xb3;
+pub const OP_NOP5:                   u8 = 0xb4;
+pub const OP_NOP6:                   u8 = 0xb5;
+pub const OP_NOP7:                   u8 = 0xb6;
+pub const OP_NOP8:                   u8 = 0xb7;
+pub const OP_NOP9:                   u8 = 0xb8;
+pub const OP_NOP10:                  u8 = 0xb9;
+
+
 fn skip_none(_: &mut Context) -> Result<(), ScriptError> {
     Ok(())
 }
@@ -34,6 +156,11 @@ fn skip_invalid(_: &mut Context) -> Result<(), ScriptError> {
     Err(ScriptError::InvalidOpcode)
 }
 
+/// Disabled opcodes fail the script even if they are not executed
+fn skip_disabled(_: &mut Context) -> Result<(), ScriptError> {
+    Err(ScriptError::DisabledOpcode)
+}
+
 
 fn disp_name(ctx: &mut Context, writer: &mut io::Write) -> io::Result<()> {
     let opcode = &OPCODES[ctx.script1[ctx.ip] as usize];
@@ -55,351 +182,342 @@ fn op_nop(_: &mut Context) -> Result<(), ScriptError> {
     Ok(())
 }
 
-fn op_add(ctx: &mut Context) -> Result<(), ScriptError> {
-    let n1 = { try!(ctx.stack.pop_scriptnum()) };
-    let n2 = { try!(ctx.stack.pop_scriptnum()) };
-     
-    ctx.stack.push_scriptnum(n1 + n2)   
-    
-}
-
-pub fn run()  {
-    println!("{}", OPCODES[0].name);
-}
-    
-fn op_unimplemented(_: &mut Context) -> Result<(), ScriptError> {
-    unimplemented!()
-}
-
-fn skip_unimplemented(_: &mut Context) -> Result<(), ScriptError> {
-    unimplemented!()
+/// The NOPs that may be given a meaning by a soft-fork
+fn op_upgradable_nop(ctx: &mut Context) -> Result<(), ScriptError> {
+    if ctx.has_flag(VERIFY_DISCOURAGE_UPGRADABLE_NOPS) {
+        return Err(ScriptError::DiscourageUpgradableNops);
+    }
+    Ok(())
 }
 
-
 fn op_invalid(_: &mut Context) -> Result<(), ScriptError> {
     Err(ScriptError::InvalidOpcode)
 }
 
+fn op_disabled(_: &mut Context) -> Result<(), ScriptError> {
+    Err(ScriptError::DisabledOpcode)
+}
+
 pub static OPCODES: [OpCode; 256] = [
     //0-7
-    OpCode { name: "OP_FALSE", display: disp_name,                     execute: op_false,                    skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",         display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",         display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",         display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",         display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",         display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",         display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "OP_FALSE",                display: disp_name,                         execute: op_false,                          skip: skip_none                          },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //8-15
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //16-23
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //24-31
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //32-39
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //40-47
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //48-55
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //56-63
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //64-71
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "", display: disp_pushdata_count_by_opcode, execute: op_pushdata_count_by_opcode, skip: skip_pushdata_count_by_opcode },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+
     //72-79
-    OpCode { name: "",           display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,      skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",           display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,      skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",           display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,      skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",           display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,      skip: skip_pushdata_count_by_opcode },
-    OpCode { name: "",           display: disp_pushdata_count_by_next_bytes, execute: op_pushdata_count_by_next_bytes,  skip: skip_pushdata_count_by_next_bytes },
-    OpCode { name: "",           display: disp_pushdata_count_by_next_bytes, execute: op_pushdata_count_by_next_bytes,  skip: skip_pushdata_count_by_next_bytes },
-    OpCode { name: "",           display: disp_pushdata_count_by_next_bytes, execute: op_pushdata_count_by_next_bytes,  skip: skip_pushdata_count_by_next_bytes },
-    OpCode { name: "OP_1NEGATE", display: disp_name,                         execute: op_pushdata_value_by_opcode,      skip: skip_none     },
-    
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "",                        display: disp_pushdata_count_by_opcode,     execute: op_pushdata_count_by_opcode,       skip: skip_pushdata_count_by_opcode      },
+    OpCode { name: "OP_PUSHDATA1",            display: disp_pushdata_count_by_next_bytes, execute: op_pushdata_count_by_next_bytes,   skip: skip_pushdata_count_by_next_bytes  },
+    OpCode { name: "OP_PUSHDATA2",            display: disp_pushdata_count_by_next_bytes, execute: op_pushdata_count_by_next_bytes,   skip: skip_pushdata_count_by_next_bytes  },
+    OpCode { name: "OP_PUSHDATA4",            display: disp_pushdata_count_by_next_bytes, execute: op_pushdata_count_by_next_bytes,   skip: skip_pushdata_count_by_next_bytes  },
+    OpCode { name: "OP_1NEGATE",              display: disp_name,                         execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+
     //80-87
-    OpCode { name: "",         display: disp_invalid,                   execute: op_invalid,                   skip: skip_none     },
-    OpCode { name: "OP_TRUE",  display: disp_name,                      execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    
+    OpCode { name: "OP_RESERVED",             display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "OP_TRUE",                 display: disp_name,                         execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_2",                    display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_3",                    display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_4",                    display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_5",                    display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_6",                    display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_7",                    display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+
     //88-95
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,  skip: skip_none     },
-    
+    OpCode { name: "OP_8",                    display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_9",                    display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_10",                   display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_11",                   display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_12",                   display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_13",                   display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_14",                   display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_15",                   display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+
     //96-103
-    OpCode { name: "",         display: disp_pushdata_value_by_opcode,  execute: op_pushdata_value_by_opcode,    skip: skip_none     },
-    OpCode { name: "OP_NOP",   display: disp_name,                      execute: op_nop,                         skip: skip_none     },
-    OpCode { name: "",         display: disp_invalid,                   execute: op_invalid,                     skip: skip_none     },
-    OpCode { name: "OP_IF",    display: disp_name,                      execute: op_unimplemented,               skip: skip_unimplemented },
-    OpCode { name: "OP_NOTIF", display: disp_name,                      execute: op_unimplemented,               skip: skip_unimplemented },
-    OpCode { name: "",         display: disp_invalid,                   execute: op_invalid,                     skip: skip_invalid     },
-    OpCode { name: "",         display: disp_invalid,                   execute: op_invalid,                     skip: skip_invalid     },
-    OpCode { name: "OP_ELSE",  display: disp_name,                      execute: op_unimplemented,               skip: skip_unimplemented },
+    OpCode { name: "OP_16",                   display: disp_pushdata_value_by_opcode,     execute: op_pushdata_value_by_opcode,       skip: skip_none                          },
+    OpCode { name: "OP_NOP",                  display: disp_name,                         execute: op_nop,                            skip: skip_none                          },
+    OpCode { name: "OP_VER",                  display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "OP_IF",                   display: disp_name,                         execute: op_if,                             skip: skip_if                            },
+    OpCode { name: "OP_NOTIF",                display: disp_name,                         execute: op_notif,                          skip: skip_if                            },
+    OpCode { name: "OP_VERIF",                display: disp_invalid,                      execute: op_invalid,                        skip: skip_invalid                       },
+    OpCode { name: "OP_VERNOTIF",             display: disp_invalid,                      execute: op_invalid,                        skip: skip_invalid                       },
+    OpCode { name: "OP_ELSE",                 display: disp_name,                         execute: op_else,                           skip: op_else                            },
 
     //104-111
-    OpCode { name: "OP_ENDIF",           display: disp_name,            execute: op_unimplemented,      skip: skip_unimplemented },
-    OpCode { name: "OP_VERIFY",          display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_RETURN",          display: disp_name,            execute: op_invalid,            skip: skip_none },
-    OpCode { name: "OP_TOALTSTACK",      display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_FROMALTSTACK",    display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_2DROP",           display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_2DUP",            display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_3DUP",            display: disp_name,            execute: op_unimplemented,      skip: skip_none },
+    OpCode { name: "OP_ENDIF",                display: disp_name,                         execute: op_endif,                          skip: op_endif                           },
+    OpCode { name: "OP_VERIFY",               display: disp_name,                         execute: op_verify,                         skip: skip_none                          },
+    OpCode { name: "OP_RETURN",               display: disp_name,                         execute: op_return,                         skip: skip_none                          },
+    OpCode { name: "OP_TOALTSTACK",           display: disp_name,                         execute: op_toaltstack,                     skip: skip_none                          },
+    OpCode { name: "OP_FROMALTSTACK",         display: disp_name,                         execute: op_fromaltstack,                   skip: skip_none                          },
+    OpCode { name: "OP_2DROP",                display: disp_name,                         execute: op_2drop,                          skip: skip_none                          },
+    OpCode { name: "OP_2DUP",                 display: disp_name,                         execute: op_2dup,                           skip: skip_none                          },
+    OpCode { name: "OP_3DUP",                 display: disp_name,                         execute: op_3dup,                           skip: skip_none                          },
 
     //112-119
-    OpCode { name: "OP_2OVER",            display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_2ROT",             display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_2SWAP",            display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_IFDUP",            display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_DEPTH",            display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_DROP",             display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_DUP",              display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_NIP",              display: disp_name,            execute: op_unimplemented,      skip: skip_none },
+    OpCode { name: "OP_2OVER",                display: disp_name,                         execute: op_2over,                          skip: skip_none                          },
+    OpCode { name: "OP_2ROT",                 display: disp_name,                         execute: op_2rot,                           skip: skip_none                          },
+    OpCode { name: "OP_2SWAP",                display: disp_name,                         execute: op_2swap,                          skip: skip_none                          },
+    OpCode { name: "OP_IFDUP",                display: disp_name,                         execute: op_ifdup,                          skip: skip_none                          },
+    OpCode { name: "OP_DEPTH",                display: disp_name,                         execute: op_depth,                          skip: skip_none                          },
+    OpCode { name: "OP_DROP",                 display: disp_name,                         execute: op_drop,                           skip: skip_none                          },
+    OpCode { name: "OP_DUP",                  display: disp_name,                         execute: op_dup,                            skip: skip_none                          },
+    OpCode { name: "OP_NIP",                  display: disp_name,                         execute: op_nip,                            skip: skip_none                          },
 
     //120-127
-    OpCode { name: "OP_OVER",             display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_PICK",             display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_ROLL",             display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_ROT",              display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_SWAP",             display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "OP_TUCK",             display: disp_name,            execute: op_unimplemented,      skip: skip_none },
-    OpCode { name: "",                    display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                    display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
+    OpCode { name: "OP_OVER",                 display: disp_name,                         execute: op_over,                           skip: skip_none                          },
+    OpCode { name: "OP_PICK",                 display: disp_name,                         execute: op_pick,                           skip: skip_none                          },
+    OpCode { name: "OP_ROLL",                 display: disp_name,                         execute: op_roll,                           skip: skip_none                          },
+    OpCode { name: "OP_ROT",                  display: disp_name,                         execute: op_rot,                            skip: skip_none                          },
+    OpCode { name: "OP_SWAP",                 display: disp_name,                         execute: op_swap,                           skip: skip_none                          },
+    OpCode { name: "OP_TUCK",                 display: disp_name,                         execute: op_tuck,                           skip: skip_none                          },
+    OpCode { name: "OP_CAT",                  display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_SUBSTR",               display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
 
     //128-135
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "OP_SIZE",            display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "OP_EQUAL",           display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
+    OpCode { name: "OP_LEFT",                 display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_RIGHT",                display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_SIZE",                 display: disp_name,                         execute: op_size,                           skip: skip_none                          },
+    OpCode { name: "OP_INVERT",               display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_AND",                  display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_OR",                   display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_XOR",                  display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_EQUAL",                display: disp_name,                         execute: op_equal,                          skip: skip_none                          },
 
     //136-143
-    OpCode { name: "OP_EQUALVERIFY",     display: disp_name,         execute: op_unimplemented,         skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "OP_1ADD",            display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_1SUB",            display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "OP_NEGATE",          display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    
+    OpCode { name: "OP_EQUALVERIFY",          display: disp_name,                         execute: op_equalverify,                    skip: skip_none                          },
+    OpCode { name: "OP_RESERVED1",            display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "OP_RESERVED2",            display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "OP_1ADD",                 display: disp_name,                         execute: op_1add,                           skip: skip_none                          },
+    OpCode { name: "OP_1SUB",                 display: disp_name,                         execute: op_1sub,                           skip: skip_none                          },
+    OpCode { name: "OP_2MUL",                 display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_2DIV",                 display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_NEGATE",               display: disp_name,                         execute: op_negate,                         skip: skip_none                          },
+
     //144-151
-    OpCode { name: "OP_ABS",             display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_NOT",             display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_0NOTEQUAL",       display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_ADD",             display: disp_name,            execute: op_add,                skip: skip_none     },
-    OpCode { name: "OP_SUB",             display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
+    OpCode { name: "OP_ABS",                  display: disp_name,                         execute: op_abs,                            skip: skip_none                          },
+    OpCode { name: "OP_NOT",                  display: disp_name,                         execute: op_not,                            skip: skip_none                          },
+    OpCode { name: "OP_0NOTEQUAL",            display: disp_name,                         execute: op_0notequal,                      skip: skip_none                          },
+    OpCode { name: "OP_ADD",                  display: disp_name,                         execute: op_add,                            skip: skip_none                          },
+    OpCode { name: "OP_SUB",                  display: disp_name,                         execute: op_sub,                            skip: skip_none                          },
+    OpCode { name: "OP_MUL",                  display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_DIV",                  display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_MOD",                  display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+
     //152-159
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "OP_BOOLAND",         display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_BOOLOR",          display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_NUMEQUAL",        display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_NUMEQUALVERIFY",  display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_NUMNOTEQUAL",     display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_LESSTHEN",        display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
+    OpCode { name: "OP_LSHIFT",               display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_RSHIFT",               display: disp_name,                         execute: op_disabled,                       skip: skip_disabled                      },
+    OpCode { name: "OP_BOOLAND",              display: disp_name,                         execute: op_booland,                        skip: skip_none                          },
+    OpCode { name: "OP_BOOLOR",               display: disp_name,                         execute: op_boolor,                         skip: skip_none                          },
+    OpCode { name: "OP_NUMEQUAL",             display: disp_name,                         execute: op_numequal,                       skip: skip_none                          },
+    OpCode { name: "OP_NUMEQUALVERIFY",       display: disp_name,                         execute: op_numequalverify,                 skip: skip_none                          },
+    OpCode { name: "OP_NUMNOTEQUAL",          display: disp_name,                         execute: op_numnotequal,                    skip: skip_none                          },
+    OpCode { name: "OP_LESSTHAN",             display: disp_name,                         execute: op_lessthan,                       skip: skip_none                          },
 
     //160-167
-    OpCode { name: "OP_GREATERTHEN",        display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_LESSTHANOREQUAL",    display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_GREATERTHANOREQUAL", display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_MIN",                display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_MAX",                display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_WITHIN",             display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_RIPEMD160",          display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_SHA1",               display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
+    OpCode { name: "OP_GREATERTHAN",          display: disp_name,                         execute: op_greaterthan,                    skip: skip_none                          },
+    OpCode { name: "OP_LESSTHANOREQUAL",      display: disp_name,                         execute: op_lessthanorequal,                skip: skip_none                          },
+    OpCode { name: "OP_GREATERTHANOREQUAL",   display: disp_name,                         execute: op_greaterthanorequal,             skip: skip_none                          },
+    OpCode { name: "OP_MIN",                  display: disp_name,                         execute: op_min,                            skip: skip_none                          },
+    OpCode { name: "OP_MAX",                  display: disp_name,                         execute: op_max,                            skip: skip_none                          },
+    OpCode { name: "OP_WITHIN",               display: disp_name,                         execute: op_within,                         skip: skip_none                          },
+    OpCode { name: "OP_RIPEMD160",            display: disp_name,                         execute: op_ripemd160,                      skip: skip_none                          },
+    OpCode { name: "OP_SHA1",                 display: disp_name,                         execute: op_sha1,                           skip: skip_none                          },
 
     //168-175
-    OpCode { name: "OP_SHA256",              display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_HASH160",             display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_HASH256",             display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_CODESEPARATOR",       display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_CHECKSIG",            display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_CHECKSIGVERIFY",      display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_CHECKMULTISIG",       display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_CHECKMULTISIGVERIFY", display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    
+    OpCode { name: "OP_SHA256",               display: disp_name,                         execute: op_sha256,                         skip: skip_none                          },
+    OpCode { name: "OP_HASH160",              display: disp_name,                         execute: op_hash160,                        skip: skip_none                          },
+    OpCode { name: "OP_HASH256",              display: disp_name,                         execute: op_hash256,                        skip: skip_none                          },
+    OpCode { name: "OP_CODESEPARATOR",        display: disp_name,                         execute: op_codeseparator,                  skip: skip_codeseparator                 },
+    OpCode { name: "OP_CHECKSIG",             display: disp_name,                         execute: op_checksig,                       skip: skip_none                          },
+    OpCode { name: "OP_CHECKSIGVERIFY",       display: disp_name,                         execute: op_checksigverify,                 skip: skip_none                          },
+    OpCode { name: "OP_CHECKMULTISIG",        display: disp_name,                         execute: op_checkmultisig,                  skip: skip_none                          },
+    OpCode { name: "OP_CHECKMULTISIGVERIFY",  display: disp_name,                         execute: op_checkmultisigverify,            skip: skip_none                          },
+
     //176-183
-    OpCode { name: "OP_NOP1",                 display: disp_name,            execute: op_nop,                skip: skip_none     },
-    OpCode { name: "OP_CHECKLOCKTIMEVERIFY", display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_CHECKSEQUENCEVERIFY", display: disp_name,            execute: op_unimplemented,      skip: skip_none     },
-    OpCode { name: "OP_NOP4",                 display: disp_name,            execute: op_nop,                skip: skip_none     },
-    OpCode { name: "OP_NOP5",                 display: disp_name,            execute: op_nop,                skip: skip_none     },
-    OpCode { name: "OP_NOP6",                 display: disp_name,            execute: op_nop,                skip: skip_none     },
-    OpCode { name: "OP_NOP7",                 display: disp_name,            execute: op_nop,                skip: skip_none     },
-    OpCode { name: "OP_NOP8",                 display: disp_name,            execute: op_nop,                skip: skip_none     },
-
-    //184-185
-    OpCode { name: "OP_NOP9",                 display: disp_name,            execute: op_nop,                skip: skip_none     },
-    OpCode { name: "OP_NOP10",                display: disp_name,            execute: op_nop,                skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
+    OpCode { name: "OP_NOP1",                 display: disp_name,                         execute: op_upgradable_nop,                 skip: skip_none                          },
+    OpCode { name: "OP_CHECKLOCKTIMEVERIFY",  display: disp_name,                         execute: op_checklocktimeverify,            skip: skip_none                          },
+    OpCode { name: "OP_CHECKSEQUENCEVERIFY",  display: disp_name,                         execute: op_checksequenceverify,            skip: skip_none                          },
+    OpCode { name: "OP_NOP4",                 display: disp_name,                         execute: op_upgradable_nop,                 skip: skip_none                          },
+    OpCode { name: "OP_NOP5",                 display: disp_name,                         execute: op_upgradable_nop,                 skip: skip_none                          },
+    OpCode { name: "OP_NOP6",                 display: disp_name,                         execute: op_upgradable_nop,                 skip: skip_none                          },
+    OpCode { name: "OP_NOP7",                 display: disp_name,                         execute: op_upgradable_nop,                 skip: skip_none                          },
+    OpCode { name: "OP_NOP8",                 display: disp_name,                         execute: op_upgradable_nop,                 skip: skip_none                          },
+
+    //184-191
+    OpCode { name: "OP_NOP9",                 display: disp_name,                         execute: op_upgradable_nop,                 skip: skip_none                          },
+    OpCode { name: "OP_NOP10",                display: disp_name,                         execute: op_upgradable_nop,                 skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+
     //192-199
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+
     //200-207
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+
     //208-215
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+
     //216-223
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+
     //224-231
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+
     //232-239
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+
     //240-247
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    
-    //247-255
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
-    OpCode { name: "",                   display: disp_invalid,         execute: op_invalid,            skip: skip_none     },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+
+    //248-255
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
+    OpCode { name: "",                        display: disp_invalid,                      execute: op_invalid,                        skip: skip_none                          },
     
 ];
 
@@ -417,4 +535,4 @@ mod tests {
         
         
     }
-}
\ No newline at end of file
+}
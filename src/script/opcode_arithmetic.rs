@@ -0,0 +1,151 @@
+//! Implementing the arithmetic opcodes
+//!
+//! The arguments are scriptnums of at most 4 bytes, but results may overflow to 5 bytes;
+//! these can be pushed but not used as argument again
+
+
+use script::*;
+use script::context::Context;
+
+
+/// Replaces the top number by the result of `f`
+fn unary<F: Fn(i64) -> i64>(ctx: &mut Context, f: F) -> Result<(), ScriptError> {
+    let a = ctx.pop_scriptnum()?;
+    ctx.stack.push_scriptnum(f(a))
+}
+
+/// Replaces the top two numbers by the result of `f`; `a` is the deeper of the two
+fn binary<F: Fn(i64, i64) -> i64>(ctx: &mut Context, f: F) -> Result<(), ScriptError> {
+    ctx.stack.top(1)?;
+    let b = ctx.pop_scriptnum()?;
+    let a = ctx.pop_scriptnum()?;
+    ctx.stack.push_scriptnum(f(a, b))
+}
+
+fn from_bool(value: bool) -> i64 {
+    if value { 1 } else { 0 }
+}
+
+
+pub fn op_1add(ctx: &mut Context) -> Result<(), ScriptError> {
+    unary(ctx, |a| a + 1)
+}
+
+pub fn op_1sub(ctx: &mut Context) -> Result<(), ScriptError> {
+    unary(ctx, |a| a - 1)
+}
+
+pub fn op_negate(ctx: &mut Context) -> Result<(), ScriptError> {
+    unary(ctx, |a| -a)
+}
+
+pub fn op_abs(ctx: &mut Context) -> Result<(), ScriptError> {
+    unary(ctx, |a| a.abs())
+}
+
+pub fn op_not(ctx: &mut Context) -> Result<(), ScriptError> {
+    unary(ctx, |a| from_bool(a == 0))
+}
+
+pub fn op_0notequal(ctx: &mut Context) -> Result<(), ScriptError> {
+    unary(ctx, |a| from_bool(a != 0))
+}
+
+pub fn op_add(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| a + b)
+}
+
+pub fn op_sub(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| a - b)
+}
+
+pub fn op_booland(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| from_bool(a != 0 && b != 0))
+}
+
+pub fn op_boolor(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| from_bool(a != 0 || b != 0))
+}
+
+pub fn op_numequal(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| from_bool(a == b))
+}
+
+pub fn op_numequalverify(ctx: &mut Context) -> Result<(), ScriptError> {
+    op_numequal(ctx)?;
+    if ctx.stack.pop_bool()? {
+        Ok(())
+    }
+    else {
+        Err(ScriptError::NumEqualVerify)
+    }
+}
+
+pub fn op_numnotequal(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| from_bool(a != b))
+}
+
+pub fn op_lessthan(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| from_bool(a < b))
+}
+
+pub fn op_greaterthan(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| from_bool(a > b))
+}
+
+pub fn op_lessthanorequal(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| from_bool(a <= b))
+}
+
+pub fn op_greaterthanorequal(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| from_bool(a >= b))
+}
+
+pub fn op_min(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| if a < b { a } else { b })
+}
+
+pub fn op_max(ctx: &mut Context) -> Result<(), ScriptError> {
+    binary(ctx, |a, b| if a > b { a } else { b })
+}
+
+/// Pushes true if min <= x < max, with x, min and max popped from the stack
+pub fn op_within(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.top(2)?;
+    let max = ctx.pop_scriptnum()?;
+    let min = ctx.pop_scriptnum()?;
+    let x   = ctx.pop_scriptnum()?;
+    ctx.stack.push_bool(min <= x && x < max)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use script::ScriptError;
+    use script::context::Context;
+    use script::opcode::*;
+
+    fn run(script: &[u8]) -> Result<Vec<u8>, ScriptError> {
+        let mut ctx = Context::new(script);
+        ctx.run()?;
+        Ok(ctx.stack.top(0)?.to_vec())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(run(&[OP_2, OP_3, OP_SUB]).unwrap(), vec![0x81]);
+        assert_eq!(run(&[OP_1NEGATE, OP_ABS]).unwrap(), vec![1]);
+        assert_eq!(run(&[OP_5, OP_NOT]).unwrap(), vec![]);
+        assert_eq!(run(&[OP_3, OP_2, OP_5, OP_WITHIN]).unwrap(), vec![1]);
+        assert_eq!(run(&[OP_5, OP_2, OP_5, OP_WITHIN]).unwrap(), vec![]);
+        assert_eq!(run(&[OP_2, OP_3, OP_MIN]).unwrap(), vec![2]);
+
+        // results may exceed 4 bytes, but can't be used as argument
+        assert_eq!(run(&[0x04, 0xff, 0xff, 0xff, 0x7f, OP_1ADD]).unwrap(), vec![0, 0, 0, 0x80, 0]);
+        assert_eq!(run(&[0x04, 0xff, 0xff, 0xff, 0x7f, OP_1ADD, OP_1ADD]),
+            Err(ScriptError::NumericOverflow));
+
+        assert_eq!(run(&[OP_1, OP_2, OP_NUMEQUALVERIFY]), Err(ScriptError::NumEqualVerify));
+        assert_eq!(run(&[OP_1, OP_ADD]), Err(ScriptError::StackUnderflow));
+    }
+}
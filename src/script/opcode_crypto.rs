@@ -0,0 +1,289 @@
+//! Implementing the hash and signature opcodes
+//!
+//! Signatures are verified by the SignatureChecker of the context
+
+
+use ring;
+
+use crypto::ripemd160::ripemd160;
+use crypto::sha1::sha1;
+use hash::Hash32Buf;
+use script::*;
+use script::context::Context;
+use script::instruction::{instructions, serialize_push};
+use script::signature::{check_signature_encoding, check_pubkey_encoding};
+
+
+/// Replaces the top item by its hash
+fn hash_top<F: Fn(&[u8]) -> Vec<u8>>(ctx: &mut Context, f: F) -> Result<(), ScriptError> {
+    let item = ctx.stack.pop()?;
+    ctx.stack.push(f(&item).into_boxed_slice())
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, data).as_ref().to_vec()
+}
+
+pub fn op_ripemd160(ctx: &mut Context) -> Result<(), ScriptError> {
+    hash_top(ctx, |data| ripemd160(data).to_vec())
+}
+
+pub fn op_sha1(ctx: &mut Context) -> Result<(), ScriptError> {
+    hash_top(ctx, |data| sha1(data).to_vec())
+}
+
+pub fn op_sha256(ctx: &mut Context) -> Result<(), ScriptError> {
+    hash_top(ctx, sha256)
+}
+
+pub fn op_hash160(ctx: &mut Context) -> Result<(), ScriptError> {
+    hash_top(ctx, |data| ripemd160(&sha256(data)).to_vec())
+}
+
+pub fn op_hash256(ctx: &mut Context) -> Result<(), ScriptError> {
+    hash_top(ctx, |data| Hash32Buf::double_sha256(data).as_ref().0.to_vec())
+}
+
+
+/// Signatures only sign the part of the script after the last executed OP_CODESEPARATOR
+pub fn op_codeseparator(ctx: &mut Context) -> Result<(), ScriptError> {
+    skip_codeseparator(ctx)?;
+    ctx.code_separator = ctx.ip + 1;
+    Ok(())
+}
+
+/// With CONST_SCRIPTCODE, OP_CODESEPARATOR is not allowed in legacy scripts, not even in
+/// a branch that is not executed
+pub fn skip_codeseparator(ctx: &mut Context) -> Result<(), ScriptError> {
+    if ctx.sig_version == SigVersion::Base && ctx.has_flag(VERIFY_CONST_SCRIPTCODE) {
+        return Err(ScriptError::OpCodeSeparator);
+    }
+    Ok(())
+}
+
+
+/// Removes all pushes of `pattern` that start at an opcode boundary of the script
+///
+/// Returns the resulting script and the number of pushes that were removed
+pub fn find_and_delete(script: &[u8], pattern: &[u8]) -> (Vec<u8>, usize) {
+
+    let mut result = Vec::with_capacity(script.len());
+    let mut found  = 0;
+    let mut pos    = 0;
+    let mut copied = 0;
+
+    if pattern.is_empty() {
+        return (script.to_vec(), 0);
+    }
+
+    loop {
+        result.extend_from_slice(&script[copied..pos]);
+        while script[pos..].starts_with(pattern) {
+            pos   += pattern.len();
+            found += 1;
+        }
+        copied = pos;
+
+        let mut iter = instructions(&script[pos..]);
+        match iter.next() {
+            Some(Ok(_)) => pos += iter.position(),
+            _           => break
+        }
+    }
+
+    if found == 0 {
+        return (script.to_vec(), 0);
+    }
+    result.extend_from_slice(&script[copied..]);
+    (result, found)
+}
+
+/// Returns the script-code for signatures; in legacy scripts any of the signatures
+/// is removed from it
+fn script_code_without(ctx: &Context, sigs: &[Box<[u8]>]) -> Result<Vec<u8>, ScriptError> {
+
+    let mut script_code = ctx.script_code().to_vec();
+
+    if ctx.sig_version == SigVersion::Base {
+        for sig in sigs {
+            let (result, found) = find_and_delete(&script_code, &serialize_push(sig));
+            if found > 0 && ctx.has_flag(VERIFY_CONST_SCRIPTCODE) {
+                return Err(ScriptError::SigFindAndDelete);
+            }
+            script_code = result;
+        }
+    }
+    Ok(script_code)
+}
+
+/// Pops a signature and public key and returns whether the signature is valid
+fn checksig(ctx: &mut Context) -> Result<bool, ScriptError> {
+
+    ctx.stack.top(1)?;
+    let pubkey = ctx.stack.pop()?;
+    let sig    = ctx.stack.pop()?;
+
+    let script_code = script_code_without(ctx, &[sig.clone()])?;
+
+    check_signature_encoding(&sig, ctx.flags)?;
+    check_pubkey_encoding(&pubkey, ctx.flags, ctx.sig_version)?;
+
+    let success = ctx.checker.check_sig(&sig, &pubkey, &script_code, ctx.sig_version);
+
+    if !success && ctx.has_flag(VERIFY_NULLFAIL) && !sig.is_empty() {
+        return Err(ScriptError::SigNullFail);
+    }
+    Ok(success)
+}
+
+pub fn op_checksig(ctx: &mut Context) -> Result<(), ScriptError> {
+    let success = checksig(ctx)?;
+    ctx.stack.push_bool(success)
+}
+
+pub fn op_checksigverify(ctx: &mut Context) -> Result<(), ScriptError> {
+    if checksig(ctx)? {
+        Ok(())
+    }
+    else {
+        Err(ScriptError::CheckSigVerify)
+    }
+}
+
+/// Pops the keys, signatures and the dummy element and returns whether all signatures are valid
+///
+/// The signatures must be in the same order as the keys they belong to
+fn checkmultisig(ctx: &mut Context) -> Result<bool, ScriptError> {
+
+    let key_count = ctx.pop_scriptnum()?;
+    if key_count < 0 || key_count > MAX_PUBKEYS_PER_MULTISIG {
+        return Err(ScriptError::PubkeyCount);
+    }
+
+    // each key counts as an operation
+    ctx.op_count += key_count as usize;
+    if ctx.op_count > MAX_OPS_PER_SCRIPT {
+        return Err(ScriptError::OpCount);
+    }
+
+    let mut keys = Vec::with_capacity(key_count as usize);
+    for _ in 0..key_count {
+        keys.push(ctx.stack.pop()?);
+    }
+
+    let sig_count = ctx.pop_scriptnum()?;
+    if sig_count < 0 || sig_count > key_count {
+        return Err(ScriptError::SigCount);
+    }
+
+    let mut sigs = Vec::with_capacity(sig_count as usize);
+    for _ in 0..sig_count {
+        sigs.push(ctx.stack.pop()?);
+    }
+
+    // bitcoin-core pops one item too many; it must be empty with NULLDUMMY
+    let dummy = ctx.stack.pop()?;
+
+    let script_code = script_code_without(ctx, &sigs)?;
+
+    // like bitcoin-core, keys and signatures are matched from the top of the stack down
+    let mut key = 0;
+    let mut sig = 0;
+    let mut success = true;
+    while success && sig < sigs.len() {
+
+        check_signature_encoding(&sigs[sig], ctx.flags)?;
+        check_pubkey_encoding(&keys[key], ctx.flags, ctx.sig_version)?;
+
+        if ctx.checker.check_sig(&sigs[sig], &keys[key], &script_code, ctx.sig_version) {
+            sig += 1;
+        }
+        key += 1;
+
+        // fail if there are more signatures left than keys
+        if sigs.len() - sig > keys.len() - key {
+            success = false;
+        }
+    }
+
+    if !success && ctx.has_flag(VERIFY_NULLFAIL) && sigs.iter().any(|sig| !sig.is_empty()) {
+        return Err(ScriptError::SigNullFail);
+    }
+
+    if ctx.has_flag(VERIFY_NULLDUMMY) && !dummy.is_empty() {
+        return Err(ScriptError::SigNullDummy);
+    }
+
+    Ok(success)
+}
+
+pub fn op_checkmultisig(ctx: &mut Context) -> Result<(), ScriptError> {
+    let success = checkmultisig(ctx)?;
+    ctx.stack.push_bool(success)
+}
+
+pub fn op_checkmultisigverify(ctx: &mut Context) -> Result<(), ScriptError> {
+    if checkmultisig(ctx)? {
+        Ok(())
+    }
+    else {
+        Err(ScriptError::CheckMultiSigVerify)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use script::opcode::*;
+    use util::*;
+
+    #[test]
+    fn test_hashes() {
+        let script = vec![OP_0, OP_HASH160];
+        let mut ctx = Context::new(&script);
+        ctx.run().unwrap();
+        assert_eq!(to_hex(ctx.stack.top(0).unwrap()), "b472a266d0bd89c13706a4132ccfb16f7c3b9fcb");
+
+        let script = vec![OP_0, OP_HASH256];
+        let mut ctx = Context::new(&script);
+        ctx.run().unwrap();
+        assert_eq!(to_hex(ctx.stack.top(0).unwrap()),
+            "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456");
+    }
+
+    #[test]
+    fn test_find_and_delete() {
+        assert_eq!(find_and_delete(&[0x01, 0x02, 0x03], &[0x01, 0x02]), (vec![0x03], 1));
+
+        // only at opcode boundaries
+        assert_eq!(find_and_delete(&[0x02, 0x01, 0x02], &[0x01, 0x02]), (vec![0x02, 0x01, 0x02], 0));
+        assert_eq!(find_and_delete(&[0x01, 0x02, 0x01, 0x02, OP_1], &[0x01, 0x02]), (vec![OP_1], 2));
+        assert_eq!(find_and_delete(&[OP_1, 0x01, 0x02], &[]), (vec![OP_1, 0x01, 0x02], 0));
+    }
+
+    #[test]
+    fn test_checkmultisig_without_checker() {
+        // 0 0 0 CHECKMULTISIG succeeds without signatures
+        let script = vec![OP_0, OP_0, OP_0, OP_CHECKMULTISIG];
+        let mut ctx = Context::new(&script);
+        ctx.run().unwrap();
+        assert_eq!(ctx.stack.top(0).unwrap(), &[1]);
+        assert_eq!(ctx.op_count, 1);
+
+        // a missing dummy element fails
+        let script = vec![OP_0, OP_0, OP_CHECKMULTISIG];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::StackUnderflow));
+
+        let script = vec![OP_0, OP_2, OP_1, OP_CHECKMULTISIG];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::StackUnderflow));
+
+        // each key counts as an operation
+        let mut script = vec![OP_0, OP_0];
+        script.extend_from_slice(&[OP_0; 20]);
+        script.extend_from_slice(&[0x01, 20, OP_CHECKMULTISIG]);
+        let mut ctx = Context::new(&script);
+        ctx.run().unwrap();
+        assert_eq!(ctx.op_count, 21);
+    }
+}
@@ -0,0 +1,109 @@
+//! Implementing the flow control opcodes
+//!
+//! OP_IF and OP_NOTIF push an item on the exec_stack of the context which determines whether
+//! the following opcodes are executed or skipped
+
+
+use script::*;
+use script::context::Context;
+
+
+/// Pops the argument of OP_IF or OP_NOTIF
+///
+/// In witness scripts with MINIMALIF, the argument must be empty or exactly 1
+fn pop_condition(ctx: &mut Context) -> Result<bool, ScriptError> {
+    let value = ctx.stack.pop()
+        .map_err(|_| ScriptError::UnbalancedConditional)?;
+
+    if ctx.sig_version == SigVersion::WitnessV0 && ctx.has_flag(VERIFY_MINIMALIF) {
+        if value.len() > 1 || (value.len() == 1 && value[0] != 1) {
+            return Err(ScriptError::MinimalIf);
+        }
+    }
+
+    Ok(stack::as_bool(&value))
+}
+
+pub fn op_if(ctx: &mut Context) -> Result<(), ScriptError> {
+    let condition = pop_condition(ctx)?;
+    ctx.exec_stack.push(condition);
+    Ok(())
+}
+
+pub fn op_notif(ctx: &mut Context) -> Result<(), ScriptError> {
+    let condition = pop_condition(ctx)?;
+    ctx.exec_stack.push(!condition);
+    Ok(())
+}
+
+/// OP_IF and OP_NOTIF in a branch that is not executed don't pop an argument;
+/// both of their branches are skipped
+pub fn skip_if(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.exec_stack.push(false);
+    Ok(())
+}
+
+/// Used both for executing and skipping
+pub fn op_else(ctx: &mut Context) -> Result<(), ScriptError> {
+    match ctx.exec_stack.last_mut() {
+        Some(exec) => { *exec = !*exec; Ok(()) },
+        None       => Err(ScriptError::UnbalancedConditional)
+    }
+}
+
+/// Used both for executing and skipping
+pub fn op_endif(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.exec_stack.pop()
+        .map(|_| ())
+        .ok_or(ScriptError::UnbalancedConditional)
+}
+
+pub fn op_verify(ctx: &mut Context) -> Result<(), ScriptError> {
+    if ctx.stack.pop_bool()? {
+        Ok(())
+    }
+    else {
+        Err(ScriptError::Verify)
+    }
+}
+
+pub fn op_return(_: &mut Context) -> Result<(), ScriptError> {
+    Err(ScriptError::OpReturn)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use script::*;
+    use script::context::Context;
+    use script::opcode::*;
+
+    #[test]
+    fn test_nested_if() {
+        // 1 IF 0 IF 2 ELSE 3 ENDIF ENDIF
+        let script = vec![OP_1, OP_IF, OP_0, OP_IF, OP_2, OP_ELSE, OP_3, OP_ENDIF, OP_ENDIF];
+        let mut ctx = Context::new(&script);
+        ctx.run().unwrap();
+        assert_eq!(ctx.stack.items(), &[vec![3].into_boxed_slice()]);
+
+        // 0 NOTIF RETURN ENDIF
+        let script = vec![OP_0, OP_NOTIF, OP_RETURN, OP_ENDIF];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::OpReturn));
+
+        let script = vec![OP_ELSE];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::UnbalancedConditional));
+
+        let script = vec![OP_IF, OP_ENDIF];
+        assert_eq!(Context::new(&script).run(), Err(ScriptError::UnbalancedConditional));
+    }
+
+    #[test]
+    fn test_minimal_if() {
+        let script = vec![OP_2, OP_IF, OP_ENDIF];
+        assert_eq!(Context::new(&script).run(), Ok(()));
+
+        let mut ctx = Context::with_checker(&script, VERIFY_MINIMALIF, SigVersion::WitnessV0,
+            &checker::NO_CHECKER);
+        assert_eq!(ctx.run(), Err(ScriptError::MinimalIf));
+    }
+}
@@ -0,0 +1,59 @@
+//! Implementing OP_CHECKLOCKTIMEVERIFY (BIP65) and OP_CHECKSEQUENCEVERIFY (BIP112)
+//!
+//! Without their verification flags, these behave as the NOPs they replaced
+
+
+use script::*;
+use script::context::Context;
+
+
+/// If set, the sequence number of the input has no consensus meaning
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: i64 = 1 << 31;
+
+
+/// Reads the top number without popping it; this may be up to 5 bytes to allow for
+/// all possible locktimes
+fn peek_locktime(ctx: &Context) -> Result<i64, ScriptError> {
+    let bytes = ctx.stack.top(0)?;
+    let value = stack::decode_scriptnum(bytes, 5, ctx.has_flag(VERIFY_MINIMALDATA))?;
+    if value < 0 {
+        return Err(ScriptError::NegativeLocktime);
+    }
+    Ok(value)
+}
+
+fn upgradable_nop(ctx: &Context) -> Result<(), ScriptError> {
+    if ctx.has_flag(VERIFY_DISCOURAGE_UPGRADABLE_NOPS) {
+        Err(ScriptError::DiscourageUpgradableNops)
+    }
+    else {
+        Ok(())
+    }
+}
+
+pub fn op_checklocktimeverify(ctx: &mut Context) -> Result<(), ScriptError> {
+    if !ctx.has_flag(VERIFY_CHECKLOCKTIMEVERIFY) {
+        return upgradable_nop(ctx);
+    }
+
+    let lock_time = peek_locktime(ctx)?;
+    if !ctx.checker.check_lock_time(lock_time) {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    }
+    Ok(())
+}
+
+pub fn op_checksequenceverify(ctx: &mut Context) -> Result<(), ScriptError> {
+    if !ctx.has_flag(VERIFY_CHECKSEQUENCEVERIFY) {
+        return upgradable_nop(ctx);
+    }
+
+    let sequence = peek_locktime(ctx)?;
+    if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+        return Ok(());
+    }
+    if !ctx.checker.check_sequence(sequence) {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    }
+    Ok(())
+}
@@ -14,24 +14,23 @@
 use std::io;
 
 
-use script::ScriptError;
+use script::*;
 use script::context::Context;
-
-
-/// Maximum number of bytes to push to the stack at once
-const MAX_SCRIPT_ELEMENT_SIZE: u64 = 520;
+use script::opcode::*;
 
 
 /// Pushes the next `n` bytes to the stack, where `n` is the current opcode
 pub fn op_pushdata_count_by_opcode(ctx: &mut Context) -> Result<(), ScriptError> {
-    let count = ctx.script1[ctx.ip] as u64;
-    op_pushdata_next_bytes(ctx, count)
+    let opcode = ctx.script1[ctx.ip];
+    op_pushdata_next_bytes(ctx, opcode, opcode as u64)
 }
 
 /// Skips the next `n` bytes where `n` is the current opcode
+///
+/// The push must still be valid
 pub fn skip_pushdata_count_by_opcode(ctx: &mut Context) -> Result<(), ScriptError> {
-    let count = ctx.script1[ctx.ip] as usize;
-    ctx.ip += count;
+    let count = ctx.script1[ctx.ip] as u64;
+    ctx.next_bytes(count)?;
 
     Ok(())
 }
@@ -45,15 +44,16 @@ pub fn disp_pushdata_count_by_opcode(ctx: &mut Context,  writer: &mut io::Write)
     
 }
 
+/// Pushes the number -1 or 1 to 16 for OP_1NEGATE and OP_1 to OP_16
 pub fn op_pushdata_value_by_opcode(ctx: &mut Context) -> Result<(), ScriptError> {
-    let value = ctx.script1[ctx.ip] as i32 - 0x80_i32;
-    ctx.stack.push(Box::new([value as u8]))
+    let value = ctx.script1[ctx.ip] as i64 - (OP_1 as i64 - 1);
+    ctx.stack.push_scriptnum(value)
 }
 
 
 
 pub fn disp_pushdata_value_by_opcode(ctx: &mut Context,  writer: &mut io::Write) -> io::Result<()> {
-    let value = ctx.script1[ctx.ip] as i32 - 0x80_i32;
+    let value = ctx.script1[ctx.ip] as i32 - (OP_1 as i32 - 1);
     write!(writer, "{:02x}", value)
 }
 
@@ -75,7 +75,10 @@ pub fn skip_pushdata_count_by_next_bytes(ctx: &mut Context) -> Result<(), Script
     let size = { size_from_opcode(ctx) };
     let bytecount = try!(ctx.next_uint(size));
 
-    ctx.ip += bytecount as usize;
+    if bytecount > MAX_SCRIPT_ELEMENT_SIZE as u64 {
+        return Err(ScriptError::PushdataTooLarge);
+    }
+    ctx.next_bytes(bytecount)?;
 
     Ok(())
 }
@@ -93,17 +96,18 @@ pub fn disp_pushdata_count_by_next_bytes(ctx: &mut Context,  writer: &mut io::Wr
 /// 
 /// The number of bytes depends on the opcode being OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHDATA4
 pub fn op_pushdata_count_by_next_bytes(ctx: &mut Context) -> Result<(), ScriptError> {
+    let opcode = ctx.script1[ctx.ip];
     let size = { size_from_opcode(ctx) };
     let bytecount = try!(ctx.next_uint(size));
 
-    op_pushdata_next_bytes(ctx, bytecount)
+    op_pushdata_next_bytes(ctx, opcode, bytecount)
 }
 
 /// Helper to push the next `count` bytes to the stack
 ///
-/// Can return an error if `count` is too large
-fn op_pushdata_next_bytes(ctx: &mut Context,  count: u64) -> Result<(), ScriptError> {
-    if count > MAX_SCRIPT_ELEMENT_SIZE {
+/// Can return an error if `count` is too large, or if `opcode` is not the minimal push
+fn op_pushdata_next_bytes(ctx: &mut Context, opcode: u8, count: u64) -> Result<(), ScriptError> {
+    if count > MAX_SCRIPT_ELEMENT_SIZE as u64 {
         return Err(ScriptError::PushdataTooLarge);
     }
 
@@ -112,12 +116,32 @@ fn op_pushdata_next_bytes(ctx: &mut Context,  count: u64) -> Result<(), ScriptEr
     let bytes = try!(ctx.next_bytes(count))
         .to_vec()
         .into_boxed_slice();
+
+    if ctx.has_flag(VERIFY_MINIMALDATA) && !is_minimal_push(opcode, &bytes) {
+        return Err(ScriptError::MinimalData);
+    }
     
     // and push them on the stack
     ctx.stack.push(bytes)
 }
 
 
+/// Returns true if `data` is pushed with the smallest possible opcode
+///
+/// Single bytes 1 to 16 and 0x81 must use OP_1 to OP_16 and OP_1NEGATE
+pub fn is_minimal_push(opcode: u8, data: &[u8]) -> bool {
+    match data.len() {
+        0                                 => opcode == OP_0,
+        1 if data[0] >= 1 && data[0] <= 16 => false,
+        1 if data[0] == 0x81              => false,
+        n if n <= 75                      => opcode as usize == n,
+        n if n <= 0xff                    => opcode == OP_PUSHDATA1,
+        n if n <= 0xffff                  => opcode == OP_PUSHDATA2,
+        _                                 => true
+    }
+}
+
+
 /// Internal helper to display the next `count` bytes to writer;
 /// used to render one of the pushdata operations
 fn disp_pushdata_next_bytes(ctx: &mut Context,  writer: &mut io::Write, count: Result<u64, ScriptError>) -> io::Result<()> {
@@ -130,7 +154,7 @@ fn disp_pushdata_next_bytes(ctx: &mut Context,  writer: &mut io::Write, count: R
     }
 
     let count_val = count.unwrap();
-    if count_val > MAX_SCRIPT_ELEMENT_SIZE {
+    if count_val > MAX_SCRIPT_ELEMENT_SIZE as u64 {
         return write!(writer, "{}", PUSHDATA_TOO_LARGE);
         
     }
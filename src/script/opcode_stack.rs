@@ -0,0 +1,191 @@
+//! Implementing the opcodes that manipulate the stack and the alt-stack
+//!
+//! Depths are counted from the top of the stack, which is at depth 0
+
+
+use script::*;
+use script::context::Context;
+
+
+pub fn op_toaltstack(ctx: &mut Context) -> Result<(), ScriptError> {
+    let item = ctx.stack.pop()?;
+    ctx.alt_stack.push(item)
+}
+
+pub fn op_fromaltstack(ctx: &mut Context) -> Result<(), ScriptError> {
+    let item = ctx.alt_stack.pop()
+        .map_err(|_| ScriptError::AltStackUnderflow)?;
+    ctx.stack.push(item)
+}
+
+pub fn op_2drop(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.top(1)?;
+    ctx.stack.pop()?;
+    ctx.stack.pop()?;
+    Ok(())
+}
+
+/// Pushes copies of the top `count` items
+fn dup_top(ctx: &mut Context, count: usize) -> Result<(), ScriptError> {
+    ctx.stack.top(count - 1)?;
+    for _ in 0..count {
+        let item = Box::from(ctx.stack.top(count - 1)?);
+        ctx.stack.push(item)?;
+    }
+    Ok(())
+}
+
+pub fn op_2dup(ctx: &mut Context) -> Result<(), ScriptError> {
+    dup_top(ctx, 2)
+}
+
+pub fn op_3dup(ctx: &mut Context) -> Result<(), ScriptError> {
+    dup_top(ctx, 3)
+}
+
+pub fn op_2over(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.top(3)?;
+    for _ in 0..2 {
+        let item = Box::from(ctx.stack.top(3)?);
+        ctx.stack.push(item)?;
+    }
+    Ok(())
+}
+
+pub fn op_2rot(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.top(5)?;
+    for _ in 0..2 {
+        let item = ctx.stack.remove(5)?;
+        ctx.stack.push(item)?;
+    }
+    Ok(())
+}
+
+pub fn op_2swap(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.swap(0, 2)?;
+    ctx.stack.swap(1, 3)
+}
+
+pub fn op_ifdup(ctx: &mut Context) -> Result<(), ScriptError> {
+    let item = Box::from(ctx.stack.top(0)?);
+    if stack::as_bool(&item) {
+        ctx.stack.push(item)?;
+    }
+    Ok(())
+}
+
+pub fn op_depth(ctx: &mut Context) -> Result<(), ScriptError> {
+    let depth = ctx.stack.len() as i64;
+    ctx.stack.push_scriptnum(depth)
+}
+
+pub fn op_drop(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.pop()?;
+    Ok(())
+}
+
+pub fn op_dup(ctx: &mut Context) -> Result<(), ScriptError> {
+    dup_top(ctx, 1)
+}
+
+pub fn op_nip(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.remove(1)?;
+    Ok(())
+}
+
+pub fn op_over(ctx: &mut Context) -> Result<(), ScriptError> {
+    let item = Box::from(ctx.stack.top(1)?);
+    ctx.stack.push(item)
+}
+
+/// Pops `n` and returns it as a depth on the remaining stack
+fn pop_depth(ctx: &mut Context) -> Result<usize, ScriptError> {
+    let n = ctx.pop_scriptnum()?;
+    if n < 0 || n as usize >= ctx.stack.len() {
+        return Err(ScriptError::StackUnderflow);
+    }
+    Ok(n as usize)
+}
+
+pub fn op_pick(ctx: &mut Context) -> Result<(), ScriptError> {
+    let depth = pop_depth(ctx)?;
+    let item = Box::from(ctx.stack.top(depth)?);
+    ctx.stack.push(item)
+}
+
+pub fn op_roll(ctx: &mut Context) -> Result<(), ScriptError> {
+    let depth = pop_depth(ctx)?;
+    let item = ctx.stack.remove(depth)?;
+    ctx.stack.push(item)
+}
+
+pub fn op_rot(ctx: &mut Context) -> Result<(), ScriptError> {
+    let item = ctx.stack.remove(2)?;
+    ctx.stack.push(item)
+}
+
+pub fn op_swap(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.swap(0, 1)
+}
+
+pub fn op_tuck(ctx: &mut Context) -> Result<(), ScriptError> {
+    let item = Box::from(ctx.stack.top(0)?);
+    ctx.stack.top(1)?;
+    ctx.stack.insert(2, item)
+}
+
+pub fn op_size(ctx: &mut Context) -> Result<(), ScriptError> {
+    let size = ctx.stack.top(0)?.len() as i64;
+    ctx.stack.push_scriptnum(size)
+}
+
+pub fn op_equal(ctx: &mut Context) -> Result<(), ScriptError> {
+    ctx.stack.top(1)?;
+    let a = ctx.stack.pop()?;
+    let b = ctx.stack.pop()?;
+    ctx.stack.push_bool(a == b)
+}
+
+pub fn op_equalverify(ctx: &mut Context) -> Result<(), ScriptError> {
+    op_equal(ctx)?;
+    if ctx.stack.pop_bool()? {
+        Ok(())
+    }
+    else {
+        Err(ScriptError::EqualVerify)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use script::ScriptError;
+    use script::context::Context;
+    use script::opcode::*;
+
+    fn run(script: &[u8]) -> Result<Vec<Vec<u8>>, ScriptError> {
+        let mut ctx = Context::new(script);
+        ctx.run()?;
+        Ok(ctx.stack.items().iter().map(|item| item.to_vec()).collect())
+    }
+
+    #[test]
+    fn test_stack_ops() {
+        assert_eq!(run(&[OP_1, OP_2, OP_3, OP_ROT]).unwrap(), vec![vec![2], vec![3], vec![1]]);
+        assert_eq!(run(&[OP_1, OP_2, OP_TUCK]).unwrap(), vec![vec![2], vec![1], vec![2]]);
+        assert_eq!(run(&[OP_1, OP_2, OP_3, OP_2, OP_PICK]).unwrap(),
+            vec![vec![1], vec![2], vec![3], vec![1]]);
+        assert_eq!(run(&[OP_1, OP_2, OP_3, OP_2, OP_ROLL]).unwrap(),
+            vec![vec![2], vec![3], vec![1]]);
+        assert_eq!(run(&[OP_1, OP_2, OP_3, OP_4, OP_2SWAP]).unwrap(),
+            vec![vec![3], vec![4], vec![1], vec![2]]);
+        assert_eq!(run(&[OP_1, OP_2, OP_3, OP_4, OP_5, OP_6, OP_2ROT]).unwrap(),
+            vec![vec![3], vec![4], vec![5], vec![6], vec![1], vec![2]]);
+        assert_eq!(run(&[OP_0, OP_IFDUP, OP_DEPTH]).unwrap(), vec![vec![], vec![1]]);
+
+        assert_eq!(run(&[OP_1, OP_1, OP_PICK]), Err(ScriptError::StackUnderflow));
+        assert_eq!(run(&[OP_1, OP_TUCK]), Err(ScriptError::StackUnderflow));
+        assert_eq!(run(&[OP_FROMALTSTACK]), Err(ScriptError::AltStackUnderflow));
+        assert_eq!(run(&[OP_1, OP_2, OP_EQUALVERIFY]), Err(ScriptError::EqualVerify));
+    }
+}
@@ -0,0 +1,160 @@
+//! Encoding rules for signatures and public keys
+//!
+//! These are the standardness and soft-fork rules of bitcoin-core's interpreter; the
+//! signatures themselves are verified by the `SignatureChecker`
+
+
+use crypto::secp256k1;
+use script::*;
+use sighash::{SIGHASH_ALL, SIGHASH_SINGLE, SIGHASH_ANYONECANPAY};
+
+
+/// Returns true if `sig` is a strict DER signature (BIP66) followed by a hashtype byte
+pub fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+
+    // 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S] [sighash]
+    let len = sig.len();
+    if len < 9 || len > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != len - 3 {
+        return false;
+    }
+
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= len {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != len {
+        return false;
+    }
+
+    // R must be a positive integer without superfluous padding
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+
+    // and so must S
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+
+    true
+}
+
+/// Returns true if the hashtype of the signature is ALL, NONE or SINGLE, optionally
+/// combined with ANYONECANPAY
+pub fn is_defined_hashtype(sig: &[u8]) -> bool {
+    match sig.last() {
+        Some(hashtype) => {
+            let base = hashtype & !SIGHASH_ANYONECANPAY;
+            base >= SIGHASH_ALL && base <= SIGHASH_SINGLE
+        },
+        None => false
+    }
+}
+
+fn has_low_s(sig: &[u8]) -> bool {
+    match secp256k1::Signature::parse_der_lax(&sig[..sig.len() - 1]) {
+        Some(sig) => sig.has_low_s(),
+        None      => false
+    }
+}
+
+/// Checks the encoding of a signature as required by the flags
+///
+/// An empty signature is always allowed as it is a compact way to fail a CHECKSIG
+pub fn check_signature_encoding(sig: &[u8], flags: u32) -> Result<(), ScriptError> {
+    if sig.is_empty() {
+        return Ok(());
+    }
+
+    if flags & (VERIFY_DERSIG | VERIFY_LOW_S | VERIFY_STRICTENC) != 0
+        && !is_valid_signature_encoding(sig) {
+        return Err(ScriptError::SigDer);
+    }
+    if flags & VERIFY_LOW_S != 0 && !has_low_s(sig) {
+        return Err(ScriptError::SigHighS);
+    }
+    if flags & VERIFY_STRICTENC != 0 && !is_defined_hashtype(sig) {
+        return Err(ScriptError::SigHashType);
+    }
+    Ok(())
+}
+
+fn is_compressed_or_uncompressed(pubkey: &[u8]) -> bool {
+    match pubkey.first() {
+        Some(&0x04)          => pubkey.len() == 65,
+        Some(&0x02) |
+        Some(&0x03)          => pubkey.len() == 33,
+        _                    => false
+    }
+}
+
+fn is_compressed(pubkey: &[u8]) -> bool {
+    pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03)
+}
+
+/// Checks the encoding of a public key as required by the flags
+pub fn check_pubkey_encoding(pubkey: &[u8], flags: u32, sig_version: SigVersion) -> Result<(), ScriptError> {
+    if flags & VERIFY_STRICTENC != 0 && !is_compressed_or_uncompressed(pubkey) {
+        return Err(ScriptError::PubkeyType);
+    }
+    if flags & VERIFY_WITNESS_PUBKEYTYPE != 0 && sig_version == SigVersion::WitnessV0
+        && !is_compressed(pubkey) {
+        return Err(ScriptError::WitnessPubkeyType);
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::*;
+
+    #[test]
+    fn test_signature_encoding() {
+        let sig = from_hex("3044022057292e2d4dfe775becdd0a9e6547997c728cdf35390f6a017da56d654d3\
+            74e4902206b643be2fc53763b4e284845bfea2c597d2dc7759941dce937636c9d341b71ed01");
+
+        assert!(is_valid_signature_encoding(&sig));
+        assert_eq!(check_signature_encoding(&sig, VERIFY_STRICTENC | VERIFY_LOW_S), Ok(()));
+
+        // padded R
+        let mut padded = sig.clone();
+        padded[1] += 1;
+        padded[3] += 1;
+        padded.insert(4, 0);
+        assert!(!is_valid_signature_encoding(&padded));
+        assert_eq!(check_signature_encoding(&padded, VERIFY_NONE), Ok(()));
+        assert_eq!(check_signature_encoding(&padded, VERIFY_DERSIG), Err(ScriptError::SigDer));
+
+        let mut hashtype = sig.clone();
+        *hashtype.last_mut().unwrap() = 0x21;
+        assert_eq!(check_signature_encoding(&hashtype, VERIFY_DERSIG), Ok(()));
+        assert_eq!(check_signature_encoding(&hashtype, VERIFY_STRICTENC), Err(ScriptError::SigHashType));
+
+        assert_eq!(check_signature_encoding(&[], VERIFY_STRICTENC), Ok(()));
+    }
+
+    #[test]
+    fn test_pubkey_encoding() {
+        let mut key = vec![0x04; 65];
+        assert_eq!(check_pubkey_encoding(&key, VERIFY_STRICTENC, SigVersion::Base), Ok(()));
+        assert_eq!(check_pubkey_encoding(&key, VERIFY_WITNESS_PUBKEYTYPE, SigVersion::WitnessV0),
+            Err(ScriptError::WitnessPubkeyType));
+
+        key[0] = 0x06;
+        assert_eq!(check_pubkey_encoding(&key, VERIFY_NONE, SigVersion::Base), Ok(()));
+        assert_eq!(check_pubkey_encoding(&key, VERIFY_STRICTENC, SigVersion::Base),
+            Err(ScriptError::PubkeyType));
+    }
+}
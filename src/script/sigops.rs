@@ -0,0 +1,142 @@
+//! Counting of signature operations
+//!
+//! This follows bitcoin-core's GetSigOpCount. The signature operations of a block are limited,
+//! as each costs a verification. Legacy counting only inspects the scripts of the transaction
+//! itself and counts each multisig as the maximum of 20 keys. Accurate counting uses the number
+//! of keys pushed before the multisig; this is used for P2SH redeem scripts and witness scripts,
+//! which are only known from the outputs that are spent.
+
+
+use script::*;
+use script::interpreter::{is_p2sh, is_push_only, witness_program};
+use script::instruction::instructions;
+use script::opcode::*;
+
+
+/// Returns the number of signature operations in the script
+///
+/// Counting stops at a truncated push, as bitcoin-core does
+pub fn count_sigops(script: &[u8], accurate: bool) -> usize {
+
+    let mut count = 0;
+    let mut last_opcode = None;
+
+    for op in instructions(script) {
+        let op = match op {
+            Ok(op) => op,
+            Err(_) => break
+        };
+
+        count += match op.opcode {
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => 1,
+
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY =>
+                match last_opcode {
+                    Some(n) if accurate && n >= OP_1 && n <= OP_16 => (n - OP_1 + 1) as usize,
+                    _ => MAX_PUBKEYS_PER_MULTISIG as usize
+                },
+
+            _ => 0
+        };
+        last_opcode = Some(op.opcode);
+    }
+    count
+}
+
+/// Returns the number of signature operations in the redeem script of an input that spends a
+/// P2SH output; this is zero for other outputs
+///
+/// The redeem script is the last push of the script-sig
+pub fn count_p2sh_sigops(script_sig: &[u8], script_pubkey: &[u8]) -> usize {
+
+    if !is_p2sh(script_pubkey) || !is_push_only(script_sig) {
+        return 0;
+    }
+
+    match instructions(script_sig).last() {
+        Some(Ok(op)) => count_sigops(op.data, true),
+        _            => 0
+    }
+}
+
+/// Returns the number of signature operations of the witness program that an input spends,
+/// directly or nested in P2SH; this is zero if witness verification is not enabled
+pub fn count_witness_sigops(script_sig:    &[u8],
+                            script_pubkey: &[u8],
+                            witness:       &[&[u8]],
+                            flags:         u32) -> usize {
+
+    if flags & VERIFY_WITNESS == 0 {
+        return 0;
+    }
+
+    if let Some((version, program)) = witness_program(script_pubkey) {
+        return count_witness_program_sigops(version, program, witness);
+    }
+
+    if is_p2sh(script_pubkey) && is_push_only(script_sig) {
+        if let Some(Ok(op)) = instructions(script_sig).last() {
+            if let Some((version, program)) = witness_program(op.data) {
+                return count_witness_program_sigops(version, program, witness);
+            }
+        }
+    }
+    0
+}
+
+fn count_witness_program_sigops(version: u8, program: &[u8], witness: &[&[u8]]) -> usize {
+
+    if version != 0 {
+        return 0;
+    }
+
+    match (program.len(), witness.last()) {
+        (20, _)            => 1,
+        (32, Some(script)) => count_sigops(script, true),
+        _                  => 0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use script::asm::from_asm;
+
+    #[test]
+    fn test_count_sigops() {
+
+        let p2pkh = from_asm("DUP HASH160 0x14 0x0000000000000000000000000000000000000000 EQUALVERIFY CHECKSIG").unwrap();
+        assert_eq!(count_sigops(&p2pkh, false), 1);
+
+        let multisig = from_asm("2 0x21 0x020000000000000000000000000000000000000000000000000000000000000000 \
+                                 0x21 0x030000000000000000000000000000000000000000000000000000000000000000 \
+                                 2 CHECKMULTISIG").unwrap();
+        assert_eq!(count_sigops(&multisig, false), 20);
+        assert_eq!(count_sigops(&multisig, true), 2);
+
+        // the push is not executed, and counting stops at the truncated push
+        assert_eq!(count_sigops(&from_asm("0x01 0xac CHECKSIGVERIFY 0x4c02 0xac").unwrap(), false), 1);
+
+        // redeem script in P2SH
+        let p2sh = from_asm("HASH160 0x14 0x0000000000000000000000000000000000000000 EQUAL").unwrap();
+        let mut script_sig = vec![0x00];
+        script_sig.extend(instruction::serialize_push(&multisig));
+
+        assert_eq!(count_sigops(&script_sig, false), 0);
+        assert_eq!(count_p2sh_sigops(&script_sig, &p2sh), 2);
+        assert_eq!(count_p2sh_sigops(&script_sig, &p2pkh), 0);
+        assert_eq!(count_p2sh_sigops(&[OP_NOP], &p2sh), 0);
+
+        // witness programs
+        let p2wpkh = from_asm("0 0x14 0x0000000000000000000000000000000000000000").unwrap();
+        let p2wsh  = from_asm("0 0x20 0x0000000000000000000000000000000000000000000000000000000000000000").unwrap();
+
+        assert_eq!(count_witness_sigops(&[], &p2wpkh, &[], VERIFY_WITNESS), 1);
+        assert_eq!(count_witness_sigops(&[], &p2wpkh, &[], VERIFY_P2SH), 0);
+        assert_eq!(count_witness_sigops(&[], &p2wsh, &[&[], &multisig], VERIFY_WITNESS), 2);
+        assert_eq!(count_witness_sigops(&[], &p2wsh, &[], VERIFY_WITNESS), 0);
+        assert_eq!(count_witness_sigops(&instruction::serialize_push(&p2wpkh), &p2sh, &[], VERIFY_WITNESS), 1);
+        assert_eq!(count_witness_sigops(&[], &p2pkh, &[], VERIFY_WITNESS), 0);
+    }
+}
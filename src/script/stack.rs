@@ -1,9 +1,10 @@
+/// The stack of the script interpreter
 ///
-/// Currently unused
-///
+/// Items are byte-vectors; numbers and booleans are encoded in these
 ///
 use super::ScriptError;
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Stack(Vec<Box<[u8]>>);
         
 
@@ -12,6 +13,29 @@ impl Stack {
     pub fn new() -> Stack {
         Stack(Vec::new())
     }
+
+    /// Creates a stack with the given items; the last item is on top
+    pub fn from_items(items: Vec<Box<[u8]>>) -> Stack {
+        Stack(items)
+    }
+
+    /// Returns the items; the last item is on top
+    pub fn items(&self) -> &[Box<[u8]>] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Removes all but the bottom `len` items
+    pub fn truncate(&mut self, len: usize) {
+        self.0.truncate(len)
+    }
     
     /// Pops the top byte-vector from the stack
     ///
@@ -28,6 +52,69 @@ impl Stack {
         self.0.push(data);
         Ok(())
     }
+
+    /// Returns the item at `depth`, where 0 is the top of the stack
+    ///
+    /// Returns a StackUnderflow if the stack is not deep enough
+    pub fn top(&self, depth: usize) -> Result<&[u8], ScriptError> {
+        if depth >= self.0.len() {
+            return Err(ScriptError::StackUnderflow);
+        }
+        Ok(&self.0[self.0.len() - 1 - depth])
+    }
+
+    /// Removes and returns the item at `depth`, where 0 is the top of the stack
+    pub fn remove(&mut self, depth: usize) -> Result<Box<[u8]>, ScriptError> {
+        if depth >= self.0.len() {
+            return Err(ScriptError::StackUnderflow);
+        }
+        let index = self.0.len() - 1 - depth;
+        Ok(self.0.remove(index))
+    }
+
+    /// Inserts the item such that it ends up at `depth`
+    pub fn insert(&mut self, depth: usize, data: Box<[u8]>) -> Result<(), ScriptError> {
+        if depth > self.0.len() {
+            return Err(ScriptError::StackUnderflow);
+        }
+        let index = self.0.len() - depth;
+        self.0.insert(index, data);
+        Ok(())
+    }
+
+    /// Swaps the items at the given depths
+    pub fn swap(&mut self, depth1: usize, depth2: usize) -> Result<(), ScriptError> {
+        let len = self.0.len();
+        if depth1 >= len || depth2 >= len {
+            return Err(ScriptError::StackUnderflow);
+        }
+        self.0.swap(len - 1 - depth1, len - 1 - depth2);
+        Ok(())
+    }
+
+    /// Pops a value and interprets it as a boolean
+    pub fn pop_bool(&mut self) -> Result<bool, ScriptError> {
+        let bytes = self.pop()?;
+        Ok(as_bool(&bytes))
+    }
+
+    /// Pushes a boolean as 1 or as an empty vector
+    pub fn push_bool(&mut self, value: bool) -> Result<(), ScriptError> {
+        if value {
+            self.push(Box::new([1]))
+        }
+        else {
+            self.push(Box::new([]))
+        }
+    }
+
+    /// Pops a scriptnum of at most `max_size` bytes
+    ///
+    /// If `require_minimal` is set, a non-minimally encoded number is rejected
+    pub fn pop_scriptnum_ext(&mut self, max_size: usize, require_minimal: bool) -> Result<i64, ScriptError> {
+        let bytes = self.pop()?;
+        decode_scriptnum(&bytes, max_size, require_minimal)
+    }
     
     /// Pops a value of the stack, interprets its as a scriptnum
     /// and returns it as a i64
@@ -42,35 +129,7 @@ impl Stack {
     /// Can return a stack-underflow if no items are on the stack
     ///
     pub fn pop_scriptnum(&mut self) -> Result<i64, ScriptError> {
-        let bytes = try!(self.pop());
-        
-        if bytes.len() == 0 {
-            return Ok(0);
-        }
-        if bytes.len() > 4 {
-            return Err(ScriptError::NumericOverflow);
-        }
-        
-        // grap each bytes with default 0 
-        let b1 = *bytes.get(0).unwrap_or(&0) as i64;
-        let b2 = *bytes.get(1).unwrap_or(&0) as i64;
-        let b3 = *bytes.get(2).unwrap_or(&0) as i64;
-        let b4 = *bytes.get(3).unwrap_or(&0) as i64;
-
-        let signed_magnitude  = b1 | (b2 << 8) | (b3 << 16) | (b4 << 24);
-        
-        let sign_bit_mask = 0x80_i64 << ((bytes.len()-1)*8);
-        
-        Ok(
-            // if the signed-magnitude is negative,  
-            // convert to "normal" two-complement
-            if (signed_magnitude & sign_bit_mask) > 0 { 
-                -( signed_magnitude ^ sign_bit_mask)
-            }
-            else {
-                signed_magnitude
-            }    
-        )   
+        self.pop_scriptnum_ext(4, false)
     }
     
     /// Stores a scriptnum on the stack
@@ -79,31 +138,87 @@ impl Stack {
     /// If the value is more then 4-bytes,
     /// it will not be possible to read it back as a scriptnum 
     pub fn push_scriptnum(&mut self, n: i64) -> Result<(), ScriptError> {
-        
-        if n == 0 {
-            self.0.push(Box::new([]));
-            return Ok(());
+        self.push(encode_scriptnum(n).into_boxed_slice())
+    }
+    
+}
+
+/// Interprets a stack item as a boolean
+///
+/// Any non-zero value is true, except for negative zero
+pub fn as_bool(bytes: &[u8]) -> bool {
+    for (n, byte) in bytes.iter().enumerate() {
+        if *byte != 0 {
+            return !(n == bytes.len() - 1 && *byte == 0x80);
         }
+    }
+    false
+}
 
-        // two-complement -> sign & magnitude
-        let sign_byte: u8 = ((n >> 56) & 0x80) as u8;
-        let mut magnitude = n.abs();
+/// Decodes a scriptnum of at most `max_size` bytes
+///
+/// Scriptnums are stored as little-endian, sign-and-magnitude
+/// which means that the highest bit of the last byte of the stack-item
+/// determines the sign.
+///
+/// If `require_minimal` is set, the number must not have superfluous zero bytes
+pub fn decode_scriptnum(bytes: &[u8], max_size: usize, require_minimal: bool) -> Result<i64, ScriptError> {
+
+    if bytes.len() > max_size {
+        return Err(ScriptError::NumericOverflow);
+    }
 
-        // push all but last
-        let mut result : Vec<u8> = Vec::with_capacity(8);
-        while magnitude > 0x7F
-        {
-            result.push((magnitude & 0xFF) as u8);
-            magnitude >>= 8;
+    if bytes.is_empty() {
+        return Ok(0);
+    }
+
+    // The last byte may only be 0x00 or 0x80 if the byte before needs its sign bit
+    let last = bytes[bytes.len() - 1];
+    if require_minimal && last & 0x7f == 0 {
+        if bytes.len() == 1 || bytes[bytes.len() - 2] & 0x80 == 0 {
+            return Err(ScriptError::NonMinimalNumber);
         }
+    }
 
-        // push last with sign-byte
-        result.push(((magnitude & 0xFF) as u8) | sign_byte);
-        
-        self.0.push(result.into_boxed_slice());
-        Ok(())
+    let magnitude = bytes.iter().enumerate()
+        .fold(0i64, |sum, (n, byte)| sum | ((*byte as i64) << (8 * n)));
+
+    let sign_bit_mask = 0x80_i64 << ((bytes.len() - 1) * 8);
+
+    Ok(
+        // if the signed-magnitude is negative,
+        // convert to "normal" two-complement
+        if magnitude & sign_bit_mask != 0 {
+            -(magnitude ^ sign_bit_mask)
+        }
+        else {
+            magnitude
+        }
+    )
+}
+
+/// Encodes a number in the minimal scriptnum format
+pub fn encode_scriptnum(n: i64) -> Vec<u8> {
+
+    if n == 0 {
+        return Vec::new();
     }
-    
+
+    // two-complement -> sign & magnitude
+    let sign_byte: u8 = ((n >> 56) & 0x80) as u8;
+    let mut magnitude = n.abs();
+
+    // push all but last
+    let mut result : Vec<u8> = Vec::with_capacity(8);
+    while magnitude > 0x7F
+    {
+        result.push((magnitude & 0xFF) as u8);
+        magnitude >>= 8;
+    }
+
+    // push last with sign-byte
+    result.push(((magnitude & 0xFF) as u8) | sign_byte);
+    result
 }
 
 #[cfg(test)]
@@ -158,6 +273,26 @@ mod test {
         assert_eq!(Err(ScriptError::NumericOverflow), stack.pop_scriptnum());
     }
 
+    #[test]
+    fn test_minimal_scriptnum() {
+        assert_eq!(decode_scriptnum(&[0x00], 4, false), Ok(0));
+        assert_eq!(decode_scriptnum(&[0x00], 4, true), Err(ScriptError::NonMinimalNumber));
+        assert_eq!(decode_scriptnum(&[0x80], 4, true), Err(ScriptError::NonMinimalNumber));
+        assert_eq!(decode_scriptnum(&[0x01, 0x00], 4, true), Err(ScriptError::NonMinimalNumber));
+        assert_eq!(decode_scriptnum(&[0xff, 0x00], 4, true), Ok(255));
+        assert_eq!(decode_scriptnum(&[0xff, 0x80], 4, true), Ok(-255));
+        assert_eq!(decode_scriptnum(&[1, 2, 3, 4, 5], 5, true), Ok(0x0504030201));
+    }
+
+    #[test]
+    fn test_as_bool() {
+        assert!(!as_bool(&[]));
+        assert!(!as_bool(&[0, 0]));
+        assert!(!as_bool(&[0, 0x80]));
+        assert!(as_bool(&[0x80, 0]));
+        assert!(as_bool(&[1]));
+    }
+
     #[test]
     fn test_push_pop_scriptnum() {
 
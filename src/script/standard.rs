@@ -0,0 +1,190 @@
+//! Classification of script-pubkeys into the standard templates
+//!
+//! This follows bitcoin-core's `Solver`: the template determines how an output can be spent
+//! and which address it has, if any
+
+
+use script::interpreter::{is_p2sh, is_push_only, witness_program};
+use script::instruction::instructions;
+use script::opcode::*;
+
+
+/// The template of a script-pubkey with the data it contains
+#[derive(Debug, PartialEq)]
+pub enum ScriptType<'a> {
+
+    /// <pubkey> CHECKSIG
+    PubKey(&'a [u8]),
+
+    /// DUP HASH160 <20 bytes> EQUALVERIFY CHECKSIG
+    PubKeyHash(&'a [u8]),
+
+    /// HASH160 <20 bytes> EQUAL (BIP16)
+    ScriptHash(&'a [u8]),
+
+    /// <m> <pubkey>... <n> CHECKMULTISIG
+    MultiSig { required: usize, keys: Vec<&'a [u8]> },
+
+    /// 0 <20 bytes> (BIP141)
+    WitnessPubKeyHash(&'a [u8]),
+
+    /// 0 <32 bytes> (BIP141)
+    WitnessScriptHash(&'a [u8]),
+
+    /// 1 <32 bytes> (BIP341)
+    Taproot(&'a [u8]),
+
+    /// A witness program of a version that has no meaning yet
+    WitnessUnknown { version: u8, program: &'a [u8] },
+
+    /// RETURN followed by pushes only; the output is provably unspendable
+    NullData,
+
+    NonStandard
+}
+
+impl<'a> ScriptType<'a> {
+
+    /// Returns the name bitcoin-core uses for the template
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ScriptType::PubKey(_)               => "pubkey",
+            ScriptType::PubKeyHash(_)           => "pubkeyhash",
+            ScriptType::ScriptHash(_)           => "scripthash",
+            ScriptType::MultiSig { .. }         => "multisig",
+            ScriptType::WitnessPubKeyHash(_)    => "witness_v0_keyhash",
+            ScriptType::WitnessScriptHash(_)    => "witness_v0_scripthash",
+            ScriptType::Taproot(_)              => "witness_v1_taproot",
+            ScriptType::WitnessUnknown { .. }   => "witness_unknown",
+            ScriptType::NullData                => "nulldata",
+            ScriptType::NonStandard             => "nonstandard"
+        }
+    }
+}
+
+
+/// Returns true if the data has the size of a compressed or uncompressed public key
+fn is_pubkey(data: &[u8]) -> bool {
+    match data.first() {
+        Some(&0x02) | Some(&0x03)               => data.len() == 33,
+        Some(&0x04) | Some(&0x06) | Some(&0x07) => data.len() == 65,
+        _                                        => false
+    }
+}
+
+/// Returns the number of OP_1 to OP_16
+fn small_int(opcode: u8) -> Option<usize> {
+    if opcode >= OP_1 && opcode <= OP_16 {
+        Some((opcode - OP_1 + 1) as usize)
+    }
+    else {
+        None
+    }
+}
+
+fn match_multisig(script: &[u8]) -> Option<ScriptType> {
+
+    let ops: Vec<_> = match instructions(script).collect::<Result<Vec<_>, _>>() {
+        Ok(ops) => ops,
+        Err(_)  => return None
+    };
+    if ops.len() < 4 || ops[ops.len() - 1].opcode != OP_CHECKMULTISIG {
+        return None;
+    }
+
+    let (required, count) = match (small_int(ops[0].opcode), small_int(ops[ops.len() - 2].opcode)) {
+        (Some(required), Some(count)) => (required, count),
+        _                             => return None
+    };
+    let keys = &ops[1..ops.len() - 2];
+
+    if keys.len() != count || required > count || !keys.iter().all(|op| op.is_push() && is_pubkey(op.data)) {
+        return None;
+    }
+
+    Some(ScriptType::MultiSig {
+        required: required,
+        keys:     keys.iter().map(|op| op.data).collect()
+    })
+}
+
+/// Returns the template of the script-pubkey
+pub fn classify(script: &[u8]) -> ScriptType {
+
+    if is_p2sh(script) {
+        return ScriptType::ScriptHash(&script[2..22]);
+    }
+
+    if let Some((version, program)) = witness_program(script) {
+        return match (version, program.len()) {
+            (0, 20) => ScriptType::WitnessPubKeyHash(program),
+            (0, 32) => ScriptType::WitnessScriptHash(program),
+            (0, _)  => ScriptType::NonStandard,
+            (1, 32) => ScriptType::Taproot(program),
+            _       => ScriptType::WitnessUnknown { version: version, program: program }
+        };
+    }
+
+    if script.first() == Some(&OP_RETURN) && is_push_only(&script[1..]) {
+        return ScriptType::NullData;
+    }
+
+    // <pubkey> CHECKSIG
+    if script.len() >= 35 && script[0] as usize == script.len() - 2
+        && script[script.len() - 1] == OP_CHECKSIG
+        && is_pubkey(&script[1..script.len() - 1]) {
+        return ScriptType::PubKey(&script[1..script.len() - 1]);
+    }
+
+    if script.len() == 25
+        && script[0] == OP_DUP
+        && script[1] == OP_HASH160
+        && script[2] == 20
+        && script[23] == OP_EQUALVERIFY
+        && script[24] == OP_CHECKSIG {
+        return ScriptType::PubKeyHash(&script[3..23]);
+    }
+
+    match_multisig(script).unwrap_or(ScriptType::NonStandard)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use script::asm::from_asm;
+
+    fn classify_asm(asm: &str) -> String {
+        classify(&from_asm(asm).unwrap()).name().to_string()
+    }
+
+    #[test]
+    fn test_classify() {
+        let key = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let hash = "89abcdefabbaabbaabbaabbaabbaabbaabbaabba";
+        let hash32 = "1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262";
+
+        assert_eq!(classify_asm(&format!("{} CHECKSIG", key)), "pubkey");
+        assert_eq!(classify_asm(&format!("DUP HASH160 {} EQUALVERIFY CHECKSIG", hash)), "pubkeyhash");
+        assert_eq!(classify_asm(&format!("HASH160 {} EQUAL", hash)), "scripthash");
+        assert_eq!(classify_asm(&format!("0 {}", hash)), "witness_v0_keyhash");
+        assert_eq!(classify_asm(&format!("0 {}", hash32)), "witness_v0_scripthash");
+        assert_eq!(classify_asm(&format!("1 {}", hash32)), "witness_v1_taproot");
+        assert_eq!(classify_asm(&format!("1 {}", hash)), "witness_unknown");
+        assert_eq!(classify_asm("16 0x02 0x751e"), "witness_unknown");
+        assert_eq!(classify_asm("RETURN 0x04 0x01020304 5"), "nulldata");
+        assert_eq!(classify_asm("RETURN"), "nulldata");
+
+        assert_eq!(classify(&from_asm(&format!("1 {} {} 2 CHECKMULTISIG", key, key)).unwrap()),
+            ScriptType::MultiSig { required: 1, keys: vec![&from_asm(key).unwrap()[1..]; 2] });
+
+        // near misses
+        assert_eq!(classify_asm(&format!("0 0x15 0x{}00", hash)), "nonstandard");
+        assert_eq!(classify_asm("RETURN DUP"), "nonstandard");
+        assert_eq!(classify_asm(&format!("{}00 CHECKSIG", key)), "nonstandard");
+        assert_eq!(classify_asm(&format!("DUP HASH160 {} EQUAL CHECKSIG", hash)), "nonstandard");
+        assert_eq!(classify_asm(&format!("3 {} {} 2 CHECKMULTISIG", key, key)), "nonstandard");
+        assert_eq!(classify_asm(&format!("1 {} 2 CHECKMULTISIG", key)), "nonstandard");
+        assert_eq!(classify_asm(""), "nonstandard");
+    }
+}
@@ -0,0 +1,581 @@
+//! Signature hashes
+//!
+//! A signature does not sign a transaction as such, but a digest of the parts of the
+//! transaction selected by its hashtype. There are three algorithms:
+//!
+//! * The original algorithm for legacy scripts, including the bug that SIGHASH_SINGLE without
+//!   a corresponding output signs the number 1
+//! * BIP143 for version 0 witness programs
+//! * BIP341 for taproot (version 1 witness programs)
+//!
+//! The segwit algorithms hash the prevouts, sequences and outputs of the transaction only once
+//! for all inputs; a `SighashCache` keeps these midstates for a transaction.
+
+use std::cell::Cell;
+
+use ring;
+
+use buffer::write_compact_size;
+use hash::{Hash32, Hash32Buf};
+use script::SigVersion;
+use script::instruction;
+use script::opcode::OP_CODESEPARATOR;
+use transaction::{Transaction, TxOutput};
+
+
+/// Only valid for taproot; signs the same as SIGHASH_ALL
+pub const SIGHASH_DEFAULT:      u8 = 0;
+pub const SIGHASH_ALL:          u8 = 1;
+pub const SIGHASH_NONE:         u8 = 2;
+pub const SIGHASH_SINGLE:       u8 = 3;
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+
+#[derive(Debug, PartialEq)]
+pub enum SighashError {
+
+    /// The hashtype is not one of the values allowed by BIP341
+    InvalidHashType,
+
+    /// SIGHASH_SINGLE is used for an input without a corresponding output
+    NoSingleOutput,
+
+    /// The input index is not an input of the transaction
+    InputOutOfRange,
+
+    /// The outputs spent by all inputs are needed for taproot signature hashes
+    SpentOutputsMissing
+}
+
+pub type SighashResult<T> = Result<T, SighashError>;
+
+
+/// Computes the signature hashes for the inputs of a transaction
+///
+/// The midstates are computed when first needed and reused for the other inputs
+pub struct SighashCache<'a> {
+    tx: &'a Transaction<'a>,
+
+    /// The outputs spent by the inputs of the transaction, in the same order
+    spent_outputs: Option<&'a [TxOutput<'a>]>,
+
+    // single SHA256 midstates as used by BIP341; BIP143 hashes them once more
+    prevouts:       Cell<Option<Hash32Buf>>,
+    sequences:      Cell<Option<Hash32Buf>>,
+    outputs:        Cell<Option<Hash32Buf>>,
+    amounts:        Cell<Option<Hash32Buf>>,
+    script_pubkeys: Cell<Option<Hash32Buf>>
+}
+
+impl<'a> SighashCache<'a> {
+
+    pub fn new(tx: &'a Transaction<'a>) -> SighashCache<'a> {
+        SighashCache {
+            tx:             tx,
+            spent_outputs:  None,
+            prevouts:       Cell::new(None),
+            sequences:      Cell::new(None),
+            outputs:        Cell::new(None),
+            amounts:        Cell::new(None),
+            script_pubkeys: Cell::new(None)
+        }
+    }
+
+    /// Creates a cache that can also compute taproot signature hashes, which commit to the
+    /// amounts and scripts of all outputs spent by the transaction
+    pub fn with_spent_outputs(tx: &'a Transaction<'a>, spent_outputs: &'a [TxOutput<'a>]) -> SighashCache<'a> {
+        SighashCache {
+            spent_outputs: Some(spent_outputs),
+            .. SighashCache::new(tx)
+        }
+    }
+
+    pub fn transaction(&self) -> &'a Transaction<'a> {
+        self.tx
+    }
+
+    /// Returns the hash signed for the given input by a legacy or version 0 witness script
+    ///
+    /// The amount is the value of the spent output and is only used for witness scripts
+    pub fn signature_hash(&self,
+                          input:       usize,
+                          script_code: &[u8],
+                          amount:      i64,
+                          hashtype:    u32,
+                          sig_version: SigVersion) -> Hash32Buf {
+        match sig_version {
+            SigVersion::Base      => self.legacy(input, script_code, hashtype),
+            SigVersion::WitnessV0 => self.segwit_v0(input, script_code, amount, hashtype)
+        }
+    }
+
+    /// The original signature hash algorithm
+    ///
+    /// The full hashtype is serialized, but only its lower bits determine what is signed. An input
+    /// without a corresponding output for SIGHASH_SINGLE, or an input that doesn't exist,
+    /// signs the number 1
+    pub fn legacy(&self, input: usize, script_code: &[u8], hashtype: u32) -> Hash32Buf {
+
+        let base = hashtype as u8 & 0x1f;
+        let anyone_can_pay = hashtype as u8 & SIGHASH_ANYONECANPAY != 0;
+        let tx = self.tx;
+
+        if input >= tx.txs_in.len()
+            || (base == SIGHASH_SINGLE && input >= tx.txs_out.len()) {
+            let mut one = [0u8; 32];
+            one[0] = 1;
+            return Hash32Buf::from_slice(&one);
+        }
+
+        let mut buf = Vec::new();
+        write_u32(&mut buf, tx.version as u32);
+
+        let inputs: Vec<usize> = if anyone_can_pay { vec![input] } else { (0..tx.txs_in.len()).collect() };
+        write_compact_size(&mut buf, inputs.len());
+        for n in inputs {
+            let tx_in = &tx.txs_in[n];
+            buf.extend_from_slice(tx_in.prev_tx_out.0);
+            write_u32(&mut buf, tx_in.prev_tx_out_idx);
+
+            if n == input {
+                write_script(&mut buf, &without_codeseparators(script_code));
+                write_u32(&mut buf, tx_in.sequence);
+            }
+            else {
+                write_compact_size(&mut buf, 0);
+                let other_sequence = if base == SIGHASH_NONE || base == SIGHASH_SINGLE { 0 } else { tx_in.sequence };
+                write_u32(&mut buf, other_sequence);
+            }
+        }
+
+        match base {
+            SIGHASH_NONE => write_compact_size(&mut buf, 0),
+            SIGHASH_SINGLE => {
+                write_compact_size(&mut buf, input + 1);
+                for _ in 0..input {
+                    write_u64(&mut buf, -1i64 as u64);
+                    write_compact_size(&mut buf, 0);
+                }
+                write_output(&mut buf, &tx.txs_out[input]);
+            },
+            _ => {
+                write_compact_size(&mut buf, tx.txs_out.len());
+                for output in tx.txs_out.iter() {
+                    write_output(&mut buf, output);
+                }
+            }
+        }
+
+        write_u32(&mut buf, tx.lock_time);
+        write_u32(&mut buf, hashtype);
+
+        Hash32Buf::double_sha256(&buf)
+    }
+
+    /// The signature hash algorithm of BIP143 for version 0 witness programs
+    pub fn segwit_v0(&self, input: usize, script_code: &[u8], amount: i64, hashtype: u32) -> Hash32Buf {
+
+        let base = hashtype as u8 & 0x1f;
+        let anyone_can_pay = hashtype as u8 & SIGHASH_ANYONECANPAY != 0;
+        let tx = self.tx;
+        let zero = Hash32Buf::from_slice(&[0u8; 32]);
+
+        let hash_prevouts = if anyone_can_pay { zero } else {
+            sha256(self.prevouts().as_ref().0)
+        };
+
+        let hash_sequence = if anyone_can_pay || base == SIGHASH_SINGLE || base == SIGHASH_NONE { zero } else {
+            sha256(self.sequences().as_ref().0)
+        };
+
+        let hash_outputs = if base != SIGHASH_SINGLE && base != SIGHASH_NONE {
+            sha256(self.outputs().as_ref().0)
+        }
+        else if base == SIGHASH_SINGLE && input < tx.txs_out.len() {
+            let mut buf = Vec::new();
+            write_output(&mut buf, &tx.txs_out[input]);
+            Hash32Buf::double_sha256(&buf)
+        }
+        else {
+            zero
+        };
+
+        let tx_in = &tx.txs_in[input];
+
+        let mut buf = Vec::with_capacity(160 + script_code.len());
+        write_u32(&mut buf, tx.version as u32);
+        buf.extend_from_slice(hash_prevouts.as_ref().0);
+        buf.extend_from_slice(hash_sequence.as_ref().0);
+        buf.extend_from_slice(tx_in.prev_tx_out.0);
+        write_u32(&mut buf, tx_in.prev_tx_out_idx);
+        write_script(&mut buf, script_code);
+        write_u64(&mut buf, amount as u64);
+        write_u32(&mut buf, tx_in.sequence);
+        buf.extend_from_slice(hash_outputs.as_ref().0);
+        write_u32(&mut buf, tx.lock_time);
+        write_u32(&mut buf, hashtype);
+
+        Hash32Buf::double_sha256(&buf)
+    }
+
+    /// The signature hash algorithm of BIP341 for taproot
+    ///
+    /// `annex` is the last witness item if it starts with 0x50. For a script path spend,
+    /// `script_path` is the tapleaf hash and the position of the last executed OP_CODESEPARATOR,
+    /// or 0xffffffff if there is none
+    pub fn taproot(&self,
+                   input:       usize,
+                   hashtype:    u8,
+                   annex:       Option<&[u8]>,
+                   script_path: Option<(Hash32, u32)>) -> SighashResult<Hash32Buf> {
+
+        match hashtype {
+            0x00 ... 0x03 | 0x81 ... 0x83 => {},
+            _                              => return Err(SighashError::InvalidHashType)
+        }
+
+        let tx = self.tx;
+        let spent_outputs = match self.spent_outputs {
+            Some(spent_outputs) if spent_outputs.len() == tx.txs_in.len() => spent_outputs,
+            _                                                             => return Err(SighashError::SpentOutputsMissing)
+        };
+        if input >= tx.txs_in.len() {
+            return Err(SighashError::InputOutOfRange);
+        }
+
+        let output_type = hashtype & 0x03;
+        let anyone_can_pay = hashtype & SIGHASH_ANYONECANPAY != 0;
+
+        if output_type == SIGHASH_SINGLE && input >= tx.txs_out.len() {
+            return Err(SighashError::NoSingleOutput);
+        }
+
+        // the epoch allows future extension of the algorithm
+        let mut buf = vec![0, hashtype];
+        write_u32(&mut buf, tx.version as u32);
+        write_u32(&mut buf, tx.lock_time);
+
+        if !anyone_can_pay {
+            buf.extend_from_slice(self.prevouts().as_ref().0);
+            buf.extend_from_slice(self.amounts(spent_outputs).as_ref().0);
+            buf.extend_from_slice(self.script_pubkeys(spent_outputs).as_ref().0);
+            buf.extend_from_slice(self.sequences().as_ref().0);
+        }
+        if output_type != SIGHASH_NONE && output_type != SIGHASH_SINGLE {
+            buf.extend_from_slice(self.outputs().as_ref().0);
+        }
+
+        let spend_type = if script_path.is_some() { 2 } else { 0 } + if annex.is_some() { 1 } else { 0 };
+        buf.push(spend_type);
+
+        if anyone_can_pay {
+            let tx_in = &tx.txs_in[input];
+            buf.extend_from_slice(tx_in.prev_tx_out.0);
+            write_u32(&mut buf, tx_in.prev_tx_out_idx);
+            write_output(&mut buf, &spent_outputs[input]);
+            write_u32(&mut buf, tx_in.sequence);
+        }
+        else {
+            write_u32(&mut buf, input as u32);
+        }
+
+        if let Some(annex) = annex {
+            let mut annex_buf = Vec::with_capacity(annex.len() + 9);
+            write_script(&mut annex_buf, annex);
+            buf.extend_from_slice(sha256(&annex_buf).as_ref().0);
+        }
+
+        if output_type == SIGHASH_SINGLE {
+            let mut output_buf = Vec::new();
+            write_output(&mut output_buf, &tx.txs_out[input]);
+            buf.extend_from_slice(sha256(&output_buf).as_ref().0);
+        }
+
+        if let Some((leaf_hash, code_separator)) = script_path {
+            buf.extend_from_slice(leaf_hash.0);
+
+            // key version
+            buf.push(0);
+            write_u32(&mut buf, code_separator);
+        }
+
+        Ok(tagged_hash(b"TapSighash", &buf))
+    }
+
+    /// Returns the cached midstate or computes and caches it
+    fn cached<F: Fn() -> Hash32Buf>(cell: &Cell<Option<Hash32Buf>>, compute: F) -> Hash32Buf {
+        match cell.get() {
+            Some(hash) => hash,
+            None       => {
+                let hash = compute();
+                cell.set(Some(hash));
+                hash
+            }
+        }
+    }
+
+    fn prevouts(&self) -> Hash32Buf {
+        SighashCache::cached(&self.prevouts, || {
+            let mut buf = Vec::with_capacity(36 * self.tx.txs_in.len());
+            for tx_in in self.tx.txs_in.iter() {
+                buf.extend_from_slice(tx_in.prev_tx_out.0);
+                write_u32(&mut buf, tx_in.prev_tx_out_idx);
+            }
+            sha256(&buf)
+        })
+    }
+
+    fn sequences(&self) -> Hash32Buf {
+        SighashCache::cached(&self.sequences, || {
+            let mut buf = Vec::with_capacity(4 * self.tx.txs_in.len());
+            for tx_in in self.tx.txs_in.iter() {
+                write_u32(&mut buf, tx_in.sequence);
+            }
+            sha256(&buf)
+        })
+    }
+
+    fn outputs(&self) -> Hash32Buf {
+        SighashCache::cached(&self.outputs, || {
+            let mut buf = Vec::new();
+            for output in self.tx.txs_out.iter() {
+                write_output(&mut buf, output);
+            }
+            sha256(&buf)
+        })
+    }
+
+    fn amounts(&self, spent_outputs: &[TxOutput]) -> Hash32Buf {
+        SighashCache::cached(&self.amounts, || {
+            let mut buf = Vec::with_capacity(8 * spent_outputs.len());
+            for output in spent_outputs {
+                write_u64(&mut buf, output.value as u64);
+            }
+            sha256(&buf)
+        })
+    }
+
+    fn script_pubkeys(&self, spent_outputs: &[TxOutput]) -> Hash32Buf {
+        SighashCache::cached(&self.script_pubkeys, || {
+            let mut buf = Vec::new();
+            for output in spent_outputs {
+                write_script(&mut buf, output.pk_script);
+            }
+            sha256(&buf)
+        })
+    }
+}
+
+
+fn sha256(data: &[u8]) -> Hash32Buf {
+    Hash32Buf::from_slice(ring::digest::digest(&ring::digest::SHA256, data).as_ref())
+}
+
+/// The tagged hash of BIP340: SHA256(SHA256(tag) || SHA256(tag) || data)
+pub fn tagged_hash(tag: &[u8], data: &[u8]) -> Hash32Buf {
+    let tag_hash = ring::digest::digest(&ring::digest::SHA256, tag);
+
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    ctx.update(tag_hash.as_ref());
+    ctx.update(tag_hash.as_ref());
+    ctx.update(data);
+    Hash32Buf::from_slice(ctx.finish().as_ref())
+}
+
+/// Returns the script with all OP_CODESEPARATORs removed
+fn without_codeseparators(script: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(script.len());
+    let mut iter = instruction::instructions(script);
+    let mut start = 0;
+    loop {
+        match iter.next() {
+            Some(Ok(ref op)) if op.opcode == OP_CODESEPARATOR => {},
+            Some(Ok(_)) => result.extend_from_slice(&script[start..iter.position()]),
+
+            // a truncated push is copied as is
+            Some(Err(_)) => result.extend_from_slice(&script[start..]),
+            None         => break
+        }
+        start = iter.position();
+    }
+    result
+}
+
+fn write_u32(buf: &mut Vec<u8>, value: u32) {
+    for n in 0..4 {
+        buf.push((value >> (n * 8)) as u8);
+    }
+}
+
+fn write_u64(buf: &mut Vec<u8>, value: u64) {
+    for n in 0..8 {
+        buf.push((value >> (n * 8)) as u8);
+    }
+}
+
+fn write_script(buf: &mut Vec<u8>, script: &[u8]) {
+    write_compact_size(buf, script.len());
+    buf.extend_from_slice(script);
+}
+
+fn write_output(buf: &mut Vec<u8>, output: &TxOutput) {
+    write_u64(buf, output.value as u64);
+    write_script(buf, output.pk_script);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use buffer::{Buffer, Parse};
+    use util::*;
+
+    /// Returns the hash as displayed by bitcoin-core, which reverses the bytes
+    fn reversed_hex(hash: Hash32Buf) -> String {
+        let mut bytes = hash.as_ref().0.to_vec();
+        bytes.reverse();
+        to_hex(&bytes)
+    }
+
+    /// The number of vectors in tests/data/sighash.json; updated with the vectors
+    const SIGHASH_VECTORS: usize = 50;
+
+    #[test]
+    fn test_sighash_vectors() {
+
+        // [raw transaction, script, input index, hashtype, signature hash]
+        let vectors: serde_json::Value = serde_json::from_str(
+            include_str!("../tests/data/sighash.json")).unwrap();
+
+        let mut count = 0;
+        for vector in vectors.as_array().unwrap() {
+            let vector = vector.as_array().unwrap();
+
+            // skip comments
+            if vector.len() < 5 {
+                continue;
+            }
+
+            let raw      = from_hex(vector[0].as_str().unwrap());
+            let script   = from_hex(vector[1].as_str().unwrap());
+            let input    = vector[2].as_f64().unwrap() as usize;
+            let hashtype = vector[3].as_f64().unwrap() as i32;
+            let expected = vector[4].as_str().unwrap();
+
+            let tx = Transaction::parse(&mut Buffer::new(&raw)).unwrap();
+            let cache = SighashCache::new(&tx);
+
+            assert_eq!(reversed_hex(cache.legacy(input, &script, hashtype as u32)), expected,
+                "Failed vector {:?}", vector);
+            count += 1;
+        }
+        assert_eq!(count, SIGHASH_VECTORS);
+    }
+
+    #[test]
+    fn test_segwit_v0() {
+
+        // Native P2WPKH example of BIP143
+        let raw = from_hex("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00\
+                      00000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a\
+                      0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a\
+                      6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000");
+        let tx = Transaction::parse(&mut Buffer::new(&raw)).unwrap();
+        let cache = SighashCache::new(&tx);
+
+        let script_code = from_hex("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac");
+        assert_eq!(to_hex(cache.segwit_v0(1, &script_code, 600_000_000, SIGHASH_ALL as u32).as_ref().0),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670");
+
+        // BIP143 hashes the cached midstates once more
+        assert_eq!(to_hex(sha256(cache.prevouts().as_ref().0).as_ref().0),
+            "96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37");
+        assert_eq!(to_hex(sha256(cache.sequences().as_ref().0).as_ref().0),
+            "52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b");
+        assert_eq!(to_hex(sha256(cache.outputs().as_ref().0).as_ref().0),
+            "863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5");
+    }
+
+    #[test]
+    fn test_taproot() {
+
+        // The key path spending transaction of the BIP341 test vectors
+        let raw = from_hex("02000000097de20cbff686da83a54981d2b9bab3586f4ca7e48f57f5b55963115f3b334e9c01\
+            0000000000000000d7b7cab57b1393ace2d064f4d4a2cb8af6def61273e127517d44759b6dafdd990000000000\
+            fffffffff8e1f583384333689228c5d28eac13366be082dc57441760d957275419a418420000000000ffffffff\
+            f0689180aa63b30cb162a73c6d2a38b7eeda2a83ece74310fda0843ad604853b0100000000feffffffaa5202bd\
+            f6d8ccd2ee0f0202afbbb7461d9264a25e5bfd3c5a52ee1239e0ba6c0000000000feffffff956149bdc66faa96\
+            8eb2be2d2faa29718acbfe3941215893a2a3446d32acd050000000000000000000e664b9773b88c09c32cb70a2\
+            a3e4da0ced63b7ba3b22f848531bbb1d5d5f4c94010000000000000000e9aa6b8e6c9de67619e6a3924ae25696\
+            bb7b694bb677a632a74ef7eadfd4eabf0000000000ffffffffa778eb6a263dc090464cd125c466b5a99667720b\
+            1c110468831d058aa1b82af10100000000ffffffff0200ca9a3b000000001976a91406afd46bcdfd22ef94ac12\
+            2aa11f241244a37ecc88ac807840cb0000000020ac9a87f5594be208f8532db38cff670c450ed2fea8fcdefcc9\
+            a663f78bab962b0065cd1d");
+        let tx = Transaction::parse(&mut Buffer::new(&raw)).unwrap();
+
+        let amounts = [420000000, 462000000, 294000000, 504000000, 630000000,
+                       378000000, 672000000, 546000000, 588000000];
+        let scripts: Vec<Vec<u8>> = [
+            "512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343",
+            "5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3",
+            "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac",
+            "5120e4d810fd50586274face62b8a807eb9719cef49c04177cc6b76a9a4251d5450e",
+            "512091b64d5324723a985170e4dc5a0f84c041804f2cd12660fa5dec09fc21783605",
+            "00147dd65592d0ab2fe0d0257d571abf032cd9db93dc",
+            "512075169f4001aa68f15bbed28b218df1d0a62cbbcf1188c6665110c293c907b831",
+            "5120712447206d7a5238acc7ff53fbe94a3b64539ad291c7cdbd490b7577e4b17df5",
+            "512077e30a5522dd9f894c3f8b8bd4c4b2cf82ca7da8a3ea6a239655c39c050ab220"
+        ].iter().map(|script| from_hex(script)).collect();
+
+        let spent_outputs: Vec<TxOutput> = amounts.iter().zip(scripts.iter())
+            .map(|(amount, script)| TxOutput { value: *amount, pk_script: script })
+            .collect();
+
+        assert_eq!(SighashCache::new(&tx).taproot(0, SIGHASH_DEFAULT, None, None),
+            Err(SighashError::SpentOutputsMissing));
+
+        let cache = SighashCache::with_spent_outputs(&tx, &spent_outputs);
+
+        assert_eq!(to_hex(cache.prevouts().as_ref().0),
+            "e3b33bb4ef3a52ad1fffb555c0d82828eb22737036eaeb02a235d82b909c4c3f");
+        assert_eq!(to_hex(cache.amounts(&spent_outputs).as_ref().0),
+            "58a6964a4f5f8f0b642ded0a8a553be7622a719da71d1f5befcefcdee8e0fde6");
+        assert_eq!(to_hex(cache.sequences().as_ref().0),
+            "18959c7221ab5ce9e26c3cd67b22c24f8baa54bac281d8e6b05e400e6c3a957e");
+        assert_eq!(to_hex(cache.outputs().as_ref().0),
+            "a2e6dab7c1f0dcd297c8d61647fd17d821541ea69c3cc37dcbad7f90d4eb4bc5");
+
+        let taproot = |input, hashtype| to_hex(cache.taproot(input, hashtype, None, None).unwrap().as_ref().0);
+        assert_eq!(taproot(1, SIGHASH_SINGLE | SIGHASH_ANYONECANPAY),
+            "325a644af47e8a5a2591cda0ab0723978537318f10e6a63d4eed783b96a71a4d");
+        assert_eq!(taproot(7, SIGHASH_NONE | SIGHASH_ANYONECANPAY),
+            "a82a56ebd928a1b7dbc24ae7747ca1bd717ac9a30ca98b3e0e97607dc38e6a8f");
+        assert_eq!(taproot(8, SIGHASH_ALL | SIGHASH_ANYONECANPAY),
+            "cccb739eca6c13a8a89e6e5cd317ffe55669bbda23f2fd37b0f18755e008edd2");
+        assert_eq!(taproot(3, SIGHASH_ALL),
+            "e3a027df20a8b374b4018af18550817339b671afd89e4a565aa436511e014f3f");
+        assert_eq!(taproot(4, SIGHASH_DEFAULT),
+            "a886e852db0eccbfeac0102044e66364645e3d2c05f52f0c7ca64a1a648b9edc");
+
+        // script path with an annex
+        let leaf_hash = tagged_hash(b"TapLeaf", &[0xc0, 0x01, 0x51]);
+        assert_eq!(to_hex(leaf_hash.as_ref().0),
+            "a85b2107f791b26a84e7586c28cec7cb61202ed3d01944d832500f363782d675");
+        assert_eq!(to_hex(cache.taproot(3, SIGHASH_DEFAULT, Some(&[0x50, 0x01, 0x02]),
+                Some((leaf_hash.as_ref(), 0xffff_ffff))).unwrap().as_ref().0),
+            "640f7aa58096e1ca367e37762ce3b8a213f06cc335bf2f9a436a944aba5a8962");
+        assert_eq!(to_hex(cache.taproot(0, SIGHASH_SINGLE, None, Some((leaf_hash.as_ref(), 1))).unwrap().as_ref().0),
+            "b068eb88d70ff31a7ce9d37ddf24a6858aa0c30811df2b95558b9aa7546b7119");
+
+        assert_eq!(cache.taproot(0, 0x04, None, None), Err(SighashError::InvalidHashType));
+        assert_eq!(cache.taproot(2, SIGHASH_SINGLE, None, None), Err(SighashError::NoSingleOutput));
+        assert_eq!(cache.taproot(9, SIGHASH_ALL, None, None), Err(SighashError::InputOutOfRange));
+    }
+
+    #[test]
+    fn test_without_codeseparators() {
+        assert_eq!(without_codeseparators(&[0xab, 0x01, 0xab, 0xab, 0x51]), vec![0x01, 0xab, 0x51]);
+        assert_eq!(without_codeseparators(&[0xab, 0x4c, 0x05, 0xab]), vec![0x4c, 0x05, 0xab]);
+    }
+}
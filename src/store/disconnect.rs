@@ -0,0 +1,271 @@
+//! Disconnecting of blocks
+//!
+//! `disconnect_block` detaches a connected block from its previous block, such that a block can
+//! be invalidated, and reorgs can be made in both directions. The block and the blocks on top of
+//! it become orphans again:
+//!
+//! * their start-of-block records are reset to orphan,
+//! * the block-index entry of each of them is replaced by guards of the blocks on top of it,
+//!   such that these are verified and connected again when the block is added again,
+//! * their tips are removed; the previous block becomes a tip if no other block is on top of it.
+//!
+//! The spend-index is moved down to the previous block before the links are reset, and then to
+//! the best remaining tip. This is done with the spend-index lock held, after re-reading its tip,
+//! as other stores may have moved it; they re-read the moved tip when they next use it. The
+//! disconnected block itself is no longer referenced; adding it again stores it anew.
+
+use std::collections::HashMap;
+
+use hash::*;
+use block_add;
+
+use store::{Store, BlockPtr, Record, HashIndexGuard};
+use store::tips::{self, Tip};
+
+
+#[derive(Debug, PartialEq)]
+pub enum DisconnectError {
+
+    /// The block is not a connected block in the block-index
+    NotFound
+}
+
+
+impl Store {
+
+    /// Disconnects the block with the given hash, and the blocks on top of it
+    ///
+    /// Returns the number of blocks that are disconnected
+    pub fn disconnect_block(&mut self, block_hash: Hash32) -> Result<u64, DisconnectError> {
+
+        // other stores cannot add blocks meanwhile
+        let _lock = self.lock.exclusive();
+
+        let block = match self.block_index.get(block_hash).into_iter().find(|ptr| !ptr.is_guard()) {
+            Some(block) => block,
+            None        => return Err(DisconnectError::NotFound)
+        };
+
+        let previous = self.spend_tree.get_previous_block(block);
+        let height   = block_add::get_height(self, block);
+
+        // the blocks on top of each disconnected block, by the start of the latter
+        let mut next_blocks: HashMap<u64, Vec<BlockPtr>> = HashMap::new();
+        let mut blocks        = vec![block];
+        let mut removed_tips  = Vec::new();
+        let mut previous_work = None;
+
+        for tip in self.tips.get_tips() {
+
+            let path = match self.get_blocks_down_to(&tip, height) {
+                Some(ref path) if path.last() == Some(&block) => path.clone(),
+                _ => continue
+            };
+
+            if previous_work.is_none() {
+                let mut work = tip.work;
+                for &current in path.iter() {
+                    work = work - self.get_block_work(current);
+                }
+                previous_work = Some(work);
+            }
+
+            for pair in path.windows(2) {
+                let next = next_blocks.entry(pair[1].start.to_index()).or_insert_with(Vec::new);
+                if !next.contains(&pair[0]) {
+                    next.push(pair[0]);
+                    blocks.push(pair[0]);
+                }
+            }
+            removed_tips.push(tip);
+        }
+
+        // the spend-index is moved with the tip as left by other stores
+        let _spend_index_lock = self.lock_spend_index();
+
+        // the spend-index must be valid up to the previous block before the links are reset
+        let spend_index_affected = self.spend_index.get_tip()
+            .map_or(false, |tip| blocks.contains(&tip));
+
+        if spend_index_affected {
+            match previous {
+                Some(previous) => self.spend_tree.move_spend_index(&mut self.spend_index, &self.logger, previous),
+                None           => self.spend_index.clear()
+            }
+        }
+
+        for &current in blocks.iter() {
+
+            self.spend_tree.get_block_mut(current)[0] = Record::new_orphan_block_start();
+
+            let hash = self.get_block_hash(current);
+            let mut values: Vec<BlockPtr> = self.block_index.get(hash.as_ref()).into_iter()
+                .filter(|ptr| ptr.is_guard())
+                .collect();
+
+            for next in next_blocks.get(&current.start.to_index()).into_iter().flat_map(|next| next.iter()) {
+                if !values.contains(&next.to_guard()) {
+                    values.push(next.to_guard());
+                }
+            }
+
+            self.block_index.replace(hash.as_ref(), &values);
+        }
+
+        for tip in removed_tips.iter() {
+            self.tips.remove_tip(tip);
+        }
+
+        if let (Some(previous), Some(work)) = (previous, previous_work) {
+
+            let mut is_tip = true;
+            for tip in self.tips.get_tips() {
+                if let Some(path) = self.get_blocks_down_to(&tip, height - 1) {
+                    is_tip = is_tip && path.last() != Some(&previous);
+                }
+            }
+
+            if is_tip {
+                let previous_hash = self.get_block_hash(previous);
+                tips::add_tip(&self.tips, previous_hash, None, work, height - 1);
+            }
+        }
+
+        info!(self.logger, "disconnect_block";
+            "hash"   => format!("{:?}", block_hash),
+            "blocks" => blocks.len());
+
+        block_add::update_main_chain(self);
+
+        Ok(blocks.len() as u64)
+    }
+
+    /// Returns the blocks from the block of the tip down to the given height
+    ///
+    /// Returns None if the tip is lower, or if its block is not connected that far
+    fn get_blocks_down_to(&mut self, tip: &Tip, height: u64) -> Option<Vec<BlockPtr>> {
+
+        if tip.height < height {
+            return None;
+        }
+
+        let mut block = match self.block_index.get(tip.block_hash.as_ref()).into_iter().find(|ptr| !ptr.is_guard()) {
+            Some(block) => block,
+            None        => return None
+        };
+
+        let mut blocks = vec![block];
+        for _ in height..tip.height {
+            block = match self.spend_tree.get_previous_block(block) {
+                Some(previous) => previous,
+                None           => return None
+            };
+            blocks.push(block);
+        }
+        Some(blocks)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use block::BlockError;
+    use block_add::{add_block, BlockAddOk};
+    use store::SpendingError;
+
+    fn hash(block: &[u8]) -> Hash32Buf {
+        Hash32Buf::double_sha256(&block[0..80])
+    }
+
+    #[test]
+    fn test_disconnect_block() {
+
+        let mut store = Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0  = genesis!();
+        let block1  = blk!(prev = block0;  tx!(bld; coinbase => a;10, b;10 ));
+        let block2  = blk!(prev = block1;  tx!(bld; coinbase => c;11 ), tx!(bld; a => d ));
+        let block3  = blk!(prev = block2;  tx!(bld; coinbase => e;12 ), tx!(bld; d => f ));
+        let block2b = blk!(prev = block1;  tx!(bld; coinbase => g;13 ), tx!(bld; b => h ));
+
+        // spends a, which is only spent on the chain of block2
+        let block3b = blk!(prev = block2b; tx!(bld; coinbase => i;14 ), tx!(bld; a => j ));
+
+        for block in [&block0, &block1, &block2, &block3, &block2b].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+        assert_eq!(store.tips.get_most_work_tip().unwrap().block_hash, hash(&block3));
+
+        assert_eq!(store.disconnect_block(hash(&block2).as_ref()), Ok(2));
+        assert_eq!(store.disconnect_block(hash(&block2).as_ref()), Err(DisconnectError::NotFound));
+
+        let tips = store.tips.get_tips();
+        assert_eq!(tips.len(), 1);
+        assert_eq!(tips[0].block_hash, hash(&block2b));
+        assert_eq!(store.verify_integrity(), vec![]);
+
+        assert_eq!(add_block(&mut store, &block3b), Ok(BlockAddOk::Connected));
+
+        // the previous block becomes a tip again
+        assert_eq!(store.disconnect_block(hash(&block3b).as_ref()), Ok(1));
+
+        let tip = store.tips.get_tip(hash(&block2b)).unwrap();
+        assert_eq!(tip.height, 2);
+        assert_eq!(store.tips.get_tips().len(), 1);
+        assert_eq!(store.verify_integrity(), vec![]);
+
+        // adding block2 again connects block3 on top of it
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+
+        let tip = store.tips.get_most_work_tip().unwrap();
+        assert_eq!(tip.block_hash, hash(&block3));
+        assert_eq!(tip.height, 3);
+        assert_eq!(store.verify_integrity(), vec![]);
+
+        let spent = blk!(prev = block3; tx!(bld; coinbase => k;15 ), tx!(bld; a => l ));
+        assert_eq!(add_block(&mut store, &spent),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+        let block4 = blk!(prev = block3; tx!(bld; coinbase => m;16 ), tx!(bld; f => n ));
+        assert_eq!(add_block(&mut store, &block4), Ok(BlockAddOk::Connected));
+        assert_eq!(store.verify_integrity(), vec![]);
+    }
+
+    #[test]
+    fn test_disconnect_block_other_store() {
+
+        // a store that has moved the spend-index before another store disconnects blocks,
+        // must not roll back from its own old tip
+        let cfg = test_cfg!();
+        let mut store = Store::new(&cfg);
+        let mut other = Store::new(&cfg);
+        store.verify_headers = false;
+        other.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0  = genesis!();
+        let block1  = blk!(prev = block0;  tx!(bld; coinbase => a;10, b;10 ));
+        let block2  = blk!(prev = block1;  tx!(bld; coinbase => c;11 ), tx!(bld; a => d ));
+        let block3  = blk!(prev = block2;  tx!(bld; coinbase => e;12 ), tx!(bld; b => f ));
+        let block2b = blk!(prev = block1;  tx!(bld; coinbase => g;13 ), tx!(bld; b => h ));
+        let block3b = blk!(prev = block2b; tx!(bld; coinbase => i;14 ), tx!(bld; b => j ));
+
+        for block in [&block0, &block1, &block2, &block3].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+
+        assert_eq!(other.disconnect_block(hash(&block2).as_ref()), Ok(2));
+        assert_eq!(add_block(&mut other, &block2b), Ok(BlockAddOk::Connected));
+
+        // rolling back block3 would unset the spend of b by block2b
+        assert_eq!(add_block(&mut store, &block3b),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+        assert_eq!(store.verify_integrity(), vec![]);
+    }
+}
@@ -15,7 +15,7 @@ use std::sync::atomic;
 use std::io::Write;
 use std::fmt::{Debug,Formatter,Error};
 
-use std::path::{Path};
+use std::path::{Path, PathBuf};
 use memmap;
 
 const WRITEPOS_OFFSET  : isize = 8;
@@ -24,6 +24,7 @@ pub const INITIAL_WRITEPOS : u64   = 0x10;
 
 pub struct FlatFile {
 
+    path:      PathBuf,
     file:      Option<fs::File>,
     map:       Option<memmap::Mmap>,
     ptr:       *mut u8,
@@ -37,6 +38,7 @@ impl Clone for FlatFile {
     fn clone(&self) -> FlatFile {
 
         FlatFile {
+            path:     self.path.clone(),
             file:     None,
             map:      None,
             ptr:      self.ptr,
@@ -61,6 +63,7 @@ impl FlatFile {
         let write_ptr = unsafe { mem::transmute(ptr.offset(WRITEPOS_OFFSET)) };
 
         FlatFile {
+            path:      PathBuf::from(path),
             file:      Some(file),
             map:       Some(map),
             ptr:       ptr,
@@ -137,9 +140,9 @@ impl FlatFile {
             mem::transmute( self.ptr.offset(filepos as isize))
         };
 
-        unsafe {
+        guarded_write(&self.path, 1, |_| unsafe {
             ptr::copy_nonoverlapping(value, target, 1);
-        };
+        });
     }
 
     pub fn get_slice<T>(&self, filepos: usize, size: usize) -> &'static mut [T] {
@@ -156,9 +159,9 @@ impl FlatFile {
             mem::transmute( self.ptr.offset(filepos as isize))
         };
 
-        unsafe {
-            ptr::copy_nonoverlapping(value.get_unchecked(0), target, value.len());
-        };
+        guarded_write(&self.path, value.len(), |count| unsafe {
+            ptr::copy_nonoverlapping(value.get_unchecked(0), target, count);
+        });
     }
 
     /// Reserves `size` bytes for writing, updates the write_pos atomically
@@ -188,6 +191,123 @@ impl FlatFile {
 
     }
 
+    /// Returns the position up to which space is allocated
+    pub fn write_pos(&self) -> u64 {
+
+        let write_ptr = unsafe { &*self.write_ptr };
+
+        write_ptr.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Clears the space allocated after `pos` and moves the write position back to `pos`
+    ///
+    /// The space is cleared first, such that this can be redone if it is interrupted
+    pub fn truncate(&self, pos: u64) {
+
+        let write_ptr = unsafe { &*self.write_ptr };
+        let write_pos = write_ptr.load(atomic::Ordering::Relaxed);
+
+        if pos < write_pos {
+            unsafe {
+                ptr::write_bytes(self.ptr.offset(pos as isize), 0, (write_pos - pos) as usize);
+            }
+            write_ptr.store(pos, atomic::Ordering::Relaxed);
+        }
+    }
+
+}
+
+/// Performs a write of `len` elements to the file at `path`
+///
+/// `write` is called with the number of elements to write. This is always `len`, except in tests
+/// that simulate a crash; see `crash`
+#[cfg(not(test))]
+#[inline]
+pub fn guarded_write<F: FnOnce(usize)>(_path: &Path, len: usize, write: F) {
+    write(len)
+}
+
+#[cfg(test)]
+pub use self::crash::guarded_write;
+
+
+/// Simulates a process that is killed while writing, to test recovery
+///
+/// After `kill_after(dir, n)`, the writes to the files in `dir` are counted, and write `n`
+/// (counting from 0) is torn: only the first half of its elements is written before the writing thread panics. All
+/// later writes to the directory panic without writing anything, as the process is "dead"
+#[cfg(test)]
+pub mod crash {
+
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    struct Crash {
+        dir:     PathBuf,
+        kill_at: usize,
+        writes:  usize
+    }
+
+    // checked first, such that other tests are not slowed down by the mutex
+    static ACTIVE: AtomicBool = AtomicBool::new(false);
+    static CRASH:  Mutex<Option<Crash>> = Mutex::new(None);
+
+    /// Starts counting the writes to `dir`, and kills the writer at write `kill_at`
+    pub fn kill_after(dir: &Path, kill_at: usize) {
+
+        *CRASH.lock().unwrap() = Some(Crash {
+            dir:     PathBuf::from(dir),
+            kill_at: kill_at,
+            writes:  0
+        });
+        ACTIVE.store(true, Ordering::SeqCst);
+    }
+
+    /// Stops counting and returns the number of writes, including the torn one
+    pub fn revive() -> usize {
+
+        ACTIVE.store(false, Ordering::SeqCst);
+
+        match CRASH.lock().unwrap().take() {
+            Some(crash) => crash.writes,
+            None        => 0
+        }
+    }
+
+    pub fn guarded_write<F: FnOnce(usize)>(path: &Path, len: usize, write: F) {
+
+        let count = if ACTIVE.load(Ordering::SeqCst) { allowed(path, len) } else { len };
+
+        if count > 0 {
+            write(count);
+        }
+        if count < len {
+            panic!("Killed while writing to {:?}", path);
+        }
+    }
+
+    // Returns the number of elements of the write that are done
+    fn allowed(path: &Path, len: usize) -> usize {
+
+        let mut crash = CRASH.lock().unwrap();
+        let crash = match *crash {
+            Some(ref mut crash) if path.starts_with(&crash.dir) => crash,
+            _ => return len
+        };
+
+        crash.writes += 1;
+        if crash.writes <= crash.kill_at {
+            len
+        }
+        else if crash.writes == crash.kill_at + 1 {
+            len / 2
+        }
+        else {
+            // dead
+            0
+        }
+    }
 }
 
 impl Debug for FlatFile {
@@ -11,15 +11,21 @@
 //! An index to a file consists is usually 48 bits but depends on the filesize
 //! A FlatFileSet is generalized with a FlatFilePtr that provides an index
 //!
+//! A length-prefixed blob is written before its length. As the allocated space is zero, a
+//! non-zero length marks a complete blob; this is used to find partial writes after a crash
+//!
 
 use std::path::{Path,PathBuf};
 use std::mem;
 use std::fs;
+use std::ops::Range;
+use std::cmp;
+use std::fmt;
 
 use itertools::Itertools;
 use itertools::MinMaxResult::{NoElements, OneElement, MinMax};
 
-use store::flatfile::FlatFile;
+use store::flatfile::{FlatFile, INITIAL_WRITEPOS};
 
 
 
@@ -30,6 +36,25 @@ pub trait FlatFilePtr {
     fn get_file_offset(self) -> u64;
 }
 
+/// Returns true if `ptr` points to data that is written before `end`
+pub fn is_before<P: FlatFilePtr + Copy>(ptr: P, end: P) -> bool {
+
+    (ptr.get_file_number(), ptr.get_file_offset()) < (end.get_file_number(), end.get_file_offset())
+}
+
+/// The position of data on disk; used to report problems
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file:   PathBuf,
+    pub offset: u64
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}", self.file.display(), self.offset)
+    }
+}
+
 
 /// FlatFileSet is a sequential set of files in form of prefixNNNN where NNNN is
 /// sequential signed 16 bit big-endian number.
@@ -188,6 +213,21 @@ impl<P: FlatFilePtr + Copy + Clone> FlatFileSet<P> {
         }
     }
 
+    /// Returns the range of file numbers of the set
+    ///
+    /// This includes the file that is currently written to, which may not yet exist
+    pub fn file_numbers(&self) -> Range<i16> {
+        self.first_file..self.last_file
+    }
+
+    /// Extends the range of file numbers with the files that other users of the set have created
+    /// since it was loaded
+    pub fn reload_file_numbers(&mut self) {
+
+        let (_, max) = find_min_max_filenumbers(&self.path, self.prefix);
+        self.last_file = cmp::max(self.last_file, max);
+    }
+
     /// Returns a mutable reference to the given Flatfile
     ///
     /// Opens it if needed
@@ -294,9 +334,30 @@ impl<P: FlatFilePtr + Copy + Clone> FlatFileSet<P> {
 
         let flatfile   = self.get_flatfile(target_ptr.get_file_number());
 
-        // write size & buffer
-        flatfile.put(&buffer_len,  target_ptr.get_file_offset() as usize);
+        // write buffer & size; the size marks the write as complete
         flatfile.put_slice(buffer, (target_ptr.get_file_offset() + 4) as usize);
+        flatfile.put(&buffer_len,  target_ptr.get_file_offset() as usize);
+
+        target_ptr
+    }
+
+    /// Appends the slice followed by the given value and returns a filepos
+    ///
+    /// This is written as a single allocation, such that the value always directly follows the
+    /// slice. The result can be read with `read`
+    pub fn write_with_fixed<T>(&mut self, buffer: &[u8], value: &T) -> P {
+
+        let buffer_len = buffer.len() as u32;
+        let write_len  = 4 + buffer.len() + mem::size_of::<T>();
+
+        let target_ptr = self.alloc_write_space(write_len as u64);
+        let offset     = target_ptr.get_file_offset() as usize;
+
+        let flatfile   = self.get_flatfile(target_ptr.get_file_number());
+
+        flatfile.put_slice(buffer, offset + 4);
+        flatfile.put(value, offset + 4 + buffer.len());
+        flatfile.put(&buffer_len, offset);
 
         target_ptr
     }
@@ -342,6 +403,42 @@ impl<P: FlatFilePtr + Copy + Clone> FlatFileSet<P> {
         file.get_slice(filepos+4, len as usize)
     }
 
+    /// Reads the length-prefixed buffer at the given position, or returns None if it is not a
+    /// complete blob within the written part of the set
+    ///
+    /// Unlike `read`, this can be used with a pointer that may be invalid
+    pub fn try_read(&mut self, pos: P) -> Option<&'static [u8]> {
+
+        let fileno  = pos.get_file_number();
+        let filepos = pos.get_file_offset();
+        let files   = self.file_numbers();
+
+        if fileno < files.start || fileno >= files.end || filepos < INITIAL_WRITEPOS {
+            return None;
+        }
+
+        let end = self.write_end(fileno).get_file_offset();
+        if filepos + 4 > end {
+            return None;
+        }
+
+        let len: u32 = *self.read_fixed(pos);
+        if len == 0 || filepos + 4 + len as u64 > end {
+            return None;
+        }
+
+        Some(self.read(pos))
+    }
+
+    /// Returns the file and offset of the given position
+    pub fn location(&self, pos: P) -> Location {
+
+        Location {
+            file:   fileno_to_filename(&self.path, self.prefix, pos.get_file_number()),
+            offset: pos.get_file_offset()
+        }
+    }
+
     /// Reads the fixed size buffer at the given position
     pub fn read_fixed<T>(&mut self, pos: P) -> &'static mut T {
 
@@ -365,10 +462,92 @@ impl<P: FlatFilePtr + Copy + Clone> FlatFileSet<P> {
         file.get(filepos as usize)
     }
 
+    /// Returns the position up to which space is allocated in the given file
+    pub fn write_end(&mut self, fileno: i16) -> P {
+
+        let write_pos = self.get_flatfile(fileno).write_pos();
+        P::new(fileno, write_pos)
+    }
+
+    /// Drops everything that is written at or after `pos`
+    ///
+    /// The rest of the file is cleared and later files are removed; new writes continue at `pos`
+    pub fn truncate(&mut self, pos: P) {
+
+        let fileno = pos.get_file_number();
+
+        for later in (fileno + 1)..self.last_file {
+            let file_idx = (later - self.first_file) as usize;
+            if file_idx < self.files.len() {
+                self.files[file_idx] = None;
+            }
+            let _ = fs::remove_file(fileno_to_filename(&self.path, self.prefix, later));
+        }
+
+        self.files.truncate((fileno + 1 - self.first_file) as usize);
+        self.last_file = fileno + 1;
+
+        self.get_flatfile(fileno).truncate(pos.get_file_offset());
+    }
+
+    /// Removes all files of the set
+    ///
+    /// The files may not be in use by others, and references to their content become invalid
+    pub fn remove_files(&mut self) {
+
+        for fileno in self.file_numbers() {
+            let _ = fs::remove_file(fileno_to_filename(&self.path, self.prefix, fileno));
+        }
+
+        self.files      = vec![None];
+        self.first_file = 0;
+        self.last_file  = 1;
+    }
+
+    /// Verifies the length-prefixed blobs in the last two files of the set, and truncates the set
+    /// at the first blob that is partially written or that fails `verify`
+    ///
+    /// `fixed_size` is the size of a fixed value written after each blob; see `write_with_fixed`.
+    /// `verify` is called with the blob and the bytes of this value.
+    ///
+    /// Returns the position at which the set is truncated, or None if all blobs are complete.
+    /// Earlier files are not verified, as these cannot have been written at the time of a crash
+    pub fn truncate_partial<F>(&mut self, fixed_size: usize, mut verify: F) -> Option<P>
+        where F: FnMut(&[u8], &[u8]) -> bool
+    {
+        let files = self.file_numbers();
+
+        for fileno in cmp::max(files.start, files.end - 2)..files.end {
+
+            let end     = self.write_end(fileno).get_file_offset();
+            let mut pos = INITIAL_WRITEPOS;
+
+            while pos < end {
+                let ptr       = P::new(fileno, pos);
+                let len: u32  = *self.read_fixed(ptr);
+                let next      = pos + 4 + len as u64 + fixed_size as u64;
+
+                let complete = len > 0 && next <= end && {
+                    let blob  = self.read(ptr);
+                    let fixed = self.read_mut_slice(P::new(fileno, next - fixed_size as u64), fixed_size);
+                    verify(blob, fixed)
+                };
+
+                if !complete {
+                    self.truncate(ptr);
+                    return Some(ptr);
+                }
+                pos = next;
+            }
+        }
+
+        None
+    }
+
     fn offset(&mut self, pos: P, bytes: usize) -> P {
         if pos.get_file_offset() > self.max_size {
 
-            P::new(pos.get_file_number() + 1, super::flatfile::INITIAL_WRITEPOS)
+            P::new(pos.get_file_number() + 1, INITIAL_WRITEPOS)
         } else {
 
             P::new(pos.get_file_number(), pos.get_file_offset() + bytes as u64)
@@ -395,17 +574,23 @@ impl<P: FlatFilePtr + Copy + Clone> FlatFileSet<P> {
     /// It reads and returns all blockheaders+txcount
     #[cfg(test)]
     pub fn read_block_headers(&mut self) -> Vec<(&'static [u8], usize)> {
+        use store::BlockInfo;
+
         let mut result = Vec::new();
-        let mut pos = P::new(0, super::flatfile::INITIAL_WRITEPOS);
+        let mut pos = P::new(0, INITIAL_WRITEPOS);
         loop {
             let blob = self.read(pos);
             if blob.len() == 0 {
                 break;
             }
             pos = self.offset(pos, blob.len() + 4);
-            let tx_count = self.read_fixed(pos);
-            pos = self.offset(pos, mem::size_of::<usize>());
-            result.push( (blob, *tx_count) );
+            let info: &BlockInfo = self.read_fixed(pos);
+            pos = self.offset(pos, mem::size_of::<BlockInfo>());
+
+            // skips the record with the layout of BlockInfo
+            if blob.len() == 80 {
+                result.push( (blob, info.tx_count as usize) );
+            }
 
         };
 
@@ -5,7 +5,8 @@
 //! This is used for transactions & blockheaders; the values found for a hash can be:
 //!
 //!  * a single fileptr pointing to a transaction
-//!  The transaction can be found at the ptr in block_content and is fully validated
+//!  The transaction can be found at the ptr in block_content and is fully validated. Coinbases
+//!  in different blocks can have the same hash; these are stored separately and each is added
 //!
 //!  * a set of fileptr pointing to inputs
 //!  Transaction cannot be found, but these inputs need this transaction
@@ -20,15 +21,27 @@
 //!  The block cannot be found, but the blocks pointed to by these ptrs are having the given hash as previous block;
 //!  they are "expecting" this block, and should be appended when this block comes in
 //!
-//! The implementation is a large root hash table, with colliding keys added to an unbalanced binary tree.
+//! The implementation is a hash array mapped trie (HAMT). A root table of 2^16 slots is indexed
+//! with the first 16 bits of the hash; each slot is empty, holds a node with a hash and its
+//! values, or points to a branch. A branch has 16 slots of the same kind, indexed by the next 4
+//! bits of the hash.
 //!
-//!  TODO There is probably quite some gain to be made by moving to HAMT instead unbalanced binary trees
-//!  for the branches; especially for low-resource uses.
+//! A hash is added by atomically replacing an empty slot with a node. If the slot already holds a
+//! node with another hash, that node is first moved down into a new branch which then replaces the
+//! slot. Slots only change from empty to node to branch, so this is lock-free: nothing is copied
+//! and no concurrent update can be lost.
+//!
+//! The branches are not compressed with a bitmap, as is usual for HAMTs: in an append-only file,
+//! a compressed branch must be copied whenever a slot is added, and the dropped copies take more
+//! space than the empty slots of uncompressed branches. See `bench_layout`.
+//!
+//! The index starts with a header with the version of this layout, followed by the root table.
+//! An index with another layout, such as one written by an earlier version that has no header,
+//! is refused; it must be removed and rebuilt with Store::reindex.
 
 
-use std::{mem};
+use std::{mem, cmp};
 use std::sync::atomic;
-use std::cmp::{Ord,Ordering};
 
 use config;
 use hash::*;
@@ -38,10 +51,24 @@ use store::flatfileset::FlatFileSet;
 
 
 
-const FILE_SIZE:          u64 = 1 * 1024*1024*1024;
-const MAX_CONTENT_SIZE:   u64 = FILE_SIZE - 10 * 1024*1024;
+/// The number of bits of the hash used to index the root table
+const ROOT_BITS:        usize = 16;
+const ROOT_COUNT:       usize = 1 << ROOT_BITS;
+
+/// The number of bits of the hash used to index a branch
+const BRANCH_BITS:      usize = 4;
+const BRANCH_COUNT:     usize = 1 << BRANCH_BITS;
 
-const HASH_ROOT_COUNT:  usize = 256*256*256;
+/// The maximum number of branches on the path to a node
+const MAX_DEPTH:        usize = (256 - ROOT_BITS) / BRANCH_BITS;
+
+/// Identifies the layout of the index; stored in its header
+const MAGIC:            [u8; 8] = *b"bc-hidx\0";
+const VERSION:          u64 = 1;
+
+// Values of the tag of an IndexPtr
+const TAG_NODE:           u16 = 0;
+const TAG_BRANCH:         u16 = 1;
 
 
 /// Trait for objects that can be used as a guard
@@ -58,7 +85,7 @@ pub struct HashIndex<T : HashIndexGuard + Copy + Clone> {
 
     fileset:         FlatFileSet<IndexPtr>,
 
-    hash_index_root: &'static [IndexPtr; HASH_ROOT_COUNT],
+    hash_index_root: &'static [IndexPtr; ROOT_COUNT],
 
     phantom:         ::std::marker::PhantomData<T>
 
@@ -70,7 +97,7 @@ impl<T : HashIndexGuard + Copy + Clone> Clone for HashIndex<T> {
     fn clone(&self) -> HashIndex<T> {
 
         let mut fileset = self.fileset.clone();
-        let root = fileset.read_fixed(IndexPtr::new(0, super::flatfile::INITIAL_WRITEPOS));
+        let root = fileset.read_fixed(root_ptr());
         HashIndex {
 
             fileset:         fileset,
@@ -83,11 +110,13 @@ impl<T : HashIndexGuard + Copy + Clone> Clone for HashIndex<T> {
 }
 
 /// A persistent pointer into the hash-index
+///
+/// The tag tells whether a slot points to a node or to a branch
 #[derive(Debug, Clone, Copy)]
 pub struct IndexPtr {
     file_offset: u32,
     file_number: i16,
-    zero: u16
+    tag: u16
 }
 
 impl FlatFilePtr for IndexPtr {
@@ -96,7 +125,7 @@ impl FlatFilePtr for IndexPtr {
         IndexPtr {
             file_offset: file_offset as u32,
             file_number: file_number,
-            zero: 0  // we must pad with zero to ensure atomic CAS works
+            tag: TAG_NODE
         }
     }
 
@@ -112,6 +141,17 @@ impl IndexPtr {
 
     pub fn is_null(&self) -> bool { self.file_offset == 0 && self.file_number == 0 }
 
+    fn is_branch(&self) -> bool { self.tag == TAG_BRANCH }
+
+    fn to_branch(self) -> IndexPtr {
+        IndexPtr { tag: TAG_BRANCH, .. self }
+    }
+
+    /// Returns true if this points to a location that was written before `other`
+    pub fn is_before(&self, other: IndexPtr) -> bool {
+        (self.file_number, self.file_offset) < (other.file_number, other.file_offset)
+    }
+
 
     /// atomically replaces a hash indexptr value with a new_value,
     /// fails if the current value is no longer the value supplied
@@ -138,22 +178,38 @@ enum FindNodeResult {
     /// Tha hash is found and the location is returned
     Found(&'static Node),
 
-    /// The hash is not found; the location where the node should be inserted
-    /// is returned
-    NotFound(&'static IndexPtr)
+    /// The hash is not found; the slot where the node should be inserted is returned
+    /// with its content, which is either null or a node with another hash, and the number of
+    /// branches on the path to the slot
+    NotFound(&'static IndexPtr, IndexPtr, usize)
+}
+
+/// Returns the position of the root table, which directly follows the header
+fn root_ptr() -> IndexPtr {
+    IndexPtr::new(0, super::flatfile::INITIAL_WRITEPOS + mem::size_of::<Header>() as u64)
 }
 
 /// Structures as stored in the fileset
 #[derive(Debug)]
+#[repr(C)]
+struct Header {
+    magic:   [u8; 8],
+    version: u64
+}
+
+#[derive(Debug)]
+#[repr(C)]
 struct Node {
     hash: Hash32Buf,
-    prev: IndexPtr,  // to Node
-    next: IndexPtr,  // to Node
     leaf: IndexPtr,  // to Leaf
 }
 
+/// A branch of the trie; the slots are null, or point to a node or another branch
+type Branch = [IndexPtr; BRANCH_COUNT];
+
 /// Leaf of the binary tree
 /// The supplied Type is the type of the elements that are stored in the tree
+#[repr(C)]
 struct Leaf<T : HashIndexGuard> {
     value: T, /// to Data file
     next: IndexPtr, // to Leaf
@@ -174,25 +230,51 @@ impl Node {
     fn new(hash: Hash32, leaf_ptr: IndexPtr) -> Self {
         Node {
             hash: hash.as_buf(),
-            prev: IndexPtr::null(),
-            next: IndexPtr::null(),
             leaf: leaf_ptr
         }
     }
 }
 
 
-// Returns the first 24-bits of the hash
+// Returns the first 16-bits of the hash
 //
-// This is the index into the root-hash table
-fn hash_to_index(hash: Hash32) -> usize {
+// This is the index into the root table
+fn root_index(hash: Hash32) -> usize {
 
     (hash.0[0] as usize) |
-        (hash.0[1] as usize) << 8  |
-        (hash.0[2] as usize) << 16
+        (hash.0[1] as usize) << 8
 
 }
 
+// Returns the 4 bits of the hash that index the branch at the given depth
+fn branch_index(hash: Hash32, depth: usize) -> usize {
+
+    debug_assert!(depth < MAX_DEPTH);
+
+    let byte = hash.0[ROOT_BITS / 8 + depth / 2] as usize;
+    if depth % 2 == 0 { byte & 0x0F } else { byte >> 4 }
+}
+
+
+/// Statistics of the layout of the hash-index
+#[derive(Debug, Default, PartialEq)]
+pub struct HashIndexStats {
+
+    /// Number of hashes stored
+    pub nodes:       usize,
+    pub branches:    usize,
+
+    /// The number of values, including guards
+    pub leaves:      usize,
+
+    /// The number of branches on the path to a node, summed over all nodes
+    pub total_depth: usize,
+    pub max_depth:   usize,
+
+    /// The number of bytes used by the root, branches, nodes and leaves
+    pub bytes:       usize
+}
+
 
 impl<T :'static> HashIndex<T>
     where T : HashIndexGuard + PartialEq + Copy + Clone
@@ -207,20 +289,28 @@ impl<T :'static> HashIndex<T>
         let is_new = !dir.exists();
 
         let mut fileset = FlatFileSet::new(
-            dir, "hi-", FILE_SIZE, MAX_CONTENT_SIZE);
+            dir, "hi-", cfg.hash_index_file_size, config::max_content_size(cfg.hash_index_file_size));
 
         let hash_root_fileptr = if is_new {
 
-            // allocate space for root hash table
-            fileset.alloc_write_space(mem::size_of::<[IndexPtr; HASH_ROOT_COUNT]>() as u64)
+            let header_ptr = fileset.alloc_write_space(mem::size_of::<Header>() as u64);
+            *fileset.read_fixed(header_ptr) = Header { magic: MAGIC, version: VERSION };
+
+            // allocate space for root table
+            fileset.alloc_write_space(mem::size_of::<[IndexPtr; ROOT_COUNT]>() as u64)
         }
         else {
-            // hash root must have been the first thing written
-            IndexPtr::new(0, super::flatfile::INITIAL_WRITEPOS)
+            let header: &Header = fileset.read_fixed(IndexPtr::new(0, super::flatfile::INITIAL_WRITEPOS));
+            if header.magic != MAGIC || header.version != VERSION {
+                panic!("Hash-index {:?} has an unsupported layout; remove it and run reindex-store to rebuild it", dir);
+            }
+
+            // hash root must have been written directly after the header
+            root_ptr()
         };
 
         // and keep a reference to it
-        let hash_root_ref: &'static [IndexPtr; HASH_ROOT_COUNT]
+        let hash_root_ref: &'static [IndexPtr; ROOT_COUNT]
             = fileset.read_fixed(hash_root_fileptr);
 
         HashIndex {
@@ -238,8 +328,7 @@ impl<T :'static> HashIndex<T>
         let mut leaf_ptr = node.leaf;
 
         while !leaf_ptr.is_null() {
-            let v: &T = self.fileset.read_fixed(leaf_ptr);
-            let leaf: Leaf<T> = Leaf::new(*v);
+            let leaf: &Leaf<T> = self.fileset.read_fixed(leaf_ptr);
             result.push(leaf.value);
 
             leaf_ptr = leaf.next;
@@ -247,24 +336,77 @@ impl<T :'static> HashIndex<T>
         result
     }
 
-    // Finds the node containing the hash, or the location the hash should be inserted
+    // Finds the node containing the hash, or the slot the hash should be inserted
     fn find_node(&mut self, hash: Hash32) -> FindNodeResult {
 
-        // use the first 24-bit as index in the root hash table
-        let mut ptr = &self.hash_index_root[hash_to_index(hash)];
+        // use the first 16-bit as index in the root table
+        let mut slot: &'static IndexPtr = &self.hash_index_root[root_index(hash)];
+        let mut depth = 0;
 
-        // from there, we follow the binary tree
-        while !ptr.is_null() {
-            let node: &Node = self.fileset.read_fixed(*ptr);
+        // from there, we follow the branches
+        loop {
+            let ptr = *slot;
 
-            ptr = match hash.0.cmp(&node.hash.as_ref().0) {
-                Ordering::Less    => &node.prev,
-                Ordering::Greater => &node.next,
-                Ordering::Equal   => return FindNodeResult::Found(node)
-            };
+            if ptr.is_null() {
+                return FindNodeResult::NotFound(slot, ptr, depth);
+            }
+            else if ptr.is_branch() {
+                let branch: &'static Branch = self.fileset.read_fixed(ptr);
+
+                slot   = &branch[branch_index(hash, depth)];
+                depth += 1;
+            }
+            else {
+                let node: &'static Node = self.fileset.read_fixed(ptr);
+
+                if hash.0 == node.hash.as_ref().0 {
+                    return FindNodeResult::Found(node);
+                }
+                return FindNodeResult::NotFound(slot, ptr, depth);
+            }
+        }
+    }
+
+    /// Writes a chain of leaves holding the values and returns a pointer to the first
+    fn write_leaves(&mut self, values: &[T]) -> IndexPtr {
+
+        let mut next = IndexPtr::null();
+        for value in values.iter().rev() {
+            let leaf = Leaf { value: *value, next: next };
+            next = self.fileset.write_fixed(&leaf);
+        }
+        next
+    }
+
+    /// Inserts a node with the given hash and values in the slot returned by find_node
+    ///
+    /// Fails if the slot was changed concurrently, or if it held another node; in the latter case
+    /// that node is moved into a new branch and the caller needs to retry
+    fn insert_node(&mut self, slot: &IndexPtr, content: IndexPtr, depth: usize, hash: Hash32, values: &[T]) -> bool {
+
+        if content.is_null() {
+
+            // create and write the leaves
+            let new_leaf_ptr = self.write_leaves(values);
+
+            // create and write a node holding the leaves
+            let new_node     = Node::new(hash, new_leaf_ptr);
+            let new_node_ptr = self.fileset.write_fixed(&new_node);
+
+            // then atomically update the pointer
+            slot.atomic_replace(IndexPtr::null(), new_node_ptr)
         }
+        else {
+            // move the other node into a new branch
+            let other: &Node = self.fileset.read_fixed(content);
+
+            let mut branch: Branch = [IndexPtr::null(); BRANCH_COUNT];
+            branch[branch_index(other.hash.as_ref(), depth)] = content;
+            let branch_ptr = self.fileset.write_fixed(&branch).to_branch();
 
-        FindNodeResult::NotFound(ptr)
+            slot.atomic_replace(content, branch_ptr);
+            false
+        }
     }
 
 
@@ -272,7 +414,7 @@ impl<T :'static> HashIndex<T>
     pub fn get(&mut self, hash: Hash32) -> Vec<T> {
 
         match self.find_node(hash) {
-            FindNodeResult::NotFound(_) => {
+            FindNodeResult::NotFound(_, _, _) => {
                 Vec::new()
             },
             FindNodeResult::Found(node) => {
@@ -301,32 +443,21 @@ impl<T :'static> HashIndex<T>
         // this loops through retries when the CAS operation fails
         loop {
             match self.find_node(hash) {
-                FindNodeResult::NotFound(target) => {
-
-                    // create and write a leaf;
-                    let new_leaf     = Leaf::new(store_ptr);
-                    let new_leaf_ptr = self.fileset.write_fixed(&new_leaf);
-
-                    // create and write a node holding the leaf
-                    let new_node     = Node::new(hash, new_leaf_ptr);
-                    let new_node_ptr = self.fileset.write_fixed(&new_node);
+                FindNodeResult::NotFound(slot, content, depth) => {
 
-                    // then atomically update the pointer
-                    if target.atomic_replace(IndexPtr::null(), new_node_ptr) {
+                    if self.insert_node(slot, content, depth, hash, &[store_ptr]) {
                         return true;
                     }
-
                 },
                 FindNodeResult::Found(node) => {
 
                     let first_value_ptr = node.leaf;
+                    let values          = self.collect_node_values(node);
 
-                    // check if there is anything waiting that is not supplied in `verified_ptrs`
-                    if !force_store &&
-                        !self
-                        .collect_node_values(node)
-                        .into_iter()
-                        .any(|val| verified_ptrs.contains(&val)) {
+                    // check if there is anything waiting that is not supplied in `verified_ptrs`;
+                    // a node can be left without values by `replace`
+                    if !force_store && !values.is_empty() &&
+                        !values.into_iter().any(|val| verified_ptrs.contains(&val)) {
 
                         return false;
                     }
@@ -358,34 +489,26 @@ impl<T :'static> HashIndex<T>
         loop {
             match self.find_node(hash) {
 
-                FindNodeResult::NotFound(ptr) => {
+                FindNodeResult::NotFound(slot, content, depth) => {
 
                     // The transaction doesn't exist; we insert guard_ptr instead
-
-                    // create and write a leaf;
-                    let new_leaf = Leaf::new(guard_ptr);
-                    let new_leaf_ptr = self.fileset.write_fixed(&new_leaf);
-
-                    // create and write a node holding the leaf
-                    let new_node = Node::new(hash, new_leaf_ptr);
-                    let new_node_ptr = self.fileset.write_fixed(&new_node);
-
-                    // then atomically update the pointer
-                    if ptr.atomic_replace(IndexPtr::null(), new_node_ptr) {
+                    if self.insert_node(slot, content, depth, hash, &[guard_ptr]) {
                         return None;
                     }
                 },
 
                 FindNodeResult::Found(node) => {
 
-                    // load first leaf
+                    // load first leaf; a node can be left without values by `replace`
                     let first_value_ptr = node.leaf;
-                    let val: &T         = self.fileset.read_fixed(first_value_ptr);
-                    let leaf: Leaf<T>   = Leaf::new(*val);
+                    if !first_value_ptr.is_null() {
 
+                        let val: &T       = self.fileset.read_fixed(first_value_ptr);
+                        let leaf: Leaf<T> = Leaf::new(*val);
 
-                    if !leaf.value.is_guard() {
-                        return Some(leaf.value);
+                        if !leaf.value.is_guard() {
+                            return Some(leaf.value);
+                        }
                     }
 
                     // create a new leaf, pointing to the previous one
@@ -402,6 +525,177 @@ impl<T :'static> HashIndex<T>
         }
 
     }
+
+    /// Adds a T in front of the values stored at the given hash, regardless of guards
+    ///
+    /// This is used for a transaction that has the same hash as a stored one
+    pub fn add(&mut self, hash: Hash32, store_ptr: T) {
+
+        assert!(! store_ptr.is_guard());
+
+        // this loops through retries when the CAS operation fails
+        loop {
+            match self.find_node(hash) {
+                FindNodeResult::NotFound(slot, content, depth) => {
+
+                    if self.insert_node(slot, content, depth, hash, &[store_ptr]) {
+                        return;
+                    }
+                },
+                FindNodeResult::Found(node) => {
+
+                    let first_value_ptr = node.leaf;
+
+                    let new_leaf     = Leaf { value: store_ptr, next: first_value_ptr };
+                    let new_leaf_ptr = self.fileset.write_fixed(&new_leaf);
+
+                    if node.leaf.atomic_replace(first_value_ptr, new_leaf_ptr) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replaces the values stored at the given hash, regardless of guards
+    ///
+    /// This is used to copy the values to another index
+    pub fn replace(&mut self, hash: Hash32, values: &[T]) {
+
+        // this loops through retries when the CAS operation fails
+        loop {
+            match self.find_node(hash) {
+                FindNodeResult::NotFound(slot, content, depth) => {
+
+                    if self.insert_node(slot, content, depth, hash, values) {
+                        return;
+                    }
+                },
+                FindNodeResult::Found(node) => {
+
+                    let first_value_ptr = node.leaf;
+                    let new_leaf_ptr    = self.write_leaves(values);
+
+                    if node.leaf.atomic_replace(first_value_ptr, new_leaf_ptr) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the position up to which the index is written
+    ///
+    /// Everything that is changed in the index afterwards is written after this position
+    pub fn write_position(&mut self) -> IndexPtr {
+        self.fileset.alloc_write_space(0)
+    }
+
+    /// Returns the start of the last two files of the index
+    ///
+    /// All recent changes are written after this position
+    pub fn tail_position(&self) -> IndexPtr {
+
+        let files = self.fileset.file_numbers();
+        IndexPtr::new(cmp::max(files.start, files.end - 2), super::flatfile::INITIAL_WRITEPOS)
+    }
+
+    /// Calls `f` with the hash and values of each node in the index
+    ///
+    /// If `since` is given, only nodes that are added or changed after that write position are
+    /// visited. Nodes can be added concurrently; these may or may not be visited
+    pub fn for_each<F>(&mut self, since: Option<IndexPtr>, f: &mut F)
+        where F: FnMut(Hash32, Vec<T>)
+    {
+        let root = self.hash_index_root;
+        for slot in root.iter() {
+            self.for_each_in(*slot, since, None, &mut |hash, values, _| f(hash, values));
+        }
+    }
+
+    /// Calls `f` with the hash and values of each node that is added before `until`, and is
+    /// added or changed after `since`; `f` is also passed whether the node is added after `since`
+    ///
+    /// This walks the index in passes, each from the `until` of the previous pass: every node is
+    /// passed as added in exactly one pass, even if nodes are added concurrently
+    pub fn for_each_until<F>(&mut self, since: Option<IndexPtr>, until: IndexPtr, f: &mut F)
+        where F: FnMut(Hash32, Vec<T>, bool)
+    {
+        let root = self.hash_index_root;
+        for slot in root.iter() {
+            self.for_each_in(*slot, since, Some(until), f);
+        }
+    }
+
+    fn for_each_in<F>(&mut self, ptr: IndexPtr, since: Option<IndexPtr>, until: Option<IndexPtr>, f: &mut F)
+        where F: FnMut(Hash32, Vec<T>, bool)
+    {
+        if ptr.is_null() {
+            return;
+        }
+        else if ptr.is_branch() {
+            let branch: &'static Branch = self.fileset.read_fixed(ptr);
+
+            for slot in branch.iter() {
+                self.for_each_in(*slot, since, until, f);
+            }
+        }
+        else {
+            let node: &'static Node = self.fileset.read_fixed(ptr);
+
+            if until.map_or(false, |until| !ptr.is_before(until)) {
+                return;
+            }
+
+            let added = since.map_or(true, |since| !ptr.is_before(since));
+            if added || !node.leaf.is_before(since.unwrap()) {
+                let values = self.collect_node_values(node);
+                f(node.hash.as_ref(), values, added);
+            }
+        }
+    }
+
+    /// Walks the trie and returns its layout statistics
+    pub fn stats(&mut self) -> HashIndexStats {
+
+        let mut stats = HashIndexStats::default();
+        stats.bytes = mem::size_of::<[IndexPtr; ROOT_COUNT]>();
+
+        let root = self.hash_index_root;
+        for slot in root.iter() {
+            self.collect_stats(*slot, 0, &mut stats);
+        }
+        stats
+    }
+
+    fn collect_stats(&mut self, ptr: IndexPtr, depth: usize, stats: &mut HashIndexStats) {
+
+        if ptr.is_null() {
+            return;
+        }
+        else if ptr.is_branch() {
+            let branch: &'static Branch = self.fileset.read_fixed(ptr);
+
+            stats.branches += 1;
+            stats.bytes    += mem::size_of::<Branch>();
+
+            for slot in branch.iter() {
+                self.collect_stats(*slot, depth + 1, stats);
+            }
+        }
+        else {
+            let node: &Node = self.fileset.read_fixed(ptr);
+
+            stats.nodes       += 1;
+            stats.total_depth += depth;
+            stats.max_depth    = ::std::cmp::max(stats.max_depth, depth);
+            stats.bytes       += mem::size_of::<Node>();
+
+            let values = self.collect_node_values(node).len();
+            stats.leaves += values;
+            stats.bytes  += values * mem::size_of::<Leaf<T>>();
+        }
+    }
 }
 
 
@@ -424,12 +718,137 @@ mod tests {
     use config;
     use hash::Hash32Buf;
     use store::TxPtr;
+    use store::flatfile::INITIAL_WRITEPOS;
     use store::flatfileset::FlatFilePtr;
 
     #[test]
     fn test_size_of_node() {
-        assert_eq!(mem::size_of::<Node>(), 56);
+        assert_eq!(mem::size_of::<Node>(), 40);
+        assert_eq!(mem::size_of::<Branch>(), 128);
+
+    }
+
+    /// Returns a hash that is zero except for the given bytes at the end
+    fn hash_with_tail(tail: &[u8]) -> Hash32Buf {
+        let mut hash = [0u8; 32];
+        hash[32 - tail.len()..].copy_from_slice(tail);
+        Hash32Buf::from_slice(&hash)
+    }
+
+    #[test]
+    fn test_collisions() {
+
+        let mut idx: HashIndex<TxPtr> = HashIndex::new(& test_cfg!(), "test");
+
+        // these only differ in the last nibble, which needs the deepest branch
+        let hash1 = hash_with_tail(&[0x01]);
+        let hash2 = hash_with_tail(&[0x11]);
+        let hash3 = hash_with_tail(&[0x02]);
+
+        assert!(idx.set(hash1.as_ref(), TxPtr::new(0, 100), &[], false));
+        assert!(idx.set(hash2.as_ref(), TxPtr::new(0, 200), &[], false));
+
+        assert_eq!(idx.get(hash1.as_ref()), vec![TxPtr::new(0, 100)]);
+        assert_eq!(idx.get(hash2.as_ref()), vec![TxPtr::new(0, 200)]);
+        assert_eq!(idx.get(hash3.as_ref()), vec![]);
+
+        let stats = idx.stats();
+        assert_eq!(stats.nodes, 2);
+        assert_eq!(stats.max_depth, MAX_DEPTH);
+        assert_eq!(stats.branches, MAX_DEPTH);
+
+        // guards are collected until the hash is set
+        let guard1 = TxPtr::new(0, 300).to_input(1);
+        let guard2 = TxPtr::new(0, 400).to_input(2);
+        assert_eq!(idx.get_or_set(hash3.as_ref(), guard1), None);
+        assert_eq!(idx.get_or_set(hash3.as_ref(), guard2), None);
+        assert_eq!(idx.get(hash3.as_ref()), vec![guard2, guard1]);
+
+        // storing is refused unless a guard is verified
+        assert!(!idx.set(hash3.as_ref(), TxPtr::new(0, 500), &[], false));
+        assert!( idx.set(hash3.as_ref(), TxPtr::new(0, 500), &[guard1], false));
+        assert_eq!(idx.get(hash3.as_ref()), vec![TxPtr::new(0, 500)]);
+        assert_eq!(idx.get_or_set(hash3.as_ref(), guard1), Some(TxPtr::new(0, 500)));
+
+        // force_store replaces the value
+        assert!(idx.set(hash1.as_ref(), TxPtr::new(0, 600), &[], true));
+        assert_eq!(idx.get(hash1.as_ref()), vec![TxPtr::new(0, 600)]);
+        assert_eq!(idx.stats().nodes, 3);
+
+        // a node without values is treated as absent
+        idx.replace(hash1.as_ref(), &[]);
+        assert_eq!(idx.get(hash1.as_ref()), vec![]);
+        assert_eq!(idx.get_or_set(hash1.as_ref(), guard1), None);
+        idx.replace(hash1.as_ref(), &[]);
+        assert!(idx.set(hash1.as_ref(), TxPtr::new(0, 700), &[], false));
+        assert_eq!(idx.get(hash1.as_ref()), vec![TxPtr::new(0, 700)]);
+
+        // add keeps the existing values
+        idx.add(hash1.as_ref(), TxPtr::new(0, 800));
+        assert_eq!(idx.get(hash1.as_ref()), vec![TxPtr::new(0, 800), TxPtr::new(0, 700)]);
+        idx.add(hash_with_tail(&[0x03]).as_ref(), TxPtr::new(0, 900));
+        assert_eq!(idx.get(hash_with_tail(&[0x03]).as_ref()), vec![TxPtr::new(0, 900)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported layout")]
+    fn test_unsupported_layout() {
+
+        let cfg = test_cfg!();
+        {
+            let mut idx: HashIndex<TxPtr> = HashIndex::new(&cfg, "test");
+
+            // as an index of an earlier version, which starts with the root table
+            let header: &mut Header = idx.fileset.read_fixed(IndexPtr::new(0, INITIAL_WRITEPOS));
+            header.magic = [0; 8];
+        }
+
+        let _idx: HashIndex<TxPtr> = HashIndex::new(&cfg, "test");
+    }
+
+    #[test]
+    fn test_stats() {
+
+        let mut idx: HashIndex<TxPtr> = HashIndex::new(& test_cfg!(), "test");
+
+        for n in 0..1000 {
+            let hash = Hash32Buf::double_sha256(format!("{}", n).as_bytes());
+            idx.set(hash.as_ref(), TxPtr::new(0, n + 100), &[], false);
+        }
+
+        let stats = idx.stats();
+        assert_eq!(stats.nodes, 1000);
+        assert_eq!(stats.leaves, 1000);
+        assert_eq!(stats.bytes,
+            ROOT_COUNT * 8 + stats.branches * 128 + stats.nodes * 40 + stats.leaves * 16);
+
+        // 1000 random hashes in 2^16 slots rarely collide
+        assert!(stats.total_depth < 50);
+    }
+
+    /// Shows the lookup depth and memory of the trie for random hashes
+    #[test]
+    #[ignore]
+    fn bench_layout() {
+
+        const COUNT: usize = 2_000_000;
+
+        let mut idx: HashIndex<TxPtr> = HashIndex::new(& test_cfg!(), "bench");
+        let mut rng = rand::thread_rng();
+
+        for n in 0..COUNT {
+            let mut hash = [0u8; 32];
+            rng.fill_bytes(&mut hash);
+
+            idx.set(Hash32(&hash), TxPtr::new(0, n as u64 + 100), &[], false);
+        }
 
+        // depths are shown as the number of reads after the root table, including the node
+        let stats = idx.stats();
+        println!("hashes:           {}", COUNT);
+        println!("trie:             avg depth {:.2}, max depth {}, {} branches, {} bytes ({:.1} per hash)",
+            stats.total_depth as f64 / COUNT as f64 + 1.0, stats.max_depth + 1, stats.branches, stats.bytes,
+            stats.bytes as f64 / COUNT as f64);
     }
 
     #[test]
@@ -441,7 +860,7 @@ mod tests {
 
         let dir = tempdir::TempDir::new("test1").unwrap();
         let path = PathBuf::from(dir.path());
-        let cfg = config::Config { root: path.clone() };
+        let cfg = config::Config::new(path.to_str().unwrap());
 
         let _idx: HashIndex<TxPtr> = HashIndex::new(& cfg, "test" );
 
@@ -458,7 +877,7 @@ mod tests {
             let path = path.clone();
             thread::spawn( move | | {
                 let mut rng = rand::thread_rng();
-                let cfg = config::Config { root: path };
+                let cfg = config::Config::new(path.to_str().unwrap());
 
                 let mut idx = HashIndex::new(&cfg, "test");
 
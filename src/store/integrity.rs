@@ -0,0 +1,437 @@
+//! Verification of the consistency of a store
+//!
+//! `verify_integrity` reads the indexes, the spend-tree and the tips, and reports every
+//! inconsistency it finds with the file and offset of the data concerned. Nothing is repaired.
+//!
+//! The pointers that are followed are checked against the written part of each fileset before
+//! they are read, such that a corrupt store is reported instead of crashing the check.
+//!
+//! Other stores cannot add blocks, move the spend-index or prune meanwhile: the store lock is
+//! held exclusively, and the spend-index is checked at its tip as re-read under the spend-index
+//! lock.
+
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+
+use buffer::*;
+use hash::*;
+use transaction::Transaction;
+
+use store::{Store, Transactions, TxPtr, RecordPtr, HashIndexGuard};
+use store::flatfileset::Location;
+
+
+/// An inconsistency found by `verify_integrity`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inconsistency {
+
+    /// The file and offset of the data that is inconsistent
+    pub location: Location,
+    pub kind:     InconsistencyKind
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InconsistencyKind {
+
+    /// A block-index entry doesn't point to a complete block in the spend-tree that starts
+    /// with a start-of-block record
+    NotABlock { block_hash: Hash32Buf },
+
+    /// The header of a block in the block-index is missing or has another hash
+    BlockHashMismatch { block_hash: Hash32Buf },
+
+    /// A transaction or output-spend record doesn't point to a stored transaction
+    NotATransaction,
+
+    /// An output-spend record refers to an output that its transaction doesn't have
+    NoSuchOutput { output_index: u32 },
+
+    /// A tx-index entry doesn't point to a stored transaction with the hash of the entry
+    TxHashMismatch { tx_hash: Hash32Buf },
+
+    /// The tip of the spend-index is not a valid block
+    InvalidSpendIndexTip,
+
+    /// A record of a block in the chain of the spend-index is not in the spend-index
+    MissingInSpendIndex,
+
+    /// A record that is only in blocks outside the chain of the spend-index is in the spend-index
+    UnexpectedInSpendIndex,
+
+    /// A tip refers to a block that is not in the block-index or not connected
+    TipNotConnected { block_hash: Hash32Buf }
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+
+        write!(fmt, "{}: ", self.location)?;
+
+        match self.kind {
+            InconsistencyKind::NotABlock { ref block_hash } =>
+                write!(fmt, "block-index entry of {:?} doesn't point to a block", block_hash),
+            InconsistencyKind::BlockHashMismatch { ref block_hash } =>
+                write!(fmt, "header of block {:?} is missing or has another hash", block_hash),
+            InconsistencyKind::NotATransaction =>
+                write!(fmt, "record doesn't point to a transaction"),
+            InconsistencyKind::NoSuchOutput { output_index } =>
+                write!(fmt, "record spends output {} that doesn't exist", output_index),
+            InconsistencyKind::TxHashMismatch { ref tx_hash } =>
+                write!(fmt, "tx-index entry of {:?} doesn't point to that transaction", tx_hash),
+            InconsistencyKind::InvalidSpendIndexTip =>
+                write!(fmt, "spend-index tip is not a block"),
+            InconsistencyKind::MissingInSpendIndex =>
+                write!(fmt, "record in the chain of the spend-index is not in the spend-index"),
+            InconsistencyKind::UnexpectedInSpendIndex =>
+                write!(fmt, "spend-index contains a record outside its chain"),
+            InconsistencyKind::TipNotConnected { ref block_hash } =>
+                write!(fmt, "tip {:?} is not a connected block", block_hash)
+        }
+    }
+}
+
+
+/// Parses the transaction at the given pointer, and returns its hash and its number of outputs
+fn read_transaction(transactions: &mut Transactions, ptr: TxPtr) -> Option<(Hash32Buf, usize)> {
+
+    let raw = match transactions.try_read(ptr) {
+        Some(raw) => raw,
+        None      => return None
+    };
+
+    let mut buffer = Buffer::new(&raw);
+    match Transaction::parse(&mut buffer) {
+        Ok(ref tx) if buffer.len() == 0 => Some((tx.txid(), tx.txs_out.len())),
+        _ => None
+    }
+}
+
+
+impl Store {
+
+    /// Verifies the consistency of the block-index, the spend-tree, the tx-index, the
+    /// spend-index and the tips
+    ///
+    /// Returns every inconsistency that is found; the store is consistent if this is empty
+    pub fn verify_integrity(&mut self) -> Vec<Inconsistency> {
+
+        // prevents other stores from adding blocks, moving the spend-index, and replacing the
+        // tx-index by pruning
+        let _lock             = self.lock_exclusive();
+        let _spend_index_lock = self.lock_spend_index();
+
+        info!(self.logger, "verify_integrity - start");
+
+        let mut found = Vec::new();
+
+        self.verify_block_index(&mut found);
+        self.verify_records(&mut found);
+        self.verify_tx_index(&mut found);
+        self.verify_spend_index(&mut found);
+        self.verify_tips(&mut found);
+
+        info!(self.logger, "verify_integrity - done"; "inconsistencies" => found.len());
+
+        found
+    }
+
+    // Every entry must point to a block in the spend-tree; connected blocks must have their hash
+    fn verify_block_index(&mut self, found: &mut Vec<Inconsistency>) {
+
+        let mut entries = Vec::new();
+        self.block_index.for_each(None, &mut |hash, values| {
+            for block in values {
+                entries.push((hash.as_buf(), block));
+            }
+        });
+
+        for (block_hash, block) in entries {
+
+            if !self.spend_tree.is_valid_block(block) {
+                found.push(Inconsistency {
+                    location: self.spend_tree.location(block.start),
+                    kind:     InconsistencyKind::NotABlock { block_hash: block_hash }
+                });
+                continue;
+            }
+
+            // a guard is stored at the hash of the previous block
+            if block.is_guard() {
+                continue;
+            }
+
+            let header_ptr = self.spend_tree.get_record(block.end()).get_block_header_ptr();
+            let matches = match self.block_headers.try_read(header_ptr) {
+                Some(header) => header.len() == 80 && Hash32Buf::double_sha256(header) == block_hash,
+                None         => false
+            };
+
+            if !matches {
+                found.push(Inconsistency {
+                    location: self.block_headers.location(header_ptr),
+                    kind:     InconsistencyKind::BlockHashMismatch { block_hash: block_hash }
+                });
+            }
+        }
+    }
+
+    // Every transaction and output-spend record must point to a transaction
+    fn verify_records(&mut self, found: &mut Vec<Inconsistency>) {
+
+        for (first, records) in self.spend_tree.get_written_records() {
+            for (n, &record) in records.iter().enumerate() {
+
+                if record.is_block_start() || record.is_block_end() || record.is_unmatched_input() {
+                    continue;
+                }
+
+                let kind = match read_transaction(&mut self.transactions, record.get_transaction_ptr()) {
+                    None => Some(InconsistencyKind::NotATransaction),
+
+                    Some((_, output_count)) if record.is_output()
+                        && record.get_output_index() as usize >= output_count =>
+
+                        Some(InconsistencyKind::NoSuchOutput { output_index: record.get_output_index() }),
+
+                    Some(_) => None
+                };
+
+                if let Some(kind) = kind {
+                    found.push(Inconsistency {
+                        location: self.spend_tree.location(RecordPtr::new(first.to_index() + n as u64)),
+                        kind:     kind
+                    });
+                }
+            }
+        }
+    }
+
+    // Every transaction in the tx-index must have the hash it is stored at
+    fn verify_tx_index(&mut self, found: &mut Vec<Inconsistency>) {
+
+        let transactions = &mut self.transactions;
+
+        self.tx_index.for_each(None, &mut |hash, values| {
+
+            for ptr in values.into_iter().filter(|ptr| !ptr.is_guard()) {
+
+                let matches = match read_transaction(transactions, ptr) {
+                    Some((txid, _)) => txid.as_ref() == hash,
+                    None            => false
+                };
+
+                if !matches {
+                    found.push(Inconsistency {
+                        location: transactions.location(ptr),
+                        kind:     InconsistencyKind::TxHashMismatch { tx_hash: hash.as_buf() }
+                    });
+                }
+            }
+        });
+    }
+
+    // The spend-index must contain the records of the blocks up to its tip, and no others
+    fn verify_spend_index(&mut self, found: &mut Vec<Inconsistency>) {
+
+        // the starts of the blocks in the chain of the tip
+        let mut chain: HashSet<u64> = HashSet::new();
+
+        let mut block = self.spend_index.get_tip();
+        while let Some(current) = block {
+
+            if !self.spend_tree.is_valid_block(current) {
+                found.push(Inconsistency {
+                    location: self.spend_tree.location(current.start),
+                    kind:     InconsistencyKind::InvalidSpendIndexTip
+                });
+                return;
+            }
+
+            chain.insert(current.start.to_index());
+            block = self.spend_tree.get_previous_block(current);
+        }
+
+        // the same transaction can be in blocks in and outside the chain; records outside the
+        // chain are only reported if their hash isn't in the chain
+        let mut unexpected: HashMap<u64, RecordPtr> = HashMap::new();
+
+        let files = self.spend_tree.get_written_records();
+        for &(first, records) in files.iter() {
+
+            let mut in_chain = false;
+            for (n, &record) in records.iter().enumerate() {
+
+                let ptr = RecordPtr::new(first.to_index() + n as u64);
+
+                if record.is_block_start() {
+                    in_chain = chain.contains(&ptr.to_index());
+                    continue;
+                }
+                if record.is_block_end() || record.is_unmatched_input() {
+                    continue;
+                }
+
+                let exists = self.spend_index.exists(record.hash());
+                if in_chain && !exists {
+                    found.push(Inconsistency {
+                        location: self.spend_tree.location(ptr),
+                        kind:     InconsistencyKind::MissingInSpendIndex
+                    });
+                }
+                else if !in_chain && exists {
+                    unexpected.insert(record.hash(), ptr);
+                }
+            }
+        }
+
+        if unexpected.is_empty() {
+            return;
+        }
+
+        for &(first, records) in files.iter() {
+
+            let mut in_chain = false;
+            for (n, &record) in records.iter().enumerate() {
+
+                if record.is_block_start() {
+                    in_chain = chain.contains(&(first.to_index() + n as u64));
+                }
+                else if in_chain && !record.is_block_end() && !record.is_unmatched_input() {
+                    unexpected.remove(&record.hash());
+                }
+            }
+        }
+
+        let mut unexpected: Vec<u64> = unexpected.into_iter().map(|(hash, _)| hash).collect();
+        unexpected.sort();
+
+        for hash in unexpected {
+            found.push(Inconsistency {
+                location: self.spend_index.location(hash),
+                kind:     InconsistencyKind::UnexpectedInSpendIndex
+            });
+        }
+    }
+
+    // Every tip must be a block in the block-index that is connected to its previous block
+    fn verify_tips(&mut self, found: &mut Vec<Inconsistency>) {
+
+        for tip in self.tips.get_tips() {
+
+            let block = self.block_index.get(tip.block_hash.as_ref()).into_iter()
+                .find(|ptr| !ptr.is_guard());
+
+            let connected = match block {
+                Some(block) => self.spend_tree.is_valid_block(block)
+                    && (tip.height == 0 || self.spend_tree.get_previous_block(block).is_some()),
+                None => false
+            };
+
+            if !connected {
+                found.push(Inconsistency {
+                    location: self.tips.location(&tip),
+                    kind:     InconsistencyKind::TipNotConnected { block_hash: tip.block_hash }
+                });
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use pow::U256;
+    use store::{BlockPtr, Record, FlatFilePtr};
+    use store::tips;
+    use block_add::{add_block, BlockAddOk};
+
+    fn hash(raw: &[u8]) -> Hash32Buf {
+        Hash32Buf::double_sha256(raw)
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+
+        let mut store = Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let coinbase1 = tx!(bld; coinbase => a;10 );
+        let coinbase2 = tx!(bld; coinbase => b;11 );
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0; coinbase1);
+        let block2 = blk!(prev = block1; coinbase2, tx!(bld; a => c ));
+
+        // the previous block of the orphan is not stored
+        let unknown = blk!(prev = block2; tx!(bld; coinbase => d;12 ));
+        let orphan  = blk!(prev = unknown; tx!(bld; coinbase => e;13 ), tx!(bld; c => f ));
+
+        for block in [&block0, &block1, &block2].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+        assert_eq!(add_block(&mut store, &orphan), Ok(BlockAddOk::Orphan));
+
+        assert_eq!(store.verify_integrity(), vec![]);
+
+        let block1_ptr: BlockPtr = store.block_index.get(hash(&block1[0..80]).as_ref())[0];
+        let orphan_ptr: BlockPtr = store.block_index.get(hash(&unknown[0..80]).as_ref())[0];
+        assert!(orphan_ptr.is_guard());
+
+        // a block-index entry that points to the record after a start-of-block
+        let not_a_block = BlockPtr { start: RecordPtr::new(block1_ptr.start.to_index() + 1), ..block1_ptr };
+        store.block_index.replace(hash(&block1[0..80]).as_ref(), &[not_a_block]);
+
+        // a record of the orphan that doesn't point to a transaction
+        let orphan_records = RecordPtr::new(orphan_ptr.start.to_index() + 1);
+        let invalid_record = RecordPtr::new(orphan_ptr.start.to_index() + 2);
+        store.spend_tree.get_block_mut(orphan_ptr)[2] = Record::new_transaction(TxPtr::new(0, 1 << 24));
+
+        // a tx-index entry that points to another transaction
+        let coinbase2_ptr = store.tx_index.get(hash(&coinbase2).as_ref())[0];
+        store.tx_index.replace(hash(&coinbase1).as_ref(), &[coinbase2_ptr]);
+
+        // a record of the main chain that isn't in the spend-index, and one outside it that is
+        let coinbase1_record = store.spend_tree.get_record(RecordPtr::new(block1_ptr.start.to_index() + 1));
+        store.spend_index.unset(coinbase1_record.hash());
+
+        let orphan_record = store.spend_tree.get_record(orphan_records);
+        store.spend_index.set(orphan_record.hash());
+
+        // a tip of a block that isn't stored
+        tips::add_tip(&store.tips, hash(&unknown[0..80]), None, U256::from(1u64), 4);
+        let tip = store.tips.get_tip(hash(&unknown[0..80])).unwrap();
+
+        let coinbase1_location = store.spend_tree.location(RecordPtr::new(block1_ptr.start.to_index() + 1));
+
+        assert_eq!(store.verify_integrity(), vec![
+            Inconsistency {
+                location: store.spend_tree.location(not_a_block.start),
+                kind:     InconsistencyKind::NotABlock { block_hash: hash(&block1[0..80]) }
+            },
+            Inconsistency {
+                location: store.spend_tree.location(invalid_record),
+                kind:     InconsistencyKind::NotATransaction
+            },
+            Inconsistency {
+                location: store.transactions.location(coinbase2_ptr),
+                kind:     InconsistencyKind::TxHashMismatch { tx_hash: hash(&coinbase1) }
+            },
+            Inconsistency {
+                location: coinbase1_location,
+                kind:     InconsistencyKind::MissingInSpendIndex
+            },
+            Inconsistency {
+                location: store.spend_index.location(orphan_record.hash()),
+                kind:     InconsistencyKind::UnexpectedInSpendIndex
+            },
+            Inconsistency {
+                location: store.tips.location(&tip),
+                kind:     InconsistencyKind::TipNotConnected { block_hash: hash(&unknown[0..80]) }
+            }
+        ]);
+    }
+}
@@ -0,0 +1,238 @@
+//! Coordination between stores that use the same directory
+//!
+//! Stores in other threads or processes can add blocks concurrently. Adding a block takes the
+//! lock shared; operations that replace files of the store, like pruning the tx-index, take it
+//! exclusively for the moment of the switch.
+//!
+//! Each store also holds a shared lock on the users-file while it is open. The first store that
+//! opens the directory can therefore detect that no other store uses it, and recover the store
+//! from a crash before others can use it.
+//!
+//! The spend-index is shared by all stores, but is only valid for the chain up to its tip. A store
+//! that moves or reads it holds a separate spend-index lock exclusively, and re-reads the tip while
+//! holding it, as another store may have moved it. This lock is taken after the store lock.
+//!
+//! The lock-file also holds the generation of the tx-index. Generation 0 is stored in `tx-index`,
+//! later generations in `tx-index-N`; a store reopens its tx-index when the generation changes.
+
+use std::fs;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+
+use libc;
+
+use config;
+
+const LOCK_FILE:  &'static str = "lock";
+const USERS_FILE: &'static str = "users";
+const SPEND_INDEX_LOCK_FILE: &'static str = "spend-index-lock";
+
+
+pub struct StoreLock {
+    file:  fs::File,
+
+    // locked shared as long as the store is open
+    users: fs::File,
+
+    // locked exclusively while the spend-index is moved or used
+    spend_index: fs::File
+}
+
+/// A held lock; the lock is released when this is dropped
+pub struct StoreLockGuard {
+    file: fs::File
+}
+
+impl Drop for StoreLockGuard {
+    fn drop(&mut self) {
+        flock(&self.file, libc::LOCK_UN);
+    }
+}
+
+fn flock(file: &fs::File, operation: libc::c_int) {
+    let result = unsafe { libc::flock(file.as_raw_fd(), operation) };
+    assert_eq!(result, 0, "Cannot lock store");
+}
+
+// Returns false if the lock is held by another file
+fn try_flock(file: &fs::File, operation: libc::c_int) -> bool {
+    let result = unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) };
+    result == 0
+}
+
+fn open_file(cfg: &config::Config, name: &str) -> fs::File {
+
+    fs::OpenOptions::new().read(true).write(true).create(true)
+        .open(cfg.root.join(name))
+        .expect("Cannot open lock-file of store")
+}
+
+
+impl StoreLock {
+
+    /// Opens the lock-file of the store at the location given in the config
+    pub fn new(cfg: &config::Config) -> StoreLock {
+
+        fs::create_dir_all(&cfg.root)
+            .expect("Cannot create store directory");
+
+        StoreLock {
+            file:  open_file(cfg, LOCK_FILE),
+            users: open_file(cfg, USERS_FILE),
+            spend_index: open_file(cfg, SPEND_INDEX_LOCK_FILE)
+        }
+    }
+
+    /// Registers the store as user of the directory
+    ///
+    /// If no other store uses the directory, the lock is returned, held exclusively, such that
+    /// the store can be recovered before others can use it
+    pub fn open(&self) -> Option<StoreLockGuard> {
+
+        // nobody can open or close meanwhile
+        let guard = self.exclusive();
+
+        let first = try_flock(&self.users, libc::LOCK_EX);
+        flock(&self.users, libc::LOCK_SH);
+
+        if first { Some(guard) } else { None }
+    }
+
+    /// Returns true if no other store uses the directory; the lock must be held exclusively
+    pub fn is_only_user(&self) -> bool {
+
+        // others cannot open or close meanwhile, so the lock can be converted
+        let only = try_flock(&self.users, libc::LOCK_EX);
+        flock(&self.users, libc::LOCK_SH);
+
+        only
+    }
+
+    fn lock(file: &fs::File, operation: libc::c_int) -> StoreLockGuard {
+
+        // the guard uses its own handle to the same open file, such that it doesn't borrow the store
+        let file = file.try_clone().expect("Cannot lock store");
+        flock(&file, operation);
+
+        StoreLockGuard { file: file }
+    }
+
+    /// Waits until no other store holds the lock exclusively, and locks it shared
+    pub fn shared(&self) -> StoreLockGuard {
+        StoreLock::lock(&self.file, libc::LOCK_SH)
+    }
+
+    /// Waits until no other store holds the lock, and locks it exclusively
+    pub fn exclusive(&self) -> StoreLockGuard {
+        StoreLock::lock(&self.file, libc::LOCK_EX)
+    }
+
+    /// Waits until no other store holds the spend-index lock, and locks it exclusively
+    ///
+    /// The guards share the open file of this store, so the lock may not be taken again before
+    /// the guard is dropped
+    pub fn spend_index(&self) -> StoreLockGuard {
+        StoreLock::lock(&self.spend_index, libc::LOCK_EX)
+    }
+
+    /// Returns the current generation of the tx-index
+    pub fn get_tx_index_generation(&self) -> u64 {
+
+        let mut buf = [0u8; 8];
+        match self.file.read_at(&mut buf, 0) {
+            Ok(8) => u64_from_le(&buf),
+            _     => 0
+        }
+    }
+
+    /// Registers a new generation of the tx-index; the lock must be held exclusively
+    pub fn set_tx_index_generation(&self, generation: u64) {
+
+        let buf: Vec<u8> = (0..8).map(|n| (generation >> (n * 8)) as u8).collect();
+        let written = self.file.write_at(&buf, 0)
+            .expect("Cannot write lock-file of store");
+        assert_eq!(written, 8, "Cannot write lock-file of store");
+    }
+}
+
+fn u64_from_le(buf: &[u8]) -> u64 {
+    buf.iter().rev().fold(0, |acc, b| (acc << 8) | *b as u64)
+}
+
+/// Returns the name of the directory of the given generation of the tx-index
+pub fn tx_index_dir(generation: u64) -> String {
+    if generation == 0 {
+        "tx-index".to_string()
+    }
+    else {
+        format!("tx-index-{}", generation)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generation() {
+
+        let cfg = test_cfg!();
+        let lock = StoreLock::new(&cfg);
+        assert_eq!(lock.get_tx_index_generation(), 0);
+
+        {
+            let _guard = lock.exclusive();
+            lock.set_tx_index_generation(0x1_0000_0002);
+        }
+
+        // another store sees the new generation
+        let other = StoreLock::new(&cfg);
+        let _guard1 = other.shared();
+        let _guard2 = lock.shared();
+        assert_eq!(other.get_tx_index_generation(), 0x1_0000_0002);
+        assert_eq!(tx_index_dir(0), "tx-index");
+        assert_eq!(tx_index_dir(2), "tx-index-2");
+    }
+
+    #[test]
+    fn test_open() {
+
+        let cfg = test_cfg!();
+        {
+            let first = StoreLock::new(&cfg);
+            assert!(first.open().is_some());
+
+            let other = StoreLock::new(&cfg);
+            assert!(other.open().is_none());
+
+            let _guard = first.exclusive();
+            assert!(!first.is_only_user());
+        }
+
+        // both are closed
+        let lock = StoreLock::new(&cfg);
+        assert!(lock.open().is_some());
+
+        let _guard = lock.exclusive();
+        assert!(lock.is_only_user());
+    }
+
+    #[test]
+    fn test_spend_index_lock() {
+
+        let cfg   = test_cfg!();
+        let lock  = StoreLock::new(&cfg);
+        let other = StoreLock::new(&cfg);
+
+        // the spend-index lock is independent of the store lock
+        let _shared = lock.shared();
+        let guard   = lock.spend_index();
+        assert!(!try_flock(&other.spend_index, libc::LOCK_EX));
+        assert!(try_flock(&other.file, libc::LOCK_SH));
+        flock(&other.file, libc::LOCK_UN);
+
+        drop(guard);
+        assert!(try_flock(&other.spend_index, libc::LOCK_EX));
+    }
+}
@@ -10,7 +10,10 @@
 //! # block_headers
 //!
 //! This contains raw block-headers
-//! These are directly written to their flatfileset
+//! These are directly written to their flatfileset, each followed by a BlockInfo with the
+//! transaction count, and the height and accumulated work once the block is connected.
+//! The fileset starts with a record with the version of the layout of BlockInfo; block headers
+//! with another layout are refused.
 //!
 //!
 //! # tx_index
@@ -39,8 +42,6 @@
 
 
 use slog ;
-use slog_term;
-use slog::DrainExt;
 
 
 
@@ -56,6 +57,12 @@ mod spend_index;
 mod spend_tree;
 
 mod prune;
+mod recover;
+mod integrity;
+mod reindex;
+mod disconnect;
+
+mod lock;
 
 pub mod tips;
 
@@ -63,15 +70,22 @@ mod transactions;
 
 pub use self::spend_tree::SpendingError;
 pub use self::spend_tree::BlockPtr;
+pub use self::spend_tree::find_output;
 pub use self::spend_tree::record::{RecordPtr,Record};
+pub use self::spend_tree::record::RECORDS_FILE_SIZE;
 
 pub use self::txptr::TxPtr;
 pub use self::hash_index::{HashIndex, HashIndexGuard};
+pub use self::spend_index::SpendIndex;
 pub use self::blockheaderptr::BlockHeaderPtr;
 
-pub use self::flatfileset::{FlatFilePtr,FlatFileSet};
+pub use self::flatfileset::{FlatFilePtr,FlatFileSet,Location};
 
 pub use self::transactions::Transactions;
+pub use self::prune::PruneProgress;
+pub use self::integrity::{Inconsistency, InconsistencyKind};
+pub use self::reindex::{ReindexStats, ReindexError};
+pub use self::disconnect::DisconnectError;
 pub type TxIndex = HashIndex<TxPtr>;
 
 use config;
@@ -80,13 +94,39 @@ use hash::*;
 
 
 use metrics::Metrics;
+use chain_params::ChainParams;
+use block::BlockHeader;
+use buffer::*;
+use pow;
+use pow::U256;
+
+
+
+/// Identifies the layout of BlockInfo; stored as the first record of the block headers
+const BLOCK_INFO_MAGIC:   [u8; 8] = *b"bc-binf\0";
+const BLOCK_INFO_VERSION: u64 = 1;
+
+/// The fixed value that is stored after each block header
+///
+/// The height and the accumulated work are set when the block is connected, such that these are
+/// known for every connected block without walking the spend-tree
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockInfo {
+    pub tx_count: u64,
+    pub height:   u64,
+    pub work:     U256
+}
 
-
-
-const MB:                 u64 = 1024 * 1024;
-const FILE_SIZE:          u64 = 2 * 1024 * MB;
-pub const MAX_CONTENT_SIZE:   u64 = FILE_SIZE - 10 * MB as u64 ;
-
+impl BlockInfo {
+    pub fn new(tx_count: usize) -> BlockInfo {
+        BlockInfo {
+            tx_count: tx_count as u64,
+            height:   0,
+            work:     U256::zero()
+        }
+    }
+}
 
 
 /// This is the accessor to all stuff on disk.
@@ -114,6 +154,19 @@ pub struct Store {
 
     pub initial_sync: bool,
 
+    // Verify proof-of-work, difficulty and time of block headers.
+    // Only turned off by tests that construct their own blocks
+    pub verify_headers: bool,
+
+    // The parameters of the chain selected in the config
+    pub params: ChainParams,
+
+    // Shared with other stores using the same directory
+    lock: lock::StoreLock,
+
+    // The generation of the tx-index that is opened; see lock.rs
+    tx_index_generation: u64,
+
     // needed for cloning
     cfg: config::Config,
 
@@ -125,17 +178,24 @@ impl Store {
 
     pub fn new(cfg: &config::Config) -> Store {
 
-        Store {
+        let lock = lock::StoreLock::new(&cfg);
+
+        // the first store to open the directory recovers it, before others can use it
+        let recover_lock = lock.open();
+
+        let tx_index_generation = lock.get_tx_index_generation();
+
+        let mut store = Store {
             transactions:  transactions::Transactions::new(&cfg),
 
 
             block_headers:  FlatFileSet::new(
                 &cfg.root.clone().join("headers"),
                 "bh",
-                FILE_SIZE,
-                MAX_CONTENT_SIZE),
+                cfg.block_headers_file_size,
+                config::max_content_size(cfg.block_headers_file_size)),
 
-            tx_index:     hash_index::HashIndex::new(&cfg, "tx-index"),
+            tx_index:     hash_index::HashIndex::new(&cfg, &lock::tx_index_dir(tx_index_generation)),
             block_index:  hash_index::HashIndex::new(&cfg, "block-index"),
 
             spend_tree:   spend_tree::SpendTree::new(&cfg),
@@ -144,14 +204,97 @@ impl Store {
             tips:         tips::Tips::new(&cfg),
 
             metrics:       Metrics::new(),
-            logger:        slog::Logger::root(slog_term::streamer().compact().build().fuse(), o!()),
+            logger:        cfg.logger(),
+            lock:          lock,
+            tx_index_generation: tx_index_generation,
             cfg:           cfg.clone(),
 
-            initial_sync:  true,
+            initial_sync:  cfg.initial_sync,
+            verify_headers: true,
+            params:         ChainParams::new(cfg.chain),
+        };
+
+        // recovering would truncate headers of another layout, so these are refused first
+        store.verify_block_info_layout(recover_lock.is_some());
+
+        if let Some(_lock) = recover_lock {
+            store.recover();
         }
+        store
     }
 
+    /// Verifies that the block headers start with the record of the current layout of BlockInfo
+    ///
+    /// If the headers are empty, this record is written if `first` is set, as only the first
+    /// store to open the directory may write it
+    fn verify_block_info_layout(&mut self, first: bool) {
 
+        let layout = block_info_layout();
+        let stored = self.block_headers.read(BlockHeaderPtr::new(0, flatfile::INITIAL_WRITEPOS));
+
+        if stored.is_empty() {
+            if first {
+                self.block_headers.write_with_fixed(&layout, &BlockInfo::new(0));
+            }
+        }
+        else if stored != &layout[..] {
+            panic!("Block headers in {:?} have an unsupported layout; the store must be imported again",
+                self.cfg.root);
+        }
+    }
+
+
+
+
+    /// Returns the config the store is opened with
+    pub fn config(&self) -> &config::Config {
+        &self.cfg
+    }
+
+
+    /// Takes the store lock shared, to prevent the tx-index from being replaced while it is used
+    ///
+    /// If another store has replaced the tx-index, the new one is opened
+    pub fn lock_shared(&mut self) -> lock::StoreLockGuard {
+
+        let guard = self.lock.shared();
+        self.reopen_tx_index();
+        guard
+    }
+
+    /// Takes the store lock exclusively, such that no other store can add blocks or replace the
+    /// tx-index; as with lock_shared, the current generation of the tx-index is opened
+    pub fn lock_exclusive(&mut self) -> lock::StoreLockGuard {
+
+        let guard = self.lock.exclusive();
+        self.reopen_tx_index();
+        guard
+    }
+
+    /// Opens the current generation of the tx-index if another store has replaced it
+    fn reopen_tx_index(&mut self) {
+
+        let generation = self.lock.get_tx_index_generation();
+        if generation != self.tx_index_generation {
+
+            info!(self.logger, "reopening tx-index"; "generation" => generation);
+            self.tx_index = hash_index::HashIndex::new(&self.cfg, &lock::tx_index_dir(generation));
+            self.tx_index_generation = generation;
+        }
+    }
+
+
+    /// Takes the spend-index lock, such that other stores cannot move the spend-index while this
+    /// store moves or uses it
+    ///
+    /// The tip is re-read under the lock, as another store may have moved the index. The store
+    /// lock must be taken before, and the spend-index lock may not be taken twice at once
+    pub fn lock_spend_index(&mut self) -> lock::StoreLockGuard {
+
+        let guard = self.lock.spend_index();
+        self.spend_index.reload();
+        guard
+    }
 
 
     /// Gets the block hash from a block-ptr;
@@ -160,16 +303,60 @@ impl Store {
     /// Note: This might be not the best spot for this...
     pub fn get_block_hash(&mut self, block_ptr: BlockPtr) -> Hash32Buf {
 
+        Hash32Buf::double_sha256(self.get_block_header(block_ptr))
+
+    }
+
+    /// Gets the raw block header from a block-ptr
+    pub fn get_block_header(&mut self, block_ptr: BlockPtr) -> &'static [u8] {
+
         // follow indirection through spend-tree
         let block_hdr_rec = self.spend_tree.get_record(block_ptr.end());
-        let block_hdr     = self.block_headers.read(block_hdr_rec.get_block_header_ptr());
 
-        Hash32Buf::double_sha256(block_hdr)
+        self.block_headers.read(block_hdr_rec.get_block_header_ptr())
+    }
+
+    /// Returns the work of a block as specified by the difficulty target in its header
+    pub fn get_block_work(&mut self, block_ptr: BlockPtr) -> U256 {
+
+        let header = BlockHeader::parse(&mut Buffer::new(self.get_block_header(block_ptr)))
+            .expect("Stored block header must be valid");
+
+        pow::difficulty_target_to_work(pow::from_compact(header.bits()))
+    }
+
+    /// Gets the info stored after the header of the block
+    pub fn get_block_info(&mut self, block_ptr: BlockPtr) -> BlockInfo {
+
+        *self.block_info_mut(block_ptr)
+    }
+
+    /// Stores the height and the accumulated work of a block that is connected
+    pub fn set_block_info(&mut self, block_ptr: BlockPtr, height: u64, work: U256) {
 
+        let info = self.block_info_mut(block_ptr);
+        info.height = height;
+        info.work   = work;
     }
 
+    fn block_info_mut(&mut self, block_ptr: BlockPtr) -> &'static mut BlockInfo {
+
+        let header_ptr = self.spend_tree.get_record(block_ptr.end()).get_block_header_ptr();
+        let header_len = self.block_headers.read(header_ptr).len() as u64;
+
+        let info_ptr = BlockHeaderPtr::new(header_ptr.get_file_number(),
+                                           header_ptr.get_file_offset() + 4 + header_len);
+
+        self.block_headers.read_fixed(info_ptr)
+    }
+}
 
+/// Returns the first record of the block headers, which identifies the layout of BlockInfo
+fn block_info_layout() -> Vec<u8> {
 
+    let mut layout = BLOCK_INFO_MAGIC.to_vec();
+    layout.extend((0..8).map(|n| (BLOCK_INFO_VERSION >> (n * 8)) as u8));
+    layout
 }
 
 impl Clone for Store {
@@ -187,10 +374,10 @@ unsafe impl Sync for Store {}
 #[cfg(test)]
 mod tests {
 
+    use std::mem;
     use super::*;
 
     use block::BlockHeader;
-    use buffer::*;
 
     #[test]
     fn test_get_block_hash() {
@@ -219,6 +406,41 @@ mod tests {
         let _ = Store::new(& test_cfg!());
     }
 
+    #[test]
+    fn test_block_info_layout() {
+
+        assert_eq!(mem::size_of::<BlockInfo>(), 48);
+
+        let cfg = test_cfg!();
+        {
+            let mut store = Store::new(&cfg);
+            let layout = store.block_headers.read(BlockHeaderPtr::new(0, flatfile::INITIAL_WRITEPOS));
+            assert_eq!(layout, &block_info_layout()[..]);
+        }
+
+        // reopening keeps the layout record, and the headers after it
+        let mut store = Store::new(&cfg);
+        let block_hdr_ptr = store.block_headers.write_with_fixed(&[12u8; 80], &BlockInfo::new(1));
+        let blockptr = store.spend_tree.store_block(block_hdr_ptr, vec![]);
+        assert_eq!(store.get_block_info(blockptr), BlockInfo::new(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "unsupported layout")]
+    fn test_unsupported_block_info_layout() {
+
+        let cfg = test_cfg!();
+        {
+            // as the headers of an earlier version, which start with a block header
+            let mut store = Store::new(&cfg);
+            let layout: &mut [u8] = store.block_headers.read_mut_slice(
+                BlockHeaderPtr::new(0, flatfile::INITIAL_WRITEPOS + 4), 8);
+            layout.copy_from_slice(&[0; 8]);
+        }
+
+        let _store = Store::new(&cfg);
+    }
+
     // this takes a fake spend tree (created with block! macro's) and use it to construct
     // valid transactions and blocks
     /*fn test_create_store_from_spend_tree(spend_tree: RecordPtr) -> Store {
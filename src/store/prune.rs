@@ -1,104 +1,389 @@
+//! Pruning of the tx-index
+//!
+//! Transactions of which all outputs are spent are no longer needed in the tx-index. Pruning
+//! copies the other entries to a new generation of the tx-index and then switches all stores to
+//! it; the old index is removed.
+//!
+//! This can run while other stores add blocks. The entries are copied without holding the lock;
+//! entries that other stores add or change in the meantime are copied again in a second pass.
+//! Only the final pass, which copies the changes made during the second, holds the lock
+//! exclusively. This works because the index is append-only: everything that is changed after a
+//! given write position is written after that position.
+//!
+//! Spends in the last blocks of the chain can still be undone by a reorg or by disconnect_block,
+//! after which the outputs must be found again. Transactions that are spent in the last `depth`
+//! blocks are therefore kept; these are collected in the final pass, with the spend-index lock
+//! held. Reorgs or disconnects deeper than `depth` are not supported after pruning.
 
-
-//! Preliminairy pruning of tx-index
-//! This is probably less needed when using HAMT
-
-// this is needed because of #[ignore]
-#![allow(unused_imports)]
-#![allow(dead_code)]
-
-use std::env;
 use std::fs;
-use std::path;
 
-use hash::*;
 use buffer::*;
-use config::Config;
-use store::hash_index;
-use store::{Store,TxPtr,Record};
 use transaction::Transaction;
+use store::{Store, TxPtr, Record, HashIndexGuard};
+use store::hash_index::{HashIndex, IndexPtr};
+use store::lock;
 
 
-/// Prunes the tx-index of the store to a separate tx-index-pruned folder
-fn prune_to_new_index() {
+/// Progress of pruning the tx-index, as reported to the callback
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PruneProgress {
 
-    let cfg = test_cfg!();
-    let mut store = Store::new(&cfg);
+    /// The pass over the index; 1 is the full pass and 2 and 3 copy the changes made concurrently
+    pub pass:    u32,
 
+    /// Number of hashes visited; a hash that is changed meanwhile and visited again in a later
+    /// pass is counted once
+    pub visited: u64,
 
-    let mut new_tx_index: hash_index::HashIndex<TxPtr>
-        = hash_index::HashIndex::new(&cfg, "tx-index-pruned");
+    /// Number of the visited hashes that are dropped
+    pub pruned:  u64,
 
-    let mut tx_ptr = TxPtr::first();
-    let mut count: u64 = 0;
-    let mut count_purged: u64 = 0;
-    loop {
-        let result = store.transactions.next(tx_ptr);
+    /// Number of the visited hashes that are kept
+    pub kept:    u64
+}
 
-        if result == None {
-            break;
-        }
-        let (tx_raw, next_ptr) = result.unwrap();
+/// The number of hashes after which progress is reported
+const REPORT_INTERVAL: u64 = 100_000;
 
-        // we parse it only for the output_count
-        let tx = Transaction::parse(&mut Buffer::new(tx_raw.as_slice())).unwrap();
+/// The number of blocks of which the spent transactions are kept by default; this is the number
+/// of blocks that bitcoin-core keeps when pruning (MIN_BLOCKS_TO_KEEP)
+pub const DEFAULT_PRUNE_DEPTH: u64 = 288;
 
-        let input_count = tx.txs_out.len() as u32;
 
-        let tx_ptr_copy = tx_ptr;
-        let spend_outputs = (0..input_count)
-            .map(|n|   Record::new_output(tx_ptr_copy, n))
-            .map(|rec| rec.hash())
-            .filter(|hash| store.spend_index.exists(*hash))
-            .count() as u32;
+impl Store {
 
-        if spend_outputs  < input_count {
+    /// Drops all fully spent transactions from the tx-index
+    ///
+    /// A transaction is fully spent if all its outputs are in the spend-index. Transactions of
+    /// which an output is spent in the last `depth` blocks, and guards are always kept.
+    /// `progress` is called periodically and at the end of each pass.
+    pub fn prune_tx_index<F>(&mut self, depth: u64, mut progress: F) -> PruneProgress
+        where F: FnMut(&PruneProgress)
+    {
+        // make sure we start from the current generation
+        let generation = {
+            let _lock = self.lock_shared();
+            self.tx_index_generation + 1
+        };
 
-            // we still need this one
-            let hash = Hash32Buf::double_sha256(tx.to_raw());
+        let new_dir = lock::tx_index_dir(generation);
+        let _ = fs::remove_dir_all(self.cfg.root.join(&new_dir)); // left-over of an aborted run
+        let mut new_index: HashIndex<TxPtr> = HashIndex::new(&self.cfg, &new_dir);
 
-            assert_eq!(store.tx_index.get(hash.as_ref()).len(),1);
+        info!(self.logger, "prune_tx_index - start"; "generation" => generation);
 
-            new_tx_index.set(hash.as_ref(), tx_ptr, &[], true);
+        let mut stats = PruneProgress::default();
+
+        // first a full pass, and then a pass with what is changed since the previous one
+        let mut since = None;
+        for pass in 1..3 {
+            let until = self.tx_index.write_position();
+            self.copy_unspent(&mut new_index, since, until, pass, &mut stats, &mut progress);
+            since = Some(until);
         }
-            else {
-                // all inputs are spend; don't add it to the new-index
-                count_purged += 1;
-            }
 
+        // the final pass is done exclusively; no blocks can be added meanwhile
+        {
+            let _lock             = self.lock.exclusive();
+            let _spend_index_lock = self.lock_spend_index();
 
-        count = count + 1;
-        if count % 1000 == 0 {
-            println!("Done: {}; purged {} %", count, count_purged as u64 * 100 / count as u64);
+            let until = self.tx_index.write_position();
+            self.copy_unspent(&mut new_index, since, until, 3, &mut stats, &mut progress);
+            self.keep_recently_spent(&mut new_index, depth, &mut stats);
+            self.lock.set_tx_index_generation(generation);
         }
 
-        tx_ptr = next_ptr;
+        let old_dir = lock::tx_index_dir(self.tx_index_generation);
+        self.tx_index = new_index;
+        self.tx_index_generation = generation;
+
+        // other stores keep their mapping of the old index until they reopen
+        let _ = fs::remove_dir_all(self.cfg.root.join(old_dir));
+
+        info!(self.logger, "prune_tx_index - done";
+            "pruned" => stats.pruned,
+            "kept" => stats.kept);
+
+        stats
+    }
+
+    /// Copies the entries of the tx-index that are changed after `since` and added before `until`
+    /// to `new_index`, except fully spent transactions
+    ///
+    /// Other stores may move the spend-index meanwhile; this only changes the spends of the last
+    /// blocks, of which the transactions are restored by keep_recently_spent
+    fn copy_unspent<F>(&mut self,
+                       new_index: &mut HashIndex<TxPtr>,
+                       since:     Option<IndexPtr>,
+                       until:     IndexPtr,
+                       pass:      u32,
+                       stats:     &mut PruneProgress,
+                       progress:  &mut F)
+        where F: FnMut(&PruneProgress)
+    {
+        stats.pass = pass;
+
+        let transactions = &mut self.transactions;
+        let spend_index  = &self.spend_index;
+        let mut count    = 0;
+
+        self.tx_index.for_each_until(since, until, &mut |hash, values, added| {
+
+            // a node that is left without values by a recovery is dropped as well
+            let dropped = match values.first() {
+                Some(&tx_ptr) if values.len() == 1 && !tx_ptr.is_guard() => {
+
+                    let output_count = transactions.output_count(tx_ptr);
+                    (0..output_count)
+                        .map(|n| Record::new_output(tx_ptr, n).hash())
+                        .all(|hash| spend_index.exists(hash))
+                },
+                Some(_) => false,
+                None    => true
+            };
+
+            // a hash that is visited again was pruned in an earlier pass, unless it is copied;
+            // a copied hash stays kept
+            if added {
+                stats.visited += 1;
+            }
+            if !dropped {
+                if added || new_index.get(hash).is_empty() {
+                    stats.kept += 1;
+                }
+                new_index.replace(hash, &values);
+            }
+            stats.pruned = stats.visited - stats.kept;
+
+            count += 1;
+            if count % REPORT_INTERVAL == 0 {
+                progress(stats);
+            }
+        });
+
+        progress(stats);
     }
 
-    println!("Done");
-    println!("  {} transactions", count);
-    println!("  {} purged ({} %)", count_purged , count_purged as u64 * 100 / count as u64);
-    println!("  {} remain", count - count_purged ,);
+    /// Copies the entries of the transactions that are spent in the last `depth` blocks of the
+    /// spend-index to `new_index`, if these were pruned
+    ///
+    /// The spend-index lock must be held
+    fn keep_recently_spent(&mut self, new_index: &mut HashIndex<TxPtr>, depth: u64, stats: &mut PruneProgress) {
+
+        let mut block = self.spend_index.get_tip();
+
+        for _ in 0..depth {
+            let current = match block {
+                Some(current) => current,
+                None          => break
+            };
+
+            let spent: Vec<TxPtr> = self.spend_tree.get_block_records(current).iter()
+                .filter(|rec| rec.is_output())
+                .map(|rec| rec.get_transaction_ptr())
+                .collect();
 
+            for tx_ptr in spent {
+                let tx_raw = self.transactions.read(tx_ptr);
+                let hash   = Transaction::parse(&mut Buffer::new(&tx_raw))
+                    .expect("Invalid tx data in database")
+                    .txid();
+
+                if !new_index.get(hash.as_ref()).is_empty() {
+                    continue;
+                }
+
+                let values = self.tx_index.get(hash.as_ref());
+                if !values.is_empty() {
+                    new_index.replace(hash.as_ref(), &values);
+                    stats.kept += 1;
+                }
+            }
+
+            block = self.spend_tree.get_previous_block(current);
+        }
+        stats.pruned = stats.visited - stats.kept;
+    }
 }
 
-#[ignore]
-#[test]
-fn prune_tx_index() {
 
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::thread;
+
+    use config;
+    use hash::Hash32Buf;
+    use store::Store;
+    use block_add::{add_block, BlockAddOk};
+    use super::{PruneProgress, DEFAULT_PRUNE_DEPTH};
+
+    fn txid(tx: &[u8]) -> Hash32Buf {
+        Hash32Buf::double_sha256(tx)
+    }
+
+    fn count_hashes(store: &mut Store) -> u64 {
+        let mut count = 0;
+        store.tx_index.for_each(None, &mut |_, _| count += 1);
+        count
+    }
+
+    // Each pass reports every hash visited so far as either pruned or kept
+    fn check_progress(progress: &PruneProgress) {
+        assert_eq!(progress.pruned + progress.kept, progress.visited);
+    }
+
+    #[test]
+    fn test_prune_tx_index() {
+
+        let cfg = test_cfg!();
+        let mut store = Store::new(&cfg);
+        store.verify_headers = false;
 
-    let store_path = env::var(::config::ENV_BITCRUST_STORE).
-        expect(&format!("Use {} env var to specify a store to prune", ::config::ENV_BITCRUST_STORE));
+        tx_builder!(bld);
 
-    // add pruned tx-index to store_path
-    let pruned_path   = path::PathBuf::from(&store_path).join("tx-index-pruned");
-    let tx_index_path = path::PathBuf::from(&store_path).join("tx-index");
-    let _ =  fs::remove_dir_all(&pruned_path); // if exists
+        let block0 = genesis!();
 
-    prune_to_new_index();
+        let coinbase1 = tx!(bld; coinbase => b;11 );
+        let spend_b   = tx!(bld; b => c,e );
+        let block1 = blk!(prev = block0; coinbase1, spend_b);
 
-    // move the new index into position
-    fs::remove_dir_all(&tx_index_path).expect("Couldn't remove old tx-index");
-    fs::rename(pruned_path, &tx_index_path).expect("Failed to move tx-index after pruning");
+        let spend_c   = tx!(bld; c => g );
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => f;12 ), spend_c);
+        let block3 = blk!(prev = block2; tx!(bld; coinbase => h;13 ), tx!(bld; e => i ));
+        let block4 = blk!(prev = block3; tx!(bld; coinbase => j;14 ));
+        let block5 = blk!(prev = block4; tx!(bld; coinbase => k;15 ));
 
-}
\ No newline at end of file
+        for block in [&block0, &block1, &block2, &block3, &block4, &block5].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+
+        let hashes = count_hashes(&mut store);
+
+        let mut reports = 0;
+        let stats = store.prune_tx_index(2, |p| { check_progress(p); reports += 1 });
+
+        // coinbase1 and spend_b are fully spent before the last two blocks
+        assert_eq!(stats.pruned, 2);
+        assert_eq!(stats.pass, 3);
+        assert_eq!(reports, 3);
+        assert_eq!(stats.visited, hashes);
+        assert_eq!(stats.kept, count_hashes(&mut store));
+
+        assert!( store.tx_index.get(txid(&coinbase1).as_ref()).is_empty());
+        assert!( store.tx_index.get(txid(&spend_b).as_ref()).is_empty());
+        assert!(!store.tx_index.get(txid(&spend_c).as_ref()).is_empty());
+
+        // another store opens the new index and can spend the remaining outputs
+        let mut other = Store::new(&cfg);
+        other.verify_headers = false;
+        assert!( other.tx_index.get(txid(&coinbase1).as_ref()).is_empty());
+
+        let block6 = blk!(prev = block5; tx!(bld; coinbase => l;16 ), tx!(bld; g => m ));
+        assert_eq!(add_block(&mut other, &block6), Ok(BlockAddOk::Connected));
+    }
+
+    #[test]
+    fn test_prune_disconnect() {
+
+        let cfg = test_cfg!();
+        let mut store = Store::new(&cfg);
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+
+        let coinbase1 = tx!(bld; coinbase => a;10, b;10 );
+        let spend_a   = tx!(bld; a => d );
+        let block1 = blk!(prev = block0; coinbase1);
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => c;11 ), spend_a);
+        let block3 = blk!(prev = block2; tx!(bld; coinbase => e;12 ), tx!(bld; d => k ));
+        let block4 = blk!(prev = block3; tx!(bld; coinbase => g;13 ), tx!(bld; b => f ));
+        let block5 = blk!(prev = block4; tx!(bld; coinbase => h;14 ));
+
+        for block in [&block0, &block1, &block2, &block3, &block4, &block5].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+
+        // spend_a is spent in block3, and coinbase1 is spent up to block4
+        let hashes = count_hashes(&mut store);
+        let stats = store.prune_tx_index(2, check_progress);
+        assert_eq!(stats.pruned, 1);
+        assert_eq!(stats.visited, hashes);
+        assert_eq!(stats.kept, count_hashes(&mut store));
+        assert!( store.tx_index.get(txid(&spend_a).as_ref()).is_empty());
+        assert!(!store.tx_index.get(txid(&coinbase1).as_ref()).is_empty());
+
+        // b can be spent again on another branch
+        assert_eq!(store.disconnect_block(Hash32Buf::double_sha256(&block4[0..80]).as_ref()), Ok(2));
+
+        let block4b = blk!(prev = block3; tx!(bld; coinbase => i;15 ), tx!(bld; b => j ));
+        assert_eq!(add_block(&mut store, &block4b), Ok(BlockAddOk::Connected));
+    }
+
+    #[test]
+    fn test_prune_concurrent() {
+
+        let cfg = test_cfg!();
+        let mut store = Store::new(&cfg);
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let mut blocks = vec![genesis!()];
+        let mut txs = Vec::new();
+        for n in 0..100 {
+            let tx = tx!(bld; coinbase => a; n + 100);
+            let block = blk!(prev = blocks[blocks.len() - 1]; tx);
+            txs.push(tx);
+            blocks.push(block);
+        }
+
+        for block in blocks[0..50].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+
+        // another store adds the other blocks while pruning
+        let thread_cfg = cfg.clone();
+        let thread_blocks: Vec<Vec<u8>> = blocks[50..].to_vec();
+        let handle = thread::spawn(move || {
+            let mut other = Store::new(&thread_cfg);
+            other.verify_headers = false;
+            for block in thread_blocks.iter() {
+                assert_eq!(add_block(&mut other, block), Ok(BlockAddOk::Connected));
+            }
+        });
+
+        let stats = store.prune_tx_index(DEFAULT_PRUNE_DEPTH, check_progress);
+        handle.join().unwrap();
+        assert!(stats.kept <= count_hashes(&mut store));
+
+        // nothing is spent, so nothing is lost; the hashes added meanwhile are counted once
+        let _lock = store.lock_shared();
+        for tx in txs.iter() {
+            assert_eq!(store.tx_index.get(txid(tx).as_ref()).len(), 1);
+        }
+        assert_eq!(stats.pruned, 0);
+        assert_eq!(stats.visited, stats.kept);
+    }
+
+    /// Prunes the store given by the BITCRUST_STORE env var
+    #[ignore]
+    #[test]
+    fn prune_tx_index() {
+
+        let store_path = env::var(config::ENV_BITCRUST_STORE)
+            .expect(&format!("Use {} env var to specify a store to prune", config::ENV_BITCRUST_STORE));
+
+        let mut store = Store::new(&config::Config::new(&store_path));
+
+        let stats = store.prune_tx_index(DEFAULT_PRUNE_DEPTH, |p| {
+            println!("Pass {}: {} done; {} pruned, {} kept", p.pass, p.visited, p.pruned, p.kept);
+        });
+
+        println!("Done");
+        println!("  {} purged ({} %)", stats.pruned, stats.pruned * 100 / (stats.pruned + stats.kept + 1));
+        println!("  {} remain", stats.kept);
+    }
+}
@@ -0,0 +1,294 @@
+//! Recovery of a store after a crash
+//!
+//! Data is written to a fileset by first allocating space, and then filling it. If the process is
+//! killed in between, the write position already includes a partial write. On recovery, the
+//! transactions, headers and spend-tree are truncated at their first partial record: a blob is
+//! complete when its length is written (see flatfileset.rs), and a block in the spend-tree when
+//! its end-of-block record is written. Whatever is written after a partial record is dropped too,
+//! and so is a block that refers to dropped data.
+//!
+//! The indexes only refer to data after it is completely written, but if multiple stores write
+//! concurrently, a complete record can follow the partial write of another. Entries of the
+//! tx-index and the block-index that point to dropped data are therefore rolled back.
+//!
+//! A crash can also happen between adding a tip and adding the block to the block-index. Such a
+//! tip is removed, and a tip is restored for the first of its ancestors that is in the block-index.
+//! A spend-index that was being moved has no tip; it is cleared and rebuilt when a block is added.
+//!
+//! Only the last two files of each fileset are verified. This assumes the writes are persisted
+//! in order, as they are when the process is killed; after a power loss the OS may have persisted
+//! later pages of a memory map but not earlier ones, which is not detected.
+
+use std::mem;
+
+use hash::*;
+
+use store::{Store, BlockPtr, BlockInfo, TxPtr, HashIndexGuard};
+use store::block_info_layout;
+use store::flatfileset;
+use store::hash_index::HashIndex;
+use store::tips;
+
+
+impl Store {
+
+    /// Truncates partial writes and rolls back what refers to them
+    ///
+    /// This is called by the first store that opens the directory; no other store may use it
+    pub fn recover(&mut self) {
+
+        let txs_end     = self.transactions.truncate_partial();
+        let layout      = block_info_layout();
+        let headers_end = self.block_headers.truncate_partial(mem::size_of::<BlockInfo>(), |header, info| {
+            (header.len() == 80 && info[0..8].iter().any(|&b| b != 0)) || header == &layout[..]
+        });
+
+        let (records_end, blocks) = self.spend_tree.truncate_partial(headers_end, txs_end);
+
+        if txs_end.is_some() || headers_end.is_some() || records_end.is_some() {
+            warn!(self.logger, "recover - truncated partial writes";
+                "transactions" => format!("{:?}", txs_end),
+                "headers"      => format!("{:?}", headers_end),
+                "spend-tree"   => format!("{:?}", records_end));
+        }
+
+        if let Some(end) = txs_end {
+            let dropped = roll_back(&mut self.tx_index, |ptr: TxPtr| !flatfileset::is_before(ptr, end));
+            info!(self.logger, "recover - tx-index rolled back"; "hashes" => dropped);
+        }
+
+        if let Some(end) = records_end {
+            let dropped = roll_back(&mut self.block_index, |ptr: BlockPtr| ptr.start.to_index() >= end.to_index());
+            info!(self.logger, "recover - block-index rolled back"; "hashes" => dropped);
+        }
+
+        self.recover_tips(&blocks);
+
+        let spend_index_valid = match (self.spend_index.get_tip(), records_end) {
+            (None, _)                => false,
+            (Some(tip), Some(end))   => tip.start.to_index() < end.to_index(),
+            (Some(_),   None)        => true
+        };
+
+        if !spend_index_valid {
+            warn!(self.logger, "recover - clearing spend-index");
+            self.spend_index.clear();
+        }
+    }
+
+    /// Removes the tips of blocks that are not in the block-index
+    ///
+    /// If such a block is found among the given blocks, the tip is moved down to the first of its
+    /// ancestors that is in the block-index
+    fn recover_tips(&mut self, blocks: &[BlockPtr]) {
+
+        for tip in self.tips.get_tips() {
+
+            if self.is_indexed(tip.block_hash) {
+                continue;
+            }
+
+            warn!(self.logger, "recover - removing tip"; "hash" => format!("{:?}", tip.block_hash));
+
+            let mut block = blocks.iter()
+                .map(|&block| block)
+                .find(|&block| self.get_block_hash(block) == tip.block_hash);
+
+            let mut work   = tip.work;
+            let mut height = tip.height;
+
+            while let Some(current) = block {
+
+                let previous = match self.spend_tree.get_previous_block(current) {
+                    Some(previous) => previous,
+                    None           => break
+                };
+
+                work   = work - self.get_block_work(current);
+                height = height - 1;
+
+                let previous_hash = self.get_block_hash(previous);
+                if self.is_indexed(previous_hash) {
+
+                    if self.tips.get_tip(previous_hash).is_none() {
+                        tips::add_tip(&self.tips, previous_hash, None, work, height);
+                    }
+                    break;
+                }
+                block = Some(previous);
+            }
+
+            self.tips.remove_tip(&tip);
+        }
+    }
+
+    fn is_indexed(&mut self, block_hash: Hash32Buf) -> bool {
+
+        self.block_index.get(block_hash.as_ref()).iter().any(|ptr| !ptr.is_guard())
+    }
+}
+
+
+/// Removes the values for which `dropped` returns true from the entries of the index that are
+/// recently changed. Returns the number of entries changed
+fn roll_back<T, F>(index: &mut HashIndex<T>, dropped: F) -> usize
+    where T: HashIndexGuard + PartialEq + Copy + Clone + 'static,
+          F: Fn(T) -> bool
+{
+    let mut changed: Vec<(Hash32Buf, Vec<T>)> = Vec::new();
+
+    let since = index.tail_position();
+    index.for_each(Some(since), &mut |hash, values| {
+
+        if values.iter().any(|&value| dropped(value)) {
+            let kept = values.into_iter().filter(|&value| !dropped(value)).collect();
+            changed.push((hash.as_buf(), kept));
+        }
+    });
+
+    for &(ref hash, ref values) in changed.iter() {
+        index.replace(hash.as_ref(), values);
+    }
+
+    changed.len()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::panic;
+    use std::collections::HashSet;
+
+    use api;
+    use hash::Hash32Buf;
+    use block::{Block, BlockError};
+    use block_add::{add_block, update_main_chain, BlockAddOk};
+    use store::{Store, Record, HashIndexGuard, SpendingError};
+    use store::flatfile::crash;
+
+    fn hash(block: &[u8]) -> Hash32Buf {
+        Hash32Buf::double_sha256(&block[0..80])
+    }
+
+    // Verifies that the best tip is in the block-index, and that the spend-index contains
+    // exactly the stored transactions that are in the main chain
+    fn assert_consistent(store: &mut Store, blocks: &[Vec<u8>]) {
+
+        let _lock = store.lock_spend_index();
+        update_main_chain(store);
+
+        let mut main_chain: HashSet<u64> = HashSet::new();
+
+        if let Some(tip) = store.tips.get_most_work_tip() {
+
+            let mut block = store.block_index.get(tip.block_hash.as_ref()).into_iter()
+                .find(|ptr| !ptr.is_guard())
+                .expect("Tip is not in the block-index");
+
+            loop {
+                for rec in store.spend_tree.get_block_records(block).iter().filter(|rec| rec.is_transaction()) {
+                    main_chain.insert(rec.hash());
+                }
+                match store.spend_tree.get_previous_block(block) {
+                    Some(previous) => block = previous,
+                    None           => break
+                }
+            }
+        }
+
+        for raw in blocks.iter() {
+            for tx in Block::new(raw).unwrap().txs.iter() {
+
+                let ptr = store.tx_index.get(tx.txid().as_ref()).into_iter().find(|ptr| !ptr.is_guard());
+                if let Some(ptr) = ptr {
+                    let hash = Record::new_transaction(ptr).hash();
+                    assert_eq!(store.spend_index.exists(hash), main_chain.contains(&hash));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_kill_at_any_write() {
+
+        let cfg = test_cfg!();
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0; tx!(bld; coinbase => a;10, b;10 ));
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => c;11 ), tx!(bld; a => d;5, e;5 ));
+        let block3 = blk!(prev = block2; tx!(bld; coinbase => f;12 ), tx!(bld; d => g ));
+
+        // block5 comes in before block4
+        let block4 = blk!(prev = block3; tx!(bld; coinbase => h;13 ), tx!(bld; b => i ));
+        let block5 = blk!(prev = block4; tx!(bld; coinbase => j;14 ), tx!(bld; e => k ));
+
+        let blocks = vec![block0, block1, block2, block3, block5, block4];
+
+        let add_all = |store: &mut Store| {
+            for block in blocks.iter() {
+                match add_block(store, block) {
+                    Ok(_) => {},
+                    x     => panic!("Unexpected result {:?}", x)
+                }
+            }
+        };
+
+        // count the writes
+        crash::kill_after(&cfg.root, usize::max_value());
+        {
+            let mut store = Store::new(&cfg);
+            store.verify_headers = false;
+            add_all(&mut store);
+        }
+        let writes = crash::revive();
+
+        for kill_at in 0..writes {
+
+            let _ = fs::remove_dir_all(&cfg.root);
+            println!("Kill at write {}", kill_at);
+
+            {
+                let mut store = Store::new(&cfg);
+                store.verify_headers = false;
+
+                crash::kill_after(&cfg.root, kill_at);
+                let result = panic::catch_unwind(panic::AssertUnwindSafe(|| add_all(&mut store)));
+                crash::revive();
+
+                assert!(result.is_err());
+            }
+
+            // recover
+            let mut store = Store::new(&cfg);
+            store.verify_headers = false;
+            assert_consistent(&mut store, &blocks);
+
+            // and add everything again
+            add_all(&mut store);
+
+            assert_eq!(store.transactions.truncate_partial(), None);
+            assert_eq!(store.spend_tree.truncate_partial(None, None).0, None);
+
+            let tip = store.tips.get_most_work_tip().unwrap();
+            assert_eq!(tip.block_hash, hash(&blocks[4]));
+            assert_eq!(tip.height, 5);
+
+            for block in blocks.iter() {
+                assert_eq!(api::get_block(&mut store, hash(block).as_ref().0).as_ref(), Some(block));
+            }
+            assert_consistent(&mut store, &blocks);
+            assert_eq!(store.verify_integrity(), vec![]);
+
+            // a was spent in block2, c is unspent
+            let spent = blk!(prev = blocks[4]; tx!(bld; coinbase => l;15 ), tx!(bld; g, a => m ));
+            assert_eq!(add_block(&mut store, &spent),
+                Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+            let unspent = blk!(prev = blocks[4]; tx!(bld; coinbase => n;16 ), tx!(bld; g, c => o ));
+            assert_eq!(add_block(&mut store, &unspent), Ok(BlockAddOk::Connected));
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Rebuilding of the indexes
+//!
+//! The tx-index, the block-index and the spend-index are derived from the transactions, the block
+//! headers and the spend-tree. Reindexing rebuilds them, such that a corrupt index can be
+//! recovered without downloading the chain again.
+//!
+//! The tx-index is rebuilt from the transaction records of the spend-tree rather than from all
+//! stored transactions: a transaction that fails verification is stored but never indexed, and
+//! must not become indexed. Loose transactions (see tx_add.rs) and the guards of transactions
+//! that wait for their inputs are dropped; these are verified again when they come in a block.
+//!
+//! The block-index gets the connected blocks at their hash, and the orphan blocks as guards at
+//! the hash of their previous block. A block that is not connected while its previous block is
+//! stored was rejected, and is not indexed.
+//!
+//! The spend-index is rebuilt up to the best tip; the tips themselves are kept.
+//!
+//! The tx-index is switched to a new generation when it is complete, as with pruning. The other
+//! indexes are rebuilt in place; if reindexing is interrupted, it must be run again.
+
+use std::fs;
+
+use rayon::prelude::*;
+
+use buffer::*;
+use hash::*;
+use block::BlockHeader;
+use transaction::Transaction;
+
+use store::{Store, BlockPtr, Record, RecordPtr, HashIndexGuard};
+use store::hash_index::HashIndex;
+use store::lock;
+
+
+/// The number of records of which the transactions are indexed per thread
+const CHUNK_SIZE: usize = 10_000;
+
+
+/// The result of reindexing
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReindexStats {
+
+    /// Number of transactions in the tx-index
+    pub transactions: u64,
+
+    /// Number of connected blocks in the block-index
+    pub blocks:       u64,
+
+    /// Number of orphan blocks that are added as guard
+    pub orphans:      u64,
+
+    /// Number of blocks that are stored but were rejected
+    pub rejected:     u64
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReindexError {
+
+    /// Another store uses the directory
+    StoreInUse
+}
+
+
+impl Store {
+
+    /// Rebuilds the tx-index, the block-index and the spend-index
+    ///
+    /// No other store may use the directory meanwhile
+    pub fn reindex(&mut self) -> Result<ReindexStats, ReindexError> {
+
+        let _lock = self.lock.exclusive();
+
+        if !self.lock.is_only_user() {
+            return Err(ReindexError::StoreInUse);
+        }
+
+        info!(self.logger, "reindex - start");
+
+        let mut stats = ReindexStats::default();
+
+        let old_generation = self.lock.get_tx_index_generation();
+        let generation     = old_generation + 1;
+
+        let new_dir = lock::tx_index_dir(generation);
+        let _ = fs::remove_dir_all(self.cfg.root.join(&new_dir)); // left-over of an aborted run
+
+        self.tx_index            = HashIndex::new(&self.cfg, &new_dir);
+        self.tx_index_generation = generation;
+
+        stats.transactions = self.reindex_transactions();
+
+        self.lock.set_tx_index_generation(generation);
+        let _ = fs::remove_dir_all(self.cfg.root.join(lock::tx_index_dir(old_generation)));
+
+        info!(self.logger, "reindex - tx-index done"; "transactions" => stats.transactions);
+
+        let _ = fs::remove_dir_all(self.cfg.root.join("block-index"));
+        self.block_index = HashIndex::new(&self.cfg, "block-index");
+
+        self.reindex_blocks(&mut stats);
+
+        info!(self.logger, "reindex - block-index done";
+            "blocks"   => stats.blocks,
+            "orphans"  => stats.orphans,
+            "rejected" => stats.rejected);
+
+        self.reindex_spends();
+
+        info!(self.logger, "reindex - done");
+
+        Ok(stats)
+    }
+
+    /// Adds the transactions of all transaction records to the tx-index
+    ///
+    /// Returns the number of transactions added
+    fn reindex_transactions(&mut self) -> u64 {
+
+        let files = self.spend_tree.get_written_records();
+        let store: &Store = self;
+
+        let mut count = 0;
+        for &(_, records) in files.iter() {
+
+            let counts: Vec<u64> = records.par_chunks(CHUNK_SIZE).map(|chunk| {
+
+                let mut tx_index     = store.tx_index.clone();
+                let mut transactions = store.transactions.clone();
+                let mut count        = 0;
+
+                for record in chunk.iter().filter(|rec| rec.is_transaction() && !rec.is_unmatched_input()) {
+
+                    let ptr = record.get_transaction_ptr();
+
+                    // invalid data is left out; see verify_integrity
+                    let raw = match transactions.try_read(ptr) {
+                        Some(raw) => raw,
+                        None      => continue
+                    };
+
+                    if let Ok(tx) = Transaction::parse(&mut Buffer::new(&raw)) {
+
+                        // the same transaction can be in multiple blocks, and coinbases in
+                        // different blocks can have the same txid
+                        let txid = tx.txid();
+                        if tx_index.set(txid.as_ref(), ptr, &[], false) {
+                            count += 1;
+                        }
+                        else if !tx_index.get(txid.as_ref()).contains(&ptr) {
+                            tx_index.add(txid.as_ref(), ptr);
+                            count += 1;
+                        }
+                    }
+                }
+                count
+            }).collect();
+
+            count += counts.into_iter().sum::<u64>();
+        }
+        count
+    }
+
+    /// Adds the connected blocks of the spend-tree to the block-index, and the orphans as guards
+    fn reindex_blocks(&mut self, stats: &mut ReindexStats) {
+
+        let mut blocks: Vec<BlockPtr> = Vec::new();
+
+        for (first, records) in self.spend_tree.get_written_records() {
+
+            let mut start = None;
+            for (n, record) in records.iter().enumerate() {
+
+                if record.is_block_start() {
+                    start = Some(n);
+                }
+                else if record.is_block_end() {
+                    if let Some(start) = start.take() {
+                        blocks.push(BlockPtr {
+                            start:    RecordPtr::new(first.to_index() + start as u64),
+                            length:   (n - start + 1) as u64,
+                            is_guard: false
+                        });
+                    }
+                }
+            }
+        }
+
+        let headers: Vec<&'static [u8]> = blocks.iter()
+            .map(|&block| self.get_block_header(block))
+            .collect();
+
+        let hashes: Vec<Hash32Buf> = headers
+            .par_iter()
+            .map(|header| Hash32Buf::double_sha256(header))
+            .collect();
+
+        // the connected blocks go first, such that rejected blocks can be recognized
+        let mut orphans = Vec::new();
+
+        for (n, &block) in blocks.iter().enumerate() {
+
+            let start: Record = self.spend_tree.get_record(block.start);
+
+            if start.get_previous_block_end().is_some() || self.params.is_genesis_block(hashes[n].as_ref()) {
+
+                // the same block can be stored twice by concurrent stores
+                if self.block_index.set(hashes[n].as_ref(), block, &[], false) {
+                    stats.blocks += 1;
+                }
+            }
+            else {
+                orphans.push(n);
+            }
+        }
+
+        for n in orphans {
+
+            let header = BlockHeader::parse(&mut Buffer::new(headers[n]))
+                .expect("Stored block header must be valid");
+
+            match self.block_index.get_or_set(header.prev_hash, blocks[n].to_guard()) {
+                Some(_) => stats.rejected += 1,
+                None    => stats.orphans  += 1
+            }
+        }
+    }
+
+    /// Rebuilds the spend-index up to the best tip
+    fn reindex_spends(&mut self) {
+
+        let best_block = self.tips.get_most_work_tip().and_then(|tip| {
+            self.block_index.get(tip.block_hash.as_ref()).into_iter().find(|ptr| !ptr.is_guard())
+        });
+
+        match best_block {
+            Some(block) => self.spend_tree.rebuild_spend_index(&mut self.spend_index, block),
+            None        => self.spend_index.clear()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use block::BlockError;
+    use block_add::{add_block, BlockAddOk};
+    use store::SpendingError;
+
+    fn hash(raw: &[u8]) -> Hash32Buf {
+        Hash32Buf::double_sha256(raw)
+    }
+
+    #[test]
+    fn test_reindex() {
+
+        let cfg = test_cfg!();
+        let mut store = Store::new(&cfg);
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0; tx!(bld; coinbase => a;10, b;10 ));
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => c;11 ), tx!(bld; a => d ));
+
+        // spends a again
+        let rejected = blk!(prev = block2; tx!(bld; coinbase => e;12 ), tx!(bld; a => f ));
+
+        // the previous block of the orphan comes in after reindexing
+        let block3 = blk!(prev = block2; tx!(bld; coinbase => g;13 ), tx!(bld; b => h ));
+        let orphan = blk!(prev = block3; tx!(bld; coinbase => i;14 ), tx!(bld; d => j ));
+
+        for block in [&block0, &block1, &block2].iter() {
+            assert_eq!(add_block(&mut store, block), Ok(BlockAddOk::Connected));
+        }
+        assert_eq!(add_block(&mut store, &rejected),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+        assert_eq!(add_block(&mut store, &orphan), Ok(BlockAddOk::Orphan));
+
+        // corrupt the indexes
+        let block2_hash = hash(&block2[0..80]);
+        let block1_ptr  = store.block_index.get(hash(&block1[0..80]).as_ref())[0];
+        store.block_index.replace(block2_hash.as_ref(), &[block1_ptr]);
+        store.spend_index.clear();
+
+        assert!(!store.verify_integrity().is_empty());
+
+        // another store uses the directory
+        {
+            let _other = Store::new(&cfg);
+            assert_eq!(store.reindex(), Err(ReindexError::StoreInUse));
+        }
+
+        assert_eq!(store.reindex(), Ok(ReindexStats {
+            transactions: 7,
+            blocks:       3,
+            orphans:      1,
+            rejected:     1
+        }));
+
+        assert_eq!(store.verify_integrity(), vec![]);
+
+        // another store opens the new tx-index
+        let mut other = Store::new(&cfg);
+        other.verify_headers = false;
+        assert_eq!(add_block(&mut other, &block3), Ok(BlockAddOk::Connected));
+
+        // the orphan is connected and the rejected block is verified again
+        assert_eq!(add_block(&mut store, &orphan), Ok(BlockAddOk::AlreadyExists));
+        assert_eq!(add_block(&mut store, &rejected),
+            Err(BlockError::SpendingError(SpendingError::OutputAlreadySpend)));
+
+        let block5 = blk!(prev = orphan; tx!(bld; coinbase => k;15 ), tx!(bld; c => l ));
+        assert_eq!(add_block(&mut store, &block5), Ok(BlockAddOk::Connected));
+
+        let spent = blk!(prev = block5; tx!(bld; coinbase => m;16 ), tx!(bld; j => n ), tx!(bld; h => o ));
+        assert_eq!(add_block(&mut store, &spent), Ok(BlockAddOk::Connected));
+        assert_eq!(store.verify_integrity(), vec![]);
+    }
+}
@@ -9,31 +9,47 @@
 //! are given a unique bit which is set if the given transaction or spend exists
 
 use std::sync::atomic::{AtomicU64,Ordering};
+use std::path::PathBuf;
+use std::fs;
+use std::io::prelude::*;
 
-use config;
-use store::flatfileset::FlatFileSet;
-use store::RecordPtr;
+use rayon::prelude::*;
 
+use config;
+use store::flatfileset::{FlatFileSet, Location};
+use store::flatfile::guarded_write;
+use store::{BlockPtr,RecordPtr,Record};
+use store::spend_tree::record::{self, RECORDS_PER_FILE};
 
-const MB:                 u64 = 1024 * 1024;
-const FILE_SIZE:          u64 = 16 * 1024 * MB ;
-const MAX_CONTENT_SIZE:   u64 = FILE_SIZE - 10 * MB ;
 
-// TODO; make this dynamic using fileset continuation;
-// this isn't hastily needed as the OS does not actually allocate
-// all the space; (compare ls with du).
-const VEC_SIZE:         usize = 500_000_000;
+// The bit-vector is stored in files of the configured size, of which the words are addressed as
+// the records of the spend-tree (see RECORDS_PER_FILE). The files are created when a bit is set
+// in them; no more space is needed than for the transactions that are in the chain.
 
 /// Index to lookup spends
 ///
 /// Internally uses fileset
 ///
+/// The index is only valid for a single chain: it contains the transactions and spends of all
+/// blocks up to and including its tip. When a block on another branch is verified, the spend-tree
+/// moves the tip, rolling back and forward the blocks between the two.
+///
+/// The files and the tip are shared with other stores; the index may only be moved or used with
+/// the spend-index lock held, after calling `reload` (see Store::lock_spend_index)
+///
 pub struct SpendIndex {
 
-    #[allow(dead_code)]
     fileset:      FlatFileSet<RecordPtr>,
 
-    bitvector:    &'static [AtomicU64]
+    // the words of each file by file number; files that don't exist yet are empty
+    bitvectors:   Vec<&'static [AtomicU64]>,
+
+    // the number of words that fit in a file of the configured size
+    words_per_file: u64,
+
+    // the last block that is included in the index; None if the index is empty
+    tip:          Option<BlockPtr>,
+    tip_path:     PathBuf
 
 }
 
@@ -47,43 +63,213 @@ impl SpendIndex
     pub fn new(cfg: &config::Config) -> SpendIndex {
         let dir = &cfg.root.clone().join("spend-index");
 
-        let mut fileset = FlatFileSet::new(
-            dir, "si-", FILE_SIZE, MAX_CONTENT_SIZE);
+        let file_size = cfg.spend_index_file_size;
+        assert!(file_size <= record::RECORDS_FILE_SIZE);
+
+        let fileset = FlatFileSet::new(
+            dir, "si-", file_size, file_size);
+
+        let tip_path  = dir.join("tip");
+        let tip       = read_tip(&tip_path);
+
+        let mut index = SpendIndex {
+            fileset:    fileset,
+            bitvectors: Vec::new(),
+            words_per_file: record::records_per_file(file_size),
+            tip:        tip,
+            tip_path:   tip_path
+        };
+
+        // map the files that are on disk; exists() cannot map files as it is called concurrently
+        let files = index.fileset.file_numbers().end as usize;
+        if files > 0 {
+            index.get_bitvector_mut(files - 1);
+        }
+        index
+    }
+
+    /// Re-reads the tip, and maps the files that other stores have created since the index was
+    /// opened; another store may have moved the index
+    pub fn reload(&mut self) {
+
+        self.tip = read_tip(&self.tip_path);
+
+        self.fileset.reload_file_numbers();
+        let files = self.fileset.file_numbers().end as usize;
+        if files > self.bitvectors.len() {
+            self.get_bitvector_mut(files - 1);
+        }
+    }
+
+    /// Returns the words of the file with the given number, mapping it and the files before it
+    /// if needed
+    fn get_bitvector_mut(&mut self, fileno: usize) -> &'static [AtomicU64] {
 
-        let bitvector = fileset.read_mut_slice(RecordPtr::new(0), VEC_SIZE);
-        SpendIndex {
-            fileset: fileset,
-            bitvector: bitvector
+        while self.bitvectors.len() <= fileno {
+            let first = RecordPtr::new(self.bitvectors.len() as u64 * RECORDS_PER_FILE);
+            let words = self.fileset.read_mut_slice(first, self.words_per_file as usize);
+            self.bitvectors.push(words);
         }
+        self.bitvectors[fileno]
+    }
+
+    /// Returns the file and offset of the word that holds the bit of a hash
+    pub fn location(&self, hash: u64) -> Location {
+
+        let (fileno, idx) = locate(hash, self.words_per_file);
+        self.fileset.location(RecordPtr::new(fileno as u64 * RECORDS_PER_FILE + idx as u64))
+    }
+
+    /// Returns the block up to which the index is valid
+    pub fn get_tip(&self) -> Option<BlockPtr> {
+        self.tip
+    }
+
+    /// Registers the block up to which the index is valid
+    ///
+    /// The tip is written to disk such that the index remains usable after a restart
+    pub fn set_tip(&mut self, tip: BlockPtr) {
+
+        let mut file = fs::File::create(&self.tip_path)
+            .expect("Cannot create files in store");
+
+        write!(file, "{},{}", tip.start.to_index(), tip.length).unwrap();
+
+        self.tip = Some(tip);
+    }
+
+    /// Removes the tip before the index is changed; it is registered again with `set_tip`
+    ///
+    /// If the process is killed in between, the index is found without a tip, and cleared on
+    /// recovery
+    pub fn remove_tip(&mut self) {
+
+        let _ = fs::remove_file(&self.tip_path);
+        self.tip = None;
     }
 
+    /// Clears the tip and all bits of the index; the index may not be in use by other stores
+    ///
+    /// The index is then rebuilt from genesis when its tip is moved to a block
+    pub fn clear(&mut self) {
+
+        self.remove_tip();
 
+        // the files are recreated as bits are set
+        self.bitvectors.clear();
+        self.fileset.remove_files();
+    }
 
 
     /// Tests if the given hash exists.
     pub fn exists(&self, hash: u64) -> bool {
 
-        let idx =  (hash >> 6) as usize;
-        (self.bitvector[idx].load(Ordering::Relaxed) & (1 << (hash & 0x3F))) > 0
+        let (fileno, idx) = locate(hash, self.words_per_file);
+
+        // a bit in a file that isn't mapped is not set, as only set() creates files
+        match self.bitvectors.get(fileno) {
+            Some(words) => (words[idx].load(Ordering::Relaxed) & (1 << (hash & 0x3F))) > 0,
+            None        => false
+        }
     }
 
 
     /// Stores a record hash; this should uniquely identify an output or a transaction
     pub fn set(&mut self, hash: u64)  {
 
-        // CAS-loop
-        loop {
-            let idx = (hash >> 6) as usize;
-            let org = self.bitvector[idx].load(Ordering::Acquire);
-            let new = org | (1 << (hash & 0x3F));
+        let (fileno, idx) = locate(hash, self.words_per_file);
+        let words = self.get_bitvector_mut(fileno);
+
+        guarded_write(&self.tip_path, 1, |_| set_bit(&words[idx], hash));
+    }
+
+    /// Stores the hashes of the given blocks of records in parallel; used to rebuild the index
+    pub fn set_all(&mut self, blocks: &[&[Record]]) {
+
+        let max_hash = blocks.iter()
+            .flat_map(|records| records.iter())
+            .map(|record| record.hash())
+            .max();
 
-            if self.bitvector[idx].compare_and_swap(org, new, Ordering::Release) == org {
-                break;
+        // map the files first, such that the bits can be set concurrently
+        if let Some(max_hash) = max_hash {
+            self.get_bitvector_mut(locate(max_hash, self.words_per_file).0);
+        }
+
+        let bitvectors     = &self.bitvectors;
+        let words_per_file = self.words_per_file;
+        blocks.par_iter().for_each(|records| {
+            for record in records.iter() {
+                let (fileno, idx) = locate(record.hash(), words_per_file);
+                set_bit(&bitvectors[fileno][idx], record.hash());
             }
+        });
+    }
+
+    /// Removes a record hash; used to roll back a block
+    pub fn unset(&mut self, hash: u64)  {
+
+        let (fileno, idx) = locate(hash, self.words_per_file);
+        let words = match self.bitvectors.get(fileno) {
+            Some(words) => *words,
+            None        => return
+        };
+
+        guarded_write(&self.tip_path, 1, |_| {
+            // CAS-loop
+            loop {
+                let org = words[idx].load(Ordering::Acquire);
+                let new = org & !(1 << (hash & 0x3F));
+
+                if words[idx].compare_and_swap(org, new, Ordering::Release) == org {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Returns the file number and the index of the word within that file of a hash
+fn locate(hash: u64, words_per_file: u64) -> (usize, usize) {
+    let word = hash >> 6;
+    ((word / words_per_file) as usize, (word % words_per_file) as usize)
+}
+
+/// Sets the bit of the hash in the given word
+fn set_bit(word: &AtomicU64, hash: u64) {
+
+    // CAS-loop
+    loop {
+        let org = word.load(Ordering::Acquire);
+        let new = org | (1 << (hash & 0x3F));
+
+        if word.compare_and_swap(org, new, Ordering::Release) == org {
+            break;
         }
     }
 }
 
+/// Reads the tip as stored by set_tip
+fn read_tip(path: &PathBuf) -> Option<BlockPtr> {
+
+    let mut content = String::new();
+    match fs::File::open(path) {
+        Ok(mut file) => { file.read_to_string(&mut content).expect("Cannot read spend-index tip"); },
+        Err(_)       => return None
+    };
+
+    let mut parts = content.split(',').map(|x| x.trim().parse::<u64>());
+
+    match (parts.next(), parts.next()) {
+        (Some(Ok(start)), Some(Ok(length))) => Some(BlockPtr {
+            start:    RecordPtr::new(start),
+            length:   length,
+            is_guard: false
+        }),
+        _ => None
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -118,5 +304,87 @@ mod tests {
                 assert!( !idx.exists(n));
             }
         }
+
+        for n in 0..60000_u64 {
+            if n % 6 == 0 {
+                idx.unset(n);
+            }
+        }
+
+        for n in 0..60000 {
+            assert_eq!(idx.exists(n), n % 3 == 0 && n % 6 != 0);
+        }
+    }
+
+    #[test]
+    fn test_grow() {
+
+        let cfg = test_cfg!();
+
+        // a hash in the third file
+        let hash = RECORDS_PER_FILE * 64 * 2 + 12345;
+        {
+            let mut idx = SpendIndex::new(&cfg);
+            assert!(!idx.exists(hash));
+
+            idx.set(hash);
+            idx.set(3);
+            assert!( idx.exists(hash));
+            assert!(!idx.exists(hash + 1));
+        }
+
+        // reopen; the files are mapped before they are needed
+        let mut idx = SpendIndex::new(&cfg);
+        assert!( idx.exists(hash));
+        assert!( idx.exists(3));
+        assert!(!idx.exists(RECORDS_PER_FILE * 64 * 5));
+
+        idx.unset(hash);
+        idx.unset(RECORDS_PER_FILE * 64 * 5);
+        assert!(!idx.exists(hash));
+    }
+
+    #[test]
+    fn test_tip() {
+
+        let cfg = test_cfg!();
+        let tip = BlockPtr { start: RecordPtr::new(12), length: 5, is_guard: false };
+
+        {
+            let mut idx = SpendIndex::new(&cfg);
+            assert_eq!(idx.get_tip(), None);
+
+            idx.set_tip(tip);
+            assert_eq!(idx.get_tip(), Some(tip));
+        }
+
+        // reopen
+        let idx = SpendIndex::new(&cfg);
+        assert_eq!(idx.get_tip(), Some(tip));
+    }
+
+    #[test]
+    fn test_reload() {
+
+        let cfg = test_cfg!();
+        let tip = BlockPtr { start: RecordPtr::new(7), length: 3, is_guard: false };
+
+        let mut idx   = SpendIndex::new(&cfg);
+        let mut other = SpendIndex::new(&cfg);
+
+        // a hash in a file the first index hasn't mapped
+        let hash = RECORDS_PER_FILE * 64 * 3 + 5;
+        other.set(hash);
+        other.set_tip(tip);
+        assert!(!idx.exists(hash));
+        assert_eq!(idx.get_tip(), None);
+
+        idx.reload();
+        assert!(idx.exists(hash));
+        assert_eq!(idx.get_tip(), Some(tip));
+
+        other.remove_tip();
+        idx.reload();
+        assert_eq!(idx.get_tip(), None);
     }
 }
\ No newline at end of file
@@ -17,8 +17,12 @@
 ///
 
 
+use std::collections::{HashMap, HashSet};
+use std::cmp;
+
 use itertools::Itertools;
 use buffer::*;
+use hash::Hash32;
 
 use config;
 use rayon::prelude::*;
@@ -27,7 +31,7 @@ use slog;
 
 use store;
 use store::{TxPtr,BlockHeaderPtr};
-use store::flatfileset::FlatFileSet;
+use store::flatfileset::{self, FlatFileSet, FlatFilePtr, Location};
 
 use store::hash_index::{HashIndex,HashIndexGuard};
 use store::spend_index::SpendIndex;
@@ -37,18 +41,10 @@ use transaction::Transaction;
 pub mod record;
 pub use self::record::{Record,RecordPtr};
 
-const MB:                 u64 = 1024 * 1024;
-const FILE_SIZE:          u64 = 16 * 1024 * MB ;
-const MAX_CONTENT_SIZE:   u64 = FILE_SIZE - 10 * MB ;
-
 const SUBPATH: &'static str   = "spend-tree";
 const PREFIX:  &'static str   = "st-";
 
 
-// temporarily we use a vec instead of the dynamic growing flatfileset
-// this isn't a big problem because the OS will not allocate the trailing zeros
-const VEC_SIZE: usize = 800_000_000;
-
 #[derive(Debug, PartialEq)]
 pub enum SpendingError {
     OutputNotFound,
@@ -97,7 +93,30 @@ impl BlockPtr {
 
 pub struct SpendTree {
 
-    fileset:    FlatFileSet<RecordPtr>
+    fileset:    FlatFileSet<RecordPtr>,
+
+    // the number of records that fit in a file of the configured size
+    records_per_file: u64
+}
+
+
+/// All records of the spend-tree, indexed by RecordPtr index
+///
+/// The records span multiple files; a block never crosses a file boundary, but a block can
+/// point to a previous block in another file
+pub struct Records {
+    files: Vec<&'static [Record]>
+}
+
+impl ::std::ops::Index<usize> for Records {
+    type Output = Record;
+
+    fn index(&self, idx: usize) -> &Record {
+        let ptr = RecordPtr::new(idx as u64);
+        let file = &self.files[ptr.get_file_number() as usize];
+
+        &file[idx % record::RECORDS_PER_FILE as usize]
+    }
 }
 
 
@@ -136,7 +155,7 @@ impl ::std::ops::Add for SpendTreeStats {
 /// This is the algorithm to check double-spends and the existence of outputs
 /// It will call the verify_spend function on Record in parallel for each output
 fn seek_and_set_inputs(
-                       records: &[Record],
+                       records: &Records,
                        block: &mut [Record],
                        block_idx: usize,
                        spend_index: &SpendIndex,
@@ -164,21 +183,106 @@ fn seek_and_set_inputs(
 }
 
 
+/// Returns the output record of the given output
+///
+/// Coinbases in different blocks can have the same txid (see BIP30). Of these, the output of
+/// the one in the chain of the spend-index is returned. If more are in the chain, the latest
+/// replaces the earlier ones; it is kept in front in the tx-index (see
+/// block_add::verify_unique_coinbase). If none is in the chain, the first is used
+pub fn find_output(tx_index:     &mut HashIndex<TxPtr>,
+                   spend_index:  &SpendIndex,
+                   hash:         Hash32,
+                   output_index: u32) -> Option<Record> {
+
+    let outputs: Vec<Record> = tx_index
+        .get(hash)
+        .into_iter()
+        .filter(|ptr| !ptr.is_guard())
+        .map(|ptr| Record::new_output(ptr, output_index))
+        .collect();
+
+    if outputs.len() < 2 {
+        return outputs.first().map(|&output| output);
+    }
+
+    outputs.iter()
+        .find(|output| spend_index.exists(Record::new_transaction(output.get_transaction_ptr()).hash()))
+        .or(outputs.first())
+        .map(|&output| output)
+}
+
+
+/// Returns the number of records of the block at the start of `records`, if it is completely
+/// written and refers to headers and transactions before the given positions
+fn complete_block_length(records:     &[Record],
+                         headers_end: Option<BlockHeaderPtr>,
+                         txs_end:     Option<TxPtr>) -> Option<usize>
+{
+    match records.first() {
+        Some(rec) if rec.is_block_start() => {},
+        _ => return None
+    };
+
+    for (n, rec) in records.iter().enumerate().skip(1) {
+
+        if rec.is_block_start() {
+            return None;
+        }
+        else if rec.is_block_end() {
+
+            let header_dropped = match headers_end {
+                Some(end) => !flatfileset::is_before(rec.get_block_header_ptr(), end),
+                None      => false
+            };
+
+            if header_dropped || rec.get_block_record_count() != n as u64 - 1 {
+                return None;
+            }
+            return Some(n + 1);
+        }
+        else if !rec.is_unmatched_input() {
+
+            if let Some(end) = txs_end {
+                if !flatfileset::is_before(rec.get_transaction_ptr(), end) {
+                    return None;
+                }
+            }
+        }
+    }
+
+    // the end-of-block record is not written
+    None
+}
+
+
 impl SpendTree {
     pub fn new(cfg: &config::Config) -> SpendTree {
 
         let dir = &cfg.root.clone().join(SUBPATH);
 
+        let file_size = cfg.spend_tree_file_size;
+        assert!(file_size <= record::RECORDS_FILE_SIZE);
+
         SpendTree {
             fileset: FlatFileSet::new(
-                dir, PREFIX, FILE_SIZE, MAX_CONTENT_SIZE)
+                dir, PREFIX, file_size, config::max_content_size(file_size)),
+            records_per_file: record::records_per_file(file_size)
         }
     }
 
-    // Returns the full spend-tree as record-slice.
-    pub fn get_all_records(&mut self) -> &[Record] {
+    /// Returns the full spend-tree; this maps all files of the spend-tree
+    pub fn get_all_records(&mut self) -> Records {
 
-        self.fileset.read_mut_slice(RecordPtr::new(0), VEC_SIZE)
+        // the spend-tree always starts at file 0
+        let files = (0..self.fileset.file_numbers().end)
+            .map(|fileno| {
+                let first = RecordPtr::new(fileno as u64 * record::RECORDS_PER_FILE);
+                let file: &'static [Record] = self.fileset.read_mut_slice(first, self.records_per_file as usize);
+                file
+            })
+            .collect();
+
+        Records { files: files }
     }
 
     // Returns the given block as a mutable slice
@@ -193,6 +297,261 @@ impl SpendTree {
         * self.fileset.read_fixed(ptr)
     }
 
+    /// Returns the block this block is connected to,
+    /// or None for genesis and for orphan blocks
+    pub fn get_previous_block(&mut self, block: BlockPtr) -> Option<BlockPtr> {
+
+        let start = self.get_record(block.start);
+
+        start.get_previous_block_end().map(|end_ptr| {
+
+            let count = self.get_record(end_ptr).get_block_record_count();
+
+            BlockPtr {
+                start:    RecordPtr::new(end_ptr.to_index() - count - 1),
+                length:   count + 2,
+                is_guard: false
+            }
+        })
+    }
+
+    /// Returns the written records of each file, with a pointer to the first record of the file
+    pub fn get_written_records(&mut self) -> Vec<(RecordPtr, &'static [Record])> {
+
+        self.fileset.file_numbers()
+            .map(|fileno| {
+                let first = RecordPtr::new(fileno as u64 * record::RECORDS_PER_FILE);
+                let end   = self.fileset.write_end(fileno).to_index() - first.to_index();
+                let records: &'static [Record] = self.fileset.read_mut_slice(first, end as usize);
+                (first, records)
+            })
+            .collect()
+    }
+
+    /// Returns true if the block is within the written records, starts with a start-of-block
+    /// record and ends with an end-of-block record with the record count of the block. If it is
+    /// connected, the start must point to an end-of-block record
+    ///
+    /// Unlike other methods, this can be used with a pointer that may be invalid
+    pub fn is_valid_block(&mut self, block: BlockPtr) -> bool {
+
+        if block.length < 2 || block.length > record::RECORDS_PER_FILE
+            || !self.is_written(block.start)
+            || block.end().get_file_number() != block.start.get_file_number()
+            || !self.is_written(block.end()) {
+
+            return false;
+        }
+
+        let start = self.get_record(block.start);
+        let end   = self.get_record(block.end());
+
+        if !start.is_block_start() || !end.is_block_end() || end.get_block_record_count() != block.length - 2 {
+            return false;
+        }
+
+        match start.get_previous_block_end() {
+            Some(previous_end) => self.is_written(previous_end) && self.get_record(previous_end).is_block_end(),
+            None               => true
+        }
+    }
+
+    // Returns true if the given record is written
+    fn is_written(&mut self, ptr: RecordPtr) -> bool {
+
+        let fileno = ptr.get_file_number();
+
+        self.fileset.file_numbers().any(|n| n == fileno)
+            && ptr.to_index() < self.fileset.write_end(fileno).to_index()
+    }
+
+    /// Returns the file and offset of the given record
+    pub fn location(&self, ptr: RecordPtr) -> Location {
+
+        self.fileset.location(ptr)
+    }
+
+    /// Truncates the blocks that are partially written; used for recovery
+    ///
+    /// A block is also dropped if it refers to a header or a transaction at or after the given
+    /// positions, at which these are truncated, or if it is connected to a dropped block.
+    ///
+    /// Returns the position at which the spend-tree is truncated, if any, and the blocks that are
+    /// kept in the last two files; earlier files are not verified
+    pub fn truncate_partial(&mut self,
+                            headers_end: Option<BlockHeaderPtr>,
+                            txs_end:     Option<TxPtr>) -> (Option<RecordPtr>, Vec<BlockPtr>)
+    {
+        let mut blocks: Vec<BlockPtr>  = Vec::new();
+        let mut dropped: Option<u64>   = None;
+
+        let files = self.fileset.file_numbers();
+
+        'files: for fileno in cmp::max(files.start, files.end - 2)..files.end {
+
+            let first   = RecordPtr::new(fileno as u64 * record::RECORDS_PER_FILE);
+            let end     = self.fileset.write_end(fileno).to_index() - first.to_index();
+            let records: &[Record] = self.fileset.read_mut_slice(first, end as usize);
+
+            let mut start = 0;
+            while start < records.len() {
+
+                match complete_block_length(&records[start..], headers_end, txs_end) {
+                    Some(length) => {
+                        blocks.push(BlockPtr {
+                            start:    RecordPtr::new(first.to_index() + start as u64),
+                            length:   length as u64,
+                            is_guard: false
+                        });
+                        start += length;
+                    },
+                    None => {
+                        dropped = Some(first.to_index() + start as u64);
+                        break 'files;
+                    }
+                }
+            }
+        }
+
+        // a block can be connected to a later block; if that is dropped, so is this one
+        while let Some(end) = dropped {
+
+            let connected_to_dropped = blocks.iter().position(|block| {
+                match self.get_record(block.start).get_previous_block_end() {
+                    Some(previous_end) => previous_end.to_index() >= end,
+                    None               => false
+                }
+            });
+
+            match connected_to_dropped {
+                Some(n) => {
+                    dropped = Some(blocks[n].start.to_index());
+                    blocks.truncate(n);
+                },
+                None => break
+            }
+        }
+
+        let dropped = dropped.map(RecordPtr::new);
+        if let Some(ptr) = dropped {
+            self.fileset.truncate(ptr);
+        }
+
+        (dropped, blocks)
+    }
+
+    /// Finds the path between the current tip of the spend-index and the given target block
+    ///
+    /// Returns the blocks that need to be rolled back and the blocks that need to be rolled
+    /// forward; both ordered from tip to fork
+    fn find_spend_index_path(&mut self, spend_index: &SpendIndex, target: BlockPtr)
+        -> (Vec<BlockPtr>, Vec<BlockPtr>)
+    {
+        let mut back:    Vec<BlockPtr> = Vec::new();
+        let mut forward: Vec<BlockPtr> = Vec::new();
+
+        // the positions in back and forward by start index
+        let mut back_seen:    HashMap<u64, usize> = HashMap::new();
+        let mut forward_seen: HashMap<u64, usize> = HashMap::new();
+
+        let mut old = spend_index.get_tip();
+        let mut new = Some(target);
+
+        // walk down both chains simultaneously until they meet
+        while old.is_some() || new.is_some() {
+
+            if let Some(block) = old {
+                if let Some(&n) = forward_seen.get(&block.start.to_index()) {
+                    forward.truncate(n);
+                    break;
+                }
+                back_seen.insert(block.start.to_index(), back.len());
+                back.push(block);
+                old = self.get_previous_block(block);
+            }
+
+            if let Some(block) = new {
+                if let Some(&n) = back_seen.get(&block.start.to_index()) {
+                    back.truncate(n);
+                    break;
+                }
+                forward_seen.insert(block.start.to_index(), forward.len());
+                forward.push(block);
+                new = self.get_previous_block(block);
+            }
+        }
+
+        (back, forward)
+    }
+
+    /// Moves the tip of the spend-index to the given block
+    ///
+    /// The blocks from the current tip down to the fork are rolled back, and the blocks from the
+    /// fork up to the new tip are rolled forward. Normally this is just the single new block
+    pub fn move_spend_index(&mut self,
+                            spend_index: &mut SpendIndex,
+                            logger:      &slog::Logger,
+                            target:      BlockPtr) {
+
+        let (back, forward) = self.find_spend_index_path(spend_index, target);
+
+        if back.len() > 0 {
+            info!(logger, "spend-index reorg";
+                "rollback" => back.len(),
+                "rollforward" => forward.len());
+        }
+
+        // the index is invalid until all blocks are moved
+        if back.len() > 0 || forward.len() > 0 {
+            spend_index.remove_tip();
+        }
+
+        // Rollback must precede rollforward as the same transaction can be in both branches
+        for block in back.into_iter() {
+            for rec in self.get_block_records(block) {
+                spend_index.unset(rec.hash());
+            }
+        }
+
+        for block in forward.into_iter().rev() {
+            for rec in self.get_block_records(block) {
+                spend_index.set(rec.hash());
+            }
+        }
+
+        spend_index.set_tip(target);
+    }
+
+    /// Clears the spend-index and fills it with the blocks up to the given block; used to reindex
+    ///
+    /// Unlike `move_spend_index`, the records of the blocks are set in parallel
+    pub fn rebuild_spend_index(&mut self, spend_index: &mut SpendIndex, target: BlockPtr) {
+
+        spend_index.clear();
+
+        let mut blocks: Vec<&'static [Record]> = Vec::new();
+        let mut block = Some(target);
+        while let Some(current) = block {
+
+            let records: &'static [Record] = self.fileset.read_mut_slice(current.start, current.length as usize);
+            blocks.push(&records[1..records.len() - 1]);
+
+            block = self.get_previous_block(current);
+        }
+
+        spend_index.set_all(&blocks);
+        spend_index.set_tip(target);
+    }
+
+    /// Returns the transaction and output records of the given block
+    pub fn get_block_records(&mut self, block: BlockPtr) -> &[Record] {
+
+        let block = self.get_block_mut(block);
+        let len   = block.len();
+
+        &block[1..len-1]
+    }
+
     /// Stores a block in the spend_tree. The block will be initially orphan.
     ///
     /// The result is a BlockPtr that can be stored in the hash-index
@@ -219,18 +578,36 @@ impl SpendTree {
     /// If an orphan block is stored in the spend-tree, some transaction-inputs might not be resolved
     /// to their outputs. These will still be unmatched_output records instead of output-pointers
     ///
-    /// This looks up the corresponding outputs; needs to be called before connect_block
+    /// Outputs of a txid that is stored more than once are resolved to the one in the chain when
+    /// the block is stored; these are resolved again if that one is not in the chain of the
+    /// previous block, nor in the block itself
+    ///
+    /// This looks up the corresponding outputs; needs to be called before connect_block, with the
+    /// spend-index valid for the previous block
+    ///
+    /// Fails with OutputNotFound if an output is still not found
     pub fn revolve_orphan_pointers(&mut self,
                                    transactions:  &mut store::Transactions,
                                    tx_index:      &mut HashIndex<TxPtr>,
-                                   block:  BlockPtr) {
+                                   spend_index:   &SpendIndex,
+                                   block:  BlockPtr) -> Result<(), SpendingError> {
 
         let mut input_idx = 0;
         let mut last_tx_ptr: Option<TxPtr> = None;
 
+        let block_txs: HashSet<u64> = self.get_block_mut(block).iter()
+            .filter(|record| record.is_transaction() && !record.is_unmatched_input())
+            .map(|record| record.hash())
+            .collect();
+
         for record in self.get_block_mut(block) {
 
-            if record.is_unmatched_input() {
+            let not_in_chain = record.is_output() && {
+                let tx_hash = Record::new_transaction(record.get_transaction_ptr()).hash();
+                !block_txs.contains(&tx_hash) && !spend_index.exists(tx_hash)
+            };
+
+            if record.is_unmatched_input() || not_in_chain {
 
                 let bytes   = transactions.read(last_tx_ptr.unwrap());
                 let mut buf = Buffer::new(&bytes);
@@ -239,15 +616,8 @@ impl SpendTree {
                 let input = &tx.txs_in[input_idx];
 
                 // find the matching output
-                let tx_ptr: TxPtr = *tx_index
-                    .get(input.prev_tx_out)
-                    .iter()
-                    .find(|ptr| !ptr.is_guard())
-                    .expect("Could not find input; this must have been resolved before connecting the block!");
-
-                let output_record = Record::new_output(tx_ptr, input.prev_tx_out_idx);
-
-                *record = output_record;
+                *record = find_output(tx_index, spend_index, input.prev_tx_out, input.prev_tx_out_idx)
+                    .ok_or(SpendingError::OutputNotFound)?;
 
                 input_idx   += 1;
 
@@ -263,11 +633,14 @@ impl SpendTree {
 
         }
 
+        Ok(())
     }
 
 
     /// Verifies of each output in the block at target_start
     /// Then lays the connection between previous_end and target_start
+    ///
+    /// If verification fails, the block is left as orphan
     pub fn connect_block(&mut self,
                          spend_index:    &mut SpendIndex,
                          logger:         &slog::Logger,
@@ -276,28 +649,28 @@ impl SpendTree {
 
         let timer = ::std::time::Instant::now();
 
+        // Make the spend-index valid for the chain up to the previous block.
+        // This rolls back blocks of another branch if needed
+        self.move_spend_index(spend_index, logger, previous_block);
+
         let block_idx              = target_block.start.to_index();
         let block:   &mut [Record] = self.fileset.read_mut_slice(target_block.start, target_block.length as usize);
-        let records: &[Record]     = self.get_all_records();
+        let records: Records       = self.get_all_records();
 
 
         // Make the link,
         block[0] = Record::new_block_start(previous_block);
 
-
-        // Update the spend-index
-        // TODO this should jump more blocks back; and register its parent-requirement.
-        // This is important once we allow forks
-        let s = previous_block.start.to_index() as usize;
-        let l = previous_block.length as usize;
-        let immutable_block: &[Record] = &records[s+1..s+l-1];
-        for rec in immutable_block.iter() {
-
-            spend_index.set(rec.hash());
-        }
-
         // verify all inputs in the spend tree and spend-index
-        let input_count = seek_and_set_inputs(records, block, block_idx as usize, spend_index, logger)?;
+        let input_count = match seek_and_set_inputs(&records, block, block_idx as usize, spend_index, logger) {
+            Ok(input_count) => input_count,
+            Err(err) => {
+
+                // undo the link
+                block[0] = Record::new_orphan_block_start();
+                return Err(err);
+            }
+        };
 
         let elapsed : isize = timer.elapsed().as_secs() as isize * 1000 +
             timer.elapsed().subsec_nanos() as isize / 1_000_000 as isize;
@@ -469,6 +842,67 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_spend_index_reorg() {
+        let log = slog::Logger::root(slog_term::streamer().compact().build().fuse(), o!());
+
+        let mut st  = SpendTree::new(& test_cfg!());
+        let mut si  = SpendIndex::new(& test_cfg!());
+
+        // tx-ptrs are spaced such that the record hashes are unique
+
+        let block1  = st.store(block!(blk 1 => [tx 0x100]));
+
+        // branch a spends output 0 in its first block
+        let block2a = st.store(block!(blk 2 => [tx 0x200 => (0x100;0)]));
+        let block3a = st.store(block!(blk 3 => [tx 0x300]));
+        let block4a = st.store(block!(blk 4 => [tx 0x400]));
+        let block5a = st.store(block!(blk 5 => [tx 0x500]));
+
+        st.connect_block(&mut si, &log, block1,  block2a).unwrap();
+        st.connect_block(&mut si, &log, block2a, block3a).unwrap();
+        st.connect_block(&mut si, &log, block3a, block4a).unwrap();
+        st.connect_block(&mut si, &log, block4a, block5a).unwrap();
+
+        assert!(si.exists(Record::new_output(TxPtr::new(0,0x100), 0).hash()));
+        assert!(si.exists(Record::new_transaction(TxPtr::new(0,0x300)).hash()));
+
+        // branch b spends the same output deep enough to need the spend-index
+        let block2b = st.store(block!(blk 6 => [tx 0x600]));
+        let block3b = st.store(block!(blk 7 => [tx 0x700]));
+        let block4b = st.store(block!(blk 8 => [tx 0x800]));
+        let block5b = st.store(block!(blk 9 => [tx 0x900 => (0x100;0)]));
+
+        st.connect_block(&mut si, &log, block1,  block2b).unwrap();
+        st.connect_block(&mut si, &log, block2b, block3b).unwrap();
+        st.connect_block(&mut si, &log, block3b, block4b).unwrap();
+        st.connect_block(&mut si, &log, block4b, block5b).unwrap();
+
+        // the index only contains branch b
+        assert_eq!(si.get_tip(), Some(block4b));
+        assert!(!si.exists(Record::new_output(TxPtr::new(0,0x100), 0).hash()));
+        assert!(!si.exists(Record::new_transaction(TxPtr::new(0,0x300)).hash()));
+        assert!( si.exists(Record::new_transaction(TxPtr::new(0,0x600)).hash()));
+
+        // back to branch a; outputs of branch b are not available
+        let block6a = st.store(block!(blk 10 => [tx 0xA00 => (0x600;0)]));
+        assert_eq!(
+            st.connect_block(&mut si, &log, block5a, block6a).unwrap_err(),
+            SpendingError::OutputNotFound);
+
+        // and outputs spent on branch a are still spent
+        let block6a = st.store(block!(blk 10 => [tx 0xA00 => (0x100;0)]));
+        assert_eq!(
+            st.connect_block(&mut si, &log, block5a, block6a).unwrap_err(),
+            SpendingError::OutputAlreadySpend);
+
+        let block6a = st.store(block!(blk 10 => [tx 0xA00 => (0x100;1)]));
+        st.connect_block(&mut si, &log, block5a, block6a).unwrap();
+
+        assert_eq!(si.get_tip(), Some(block5a));
+        assert!( si.exists(Record::new_output(TxPtr::new(0,0x100), 0).hash()));
+        assert!(!si.exists(Record::new_transaction(TxPtr::new(0,0x600)).hash()));
+    }
 
     #[test]
     fn test_orphan_block() {
@@ -8,7 +8,7 @@ use store::FlatFilePtr;
 
 
 use store::spend_tree::SpendingError;
-use store::spend_tree::BlockPtr;
+use store::spend_tree::{BlockPtr, Records};
 use store::spend_index::SpendIndex;
 
 use store::flatfile::INITIAL_WRITEPOS;
@@ -49,6 +49,23 @@ const OUTPUT:u64         = 0x4000_0000_0000_0000;
 const ORPHAN_START_OF_BLOCK:u64 = START_OF_BLOCK | 0;
 
 
+/// The range of record indexes of each file of the spend-tree
+///
+/// A RecordPtr is a record index that spans the files; the index of the first record of file `n`
+/// is `n * RECORDS_PER_FILE`. A file holds at most this many records, depending on its configured
+/// size. This is also used for the words of the spend-index
+pub const RECORDS_PER_FILE: u64 = 1 << 27;
+
+/// Size of a file with RECORDS_PER_FILE records, including the header; files of the spend-tree
+/// and the spend-index can be configured smaller, but not larger
+pub const RECORDS_FILE_SIZE: u64 = INITIAL_WRITEPOS + RECORDS_PER_FILE * 8;
+
+/// Returns the number of records or words that fit in a file of the given size
+pub fn records_per_file(file_size: u64) -> u64 {
+    (file_size - INITIAL_WRITEPOS) / 8
+}
+
+
 #[derive(Clone,Copy,PartialEq)]
 pub struct Record(u64);
 
@@ -119,6 +136,29 @@ impl Record {
         unimplemented!()
     }
 
+    /// If called on a start-of-block record, returns the pointer to the end-of-block record
+    /// of the previous block, or None if the block is not connected
+    pub fn get_previous_block_end(self) -> Option<RecordPtr> {
+
+        debug_assert!(self.is_block_start());
+
+        if self.0 == ORPHAN_START_OF_BLOCK {
+            None
+        }
+        else {
+            Some(RecordPtr::new(self.0 & !START_OF_BLOCK))
+        }
+    }
+
+    /// If called on an end-of-block record, returns the number of records in the block,
+    /// excluding the start-of-block and end-of-block records
+    pub fn get_block_record_count(self) -> u64 {
+
+        debug_assert!(self.is_block_end());
+
+        (self.0 & 0x3FFF_FFFF_0000_0000) >> 32
+    }
+
     pub fn is_transaction(self) -> bool {
 
         (self.0 & RECORD_TYPE) == TRANSACTION
@@ -134,6 +174,13 @@ impl Record {
         )
     }
 
+    pub fn get_output_index(self) -> u32 {
+
+        debug_assert!(self.is_output());
+
+        ((self.0 & 0x3FFF_0000_0000_0000) >> 48) as u32
+    }
+
     pub fn get_block_header_ptr(self) -> BlockHeaderPtr {
 
         debug_assert!((self.0 & RECORD_TYPE) == END_OF_BLOCK);
@@ -200,16 +247,19 @@ pub struct RecordPtr(u64);
 
 impl FlatFilePtr for RecordPtr {
     fn new(file_number: i16, file_offset: u64) -> RecordPtr {
-        assert_eq!(file_number, 0); // can only have one spend-tree file
+        debug_assert!(file_number >= 0);
 
-        RecordPtr((file_offset as u64 - INITIAL_WRITEPOS)
-                      / mem::size_of::<Record>() as u64)
+        RecordPtr(file_number as u64 * RECORDS_PER_FILE
+                  + (file_offset as u64 - INITIAL_WRITEPOS) / mem::size_of::<Record>() as u64)
     }
 
 
-    fn get_file_number(self) -> i16 { 0 }
+    fn get_file_number(self) -> i16 {
+        (self.0 / RECORDS_PER_FILE) as i16
+    }
+
     fn get_file_offset(self) -> u64 {
-        INITIAL_WRITEPOS + self.0 * mem::size_of::<Record>() as u64
+        INITIAL_WRITEPOS + (self.0 % RECORDS_PER_FILE) * mem::size_of::<Record>() as u64
     }
 }
 
@@ -264,7 +314,7 @@ impl Record {
         &mut self,
         spend_index: &SpendIndex,
         seek_idx: usize,
-        records: &[Record],
+        records: &Records,
         logger: &slog::Logger) -> Result<usize, SpendingError>
 
     {
@@ -288,11 +338,9 @@ impl Record {
             trace!(logger, format!("FL# Search  {:?} @ {:?}", self, seek_idx));
 
 
-            // TODO: we need to be aware here of the chances of forking.
-            // On initial load, the spend-index is always up-to-date after one block
-            // so we should use 1 here which will make it faster;
-            // After the initial load we should probably use 4; but verify the parent-block hash
-            // to ensures fall back on large reorgs
+            // The spend-index is moved to the previous block before the block is verified
+            // (see SpendTree::move_spend_index) so it is valid on this branch.
+            // TODO: this means we could use 1 here which will make it faster
             if blocks >= 3 {
 
                 return self.verify_spend_in_index(spend_index)
@@ -341,4 +389,17 @@ mod tests {
         assert_eq!(::std::mem::size_of::<Record>(), 8);
 
     }
+
+    #[test]
+    fn test_record_ptr() {
+        let ptr = RecordPtr::new(RECORDS_PER_FILE * 2 + 5);
+        assert_eq!(ptr.get_file_number(), 2);
+        assert_eq!(ptr.get_file_offset(), INITIAL_WRITEPOS + 5 * 8);
+        assert_eq!(<RecordPtr as FlatFilePtr>::new(2, INITIAL_WRITEPOS + 5 * 8), ptr);
+
+        // the last record of a file is followed by the first record of the next
+        let last = <RecordPtr as FlatFilePtr>::new(0, RECORDS_FILE_SIZE - 8);
+        assert_eq!(last.to_index() + 1, RecordPtr::new(RECORDS_PER_FILE).to_index());
+        assert_eq!(RecordPtr::new(RECORDS_PER_FILE).get_file_offset(), INITIAL_WRITEPOS);
+    }
 }
@@ -13,22 +13,26 @@
 
 //! A tip is referenced by the block hash and it contains
 //! * The block hash
-//! * The difficulty target
-//! * Softfork info
+//! * The accumulated work
+//! * The height
+//! * Softfork info (TBD)
+//!
+//! The tip with the most work is the tip of the main chain
 //!
 //! This is a draft implementation; the format is TBD
 
 
 
-use std::path::{Path,PathBuf};
+use std::path::PathBuf;
 use std::fs;
-use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 
 use util::*;
 use hash::*;
 use config;
+use pow::U256;
+use store::flatfileset::Location;
 
 
 pub struct Tips {
@@ -36,30 +40,42 @@ pub struct Tips {
     path: PathBuf
 }
 
+/// Adds a tip for a block that is connected to its previous block
+///
+/// If the previous block is a tip, it is replaced by the new tip
 pub fn add_tip(
     tips: &Tips,
     block_hash: Hash32Buf,
     previous_hash: Option<Hash32Buf>,
-    difficulty: u64,
+    work: U256,
     height: u64)
 {
 
-
     let tip = Tip {
         block_hash: block_hash,
-        difficulty: difficulty,
-        height: height
+        work:       work,
+        height:     height
     };
-    let path = tips.path.join(format!("{}", tip.filename()));
 
-    println!("ADD TIP: {:?}", path);
+    // write to a temporary file first, such that a tip is never partially written
+    let path     = tips.path.join(tip.filename());
+    let tmp_path = tips.path.join(format!("{}.tmp", tip.filename()));
 
-    let path = tips.path.join(format!("{}", tip.filename()));
+    {
+        let mut file = fs::File::create(&tmp_path)
+            .expect("Cannot create files in store");
 
-    let mut file = fs::File::create(path)
+        tip.write(&mut file);
+    }
+
+    fs::rename(&tmp_path, &path)
         .expect("Cannot create files in store");
 
-    tip.write(&mut file);
+    // the new tip is written before the previous tip is removed;
+    // a crash in between leaves an extra tip which is harmless
+    if let Some(previous_hash) = previous_hash {
+        let _ = fs::remove_file(tips.path.join(filename(previous_hash)));
+    }
 }
 
 
@@ -79,31 +95,86 @@ impl Tips {
     }
 
 
-    pub fn get_tips() -> Vec<Tip> {
-        vec![]
+    /// Returns all tips in no particular order
+    pub fn get_tips(&self) -> Vec<Tip> {
+
+        fs::read_dir(&self.path)
+            .expect("Cannot read tips from store")
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter_map(|name| self.read_tip(&name))
+            .collect()
     }
 
-    pub fn get_most_work_tip() -> Tip {
-        unimplemented!()
+    /// Returns the tip of the given block, or None if the block is not a tip
+    pub fn get_tip(&self, block_hash: Hash32Buf) -> Option<Tip> {
+
+        self.read_tip(&filename(block_hash))
     }
 
-    pub fn remove_tip(tip: Tip) {
-        unimplemented!()
+    /// Returns the tip with the most accumulated work. This is the tip of the main chain
+    ///
+    /// Equal work is resolved by height and then by hash to ensure all instances agree
+    pub fn get_most_work_tip(&self) -> Option<Tip> {
+
+        self.get_tips()
+            .into_iter()
+            .max_by(|a, b| {
+                a.work.cmp(&b.work)
+                    .then(a.height.cmp(&b.height))
+                    .then(a.filename().cmp(&b.filename()))
+            })
     }
 
-    pub fn get_all() {
-        
+    pub fn remove_tip(&self, tip: &Tip) {
+
+        let _ = fs::remove_file(self.path.join(tip.filename()));
+    }
+
+    /// Returns the file of the given tip
+    pub fn location(&self, tip: &Tip) -> Location {
+
+        Location {
+            file:   self.path.join(tip.filename()),
+            offset: 0
+        }
+    }
+
+    // Reads the tip with the given filename; None if there is no such tip
+    fn read_tip(&self, name: &str) -> Option<Tip> {
+
+        if name.len() != 64 || !name.bytes().all(|c| (c as char).is_digit(16)) {
+            return None;
+        }
+
+        let mut content = String::new();
+        match fs::File::open(self.path.join(name)) {
+            Ok(mut file) => { file.read_to_string(&mut content).expect("Cannot read tip"); },
+            Err(_)       => return None
+        };
+
+        let mut parts = content.split(',');
+        match (parts.next(), parts.next().map(|h| h.trim().parse::<u64>())) {
+            (Some(work), Some(Ok(height))) if work.len() == 64 => Some(Tip {
+                block_hash: Hash32Buf::from_slice(&from_hex_rev(name)),
+                work:       U256::from(&from_hex(work)[..]),
+                height:     height
+            }),
+            _ => None
+        }
     }
 }
 
+/// A tip is a block without connected successors
+#[derive(Debug, Clone, PartialEq)]
 pub struct Tip {
 
-    block_hash: Hash32Buf,
+    pub block_hash: Hash32Buf,
 
-    difficulty: u64,
-    height: u64
+    /// The accumulated work of the chain up to and including this block
+    pub work: U256,
+    pub height: u64
 
-    
     // softfork rules
 
 }
@@ -111,49 +182,88 @@ pub struct Tip {
 impl Tip {
 
 
-    pub fn new(block_hash: Hash32Buf, difficulty: u64, height: u64) -> Tip {
+    pub fn new(block_hash: Hash32Buf, work: U256, height: u64) -> Tip {
 
         Tip {
             block_hash: block_hash,
-            difficulty: difficulty,
+            work: work,
             height: height
         }
     }
 
     fn write<W: io::Write>(&self, writer: &mut W) {
 
-        write!(writer,"{},{}", self.difficulty, self.height).unwrap();
+        write!(writer,"{},{}", self.work, self.height).unwrap();
     }
 
     fn filename(&self) -> String {
 
-        self.block_hash
-            .as_ref().0
-            .iter()
-            .rev()
-            .map(|n| format!("{:02x}", n))
-            .collect::<Vec<_>>()
-            .concat()
-
+        filename(self.block_hash)
     }
 }
 
+// The filename of a tip is the block hash as usually printed
+fn filename(block_hash: Hash32Buf) -> String {
+
+    block_hash
+        .as_ref().0
+        .iter()
+        .rev()
+        .map(|n| format!("{:02x}", n))
+        .collect::<Vec<_>>()
+        .concat()
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use util::*;
+
+    const HASH1: &'static str = "212300e77d897f2f059366ed03c8bf2757bc2b1dd30df15d34f6f1ee521e58e8";
+    const HASH2: &'static str = "4feec9316077e49b59bc23173303e13be9e9f5f9fa0660a58112a04a65a84ef1";
+    const HASH3: &'static str = "00000000839a8e6886ab5951d76f411475428afc90947ee320161bbf18eb6048";
 
     #[test]
     fn test_create_tip() {
 
-        const HASH1: &'static str = "212300e77d897f2f059366ed03c8bf2757bc2b1dd30df15d34f6f1ee521e58e8";
-        const HASH2: &'static str = "4feec9316077e49b59bc23173303e13be9e9f5f9fa0660a58112a04a65a84ef1";
+        let tips = Tips::new(&test_cfg!());
+
+        let hash1 = Hash32Buf::from_slice(&from_hex_rev(HASH1));
+        let hash2 = Hash32Buf::from_slice(&from_hex_rev(HASH2));
+
+        add_tip(&tips, hash1, None, U256::from(1u64), 2);
+        add_tip(&tips, hash2, None, U256::from(3u64), 4);
+
+        assert_eq!(tips.get_tips().len(), 2);
+        assert_eq!(tips.get_tip(hash1), Some(Tip::new(hash1, U256::from(1u64), 2)));
+        assert_eq!(tips.get_most_work_tip(), Some(Tip::new(hash2, U256::from(3u64), 4)));
+
+    }
+
+    #[test]
+    fn test_replace_tip() {
 
         let tips = Tips::new(&test_cfg!());
 
-        add_tip(&tips, Hash32Buf::from_slice(&from_hex_rev(HASH1)), None, 1, 2);
-        add_tip(&tips, Hash32Buf::from_slice(&from_hex_rev(HASH2)), None, 3, 4);
+        assert_eq!(tips.get_most_work_tip(), None);
+
+        let hash1 = Hash32Buf::from_slice(&from_hex_rev(HASH1));
+        let hash2 = Hash32Buf::from_slice(&from_hex_rev(HASH2));
+        let hash3 = Hash32Buf::from_slice(&from_hex_rev(HASH3));
 
+        add_tip(&tips, hash1, None, U256::from(10u64), 1);
+        add_tip(&tips, hash2, Some(hash1), U256::from(20u64), 2);
+
+        assert_eq!(tips.get_tip(hash1), None);
+        assert_eq!(tips.get_tips(), vec![Tip::new(hash2, U256::from(20u64), 2)]);
+
+        // a fork adds a tip
+        add_tip(&tips, hash3, Some(hash1), U256::from(30u64), 2);
+        assert_eq!(tips.get_tips().len(), 2);
+        assert_eq!(tips.get_most_work_tip().unwrap().block_hash, hash3);
+
+        let tip3 = tips.get_tip(hash3).unwrap();
+        tips.remove_tip(&tip3);
+        assert_eq!(tips.get_most_work_tip().unwrap().block_hash, hash2);
     }
-}
\ No newline at end of file
+}
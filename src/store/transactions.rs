@@ -5,20 +5,38 @@
 //!
 //! This is a bit messy for now as we're not using typed access to these store
 //! as they are still WIP
+//!
+//! A transaction can be marked as unverified: not all of its scripts are known to be valid. Such
+//! a transaction is verified again when it is used in a block (see transaction::verify_and_store
+//! and block_add::verify_block_inputs). A verified transaction records the script flags it is
+//! verified with, as a block at another height may require other flags
 
 
 use buffer::*;
 use config;
-use store::flatfileset::FlatFileSet;
+use store::flatfileset::{self, FlatFileSet, Location};
 use store::TxPtr;
 
 use transaction::Transaction;
 use store::flatfileset::FlatFilePtr;
 
-const MB:                 u64 = 1024 * 1024;
-const FILE_SIZE:          u64 = 2 * 1024 * MB ;
-const MAX_CONTENT_SIZE:   u64 = FILE_SIZE - 10 * MB ;
 
+// The first field of the header of the second part holds the file number of the first part,
+// the script flags the transaction is verified with, and a marker if it is not verified
+const FILE_NUMBER:  u32 = 0x0000_ffff;
+const SCRIPT_FLAGS: u32 = 0x7fff_0000;
+const UNVERIFIED:   u32 = 0x8000_0000;
+
+// Flags with bits that don't fit are not recorded; these never equal the flags of a block, so
+// such a transaction is always verified again
+fn flags_marker(script_flags: u32) -> u32 {
+    if script_flags & !(SCRIPT_FLAGS >> 16) == 0 {
+        script_flags << 16
+    }
+    else {
+        UNVERIFIED
+    }
+}
 
 /// Transaction store
 pub struct Transactions {
@@ -29,6 +47,8 @@ pub struct Transactions {
     // part2 stores the outputs
     transactions2: FlatFileSet<TxPtr>,
 
+    // needed to find the transaction following another
+    max_content_size: u64
 }
 
 impl Clone for Transactions {
@@ -39,7 +59,8 @@ impl Clone for Transactions {
         Transactions {
 
             transactions1: self.transactions1.clone(),
-            transactions2: self.transactions2.clone()
+            transactions2: self.transactions2.clone(),
+            max_content_size: self.max_content_size
         }
 
     }
@@ -55,14 +76,27 @@ impl Transactions
         let dir1 = &cfg.root.clone().join("transactions1");
         let dir2 = &cfg.root.clone().join("transactions2");
 
+        let file_size        = cfg.transactions_file_size;
+        let max_content_size = config::max_content_size(file_size);
+
         Transactions {
-            transactions1: FlatFileSet::new(dir1, "t1-", FILE_SIZE, MAX_CONTENT_SIZE),
-            transactions2: FlatFileSet::new(dir2, "t2-", FILE_SIZE, MAX_CONTENT_SIZE)
+            transactions1: FlatFileSet::new(dir1, "t1-", file_size, max_content_size),
+            transactions2: FlatFileSet::new(dir2, "t2-", file_size, max_content_size),
+            max_content_size: max_content_size
         }
     }
 
-    /// Writes the transaction to the store
-    pub fn write(&mut self, tx: &Transaction) -> TxPtr {
+    /// Writes the transaction to the store, as verified with the given script flags
+    pub fn write(&mut self, tx: &Transaction, script_flags: u32) -> TxPtr {
+        self.write_marked(tx, flags_marker(script_flags))
+    }
+
+    /// Writes the transaction to the store, marked as unverified
+    pub fn write_unverified(&mut self, tx: &Transaction) -> TxPtr {
+        self.write_marked(tx, UNVERIFIED)
+    }
+
+    fn write_marked(&mut self, tx: &Transaction, marker: u32) -> TxPtr {
 
         // We're doing "manual" serialization for now;
         // to test performance
@@ -78,7 +112,7 @@ impl Transactions
         let part1_ptr = self.transactions1.write(raw_part1);
 
         let header = vec![
-        part1_ptr.get_file_number() as u32,
+        (part1_ptr.get_file_number() as u32 & FILE_NUMBER) | marker,
         part1_ptr.get_file_offset() as u32,
         tx.txs_out_idx.len() as u32];
 
@@ -119,7 +153,7 @@ impl Transactions
         }
 
         let part1_ptr = TxPtr::new(
-            bytes_to_u32(&part2[0..4]) as i16,
+            (bytes_to_u32(&part2[0..4]) & FILE_NUMBER) as i16,
             bytes_to_u32(&part2[4..8]) as u64
         );
 
@@ -135,10 +169,93 @@ impl Transactions
         let mut tx: Vec<u8> = part1.into_iter().map(|&x| x).collect();
         tx.extend_from_slice(part2);
 
-        Some((tx, ptr.offset(len + 4)))
+        Some((tx, ptr.offset(len + 4, self.max_content_size)))
     }
 
 
+    /// Reads the full transaction from the given pointer, or returns None if the pointer doesn't
+    /// point to a complete transaction; used to verify the store
+    pub fn try_read(&mut self, ptr: TxPtr) -> Option<Vec<u8>> {
+
+        let part2 = match self.transactions2.try_read(ptr) {
+            Some(part2) if part2.len() >= 12 => part2,
+            _ => return None
+        };
+
+        let part1_ptr = TxPtr::new(
+            (bytes_to_u32(&part2[0..4]) & FILE_NUMBER) as i16,
+            bytes_to_u32(&part2[4..8]) as u64
+        );
+
+        let output_count = bytes_to_u32(&part2[8..12]) as usize;
+        let header_size = 4 + 4 + 4 + output_count * 4;
+        if header_size > part2.len() {
+            return None;
+        }
+
+        let part1 = match self.transactions1.try_read(part1_ptr) {
+            Some(part1) => part1,
+            None        => return None
+        };
+
+        let mut tx = part1.to_vec();
+        tx.extend_from_slice(&part2[header_size..]);
+        Some(tx)
+    }
+
+    /// Returns false if the transaction is marked as unverified
+    pub fn is_verified(&mut self, ptr: TxPtr) -> bool {
+
+        self.get_script_flags(ptr).is_some()
+    }
+
+    /// Returns the script flags the transaction is verified with, or None if it is marked as
+    /// unverified
+    pub fn get_script_flags(&mut self, ptr: TxPtr) -> Option<u32> {
+
+        let part2 = self.transactions2.read(ptr);
+        let field = bytes_to_u32(&part2[0..4]);
+        if field & UNVERIFIED == 0 {
+            Some((field & SCRIPT_FLAGS) >> 16)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Marks the transaction as verified with the given script flags
+    pub fn set_verified(&mut self, ptr: TxPtr, script_flags: u32) {
+        self.set_marker(ptr, flags_marker(script_flags))
+    }
+
+    /// Marks the transaction as unverified
+    pub fn set_unverified(&mut self, ptr: TxPtr) {
+        self.set_marker(ptr, UNVERIFIED)
+    }
+
+    fn set_marker(&mut self, ptr: TxPtr, marker: u32) {
+
+        // the header follows the length of the second part; the file number is left as is
+        let header_ptr = TxPtr::new(ptr.get_file_number(), ptr.get_file_offset() + 4);
+        let header: &mut [u8] = self.transactions2.read_mut_slice(header_ptr, 4);
+
+        let field = (bytes_to_u32(header) & FILE_NUMBER) | marker;
+        header.copy_from_slice(&u32_to_bytes(field));
+    }
+
+    /// Returns the file and offset of the transaction with the given pointer
+    pub fn location(&self, ptr: TxPtr) -> Location {
+
+        self.transactions2.location(ptr)
+    }
+
+    /// Returns the number of outputs of the given transaction
+    pub fn output_count(&mut self, ptr: TxPtr) -> u32 {
+
+        let part2 = self.transactions2.read(ptr);
+        bytes_to_u32(&part2[8..12])
+    }
+
     /// Returns only an output from the given transaction
     /// The resulting Vec overflows until the end of the transaction
     pub fn read_output(&mut self, ptr: TxPtr, output_index: u32) -> Option<Vec<u8>> {
@@ -147,7 +264,7 @@ impl Transactions
         let part2 = self.transactions2.read(ptr);
         let output_count = bytes_to_u32(&part2[8..12]);
 
-        if output_count < output_index {
+        if output_index >= output_count {
             return None;
         }
 
@@ -159,6 +276,32 @@ impl Transactions
         
         Some(part2[output_offset_from_part2..].into_iter().map(|&x| x).collect())
     }
+
+    /// Truncates the transactions that are partially written; used for recovery
+    ///
+    /// A transaction is also dropped if its first part is dropped. Returns the position at which
+    /// the transactions are truncated, or None if all are complete
+    pub fn truncate_partial(&mut self) -> Option<TxPtr> {
+
+        let part1_end = self.transactions1.truncate_partial(0, |_, _| true);
+
+        self.transactions2.truncate_partial(0, |part2, _| {
+
+            if part2.len() < 12 {
+                return false;
+            }
+
+            let part1_ptr = TxPtr::new(
+                (bytes_to_u32(&part2[0..4]) & FILE_NUMBER) as i16,
+                bytes_to_u32(&part2[4..8]) as u64
+            );
+
+            match part1_end {
+                Some(end) => flatfileset::is_before(part1_ptr, end),
+                None      => true
+            }
+        })
+    }
 }
 
 // helper
@@ -199,15 +342,15 @@ mod tests {
 
         let mut store = ::store::Store::new(& test_cfg!());
 
-        let ptr  = store.transactions.write(&tx1p);
+        let ptr  = store.transactions.write(&tx1p, 0);
         let read = store.transactions.read(ptr);
         assert_eq!(tx1, read.as_slice());
 
-        let ptr = store.transactions.write(&tx2p);
+        let ptr = store.transactions.write(&tx2p, 0);
         let read = store.transactions.read(ptr);
         assert_eq!(tx2, read.as_slice());
 
-        let ptr = store.transactions.write(&tx3p);
+        let ptr = store.transactions.write(&tx3p, 0);
         let read = store.transactions.read(ptr);
         assert_eq!(tx3, read.as_slice());
 
@@ -228,7 +371,7 @@ mod tests {
 
         let mut store = ::store::Store::new(& test_cfg!());
 
-        let ptr  = store.transactions.write(&tx3p);
+        let ptr  = store.transactions.write(&tx3p, 0);
 
         // read only one output
         let read_out_bytes = store.transactions.read_output(ptr, 3).unwrap();
@@ -238,4 +381,38 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_verified() {
+        tx_builder!(bld);
+
+        let tx1 = tx!(bld; coinbase => a;12);
+        let tx2 = tx!(bld; a     => b, c );
+
+        let tx1p = Transaction::parse(&mut Buffer::new(&tx1)).unwrap();
+        let tx2p = Transaction::parse(&mut Buffer::new(&tx2)).unwrap();
+
+        let mut store = ::store::Store::new(& test_cfg!());
+
+        let ptr1 = store.transactions.write(&tx1p, 0x0e15);
+        let ptr2 = store.transactions.write_unverified(&tx2p);
+        assert_eq!(store.transactions.get_script_flags(ptr1), Some(0x0e15));
+        assert_eq!(store.transactions.get_script_flags(ptr2), None);
+        assert!(!store.transactions.is_verified(ptr2));
+
+        // the marker doesn't affect the content
+        assert_eq!(tx2, store.transactions.read(ptr2).as_slice());
+        assert_eq!(store.transactions.output_count(ptr2), 2);
+
+        store.transactions.set_verified(ptr2, 1);
+        store.transactions.set_unverified(ptr1);
+        assert_eq!(store.transactions.get_script_flags(ptr2), Some(1));
+        assert!(!store.transactions.is_verified(ptr1));
+        assert_eq!(tx1, store.transactions.read(ptr1).as_slice());
+
+        // flags that don't fit are not recorded
+        store.transactions.set_verified(ptr1, 1 << 16);
+        assert!(!store.transactions.is_verified(ptr1));
+        assert_eq!(tx1, store.transactions.read(ptr1).as_slice());
+    }
+
 }
\ No newline at end of file
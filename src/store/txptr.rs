@@ -71,8 +71,8 @@ impl TxPtr {
         }
     }
 
-    pub fn offset(self, offset: u32) -> TxPtr {
-        if self.file_offset + offset > super::MAX_CONTENT_SIZE as u32 {
+    pub fn offset(self, offset: u32, max_content_size: u64) -> TxPtr {
+        if self.file_offset + offset > max_content_size as u32 {
             println!("Next file!");
             TxPtr {
                 file_number: self.file_number + 1,
@@ -98,7 +98,7 @@ mod tests {
     fn test_skip()
     {
         let x = TxPtr::first();
-        let y = x.offset(1000);
+        let y = x.offset(1000, 1 << 30);
         assert_eq!(x.file_number, 0);
         assert_eq!(y.file_offset, 1000 + INITIAL_WRITEPOS as u32);
 
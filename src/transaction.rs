@@ -10,7 +10,12 @@ use itertools::Itertools;
 
 use buffer::*;
 use hash::*;
-use script::context;
+use util;
+use script;
+use script::ScriptError;
+use script::sigops;
+use sighash::SighashCache;
+#[cfg(feature = "bitcoinconsensus")]
 use ffi;
 
 use store;
@@ -18,10 +23,20 @@ use store::TxPtr;
 use store::Record;
 use store::HashIndexGuard;
 use store::TxIndex;
+use store::{SpendIndex, find_output};
 
 const MAX_TRANSACTION_SIZE: usize = 1_000_000;
 
-#[derive(Debug)]
+/// Number of satoshis in one bitcoin
+pub const COIN: i64 = 100_000_000;
+
+/// No amount can exceed the total supply
+pub const MAX_MONEY: i64 = 21_000_000 * COIN;
+
+/// The factor by which non-witness data weighs more than witness data (BIP141)
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+
+#[derive(Debug, PartialEq)]
 pub enum TransactionError {
     UnexpectedEndOfData,
     TransactionTooLarge,
@@ -29,11 +44,42 @@ pub enum TransactionError {
     NoOutputs,
     DuplicateInputs,
 
+    /// The flag of an extended serialization is not the witness flag
+    UnknownOptionalData,
+
+    /// The extended serialization is used but all witnesses are empty
+    SuperfluousWitness,
+
     OutputTransactionNotFound,
     OutputIndexNotFound,
 
-    ScriptError(i32)
+    /// A loose transaction spends outputs of transactions that are not stored
+    MissingParents {
+        parents: Vec<Hash32Buf>
+    },
+
+    /// Input `input` of a loose transaction spends an output that is already spent in the main chain
+    OutputAlreadySpent {
+        input: u32
+    },
+
+    /// A coinbase can only be added as part of a block
+    LooseCoinbase,
+
+    /// An output value is negative or above MAX_MONEY
+    OutputAmountOutOfRange,
 
+    /// The sum of the output values is above MAX_MONEY
+    TotalOutputAmountOutOfRange,
+
+    /// The sum of the input values is less than the sum of the output values
+    InsufficientInputAmount,
+
+    /// The script of input `input` of this transaction fails
+    ScriptError {
+        input: u32,
+        error: ScriptError
+    }
 }
 
 #[derive(Debug)]
@@ -61,7 +107,8 @@ impl From<EndOfBufferError> for TransactionError {
 
 /// A transaction represents a parsed transaction
 ///
-/// It always contains a reference to the buffer it was read from
+/// It always contains a reference to the buffer it was read from. For a segwit transaction this
+/// includes the marker, flag and witnesses; the txid is calculated without copying these out
 #[derive(Debug)]
 pub struct Transaction<'a> {
     pub version:   i32,
@@ -69,10 +116,17 @@ pub struct Transaction<'a> {
     pub txs_out:   Vec<TxOutput<'a>>,
     pub lock_time: u32,
 
+    /// One witness per input; empty if the transaction uses the legacy serialization
+    pub witnesses: Vec<TxWitness<'a>>,
+
     pub txs_out_idx: Vec<u32>,
     raw:           Buffer<'a>,
 
+    // flag of the extended serialization; 0 for the legacy serialization
+    flag:          u8,
 
+    // offset of the witnesses in raw
+    witness_start: usize,
 }
 
 
@@ -83,13 +137,38 @@ impl<'a> Parse<'a> for Transaction<'a> {
 
     /// Parses the raw bytes into individual fields
     /// and perform basic syntax checks
+    ///
+    /// The extended serialization (BIP144) is recognized by a zero marker in place of the input
+    /// count, followed by a non-zero flag
     fn parse(buffer: &mut Buffer<'a>) -> Result<Transaction<'a>, EndOfBufferError> {
 
         let org_buffer = *buffer;
 
         let version         = i32::parse(buffer)?;
-        let txs_in          = Vec::parse(buffer)?;
+
+        let flag = if buffer.inner.len() >= 2 && buffer.inner[0] == 0 && buffer.inner[1] != 0 {
+            let _marker = u8::parse(buffer)?;
+            u8::parse(buffer)?
+        }
+        else {
+            0
+        };
+
+        let txs_in: Vec<TxInput> = Vec::parse(buffer)?;
         let (txs_out,idxs)  = buffer.parse_vec_with_indices(org_buffer)?;
+
+        let witness_start   = org_buffer.len() - buffer.len();
+        let witnesses       = if flag & 1 != 0 {
+            let mut witnesses = Vec::with_capacity(txs_in.len());
+            for _ in 0..txs_in.len() {
+                witnesses.push(TxWitness::parse(buffer)?);
+            }
+            witnesses
+        }
+        else {
+            Vec::new()
+        };
+
         let lock_time       = u32::parse(buffer)?;
 
         Ok(Transaction {
@@ -98,7 +177,10 @@ impl<'a> Parse<'a> for Transaction<'a> {
             txs_out:   txs_out,
             txs_out_idx: idxs,
             lock_time: lock_time,
-            raw:       buffer.consumed_since(org_buffer)
+            witnesses: witnesses,
+            raw:       buffer.consumed_since(org_buffer),
+            flag:      flag,
+            witness_start: witness_start
 
         })
     }
@@ -112,13 +194,60 @@ impl<'a> ToRaw<'a> for Transaction<'a> {
 
 impl<'a> Transaction<'a> {
 
+    /// Returns true if the transaction uses the extended serialization with witnesses
+    pub fn has_witness(&self) -> bool {
+        self.flag != 0
+    }
+
+    /// Returns the size of the transaction without witness data
+    pub fn stripped_size(&self) -> usize {
+        if self.has_witness() {
+            self.raw.len() - 2 - (self.raw.len() - 4 - self.witness_start)
+        }
+        else {
+            self.raw.len()
+        }
+    }
+
+    /// Returns the hash of the transaction without witness data
+    ///
+    /// This is the hash by which transactions are referenced
+    pub fn txid(&self) -> Hash32Buf {
+        if self.has_witness() {
+            let raw = self.raw.inner;
+            Hash32Buf::double_sha256_from_parts(&[
+                &raw[..4],
+                &raw[6..self.witness_start],
+                &raw[raw.len()-4..]
+            ])
+        }
+        else {
+            Hash32Buf::double_sha256(self.raw.inner)
+        }
+    }
+
+    /// Returns the hash of the transaction including witness data
+    ///
+    /// This equals the txid if the transaction has no witnesses
+    pub fn wtxid(&self) -> Hash32Buf {
+        Hash32Buf::double_sha256(self.raw.inner)
+    }
+
     /// Performs basic syntax checks on the transaction
     pub fn verify_syntax(&self) -> TransactionResult<()> {
 
-        if self.raw.len() > MAX_TRANSACTION_SIZE {
+        if self.stripped_size() > MAX_TRANSACTION_SIZE {
             return Err(TransactionError::TransactionTooLarge);
         }
 
+        if self.flag > 1 {
+            return Err(TransactionError::UnknownOptionalData);
+        }
+
+        if self.has_witness() && self.witnesses.iter().all(|w| w.items.is_empty()) {
+            return Err(TransactionError::SuperfluousWitness);
+        }
+
         if self.txs_in.is_empty() {
             return Err(TransactionError::NoInputs);
         }
@@ -127,6 +256,20 @@ impl<'a> Transaction<'a> {
             return Err(TransactionError::NoOutputs);
         }
 
+        // Amounts must be in range
+        let mut total_output_value: i64 = 0;
+        for output in self.txs_out.iter() {
+
+            if output.value < 0 || output.value > MAX_MONEY {
+                return Err(TransactionError::OutputAmountOutOfRange);
+            }
+
+            total_output_value += output.value;
+            if total_output_value > MAX_MONEY {
+                return Err(TransactionError::TotalOutputAmountOutOfRange);
+            }
+        }
+
         // No double inputs
         if self.txs_in.iter().combinations(2).any(|pair|
                pair[0].prev_tx_out_idx == pair[1].prev_tx_out_idx
@@ -143,63 +286,197 @@ impl<'a> Transaction<'a> {
         self.txs_in.len() == 1 && self.txs_in[0].prev_tx_out.is_null()
     }
 
+    /// Returns the sum of the output values
+    ///
+    /// This doesn't check for overflow; the transaction must pass verify_syntax
+    pub fn get_output_value(&self) -> i64 {
+
+        self.txs_out.iter().map(|output| output.value).sum()
+    }
+
+    /// Returns the number of signature operations in the scripts of the transaction itself,
+    /// counted as by legacy rules
+    pub fn get_legacy_sigop_count(&self) -> usize {
+
+        let inputs: usize  = self.txs_in.iter().map(|input| sigops::count_sigops(input.script, false)).sum();
+        let outputs: usize = self.txs_out.iter().map(|output| sigops::count_sigops(output.pk_script, false)).sum();
+
+        inputs + outputs
+    }
+
+    /// Returns the sigop cost of the transaction, given the outputs spent by its inputs
+    ///
+    /// Legacy and P2SH signature operations cost more than those in witnesses; P2SH and witness
+    /// sigops are only counted if the corresponding flag is set
+    pub fn get_sigop_cost(&self, spent_outputs: &[TxOutput], flags: u32) -> usize {
+
+        let mut cost = self.get_legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+
+        if self.is_coinbase() {
+            return cost;
+        }
+
+        for (index, (input, output)) in self.txs_in.iter().zip(spent_outputs).enumerate() {
+
+            if flags & script::VERIFY_P2SH != 0 {
+                cost += sigops::count_p2sh_sigops(input.script, output.pk_script) * WITNESS_SCALE_FACTOR;
+            }
+
+            let witness = self.witnesses.get(index).map_or(&[][..], |witness| &witness.items[..]);
+            cost += sigops::count_witness_sigops(input.script, output.pk_script, witness, flags);
+        }
+        cost
+    }
+
+    /// Verifies that the given sum of the input values covers the outputs
+    pub fn verify_input_value(&self, input_value: i64) -> TransactionResult<()> {
+
+        if input_value < self.get_output_value() {
+            Err(TransactionError::InsufficientInputAmount)
+        }
+        else {
+            Ok(())
+        }
+    }
+
 
 
     /// Reverse script validation
     ///
     /// This checks the passed input-ptrs are valid against the corresponding output of self
     ///
-    pub fn verify_backtracking_outputs(&self, tx_store: &mut store::Transactions, inputs: &Vec<TxPtr>) {
-
+    /// A waiting transaction that fails is not a reason to reject self; it may be part of an
+    /// invalid orphan block that anyone can send. It is marked as unverified instead, such that it
+    /// is verified again when a block that contains it is added or connected. If the flags are not
+    /// known, the waiting transactions are marked as unverified as well
+    pub fn verify_backtracking_outputs(&self,
+                                       tx_index: &mut TxIndex,
+                                       tx_store: &mut store::Transactions,
+                                       hash:     Hash32,
+                                       inputs:   &Vec<TxPtr>,
+                                       flags:    Option<u32>) {
 
         for input_ptr in inputs.into_iter() {
 
             debug_assert!(input_ptr.is_guard());
 
-            // read tx from disk
-            let tx_raw_vec   = tx_store.read(*input_ptr);
-            let mut tx_raw   = Buffer::new(tx_raw_vec.as_slice());
+            let verified = match flags {
+                Some(flags) => self.verify_backtracking_output(tx_index, tx_store, hash, *input_ptr, flags).is_ok(),
+                None        => false
+            };
 
-            let tx           = Transaction::parse(&mut tx_raw).
-                    expect("Invalid tx data in database");
+            if !verified {
+                tx_store.set_unverified(*input_ptr);
+            }
+        }
+    }
 
-            // find indixes
-            let input_index  = input_ptr.get_input_index() as usize;
-            let ref input    = tx.txs_in[input_index];
-            let output_index = input.prev_tx_out_idx as usize;
+    /// Verifies a single input of a waiting transaction against the corresponding output of self
+    ///
+    /// The input value of the waiting transaction is verified if this was its last missing
+    /// input. If other inputs are still missing, this is done when these come in.
+    fn verify_backtracking_output(&self,
+                                  tx_index:  &mut TxIndex,
+                                  tx_store:  &mut store::Transactions,
+                                  hash:      Hash32,
+                                  input_ptr: TxPtr,
+                                  flags:     u32) -> TransactionResult<()> {
+
+        // read tx from disk
+        let tx_raw_vec   = tx_store.read(input_ptr);
+        let mut tx_raw   = Buffer::new(tx_raw_vec.as_slice());
+
+        let tx           = Transaction::parse(&mut tx_raw).
+                expect("Invalid tx data in database");
+
+        // find indixes
+        let input_index  = input_ptr.get_input_index() as usize;
+        let ref input    = tx.txs_in[input_index];
+        let output_index = input.prev_tx_out_idx as usize;
+
+        let output       = self.txs_out.get(output_index)
+            .ok_or(TransactionError::OutputIndexNotFound)?;
+
+        verify_script(&SighashCache::new(&tx), output.pk_script, output.value, input_index, flags)
+            .map_err(|err| TransactionError::ScriptError { input: input_index as u32, error: err })?;
+
+        // gather the input values of the waiting transaction
+        let mut input_value: i64 = 0;
+        let mut complete         = true;
+        for input in tx.txs_in.iter() {
+
+            if input.prev_tx_out == hash {
+
+                // self is not yet in the index
+                input_value += self.txs_out.get(input.prev_tx_out_idx as usize)
+                    .ok_or(TransactionError::OutputIndexNotFound)?
+                    .value;
+
+                continue;
+            }
 
-            ffi::verify_script(self.txs_out[output_index].pk_script, tx.to_raw(), input_index as u32)
-                .expect("TODO: Handle script error without panic");
+            let output = tx_index
+                .get(input.prev_tx_out)
+                .into_iter()
+                .find(|ptr| !ptr.is_guard());
 
+            match output {
+                None         => complete = false,
+                Some(output) => input_value += read_output_value(tx_store, output, input.prev_tx_out_idx)?
+            }
+        }
 
-            // TODO: verify_amount here
+        if complete {
+            tx.verify_input_value(input_value)?;
         }
+
+        Ok(())
+    }
+
+    /// Verifies the scripts of all inputs against the given outputs they spend, in order
+    pub fn verify_scripts(&self, spent_outputs: &[TxOutput], flags: u32) -> TransactionResult<()> {
+
+        if self.is_coinbase() {
+            return Ok(());
+        }
+
+        let cache = SighashCache::new(self);
+        for (index, output) in spent_outputs.iter().enumerate() {
+
+            verify_script(&cache, output.pk_script, output.value, index, flags)
+                .map_err(|err| TransactionError::ScriptError { input: index as u32, error: err })?;
+        }
+        Ok(())
     }
 
     /// Gets the output records referenced by the inputs of this tx
     ///
-    /// Uses Record new_unmatched_input placeholder for outputs not found
-    pub fn get_output_records(&self, tx_index: &mut TxIndex) -> Vec<Record> {
+    /// Uses Record new_unmatched_input placeholder for outputs not found. Of the outputs of which
+    /// the txid is stored more than once, the one in the chain of the spend-index is used (see
+    /// find_output); this is checked again when the block is connected
+    pub fn get_output_records(&self, tx_index: &mut TxIndex, spend_index: &SpendIndex) -> Vec<Record> {
 
         self.txs_in.iter()
 
             .filter(|tx_in| !tx_in.prev_tx_out.is_null())
             .map(|input| {
 
-                tx_index
-                    .get(input.prev_tx_out)
-                    .iter()
-                    .find(|ptr| !ptr.is_guard())
-                    .map_or(Record::new_unmatched_input(), |ptr| Record::new_output(*ptr, input.prev_tx_out_idx))
+                find_output(tx_index, spend_index, input.prev_tx_out, input.prev_tx_out_idx)
+                    .unwrap_or(Record::new_unmatched_input())
             })
             .collect()
     }
 
     /// Verifies and stores the transaction in the transaction_store and index
+    ///
+    /// The script flags depend on the height of the block the transaction is in. If these are
+    /// not known, as for the transactions of an orphan block, the scripts are not verified and the
+    /// transaction is stored as unverified; it is verified when its block is connected
     pub fn verify_and_store(&self,
                             tx_index:     &mut TxIndex,
                             tx_store:     &mut store::Transactions,
                             initial_sync: bool,
+                            script_flags: Option<u32>,
                             hash:         Hash32) -> TransactionResult<TransactionOk> {
 
         let mut stats: TransactionStats = Default::default();
@@ -208,8 +485,36 @@ impl<'a> Transaction<'a> {
 
         self.verify_syntax()?;
 
+        // a transaction that is already in, such as a loose transaction that is now included in
+        // a block, is not stored again if it has the same witnesses. Its scripts are verified
+        // again unless these are verified with the same flags, as the flags depend on the height
+        let existing: Vec<TxPtr> = tx_index.get(hash).into_iter().filter(|ptr| !ptr.is_guard()).collect();
+        if !existing.is_empty() {
+
+            if self.is_coinbase() {
+                return self.store_another(tx_index, tx_store, initial_sync, script_flags, hash, stats);
+            }
+
+            let same = existing.into_iter().find(|&ptr| tx_store.read(ptr).as_slice() == self.to_raw());
+            match same {
+                Some(ptr) => {
+                    if let Some(script_flags) = script_flags {
+                        if !initial_sync && tx_store.get_script_flags(ptr) != Some(script_flags) {
+                            self.verify_input_scripts(tx_index, tx_store, ptr, script_flags, &mut stats)?;
+                            tx_store.set_verified(ptr, script_flags);
+                        }
+                    }
+                    return Ok(TransactionOk::AlreadyExists { ptr: ptr });
+                },
+                None => return self.store_another(tx_index, tx_store, initial_sync, script_flags, hash, stats)
+            }
+        }
+
         // store
-        let ptr      = tx_store.write(self);
+        let ptr = match script_flags {
+            Some(script_flags) => tx_store.write(self, script_flags),
+            None               => tx_store.write_unverified(self)
+        };
 
         if initial_sync {
 
@@ -223,7 +528,9 @@ impl<'a> Transaction<'a> {
         let p1 = Instant::now();
         stats.store_tx += p1 - p0;
 
-        self.verify_input_scripts(tx_index, tx_store, ptr, &mut stats)?;
+        if let Some(script_flags) = script_flags {
+            self.verify_input_scripts(tx_index, tx_store, ptr, script_flags, &mut stats)?;
+        }
 
         let mut existing_ptrs = vec![];
 
@@ -252,14 +559,23 @@ impl<'a> Transaction<'a> {
                 if existing_ptrs
                     .iter()
                     .any(|p| !p.is_guard()) {
-                    assert_eq!(existing_ptrs.len(), 1);
 
-                    return Ok(TransactionOk::AlreadyExists { ptr: existing_ptrs[0] })
+                    // added concurrently; if it has other witnesses, ours is kept as well
+                    if !self.is_coinbase() {
+                        let same = existing_ptrs.iter()
+                            .find(|&&p| !p.is_guard() && tx_store.read(p).as_slice() == self.to_raw());
+
+                        if let Some(&same) = same {
+                            return Ok(TransactionOk::AlreadyExists { ptr: same })
+                        }
+                    }
+                    tx_index.add(hash, ptr);
+                    return Ok(TransactionOk::VerifiedAndStored {ptr: ptr, stats: stats })
                 }
 
                 // existing_ptrs (if any) are now inputs that are waiting for this transactions
                 // they need to be verified
-                self.verify_backtracking_outputs(tx_store, &existing_ptrs);
+                self.verify_backtracking_outputs(tx_index, tx_store, hash, &existing_ptrs, script_flags);
 
                 let p4 = Instant::now();
                 stats.backtracking += p4 - p3;
@@ -270,28 +586,94 @@ impl<'a> Transaction<'a> {
     }
 
 
-    /// Finds the outputs corresponding to the inputs and verify the scripts
+    /// Stores the transaction as another transaction with the txid of a stored one
+    ///
+    /// A coinbase is part of a single block, and two blocks can have coinbases with the same
+    /// txid. Whether this is allowed depends on the chain the blocks are in (BIP30); this is
+    /// verified when the block is connected.
+    ///
+    /// Any other transaction with the txid of a stored one has other witnesses. A block must use
+    /// its own version, as its witnesses are committed to in the block and are needed to verify
+    /// its scripts
+    fn store_another(&self,
+                     tx_index:     &mut TxIndex,
+                     tx_store:     &mut store::Transactions,
+                     initial_sync: bool,
+                     script_flags: Option<u32>,
+                     hash:         Hash32,
+                     mut stats:    TransactionStats) -> TransactionResult<TransactionOk> {
+
+        let ptr = match script_flags {
+            Some(script_flags) => tx_store.write(self, script_flags),
+            None               => tx_store.write_unverified(self)
+        };
+
+        if let Some(script_flags) = script_flags {
+            if !initial_sync {
+                self.verify_input_scripts(tx_index, tx_store, ptr, script_flags, &mut stats)?;
+            }
+        }
+
+        tx_index.add(hash, ptr);
+
+        Ok(TransactionOk::VerifiedAndStored { ptr: ptr, stats: stats })
+    }
+
+    /// Finds the outputs corresponding to the inputs and verify the scripts and the amounts
+    ///
+    /// This is done in two passes: first all inputs for which the output is known are verified,
+    /// and only then guards are placed for the outputs that are not yet known. This way, a
+    /// transaction that fails never leaves guards in the index
+    ///
+    /// If outputs are not yet known, the input value is verified by verify_backtracking_outputs
+    /// when the last one comes in
     pub fn verify_input_scripts(&self,
                                 tx_index: &mut TxIndex,
                                 tx_store: &mut store::Transactions,
                                 tx_ptr:   TxPtr,
+                                flags:    u32,
                                 stats:    &mut TransactionStats) -> TransactionResult<()> {
 
         if self.is_coinbase() {
             return Ok(())
         }
 
+        let mut missing_inputs = Vec::new();
+        let mut input_value: i64 = 0;
+
+        // the signature hash midstates are shared by all inputs
+        let cache = SighashCache::new(self);
+
         for (index, input) in self.txs_in.iter().enumerate() {
 
             let p0 = Instant::now();
 
+            let output = tx_index
+                .get(input.prev_tx_out)
+                .into_iter()
+                .find(|ptr| !ptr.is_guard());
+
+            stats.read_tx_idx += Instant::now() - p0;
+
+            match output {
+                None         => missing_inputs.push(index),
+                Some(output) => input_value += self.verify_input_script(&cache, tx_store, output, index, flags, stats)?
+            }
+        }
+
+        let mut complete = true;
+
+        for index in missing_inputs {
+
+            let p0 = Instant::now();
+
+            let input  = &self.txs_in[index];
             let output = tx_index.get_or_set(input.prev_tx_out,
                                                      tx_ptr.to_input(index as u16 ));
 
-            let p1 = Instant::now();
-            stats.read_tx_idx += p1 - p0;
+            stats.read_tx_idx += Instant::now() - p0;
 
-            let output = match output {
+            match output {
                 None => {
 
                     // We can't find the transaction this input is pointing to
@@ -301,44 +683,108 @@ impl<'a> Transaction<'a> {
                     // before this happens
                     //
                     // ^^ get_or_set has placed appropriate guards in the hash_index
-
-                    continue;
+                    complete = false;
                 },
-                Some(o) => o
-            };
 
+                // it came in concurrently
+                Some(output) => input_value += self.verify_input_script(&cache, tx_store, output, index, flags, stats)?
+            }
+        }
 
-            let previous_out_vec = tx_store.read_output(output, input.prev_tx_out_idx)
-                .ok_or(TransactionError::OutputIndexNotFound)?;
+        if complete {
+            self.verify_input_value(input_value)?;
+        }
 
-            let previous_tx_out = TxOutput::parse(&mut Buffer::new(&previous_out_vec))
-                .expect("Corrupt output data in store");
+        Ok(())
+    }
 
-            let p2 = Instant::now();
-            stats.read_tx += p2 - p1;
+    /// Verifies the script of a single input against the output it is spending
+    ///
+    /// Returns the value of the output
+    fn verify_input_script(&self,
+                           cache:    &SighashCache,
+                           tx_store: &mut store::Transactions,
+                           output:   TxPtr,
+                           index:    usize,
+                           flags:    u32,
+                           stats:    &mut TransactionStats) -> TransactionResult<i64> {
 
-            ffi::verify_script(previous_tx_out.pk_script, self.to_raw(), index as u32)
-                .expect("TODO: Handle script error more gracefully");
+        let p1 = Instant::now();
+        let input = &self.txs_in[index];
 
-            let p3 = Instant::now();
-            stats.script += p3 - p2;
+        let previous_out_vec = tx_store.read_output(output, input.prev_tx_out_idx)
+            .ok_or(TransactionError::OutputIndexNotFound)?;
 
-            // TODO: verify_amount here
-        }
+        let previous_tx_out = TxOutput::parse(&mut Buffer::new(&previous_out_vec))
+            .expect("Corrupt output data in store");
 
-        Ok(())
+        let p2 = Instant::now();
+        stats.read_tx += p2 - p1;
+
+        verify_script(cache, previous_tx_out.pk_script, previous_tx_out.value, index, flags)
+            .map_err(|err| TransactionError::ScriptError { input: index as u32, error: err })?;
+
+        let p3 = Instant::now();
+        stats.script += p3 - p2;
+
+        Ok(previous_tx_out.value)
     }
 
 
 }
 
+/// Verifies the script of an input against the output with the given pk_script and value
+#[cfg(not(feature = "bitcoinconsensus"))]
+fn verify_script(cache: &SighashCache, pk_script: &[u8], value: i64, input: usize, flags: u32)
+    -> Result<(), ScriptError> {
+
+    let tx = cache.transaction();
+    let checker = script::TransactionSignatureChecker::new(cache, input, value);
+    let witness = tx.witnesses.get(input).map_or(&[][..], |witness| &witness.items[..]);
+
+    script::verify_script(tx.txs_in[input].script, pk_script, witness, flags, &checker)
+}
+
+/// Verifies the script of an input against the output with the given pk_script and value
+/// using libbitcoinconsensus
+///
+/// The value is only passed on if witness verification is enabled
+#[cfg(feature = "bitcoinconsensus")]
+fn verify_script(cache: &SighashCache, pk_script: &[u8], value: i64, input: usize, flags: u32)
+    -> Result<(), ScriptError> {
+
+    let tx = cache.transaction();
+
+    let result = if flags & script::VERIFY_WITNESS != 0 {
+        ffi::verify_script_with_amount(pk_script, value, tx.to_raw(), input as u32, flags)
+    }
+    else {
+        ffi::verify_script(pk_script, tx.to_raw(), input as u32, flags)
+    };
+
+    result.map_err(ScriptError::from)
+}
+
+/// Reads the value of the output at the given index of the stored transaction
+pub fn read_output_value(tx_store: &mut store::Transactions, tx: TxPtr, output_index: u32)
+    -> TransactionResult<i64> {
+
+    let output_vec = tx_store.read_output(tx, output_index)
+        .ok_or(TransactionError::OutputIndexNotFound)?;
+
+    let output = TxOutput::parse(&mut Buffer::new(&output_vec))
+        .expect("Corrupt output data in store");
+
+    Ok(output.value)
+}
+
 
 /// Transaction input
 pub struct TxInput<'a> {
     pub prev_tx_out:     Hash32<'a>,
     pub prev_tx_out_idx: u32,
-    script:          &'a[u8],
-    sequence:        u32,
+    pub script:          &'a[u8],
+    pub sequence:        u32,
 }
 
 
@@ -358,8 +804,8 @@ impl<'a> Parse<'a> for TxInput<'a> {
 
 #[derive(PartialEq)]
 pub struct TxOutput<'a> {
-    value:     i64,
-    pk_script: &'a[u8]
+    pub value:     i64,
+    pub pk_script: &'a[u8]
 }
 
 impl<'a> Parse<'a> for TxOutput<'a> {
@@ -375,6 +821,40 @@ impl<'a> Parse<'a> for TxOutput<'a> {
 }
 
 
+/// The witness of a transaction input
+///
+/// This is a stack of byte-vectors that refer to the transaction buffer
+pub struct TxWitness<'a> {
+    pub items: Vec<&'a[u8]>
+}
+
+impl<'a> Parse<'a> for TxWitness<'a> {
+
+    fn parse(buffer: &mut Buffer<'a>) -> Result<TxWitness<'a>, EndOfBufferError> {
+
+        let count = buffer.parse_compact_size()?;
+        let mut items = Vec::with_capacity(count);
+        for _ in 0..count {
+            items.push(buffer.parse_compact_size_bytes()?);
+        }
+
+        Ok(TxWitness {
+            items: items
+        })
+    }
+}
+
+impl<'a> fmt::Debug for TxWitness<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+
+        let items: Vec<String> = self.items.iter()
+            .map(|item| util::to_hex(item))
+            .collect();
+
+        write!(fmt, "[{}]", items.join(" "))
+    }
+}
+
 impl<'a> fmt::Debug for TxInput<'a> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         try!(write!(fmt, "Prev-TX:{:?}, idx={:?}, seq={:?} script=",
@@ -382,7 +862,7 @@ impl<'a> fmt::Debug for TxInput<'a> {
             self.prev_tx_out_idx,
             self.sequence));
         
-        let ctx = context::Context::new(&self.script);
+        let ctx = script::context::Context::new(&self.script);
         write!(fmt, "{:?}", ctx)
 
         
@@ -394,7 +874,7 @@ impl<'a> fmt::Debug for TxOutput<'a> {
 
         try!(write!(fmt, "v:{:?} ", self.value));
         
-        let ctx = context::Context::new(&self.pk_script);
+        let ctx = script::context::Context::new(&self.pk_script);
         write!(fmt, "{:?}", ctx)
 
     }
@@ -496,4 +976,175 @@ mod tests {
 
         let _ = format!("{:?}", tx);
     }
+
+    #[test]
+    fn test_parse_segwit_tx() {
+
+        // Native P2WPKH example of BIP143
+        let tx_hex = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00\
+                      000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cd\
+                      d30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeff\
+                      ffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000f\
+                      fffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac90\
+                      93510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402\
+                      203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c\
+                      4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368d\
+                      a1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+
+        let slice = &from_hex(tx_hex);
+        let mut buf = buffer::Buffer::new(slice);
+
+        let tx = Transaction::parse(&mut buf).unwrap();
+
+        assert_eq!(buf.len(), 0);
+        assert!(tx.has_witness());
+        assert_eq!(tx.verify_syntax(), Ok(()));
+        assert_eq!(tx.txs_in.len(), 2);
+        assert_eq!(tx.txs_out.len(), 2);
+        assert_eq!(tx.lock_time, 0x11);
+        assert_eq!(tx.to_raw().len(), slice.len());
+
+        assert_eq!(tx.witnesses.len(), 2);
+        assert!(tx.witnesses[0].items.is_empty());
+        assert_eq!(tx.witnesses[1].items.iter().map(|i| i.len()).collect::<Vec<_>>(), vec![71, 33]);
+
+        assert_eq!(format!("{:?}", tx.txid()),
+                   "e8151a2af31c368a35053ddd4bdb285a8595c769a3ad83e0fa02314a602d4609");
+        assert_eq!(format!("{:?}", tx.wtxid()),
+                   "c36c38370907df2324d9ce9d149d191192f338b37665a82e78e76a12c909b762");
+        assert_eq!(tx.stripped_size(), slice.len() - 2 - 108);
+
+        // outputs can be read from the store
+        let mut store = ::store::Store::new(& test_cfg!());
+        let ptr = store.transactions.write(&tx, 0);
+        assert_eq!(store.transactions.read(ptr), *slice);
+        assert_eq!(read_output_value(&mut store.transactions, ptr, 1).unwrap(), 223_450_000);
+
+        // the txid of a legacy transaction is its wtxid
+        tx_builder!(bld);
+        let tx = tx!(bld; coinbase => a;1);
+        let tx = Transaction::parse(&mut buffer::Buffer::new(&tx)).unwrap();
+        assert!(!tx.has_witness());
+        assert_eq!(tx.txid(), tx.wtxid());
+        assert_eq!(tx.txid(), Hash32Buf::double_sha256(tx.to_raw()));
+    }
+
+    #[test]
+    fn test_output_amounts() {
+
+        tx_builder!(bld);
+
+        let tx1 = tx!(bld; coinbase => a;12, b;MAX_MONEY - 12);
+        let tx2 = tx!(bld; coinbase => a;MAX_MONEY + 1);
+        let tx3 = tx!(bld; coinbase => a;(-1i64));
+        let tx4 = tx!(bld; coinbase => a;12, b;MAX_MONEY - 11);
+
+        let parse = |tx| Transaction::parse(&mut buffer::Buffer::new(tx)).unwrap();
+
+        assert_eq!(parse(&tx1).verify_syntax(), Ok(()));
+        assert_eq!(parse(&tx2).verify_syntax(), Err(TransactionError::OutputAmountOutOfRange));
+        assert_eq!(parse(&tx3).verify_syntax(), Err(TransactionError::OutputAmountOutOfRange));
+        assert_eq!(parse(&tx4).verify_syntax(), Err(TransactionError::TotalOutputAmountOutOfRange));
+    }
+
+    #[test]
+    fn test_input_amounts() {
+
+        let mut store = ::store::Store::new(& test_cfg!());
+
+        tx_builder!(bld);
+
+        let tx1 = tx!(bld; coinbase => a;10, b;10 );
+        let tx2 = tx!(bld; a => c;11 );
+        let tx3 = tx!(bld; a,b => d;20 );
+
+        let hash = |tx| Hash32Buf::double_sha256(tx);
+        let tx1p = Transaction::parse(&mut buffer::Buffer::new(&tx1)).unwrap();
+        let tx2p = Transaction::parse(&mut buffer::Buffer::new(&tx2)).unwrap();
+        let tx3p = Transaction::parse(&mut buffer::Buffer::new(&tx3)).unwrap();
+
+        tx1p.verify_and_store(&mut store.tx_index, &mut store.transactions, false, Some(0), hash(&tx1).as_ref()).unwrap();
+
+        assert_eq!(
+            tx2p.verify_and_store(&mut store.tx_index, &mut store.transactions, false, Some(0), hash(&tx2).as_ref()).unwrap_err(),
+            TransactionError::InsufficientInputAmount);
+
+        tx3p.verify_and_store(&mut store.tx_index, &mut store.transactions, false, Some(0), hash(&tx3).as_ref()).unwrap();
+    }
+
+    #[test]
+    fn test_already_exists() {
+
+        let mut store = ::store::Store::new(& test_cfg!());
+
+        tx_builder!(bld);
+
+        let tx1 = tx!(bld; coinbase => a;10 );
+        let tx2 = tx!(bld; a => b;10 );
+
+        let hash = |tx| Hash32Buf::double_sha256(tx);
+        let tx1p = Transaction::parse(&mut buffer::Buffer::new(&tx1)).unwrap();
+        let tx2p = Transaction::parse(&mut buffer::Buffer::new(&tx2)).unwrap();
+
+        let mut ptrs = Vec::new();
+        for &(tx, raw) in [(&tx1p, &tx1), (&tx2p, &tx2)].iter() {
+            match tx.verify_and_store(&mut store.tx_index, &mut store.transactions, false, Some(0), hash(raw).as_ref()) {
+                Ok(TransactionOk::VerifiedAndStored { ptr, .. }) => ptrs.push(ptr),
+                x => panic!("Unexpected result {:?}", x)
+            };
+        }
+
+        // the stored transaction is reused, also during initial sync
+        for &initial_sync in [false, true].iter() {
+            match tx2p.verify_and_store(&mut store.tx_index, &mut store.transactions, initial_sync, Some(0), hash(&tx2).as_ref()) {
+                Ok(TransactionOk::AlreadyExists { ptr: existing }) => assert_eq!(existing, ptrs[1]),
+                x => panic!("Unexpected result {:?}", x)
+            }
+        }
+
+        // a coinbase with the same txid is another transaction
+        match tx1p.verify_and_store(&mut store.tx_index, &mut store.transactions, true, Some(0), hash(&tx1).as_ref()) {
+            Ok(TransactionOk::VerifiedAndStored { ptr, .. }) => {
+                assert!(ptr != ptrs[0]);
+                assert_eq!(store.tx_index.get(hash(&tx1).as_ref()), vec![ptr, ptrs[0]]);
+            },
+            x => panic!("Unexpected result {:?}", x)
+        }
+    }
+
+    #[test]
+    fn test_input_amounts_backtracking() {
+
+        let mut store = ::store::Store::new(& test_cfg!());
+
+        tx_builder!(bld);
+
+        let tx1 = tx!(bld; coinbase => a;10, b;10 );
+        let tx2 = tx!(bld; a,b => c;21 );
+
+        let hash = |tx| Hash32Buf::double_sha256(tx);
+        let tx1p = Transaction::parse(&mut buffer::Buffer::new(&tx1)).unwrap();
+        let tx2p = Transaction::parse(&mut buffer::Buffer::new(&tx2)).unwrap();
+
+        // tx2 comes first and waits for tx1 with guards
+        match tx2p.verify_and_store(&mut store.tx_index, &mut store.transactions, false, Some(0), hash(&tx2).as_ref()) {
+            Ok(TransactionOk::VerifiedAndStored { .. }) => {},
+            x => panic!("Unexpected result {:?}", x)
+        }
+
+        // the input value of tx2 can only be verified when tx1 comes in; this doesn't reject tx1
+        let tx2_ptr = match tx1p.verify_and_store(&mut store.tx_index, &mut store.transactions, false, Some(0), hash(&tx1).as_ref()) {
+            Ok(TransactionOk::VerifiedAndStored { .. }) => {
+                store.tx_index.get(hash(&tx2).as_ref())[0]
+            },
+            x => panic!("Unexpected result {:?}", x)
+        };
+
+        // tx2 is marked as unverified, and fails when it is used again
+        assert!(!store.transactions.is_verified(tx2_ptr));
+        match tx2p.verify_and_store(&mut store.tx_index, &mut store.transactions, false, Some(0), hash(&tx2).as_ref()) {
+            Err(TransactionError::InsufficientInputAmount) => {},
+            x => panic!("Unexpected result {:?}", x)
+        }
+    }
 }
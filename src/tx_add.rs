@@ -0,0 +1,155 @@
+//! Acceptance of loose transactions
+//!
+//! A loose transaction is one that is not (yet) part of a block. It is verified against the
+//! chain of the best tip: all outputs it spends must be stored and may not be spent in the main
+//! chain, and its scripts must pass with the flags of the next block.
+//!
+//! An accepted transaction is stored in the tx-index like any other. When a block comes in that
+//! contains it with the same witnesses, the block uses the stored transaction, and verifies its
+//! scripts again if the flags of the block differ. With other witnesses, the block stores its own
+//! version.
+//!
+//! Loose transactions are not checked against each other; two loose transactions spending the
+//! same output are both accepted, and only the block that includes one decides.
+
+use hash::*;
+use buffer::*;
+
+use store::{Store, find_output};
+use transaction::{Transaction, TransactionError, TransactionOk};
+use block_add;
+
+
+/// Verifies and stores a loose transaction
+pub fn add_transaction(store: &mut Store, buffer: &[u8]) -> Result<TransactionOk, TransactionError> {
+
+    // prevents concurrent pruning from replacing the tx-index
+    let _lock = store.lock_shared();
+
+    let tx   = Transaction::parse(&mut Buffer::new(buffer))?;
+    let hash = tx.txid();
+
+    info!(store.logger, "add_transaction"; "hash" => format!("{:?}", hash));
+
+    if tx.is_coinbase() {
+        return Err(TransactionError::LooseCoinbase);
+    }
+
+    // the inputs are looked up in the main chain; other stores may not move it meanwhile
+    let spend_index_lock = store.lock_spend_index();
+
+    // another store may have added blocks
+    block_add::update_main_chain(store);
+
+    let mut missing: Vec<Hash32Buf> = Vec::new();
+
+    for (index, input) in tx.txs_in.iter().enumerate() {
+
+        let output = find_output(
+            &mut store.tx_index, &store.spend_index, input.prev_tx_out, input.prev_tx_out_idx);
+
+        match output {
+            None => {
+                let parent = input.prev_tx_out.as_buf();
+                if !missing.contains(&parent) {
+                    missing.push(parent);
+                }
+            },
+            Some(record) => {
+                if store.spend_index.exists(record.hash()) {
+                    return Err(TransactionError::OutputAlreadySpent { input: index as u32 });
+                }
+            }
+        }
+    }
+
+    drop(spend_index_lock);
+
+    if !missing.is_empty() {
+        return Err(TransactionError::MissingParents { parents: missing });
+    }
+
+    let height = match store.tips.get_most_work_tip() {
+        Some(tip) => tip.height + 1,
+        None      => 0
+    };
+    let script_flags = store.params.get_script_flags(height);
+
+    // scripts are verified regardless of initial sync
+    tx.verify_and_store(&mut store.tx_index, &mut store.transactions, false, Some(script_flags), hash.as_ref())
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use store::BlockPtr;
+    use block_add::{add_block, BlockAddOk};
+
+    #[test]
+    fn test_add_transaction() {
+
+        let mut store = Store::new(& test_cfg!());
+        store.verify_headers = false;
+
+        tx_builder!(bld);
+
+        let block0 = genesis!();
+        let block1 = blk!(prev = block0;
+            tx!(bld; coinbase => a;10, b;10 ),
+            tx!(bld; a => c;10 )
+        );
+
+        assert_eq!(add_block(&mut store, &block0), Ok(BlockAddOk::Connected));
+        assert_eq!(add_block(&mut store, &block1), Ok(BlockAddOk::Connected));
+
+        // spends the output of a transaction that is not stored
+        let unknown = tx!(bld; coinbase => x;7 );
+        assert_eq!(add_transaction(&mut store, &tx!(bld; c, x => d )).unwrap_err(),
+            TransactionError::MissingParents { parents: vec![Hash32Buf::double_sha256(&unknown)] });
+
+        // a is spent in block1
+        assert_eq!(add_transaction(&mut store, &tx!(bld; b, a => e )).unwrap_err(),
+            TransactionError::OutputAlreadySpent { input: 1 });
+
+        assert_eq!(add_transaction(&mut store, &tx!(bld; coinbase => f )).unwrap_err(),
+            TransactionError::LooseCoinbase);
+
+        // a script-sig consisting of RETURN fails
+        let mut failing = tx!(bld; b => g );
+        failing[41] = 1;
+        failing.insert(42, 0x6a);
+        match add_transaction(&mut store, &failing) {
+            Err(TransactionError::ScriptError { input: 0, .. }) => {},
+            x => panic!("Unexpected result {:?}", x)
+        }
+
+        // accepted, and then reused by the block that includes it
+        let loose = tx!(bld; b => h;5 );
+        let ptr = match add_transaction(&mut store, &loose) {
+            Ok(TransactionOk::VerifiedAndStored { ptr, .. }) => ptr,
+            x => panic!("Unexpected result {:?}", x)
+        };
+        match add_transaction(&mut store, &loose) {
+            Ok(TransactionOk::AlreadyExists { ptr: existing }) => assert_eq!(existing, ptr),
+            x => panic!("Unexpected result {:?}", x)
+        }
+
+        let block2 = blk!(prev = block1; tx!(bld; coinbase => i;11 ), loose);
+        assert_eq!(add_block(&mut store, &block2), Ok(BlockAddOk::Connected));
+
+        let block_hash = Hash32Buf::double_sha256(&block2[0..80]);
+        let block_ptr: BlockPtr = store.block_index.get(block_hash.as_ref())[0];
+        let tx_ptrs: Vec<_> = store.spend_tree.get_block_records(block_ptr).iter()
+            .filter(|rec| rec.is_transaction())
+            .map(|rec| rec.get_transaction_ptr())
+            .collect();
+
+        assert_eq!(tx_ptrs[1], ptr);
+
+        // now b is spent in the main chain
+        assert_eq!(add_transaction(&mut store, &tx!(bld; b => j )).unwrap_err(),
+            TransactionError::OutputAlreadySpent { input: 0 });
+    }
+}
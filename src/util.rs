@@ -42,3 +42,11 @@ pub fn from_hex_rev(str: &str) -> Vec<u8> {
     v.reverse();
     v
 }
+
+/// Formats the bytes as lowercase hex
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .concat()
+}
@@ -1,13 +1,12 @@
 
 extern crate bitcrust_lib;
 
-extern crate byteorder;
 
 
 use std::io::BufReader;
 use std::fs::File;
 
-mod blk_file;
+use bitcrust_lib::import;
 
 use std::time::{Instant};
 extern crate rayon;
@@ -28,13 +27,13 @@ fn load_bench_init() {
 
         let mut blocks = 0;
         loop {
-            let blk = blk_file::read_block(&mut rdr).unwrap();
+            let blk = import::read_block(&mut rdr, store.params.magic).unwrap();
 
             if blk.is_none() {
                 break;
             }
 
-            bitcrust_lib::add_block(&mut store, &blk.unwrap());
+            bitcrust_lib::add_block(&mut store, &blk.unwrap()).unwrap();
 
             blocks += 1;
        }
@@ -59,13 +58,13 @@ fn load_bench_next() {
     let mut blocks = 0;
     let start = Instant::now();
     loop {
-        let blk = blk_file::read_block(&mut rdr).unwrap();
+        let blk = import::read_block(&mut rdr, store.params.magic).unwrap();
 
         if blk.is_none() {
             break;
         }
 
-        bitcrust_lib::add_block(&mut store, &blk.unwrap());
+        bitcrust_lib::add_block(&mut store, &blk.unwrap()).unwrap();
 
         blocks += 1;
     }
@@ -1,13 +1,12 @@
 
 extern crate bitcrust_lib;
 
-extern crate byteorder;
 
 
 use std::io::BufReader;
 use std::fs::File;
 
-mod blk_file;
+use bitcrust_lib::import;
 
 use std::time::{Instant};
 extern crate rayon;
@@ -27,13 +26,13 @@ fn load_file1() {
 
     let mut blocks = 0;
     loop {
-        let blk = blk_file::read_block(&mut rdr).unwrap();
+        let blk = import::read_block(&mut rdr, store.params.magic).unwrap();
 
         if blk.is_none() {
             break;
         }
 
-        bitcrust_lib::add_block(&mut store, &blk.unwrap());
+        bitcrust_lib::add_block(&mut store, &blk.unwrap()).unwrap();
 
         blocks += 1;
 
@@ -73,13 +72,13 @@ fn load_file_large() {
         let mut rdr = BufReader::new(f);
 
         loop {
-            let blk = blk_file::read_block(&mut rdr).unwrap();
+            let blk = import::read_block(&mut rdr, store.params.magic).unwrap();
 
             if blk.is_none() {
                 break;
             }
 
-            bitcrust_lib::add_block(&mut store, &blk.unwrap());
+            bitcrust_lib::add_block(&mut store, &blk.unwrap()).unwrap();
 
 
             blocks += 1;
@@ -127,13 +126,13 @@ fn load_large_concurrent() {
                 let mut rdr = BufReader::new(f);
 
                 loop {
-                    let blk = blk_file::read_block(&mut rdr).unwrap();
+                    let blk = import::read_block(&mut rdr, store.params.magic).unwrap();
 
                     if blk.is_none() {
                         break;
                     }
 
-                    bitcrust_lib::add_block(&mut store, &blk.unwrap());
+                    bitcrust_lib::add_block(&mut store, &blk.unwrap()).unwrap();
 
 
                     blocks += 1;